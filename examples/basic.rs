@@ -22,7 +22,7 @@ fn main() {
     println!("hlsl: {}", hlsl);
 }
 
-const SOURCE: &'static str = r#"
+const SOURCE: &str = r#"
 varying uv: vec2
         
 fn vertex(self) -> vec4 {
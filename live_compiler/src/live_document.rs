@@ -1,7 +1,7 @@
 //use makepad_id_macros2::*;
 use {
     crate::{
-        span::{TextPos, TextSpan},
+        span::{TextPos, TextSpan, TokenSpan},
         live_token::{TokenWithSpan,LiveTokenId},
         live_node::LiveNode,
     }
@@ -73,6 +73,16 @@ impl LiveOriginal {
     pub fn token_id_to_span(&self, token_id: LiveTokenId) -> TextSpan {
         self.tokens[token_id.token_index() as usize].span
     }
+    
+    pub fn token_span_to_span(&self, token_span: TokenSpan) -> TextSpan {
+        let last_index = self.tokens.len().saturating_sub(1);
+        let start_index = token_span.token_id.token_index().min(last_index);
+        let end_index = (start_index + token_span.len.max(1) - 1).min(last_index);
+        TextSpan {
+            start: self.tokens[start_index].span.start,
+            end: self.tokens[end_index].span.end
+        }
+    }
 }
 
 
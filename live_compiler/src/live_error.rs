@@ -2,6 +2,7 @@ use {
     std::fmt,
     std::convert::Into,
     crate::live_token::LiveTokenId,
+    crate::live_document::LiveOriginal,
     crate::span::{TextSpan,TokenSpan},
     makepad_live_tokenizer::{LiveErrorOrigin},
 };
@@ -52,23 +53,68 @@ pub struct LiveFileError {
     pub file: String,
     pub span: TextSpan,
     pub message: String,
+    /// rustc style rendering of the offending source line, empty if the source is unknown
+    pub snippet: String,
 }
 
-impl fmt::Display for LiveFileError {
-    // This trait requires `fmt` with this exact signature.
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl LiveFileError {
+    pub fn with_snippet(mut self, source: &str) -> Self {
+        self.snippet = render_snippet(source, self.span);
+        self
+    }
+    
+    /// Writes the error like `Display` does, labelled with `label` instead of `error`.
+    pub fn fmt_with_label(&self, f: &mut fmt::Formatter, label: &str) -> fmt::Result {
+        // the arrow lines up with the gutter of the snippet
+        let gutter = " ".repeat((self.span.start.line + 1).to_string().len());
         write!(
             f,
-            "{}: {}:{} - {}\n   origin: {}",
-            self.file,
-            self.span.start.line+1,
-            self.span.start.column+1,
+            "{}: {}\n{}--> {}:{}:{}",
+            label,
             self.message,
-            self.origin
-        )
+            gutter,
+            self.file,
+            self.span.start.line + 1,
+            self.span.start.column + 1,
+        ) ?;
+        if !self.snippet.is_empty() {
+            write!(f, "\n{}", self.snippet) ?;
+        }
+        Ok(())
+    }
+}
+
+fn render_snippet(source: &str, span: TextSpan) -> String {
+    let line = match source.lines().nth(span.start.line as usize) {
+        Some(line) => line,
+        None => return String::new()
+    };
+    let line_number = (span.start.line + 1).to_string();
+    let gutter = " ".repeat(line_number.len());
+    let line_len = line.chars().count() as u32;
+    let start = span.start.column.min(line_len);
+    let end = if span.end.line == span.start.line {span.end.column.min(line_len)} else {line_len};
+    let carets = (end.max(start + 1) - start) as usize;
+    format!(
+        "{} |\n{} | {}\n{} | {}{}",
+        gutter,
+        line_number,
+        line,
+        gutter,
+        line.chars().take(start as usize).map( | c | if c == '\t' {'\t'} else {' '}).collect::<String>(),
+        "^".repeat(carets)
+    )
+}
+
+impl fmt::Display for LiveFileError {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_label(f, "error")
     }
 }
 
+impl std::error::Error for LiveFileError {}
+
 
 
 impl LiveError{
@@ -79,6 +125,23 @@ impl LiveError{
             file: "".to_string(),
             span: self.span.into_text_span().unwrap(),
             message: self.message,
+            snippet: String::new(),
+        }
+    }
+    
+    /// Resolves token spans against the original document and renders a snippet
+    /// of the source the document was loaded from.
+    pub fn to_live_file_error(&self, file: &str, source: &str, original: &LiveOriginal) -> LiveFileError {
+        let span = match &self.span {
+            LiveErrorSpan::Text(span) => *span,
+            LiveErrorSpan::Token(token_span) => original.token_span_to_span(*token_span),
+        };
+        LiveFileError {
+            origin: self.origin.clone(),
+            file: file.to_string(),
+            span,
+            message: self.message.clone(),
+            snippet: render_snippet(source, span),
         }
     }
 }
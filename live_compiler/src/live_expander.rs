@@ -15,7 +15,8 @@ pub struct LiveExpander<'a> {
 
 impl<'a> LiveExpander<'a> {
     pub fn is_baseclass(id: LiveId) -> bool {
-        id == id!(Struct)
+        id == id!(Struct) || id == id!(Enum) || id == id!(texture2d) || id == id!(textureCube)
+            || id == id!(texture3d) || id == id!(texture2d_array) || id == id!(texture2d_depth)
     }
 
     pub fn shift_parent_stack(
@@ -379,7 +380,14 @@ impl<'a> LiveExpander<'a> {
                     //             });
                     // }
                     // current_parent.push((out_doc.nodes[out_index].id, out_index));
-                    unimplemented!()
+                    if !Self::is_baseclass(*clone) {
+                        self.errors.push(LiveError {
+                            origin: live_error_origin!(),
+                            span: in_doc.token_id_to_span(in_node.origin.token_id().unwrap()).into(),
+                            message: format!("Can't find live definition of {}", clone)
+                        });
+                    }
+                    current_parent.push((out_doc.nodes[out_index].id, out_index));
                 }
                 LiveValue::Class { .. } => {
                     // store the class context
@@ -29,14 +29,14 @@ impl LiveFile {
     pub fn load(source: &str) -> Result<LiveFile, LiveFileError> {
         let start_pos = TextPos { line: 0, column: 0 };
         let (tokens, strings) = match tokenize_from_str(&source, start_pos) {
-            Err(msg) => return Err(msg.into_live_file_error()), //panic!("Lex error {}", msg),
+            Err(msg) => return Err(msg.into_live_file_error().with_snippet(source)),
             Ok(lex_result) => lex_result,
         };
 
         let mut parser = LiveParser::new(&tokens, &[]);
 
         let mut original = match parser.parse_live_document() {
-            Err(msg) => return Err(msg.into_live_file_error()), //panic!("Parse error {}", msg.to_live_file_error(file, &source)),
+            Err(msg) => return Err(msg.into_live_file_error().with_snippet(source)),
             Ok(ld) => ld,
         };
 
@@ -80,7 +80,9 @@ impl LiveFile {
             expanded: LiveExpanded::new(),
         };
 
-        live_file.expand_all_documents();
+        if let Err(err) = live_file.expand_all_documents() {
+            return Err(err.into_live_file_error().with_snippet(source));
+        }
         return Ok(live_file);
     }
 
@@ -162,7 +164,7 @@ impl LiveFile {
         }
     }
 
-    pub fn expand_all_documents(&mut self) {
+    pub fn expand_all_documents(&mut self) -> Result<(), LiveError> {
         let mut errors = vec![];
         let in_doc = &self.original;
 
@@ -173,7 +175,7 @@ impl LiveFile {
         live_document_expander.expand(in_doc, &mut self.expanded);
 
         if !errors.is_empty() {
-            panic!("{:#?}", errors);
+            return Err(errors.remove(0));
         }
         // assert!(self.original.nodes.len() == self.expanded.nodes.len());
         // for i in 0..self.original.nodes.len() {
@@ -181,6 +183,7 @@ impl LiveFile {
         //     println!("{:#?}", self.expanded.nodes[i]);
         //     println!("----------------");
         // }
+        Ok(())
     }
 
     pub fn live_node_as_string(&self, node: &LiveNode) -> Option<String> {
@@ -250,7 +253,7 @@ pub fn tokenize_from_str(
                     },
                 };
                 match full_token.token {
-                    FullToken::Unknown | FullToken::OtherNumber | FullToken::Lifetime => {
+                    FullToken::Unknown | FullToken::OtherNumber => {
                         return Err(LiveError {
                             origin: live_error_origin!(),
                             span: span.into(),
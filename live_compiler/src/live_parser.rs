@@ -399,6 +399,11 @@ impl<'a> LiveParser<'a> {
                     .with_prop_type(LivePropType::Nameless),
                 ld,
             )?;
+            // `[ty; len]` is an array type in a shader, the len goes in as the second item
+            if counter == 1 && self.accept_token(LiveToken::Punct(id!(;))) {
+                counter += 1;
+                continue;
+            }
             self.accept_token(LiveToken::Punct(id!(,)));
             counter += 1;
         }
@@ -440,6 +445,29 @@ impl<'a> LiveParser<'a> {
         return Err(self.error(format!("Eof in object body"), live_error_origin!()));
     }
 
+    // `Shape: Enum {Circle, Box}`, every variant becomes an id node
+    fn expect_enum_variants(&mut self, prop_id: LiveId, ld: &mut LiveOriginal) -> Result<(), LiveError> {
+        while self.peek_token() != LiveToken::Eof {
+            let token_id = self.get_token_id();
+            if self.accept_token(LiveToken::Close(Delim::Brace)) {
+                ld.nodes.push(LiveNode {
+                    origin: LiveNodeOrigin::from_token_id(token_id),
+                    id: prop_id,
+                    value: LiveValue::Close,
+                });
+                return Ok(());
+            }
+            let variant = self.expect_ident()?;
+            ld.nodes.push(LiveNode {
+                origin: LiveNodeOrigin::from_token_id(token_id).with_prop_type(LivePropType::Field),
+                id: variant,
+                value: LiveValue::Id(variant),
+            });
+            self.accept_optional_delim();
+        }
+        Err(self.error(format!("Eof in enum body"), live_error_origin!()))
+    }
+
     fn expect_named_enum(
         &mut self,
         prop_id: LiveId,
@@ -700,7 +728,11 @@ impl<'a> LiveParser<'a> {
                             id: prop_id,
                             value: LiveValue::Clone(base),
                         });
-                        self.expect_live_class(false, prop_id, ld)?;
+                        if base == id!(Enum) {
+                            self.expect_enum_variants(prop_id, ld)?;
+                        } else {
+                            self.expect_live_class(false, prop_id, ld)?;
+                        }
                     } else {
                         ld.nodes.push(LiveNode {
                             origin,
@@ -49,8 +49,14 @@ pub enum LiveToken {
     String {index: u32, len: u32},
     Bool(bool),
     Int(i64),
+    /// `1u` or `0xffu`
+    Uint(u64),
+    /// `1i` or `0xff`, an integer that shaders don't read as a float
+    Sint(i64),
     Float(f64),
     Color(u32),
+    /// `'outer`, without the quote
+    Lifetime(LiveId),
     
     Eof,
 }
@@ -191,8 +197,11 @@ impl LiveToken {
             FullToken::Close(p) => Some(LiveToken::Close(p)),
             FullToken::Bool(p) => Some(LiveToken::Bool(p)),
             FullToken::Int(p) => Some(LiveToken::Int(p)),
+            FullToken::Uint(p) => Some(LiveToken::Uint(p)),
+            FullToken::Sint(p) => Some(LiveToken::Sint(p)),
             FullToken::Float(p) => Some(LiveToken::Float(p)),
             FullToken::Color(p) => Some(LiveToken::Color(p)),
+            FullToken::Lifetime(p) => Some(LiveToken::Lifetime(p)),
             _ => None
         }
     }
@@ -214,8 +223,11 @@ impl fmt::Display for LiveToken {
             Self::Close(Delim::Bracket) => write!(f, "]"),
             Self::Bool(lit) => write!(f, "{}", lit),
             Self::Int(lit) => write!(f, "{}", lit),
+            Self::Uint(lit) => write!(f, "{}u", lit),
+            Self::Sint(lit) => write!(f, "{}i", lit),
             Self::Float(lit) => write!(f, "{}", lit),
             Self::Color(lit) => write!(f, "#{:x}", lit),
+            Self::Lifetime(id) => write!(f, "'{}", id),
         }
     }
 }
@@ -35,9 +35,14 @@ pub enum FullToken {
     Color(u32),
     Float(f64),
     Int(i64),
+    /// `1u` or `0xffu`
+    Uint(u64),
+    /// `1i` or `0xff`, an integer that stays one in shaders where a plain `Int` can be a float
+    Sint(i64),
     
     OtherNumber,
-    Lifetime,
+    /// `'outer`, without the quote
+    Lifetime(LiveId),
     Comment,
     Whitespace,
     Unknown,
@@ -270,27 +270,9 @@ impl InitialState {
     
     fn number(self, cursor: &mut Cursor) -> (State, FullToken) {
         match (cursor.peek(0), cursor.peek(1)) {
-            ('0', 'b') => {
-                cursor.skip(2);
-                if !cursor.skip_digits(2) {
-                    return (State::Initial(InitialState), FullToken::Unknown);
-                }
-                return (State::Initial(InitialState), FullToken::OtherNumber)
-            }
-            ('0', 'o') => {
-                cursor.skip(2);
-                if !cursor.skip_digits(8) {
-                    return (State::Initial(InitialState), FullToken::Unknown);
-                }
-                return (State::Initial(InitialState), FullToken::OtherNumber)
-            }
-            ('0', 'x') => {
-                cursor.skip(2);
-                if !cursor.skip_digits(16) {
-                    return (State::Initial(InitialState), FullToken::Unknown);
-                }
-                return (State::Initial(InitialState), FullToken::OtherNumber)
-            }
+            ('0', 'b') => self.radix_number(cursor, 2),
+            ('0', 'o') => self.radix_number(cursor, 8),
+            ('0', 'x') => self.radix_number(cursor, 16),
             _ => {
                 let start = cursor.index();
                 // normal number
@@ -333,11 +315,18 @@ impl InitialState {
                         }
                     }
                     _ => {
-                        if cursor.skip_suffix() {
+                        let end = cursor.index();
+                        let suffix = cursor.int_suffix();
+                        if suffix.is_none() && cursor.skip_suffix() {
                             return (State::Initial(InitialState), FullToken::OtherNumber)
                         }
                         // normal number
-                        if let Ok(value) = cursor.from_start_to_scratch(start).parse::<i64>() {
+                        let digits = &cursor.from_start_to_scratch(start)[..end - start];
+                        if suffix.is_some() {
+                            let value = digits.replace('_', "").parse::<u64>().ok();
+                            return (State::Initial(InitialState), int_token(value, suffix).unwrap_or(FullToken::Unknown))
+                        }
+                        else if let Ok(value) = digits.parse::<i64>() {
                             return (State::Initial(InitialState), FullToken::Int(value))
                         }
                         else {
@@ -346,7 +335,24 @@ impl InitialState {
                     }
                 }
             }
-        };
+        }
+    }
+    
+    // `0b`, `0o` and `0x` numbers are always integers, `u` makes them unsigned
+    fn radix_number(self, cursor: &mut Cursor, radix: u32) -> (State, FullToken) {
+        cursor.skip(2);
+        let start = cursor.index();
+        if !cursor.skip_digits(radix) {
+            return (State::Initial(InitialState), FullToken::Unknown);
+        }
+        let end = cursor.index();
+        let suffix = cursor.int_suffix();
+        if suffix.is_none() && cursor.skip_suffix() {
+            return (State::Initial(InitialState), FullToken::OtherNumber)
+        }
+        let digits = cursor.from_start_to_scratch(start)[..end - start].replace('_', "");
+        let value = u64::from_str_radix(&digits, radix).ok();
+        (State::Initial(InitialState), int_token(value, Some(suffix.unwrap_or('i'))).unwrap_or(FullToken::Unknown))
     }
     
     fn color(self, cursor: &mut Cursor) -> (State, FullToken) {
@@ -379,6 +385,7 @@ impl InitialState {
     fn char_or_lifetime(self, cursor: &mut Cursor) -> (State, FullToken) {
         if cursor.peek(1).is_identifier_start() && cursor.peek(2) != '\'' {
             debug_assert!(cursor.peek(0) == '\'');
+            let start = cursor.index() + 1;
             cursor.skip(2);
             while cursor.skip_if( | ch | ch.is_identifier_continue()) {}
             if cursor.peek(0) == '\'' {
@@ -386,7 +393,9 @@ impl InitialState {
                 cursor.skip_suffix();
                 (State::Initial(InitialState), FullToken::String)
             } else {
-                (State::Initial(InitialState), FullToken::Lifetime)
+                (State::Initial(InitialState), FullToken::Lifetime(
+                    LiveId::from_str(cursor.from_start_to_scratch(start)).unwrap()
+                ))
             }
         } else {
             self.single_quoted_string(cursor)
@@ -473,6 +482,15 @@ impl InitialState {
     }
 }
 
+// `u` makes an unsigned integer, `i` a signed one that doesn't double as a float like plain `Int`
+fn int_token(value: Option<u64>, suffix: Option<char>) -> Option<FullToken> {
+    match (value?, suffix?) {
+        (value, 'u') => Some(FullToken::Uint(value)),
+        (value, _) if value <= i64::MAX as u64 => Some(FullToken::Sint(value as i64)),
+        _ => None
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct BlockCommentTailState {
     depth: usize,
@@ -689,6 +707,17 @@ impl<'a> Cursor<'a> {
         has_skip_digits
     }
     
+    // a lone `u` or `i` right after the digits, `1u` or `1i`
+    fn int_suffix(&mut self) -> Option<char> {
+        match self.peek(0) {
+            ch @ 'u' | ch @ 'i' if !self.peek(1).is_identifier_continue() => {
+                self.skip(1);
+                Some(ch)
+            }
+            _ => None
+        }
+    }
+    
     fn skip_suffix(&mut self) -> bool {
         if self.peek(0).is_identifier_start() {
             self.skip(1);
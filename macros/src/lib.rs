@@ -0,0 +1,324 @@
+//! Compile-time cross-compilation of nanoshredder shaders.
+//!
+//! ```ignore
+//! mod sprite {
+//!     nanoshredder_macros::shader! {
+//!         attributes {position: vec3}
+//!         uniforms {Projection: mat4}
+//!
+//!         fn vertex(self) -> vec4 {
+//!             return self.Projection * vec4(self.position, 1.0);
+//!         }
+//!
+//!         fn pixel(self) -> vec4 {
+//!             return #f00;
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! expands to `GLSL_VERTEX`, `GLSL_PIXEL`, `METAL`, `HLSL`, `WGSL`, `SPIRV`, `RUST` and the reflection
+//! as `REFLECTION_JSON` and the `ATTRIBUTES`, `INSTANCES`, `UNIFORM_BLOCKS`, `TEXTURES`, `VARYINGS` and `OUTPUTS`
+//! tables of `(name, type, slots, slot_offset)`, and the enums of the shader as `ENUMS` of `(name, variants)`.
+//! `include_shader!("sprite.shader", ...)` reads the shader from a file relative to the crate root instead.
+
+use {
+    proc_macro::{Delimiter, Span, TokenStream, TokenTree, Spacing},
+    makepad_macro_lib::{TokenBuilder, error, error_span},
+    nanoshredder::{Shader, ShaderReflectionField, ShaderTy, TextPos},
+};
+
+#[proc_macro]
+pub fn shader(item: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    let (attributes, uniforms) = match parse_decls(&mut tokens) {
+        Ok(decls) => decls,
+        Err(err) => return err
+    };
+    let mut source = Source::default();
+    source.push_stream(tokens.into_iter().collect());
+    let source_spans = source.spans;
+    compile(&source.string, "<shader!>", &attributes, &uniforms, | pos | {
+        // the token the error starts at, or the last one before it
+        source_spans.iter().rev().find( | (start, _) | *start <= pos).map( | (_, span) | *span).unwrap_or_else(Span::call_site)
+    }, false)
+}
+
+#[proc_macro]
+pub fn include_shader(item: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    let (path, span) = match tokens.first() {
+        Some(TokenTree::Literal(lit)) if lit.to_string().starts_with('"') => {
+            let path = lit.to_string();
+            (path[1..path.len() - 1].to_string(), lit.span())
+        }
+        _ => return error("expected the path of a shader file, relative to the crate root")
+    };
+    tokens.remove(0);
+    if let Some(TokenTree::Punct(punct)) = tokens.first() {
+        if punct.as_char() == ',' {
+            tokens.remove(0);
+        }
+    }
+    let (attributes, uniforms) = match parse_decls(&mut tokens) {
+        Ok(decls) => decls,
+        Err(err) => return err
+    };
+    if let Some(token) = tokens.first() {
+        return shader_error("expected `attributes {..}` or `uniforms {..}`", token.span())
+    }
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let full_path = std::path::Path::new(&manifest_dir).join(&path);
+    let source = match std::fs::read_to_string(&full_path) {
+        Ok(source) => source,
+        Err(err) => return shader_error(&format!("can't read {}: {}", full_path.display(), err), span)
+    };
+    let mut output = compile(&source, &path, &attributes, &uniforms, | _ | span, true);
+    // rebuilds when the shader file changes
+    let mut tb = TokenBuilder::new();
+    tb.add("const _ : & [u8] = include_bytes ! (").string(&full_path.to_string_lossy()).add(") ;");
+    output.extend(tb.end());
+    output
+}
+
+type Decls = Vec<(String, ShaderTy)>;
+
+// `attributes {position: vec3, ..}` and `uniforms {Projection: mat4, ..}`, in front of the shader
+fn parse_decls(tokens: &mut Vec<TokenTree>) -> Result<(Decls, Decls), TokenStream> {
+    let mut attributes = Vec::new();
+    let mut uniforms = Vec::new();
+    loop {
+        let decls = match (tokens.first(), tokens.get(1)) {
+            (Some(TokenTree::Ident(ident)), Some(TokenTree::Group(group))) if group.delimiter() == Delimiter::Brace => {
+                match ident.to_string().as_str() {
+                    "attributes" => &mut attributes,
+                    "uniforms" => &mut uniforms,
+                    _ => break
+                }
+            }
+            _ => break
+        };
+        let group = match tokens.remove(1) {
+            TokenTree::Group(group) => group,
+            _ => unreachable!()
+        };
+        tokens.remove(0);
+        let mut decl_tokens = group.stream().into_iter().peekable();
+        while let Some(token) = decl_tokens.next() {
+            let name = match token {
+                TokenTree::Ident(ident) => ident.to_string(),
+                token => return Err(shader_error("expected `name: type`", token.span()))
+            };
+            match decl_tokens.next() {
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ':' => (),
+                _ => return Err(shader_error("expected `name: type`", group.span()))
+            }
+            let ty = match decl_tokens.next() {
+                Some(TokenTree::Ident(ident)) => match parse_ty(&ident.to_string()) {
+                    Some(ty) => ty,
+                    None => return Err(shader_error(&format!("unknown type `{}`", ident), ident.span()))
+                },
+                _ => return Err(shader_error("expected `name: type`", group.span()))
+            };
+            decls.push((name, ty));
+            if let Some(TokenTree::Punct(punct)) = decl_tokens.peek() {
+                if punct.as_char() == ',' {
+                    decl_tokens.next();
+                }
+            }
+        }
+        if let Some(TokenTree::Punct(punct)) = tokens.first() {
+            if punct.as_char() == ',' {
+                tokens.remove(0);
+            }
+        }
+    }
+    Ok((attributes, uniforms))
+}
+
+fn parse_ty(ty: &str) -> Option<ShaderTy> {
+    Some(match ty {
+        "bool" => ShaderTy::Bool,
+        "int" => ShaderTy::Int,
+        "uint" => ShaderTy::Uint,
+        "float" => ShaderTy::Float,
+        "ivec2" => ShaderTy::Ivec2,
+        "ivec3" => ShaderTy::Ivec3,
+        "ivec4" => ShaderTy::Ivec4,
+        "uvec2" => ShaderTy::Uvec2,
+        "uvec3" => ShaderTy::Uvec3,
+        "uvec4" => ShaderTy::Uvec4,
+        "vec2" => ShaderTy::Vec2,
+        "vec3" => ShaderTy::Vec3,
+        "vec4" => ShaderTy::Vec4,
+        "mat2" => ShaderTy::Mat2,
+        "mat3" => ShaderTy::Mat3,
+        "mat4" => ShaderTy::Mat4,
+        "texture2d" => ShaderTy::Texture2D,
+        "textureCube" => ShaderTy::TextureCube,
+        "texture3d" => ShaderTy::Texture3D,
+        "texture2d_array" => ShaderTy::Texture2DArray,
+        "texture2d_depth" => ShaderTy::Texture2DDepth,
+        _ => return None,
+    })
+}
+
+// the shader as text, with the position of every token in it so that errors can be put back on the token
+#[derive(Default)]
+struct Source {
+    string: String,
+    column: u32,
+    spans: Vec<(TextPos, Span)>,
+    glued: bool,
+}
+
+impl Source {
+    fn push(&mut self, text: &str, span: Span) {
+        if !self.glued && !self.string.is_empty() {
+            self.string.push(' ');
+            self.column += 1;
+        }
+        self.spans.push((TextPos {line: 0, column: self.column}, span));
+        self.string.push_str(text);
+        self.column += text.chars().count() as u32;
+        self.glued = false;
+    }
+
+    fn push_stream(&mut self, stream: TokenStream) {
+        for token in stream {
+            match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.push(open, group.span_open());
+                    self.push_stream(group.stream());
+                    self.push(close, group.span_close());
+                }
+                TokenTree::Punct(punct) => {
+                    self.push(&punct.as_char().to_string(), punct.span());
+                    // `->`, `+=` and the `#` of colors stay in one piece
+                    self.glued = punct.spacing() == Spacing::Joint || punct.as_char() == '#';
+                }
+                TokenTree::Ident(ident) => self.push(&ident.to_string(), ident.span()),
+                TokenTree::Literal(lit) => self.push(&lit.to_string(), lit.span()),
+            }
+        }
+    }
+}
+
+// errors of inline shaders only carry the message, the span says where, errors of shader files
+// are rendered with their file and snippet
+fn compile(
+    source: &str,
+    file_name: &str,
+    attributes: &Decls,
+    uniforms: &Decls,
+    error_span_at: impl Fn(TextPos) -> Span,
+    render_errors: bool,
+) -> TokenStream {
+    let mut shader = match Shader::new_with_file_name(file_name, source) {
+        Ok(shader) => shader,
+        Err(err) => {
+            let message = if render_errors {rendered(&err.to_string())} else {err.message.clone()};
+            return shader_error(&message, error_span_at(err.span.start))
+        }
+    };
+    for (name, ty) in attributes {
+        shader.add_attribute(name, ty.clone());
+    }
+    for (name, ty) in uniforms {
+        shader.add_uniform(name, ty.clone());
+    }
+    if let Err(diagnostics) = shader.compile() {
+        let mut errors = TokenStream::new();
+        for diagnostic in diagnostics.iter().filter( | diagnostic | diagnostic.is_error()) {
+            let message = if render_errors {rendered(&diagnostic.to_string())} else {diagnostic.error.message.clone()};
+            errors.extend(shader_error(&message, error_span_at(diagnostic.error.span.start)));
+        }
+        return errors
+    }
+
+    let mut tb = TokenBuilder::new();
+    let (vertex, pixel) = shader.generate_glsl();
+    tb.add("pub const GLSL_VERTEX : & str =").string(&vertex).add(";");
+    tb.add("pub const GLSL_PIXEL : & str =").string(&pixel).add(";");
+    tb.add("pub const METAL : & str =").string(&shader.generate_metal()).add(";");
+    // neither has point sprites
+    tb.add("pub const HLSL : Option < & str > =");
+    match shader.check_hlsl() {
+        Ok(()) => tb.add("Some (").string(&shader.generate_hlsl()).add(") ;"),
+        Err(_) => tb.add("None ;"),
+    };
+    tb.add("pub const WGSL : Option < & str > =");
+    match shader.check_wgsl() {
+        Ok(()) => tb.add("Some (").string(&shader.generate_wgsl()).add(") ;"),
+        Err(_) => tb.add("None ;"),
+    };
+    tb.add("pub const SPIRV : & [u32] = & [");
+    for word in shader.generate_spirv() {
+        tb.suf_u32(word).add(",");
+    }
+    tb.add("] ;");
+    // not every shader fits the Rust backend, the others don't depend on it
+    tb.add("pub const RUST : Option < & str > =");
+    match shader.generate_rust() {
+        Ok(rust) => tb.add("Some (").string(&rust).add(") ;"),
+        Err(_) => tb.add("None ;"),
+    };
+
+    let reflection = shader.reflection();
+    tb.add("pub const REFLECTION_JSON : & str =").string(&reflection.to_json()).add(";");
+    add_fields(&mut tb, "ATTRIBUTES", &reflection.attributes);
+    add_fields(&mut tb, "INSTANCES", &reflection.instances);
+    add_fields(&mut tb, "TEXTURES", &reflection.textures);
+    add_fields(&mut tb, "VARYINGS", &reflection.varyings);
+    add_fields(&mut tb, "OUTPUTS", &reflection.outputs);
+    tb.add("pub const UNIFORM_BLOCKS : & [(& str , & [(& str , & str , usize , usize)])] = & [");
+    for block in &reflection.uniform_blocks {
+        tb.add("(").string(&block.name).add(", & [");
+        for field in &block.uniforms {
+            add_field(&mut tb, field);
+        }
+        tb.add("]) ,");
+    }
+    tb.add("] ;");
+    tb.add("pub const ENUMS : & [(& str , & [(& str , usize)])] = & [");
+    for shader_enum in &reflection.enums {
+        tb.add("(").string(&shader_enum.name).add(", & [");
+        for (variant, value) in &shader_enum.variants {
+            tb.add("(").string(variant).add(",").unsuf_usize(*value).add(") ,");
+        }
+        tb.add("]) ,");
+    }
+    tb.add("] ;");
+    tb.end()
+}
+
+// a `compile_error!` at a token of the shader, rustc points at all of the `compile_error!(..)`
+// so every token of it has to carry the span
+fn shader_error(message: &str, span: Span) -> TokenStream {
+    error_span(message, span).into_iter().map( | mut tt | {tt.set_span(span); tt}).collect()
+}
+
+// rustc puts its own `error: ` in front
+fn rendered(error: &str) -> String {
+    error.trim_start_matches("error: ").to_string()
+}
+
+fn add_fields(tb: &mut TokenBuilder, name: &str, fields: &[ShaderReflectionField]) {
+    tb.add("pub const").ident(name).add(": & [(& str , & str , usize , usize)] = & [");
+    for field in fields {
+        add_field(tb, field);
+    }
+    tb.add("] ;");
+}
+
+fn add_field(tb: &mut TokenBuilder, field: &ShaderReflectionField) {
+    tb.add("(").string(&field.name).add(",").string(&field.ty.to_string()).add(",");
+    tb.unsuf_usize(field.slots).add(",").unsuf_usize(field.slot_offset).add(") ,");
+}
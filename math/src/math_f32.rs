@@ -73,6 +73,7 @@ impl Transform {
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[repr(C)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -144,6 +145,7 @@ pub fn vec2(x:f32, y:f32)->Vec2{
 }*/
 
 #[derive(Clone, Copy, Default, PartialEq, Debug)]
+#[repr(C)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -243,6 +245,7 @@ impl Plane {
 
 
 #[derive(Clone, Copy, Default, Debug,PartialEq)]
+#[repr(C)]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -1,5 +1,8 @@
 // shader math glue layer
 
+use crate::math_f32::{Mat4, Vec2, Vec3, Vec4};
+use std::ops::{Div, Index, IndexMut, Mul, Sub};
+
 pub trait ShaderMath{
     fn abs(self)->Self;
     
@@ -30,6 +33,20 @@ pub trait ShaderMath{
     fn log10(self)->Self;
     fn powf(self, v:Self)->Self;
     fn powi(self, p:i32)->Self;
+
+    fn sqrt(self)->Self;
+    fn inversesqrt(self)->Self;
+    fn sign(self)->Self;
+    fn radians(self)->Self;
+    fn degrees(self)->Self;
+    fn atan2(self, x:Self)->Self;
+    // GLSL `mod`, which rounds towards negative infinity
+    fn modulo(self, v:Self)->Self;
+    // `self` is the edge
+    fn step(self, x:Self)->Self;
+    fn mix(self, y:Self, a:Self)->Self;
+    // `self` is the lower edge
+    fn smoothstep(self, edge1:Self, x:Self)->Self;
 }
 
 impl ShaderMath for f32{
@@ -61,6 +78,20 @@ impl ShaderMath for f32{
     fn log10(self)->Self{self.log10()}
     fn powf(self, v:Self)->Self{self.powf(v)}
     fn powi(self, v:i32)->Self{self.powi(v)}
+
+    fn sqrt(self)->Self{self.sqrt()}
+    fn inversesqrt(self)->Self{1.0 / self.sqrt()}
+    fn sign(self)->Self{if self > 0.0 {1.0} else if self < 0.0 {-1.0} else {0.0}}
+    fn radians(self)->Self{self.to_radians()}
+    fn degrees(self)->Self{self.to_degrees()}
+    fn atan2(self, x:Self)->Self{self.atan2(x)}
+    fn modulo(self, v:Self)->Self{self - v * (self / v).floor()}
+    fn step(self, x:Self)->Self{if x < self {0.0} else {1.0}}
+    fn mix(self, y:Self, a:Self)->Self{self * (1.0 - a) + y * a}
+    fn smoothstep(self, edge1:Self, x:Self)->Self{
+        let t = ((x - self) / (edge1 - self)).max(0.0).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
 }
 
 impl ShaderMath for f64{
@@ -94,6 +125,93 @@ impl ShaderMath for f64{
     fn log10(self)->Self{self.log10()}
     fn powf(self, v:Self)->Self{self.powf(v)}
     fn powi(self, v:i32)->Self{self.powi(v)}
+
+    fn sqrt(self)->Self{self.sqrt()}
+    fn inversesqrt(self)->Self{1.0 / self.sqrt()}
+    fn sign(self)->Self{if self > 0.0 {1.0} else if self < 0.0 {-1.0} else {0.0}}
+    fn radians(self)->Self{self.to_radians()}
+    fn degrees(self)->Self{self.to_degrees()}
+    fn atan2(self, x:Self)->Self{self.atan2(x)}
+    fn modulo(self, v:Self)->Self{self - v * (self / v).floor()}
+    fn step(self, x:Self)->Self{if x < self {0.0} else {1.0}}
+    fn mix(self, y:Self, a:Self)->Self{self * (1.0 - a) + y * a}
+    fn smoothstep(self, edge1:Self, x:Self)->Self{
+        let t = ((x - self) / (edge1 - self)).max(0.0).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// vectors apply the f32 functions to each of their components
+macro_rules!impl_shader_math_vec{
+    ($ty:ident, $($field:ident),*) => {
+        impl ShaderMath for $ty{
+            fn abs(self)->Self{$ty{$($field: self.$field.abs()),*}}
+
+            fn sin(self)->Self{$ty{$($field: self.$field.sin()),*}}
+            fn cos(self)->Self{$ty{$($field: self.$field.cos()),*}}
+            fn tan(self)->Self{$ty{$($field: self.$field.tan()),*}}
+            fn asin(self)->Self{$ty{$($field: self.$field.asin()),*}}
+            fn acos(self)->Self{$ty{$($field: self.$field.acos()),*}}
+            fn atan(self)->Self{$ty{$($field: self.$field.atan()),*}}
+
+            fn sinh(self)->Self{$ty{$($field: self.$field.sinh()),*}}
+            fn cosh(self)->Self{$ty{$($field: self.$field.cosh()),*}}
+            fn tanh(self)->Self{$ty{$($field: self.$field.tanh()),*}}
+            fn asinh(self)->Self{$ty{$($field: self.$field.asinh()),*}}
+            fn acosh(self)->Self{$ty{$($field: self.$field.acosh()),*}}
+            fn atanh(self)->Self{$ty{$($field: self.$field.atanh()),*}}
+
+            fn fract(self)->Self{$ty{$($field: ShaderMath::fract(self.$field)),*}}
+            fn ceil(self)->Self{$ty{$($field: self.$field.ceil()),*}}
+            fn floor(self)->Self{$ty{$($field: self.$field.floor()),*}}
+            fn min(self, v:Self)->Self{$ty{$($field: self.$field.min(v.$field)),*}}
+            fn max(self, v:Self)->Self{$ty{$($field: self.$field.max(v.$field)),*}}
+            fn clamp(self, low:Self, high:Self)->Self{$ty{$($field: ShaderMath::clamp(self.$field, low.$field, high.$field)),*}}
+            fn exp(self)->Self{$ty{$($field: self.$field.exp()),*}}
+            fn exp2(self)->Self{$ty{$($field: self.$field.exp2()),*}}
+            fn ln(self)->Self{$ty{$($field: self.$field.ln()),*}}
+            fn log2(self)->Self{$ty{$($field: self.$field.log2()),*}}
+            fn log10(self)->Self{$ty{$($field: self.$field.log10()),*}}
+            fn powf(self, v:Self)->Self{$ty{$($field: self.$field.powf(v.$field)),*}}
+            fn powi(self, v:i32)->Self{$ty{$($field: self.$field.powi(v)),*}}
+
+            fn sqrt(self)->Self{$ty{$($field: self.$field.sqrt()),*}}
+            fn inversesqrt(self)->Self{$ty{$($field: self.$field.inversesqrt()),*}}
+            fn sign(self)->Self{$ty{$($field: self.$field.sign()),*}}
+            fn radians(self)->Self{$ty{$($field: self.$field.radians()),*}}
+            fn degrees(self)->Self{$ty{$($field: self.$field.degrees()),*}}
+            fn atan2(self, x:Self)->Self{$ty{$($field: self.$field.atan2(x.$field)),*}}
+            fn modulo(self, v:Self)->Self{$ty{$($field: self.$field.modulo(v.$field)),*}}
+            fn step(self, x:Self)->Self{$ty{$($field: self.$field.step(x.$field)),*}}
+            fn mix(self, y:Self, a:Self)->Self{$ty{$($field: self.$field.mix(y.$field, a.$field)),*}}
+            fn smoothstep(self, edge1:Self, x:Self)->Self{$ty{$($field: self.$field.smoothstep(edge1.$field, x.$field)),*}}
+        }
+    }
+}
+
+impl_shader_math_vec!(Vec2, x, y);
+impl_shader_math_vec!(Vec3, x, y, z);
+impl_shader_math_vec!(Vec4, x, y, z, w);
+
+// the geometric functions, for f32 and the vectors
+pub trait ShaderVector: ShaderMath + Copy + Sub<Output=Self> + Mul<f32, Output=Self> + Div<f32, Output=Self>{
+    fn dot(self, v:Self)->f32;
+}
+
+impl ShaderVector for f32{
+    fn dot(self, v:Self)->f32{self * v}
+}
+
+impl ShaderVector for Vec2{
+    fn dot(self, v:Self)->f32{self.x * v.x + self.y * v.y}
+}
+
+impl ShaderVector for Vec3{
+    fn dot(self, v:Self)->f32{self.x * v.x + self.y * v.y + self.z * v.z}
+}
+
+impl ShaderVector for Vec4{
+    fn dot(self, v:Self)->f32{self.x * v.x + self.y * v.y + self.z * v.z + self.w * v.w}
 }
 
 pub fn abs<T:ShaderMath>(v:T)->T{v.abs()}
@@ -127,6 +245,163 @@ pub fn pow<T:ShaderMath>(v:T,l:T)->T{v.powf(l)}
 pub fn powf<T:ShaderMath>(v:T,l:T)->T{v.powf(l)}
 pub fn powi<T:ShaderMath>(v:T,l:i32)->T{v.powi(l)}
 
+pub fn sqrt<T:ShaderMath>(v:T)->T{v.sqrt()}
+pub fn inversesqrt<T:ShaderMath>(v:T)->T{v.inversesqrt()}
+pub fn sign<T:ShaderMath>(v:T)->T{v.sign()}
+pub fn radians<T:ShaderMath>(v:T)->T{v.radians()}
+pub fn degrees<T:ShaderMath>(v:T)->T{v.degrees()}
+pub fn atan2<T:ShaderMath>(y:T,x:T)->T{y.atan2(x)}
+pub fn modulo<T:ShaderMath>(v:T,l:T)->T{v.modulo(l)}
+pub fn step<T:ShaderMath>(e:T,v:T)->T{e.step(v)}
+pub fn mix<T:ShaderMath>(v:T,l:T,a:T)->T{v.mix(l,a)}
+pub fn smoothstep<T:ShaderMath>(e0:T,e1:T,v:T)->T{e0.smoothstep(e1,v)}
+
+pub fn dot<T:ShaderVector>(a:T,b:T)->f32{a.dot(b)}
+pub fn length<T:ShaderVector>(v:T)->f32{v.dot(v).sqrt()}
+pub fn distance<T:ShaderVector>(a:T,b:T)->f32{length(a - b)}
+pub fn normalize<T:ShaderVector>(v:T)->T{v / length(v)}
+pub fn cross(a:Vec3,b:Vec3)->Vec3{Vec3::cross(a, b)}
+pub fn reflect<T:ShaderVector>(i:T,n:T)->T{i - n * (2.0 * dot(n, i))}
+pub fn faceforward<T:ShaderVector>(n:T,i:T,nref:T)->T{if dot(nref, i) < 0.0 {n} else {n * -1.0}}
+pub fn refract<T:ShaderVector>(i:T,n:T,eta:f32)->T{
+    let d = dot(n, i);
+    let k = 1.0 - eta * eta * (1.0 - d * d);
+    if k < 0.0 {i * 0.0} else {i * eta - n * (eta * d + k.sqrt())}
+}
+
+// the column major matrices of the shader languages, `m[i]` is a column like it is in GLSL
+pub trait ShaderMatrix: Copy{
+    type Column;
+    // `self * v`
+    fn mul_vec(self, v:Self::Column)->Self::Column;
+    // `v * self`
+    fn vec_mul(self, v:Self::Column)->Self::Column;
+    // `self * m`
+    fn mul_mat(self, m:Self)->Self;
+    fn transpose(self)->Self;
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Mat2 {
+    pub v: [f32; 4],
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Mat3 {
+    pub v: [f32; 9],
+}
+
+macro_rules!impl_matrix_columns{
+    ($mat:ident, $vec:ident, $size:expr) => {
+        impl Index<usize> for $mat{
+            type Output = $vec;
+            fn index(&self, column:usize)->&$vec{&self.columns()[column]}
+        }
+
+        impl IndexMut<usize> for $mat{
+            fn index_mut(&mut self, column:usize)->&mut $vec{&mut self.columns_mut()[column]}
+        }
+
+        impl $mat{
+            // the vectors are `repr(C)`, so the columns have the layout of the components
+            fn columns(&self)->&[$vec; $size]{
+                unsafe{&*(self.v.as_ptr() as *const [$vec; $size])}
+            }
+
+            fn columns_mut(&mut self)->&mut [$vec; $size]{
+                unsafe{&mut *(self.v.as_mut_ptr() as *mut [$vec; $size])}
+            }
+        }
+    };
+}
+
+impl_matrix_columns!(Mat2, Vec2, 2);
+impl_matrix_columns!(Mat3, Vec3, 3);
+impl_matrix_columns!(Mat4, Vec4, 4);
+
+macro_rules!impl_shader_matrix{
+    ($mat:ident, $vec:ident, $size:expr) => {
+        impl ShaderMatrix for $mat{
+            type Column = $vec;
+            fn mul_vec(self, v:$vec)->$vec{
+                let mut r = $vec::default();
+                for row in 0..$size {
+                    for column in 0..$size {
+                        r[row] += self[column][row] * v[column];
+                    }
+                }
+                r
+            }
+            fn vec_mul(self, v:$vec)->$vec{self.transpose().mul_vec(v)}
+            fn mul_mat(self, m:Self)->Self{
+                let mut r = Self::default();
+                for column in 0..$size {
+                    r[column] = self.mul_vec(m[column]);
+                }
+                r
+            }
+            fn transpose(self)->Self{
+                let mut t = Self::default();
+                for i in 0..$size * $size {
+                    t.v[i] = self.v[(i % $size) * $size + i / $size];
+                }
+                t
+            }
+        }
+    };
+}
+
+impl_shader_matrix!(Mat2, Vec2, 2);
+impl_shader_matrix!(Mat3, Vec3, 3);
+
+// `Mat4` keeps its own methods, `Mat4::mul` multiplies the other way around
+impl ShaderMatrix for Mat4{
+    type Column = Vec4;
+    fn mul_vec(self, v:Vec4)->Vec4{self.transform_vec4(v)}
+    fn vec_mul(self, v:Vec4)->Vec4{self.transpose().transform_vec4(v)}
+    fn mul_mat(self, m:Self)->Self{Mat4::mul(&m, &self)}
+    fn transpose(self)->Self{
+        let mut t = Mat4::default();
+        for i in 0..16 {
+            t.v[i] = self.v[(i % 4) * 4 + i / 4];
+        }
+        t
+    }
+}
+
+macro_rules!impl_shader_vector_index{
+    ($vec:ident, $($index:expr => $component:ident),*) => {
+        impl Index<usize> for $vec{
+            type Output = f32;
+            fn index(&self, index:usize)->&f32{
+                match index {
+                    $($index => &self.$component,)*
+                    _ => panic!("index {} is out of bounds for a {}", index, stringify!($vec))
+                }
+            }
+        }
+
+        impl IndexMut<usize> for $vec{
+            fn index_mut(&mut self, index:usize)->&mut f32{
+                match index {
+                    $($index => &mut self.$component,)*
+                    _ => panic!("index {} is out of bounds for a {}", index, stringify!($vec))
+                }
+            }
+        }
+    };
+}
+
+impl_shader_vector_index!(Vec2, 0 => x, 1 => y);
+impl_shader_vector_index!(Vec3, 0 => x, 1 => y, 2 => z);
+impl_shader_vector_index!(Vec4, 0 => x, 1 => y, 2 => z, 3 => w);
+
+pub fn mat_mul_vec<M:ShaderMatrix>(m:M, v:M::Column)->M::Column{m.mul_vec(v)}
+pub fn vec_mul_mat<M:ShaderMatrix>(v:M::Column, m:M)->M::Column{m.vec_mul(v)}
+pub fn mat_mul<M:ShaderMatrix>(a:M, b:M)->M{a.mul_mat(b)}
+pub fn transpose<M:ShaderMatrix>(m:M)->M{m.transpose()}
+pub fn inverse(m:Mat4)->Mat4{m.invert()}
+
 /*
 abs
 asin
@@ -10,7 +10,7 @@ use {
         },
         shader_ast::*,
         shader::Shader,
-        ty_check::TyChecker,
+        ty_check::{TyChecker, undefined_var_message},
     },
     std::{
         cell::{Cell, RefCell},
@@ -18,6 +18,9 @@ use {
     },
 };
 
+// render targets D3D11, Metal and Vulkan all guarantee
+const MAX_PIXEL_OUTPUTS: usize = 8;
+
 #[derive(Clone, Copy)]
 pub struct ShaderAnalyseOptions {
     pub no_const_collapse: bool,
@@ -55,16 +58,21 @@ impl<'a> StructAnalyser<'a> {
         // now analyse the functions
         for fn_node_ptr in &self.struct_def.methods {
             let fn_def = self.shader_registry.all_fns.get(fn_node_ptr).unwrap();
-            FnDefAnalyser {
+            let result = FnDefAnalyser {
                 file: self.file,
                 shader_registry: self.shader_registry,
                 closure_return_ty: None,
                 fn_def,
                 scopes: &mut self.scopes,
                 options: self.options,
-                is_inside_loop: false,
+                loop_labels: Vec::new(),
+                unused_locals: Vec::new(),
+                failed_lets: Vec::new(),
+            }
+            .analyse_fn_def();
+            if let Err(err) = result {
+                self.shader_registry.push_error(err);
             }
-            .analyse_fn_def()?;
         }
         self.scopes.pop_scope();
         Ok(())
@@ -83,7 +91,18 @@ impl<'a> StructAnalyser<'a> {
                     .insert(*struct_ptr);
             }
             Ty::Array { .. } => {
-                todo!();
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: field_def.span.into(),
+                    message: String::from("struct fields can't be arrays"),
+                })
+            }
+            ty if ty.is_texture() => {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: field_def.span.into(),
+                    message: String::from("struct fields can't be textures"),
+                })
             }
             _ => (),
         }
@@ -92,14 +111,9 @@ impl<'a> StructAnalyser<'a> {
 
     fn analyse_method_decl(&mut self, decl: &FnDef) -> Result<(), LiveError> {
         for param in &decl.params {
-            self.ty_checker().ty_check_ty_expr(&param.ty_expr)?;
+            self.ty_checker().ty_check_param(param)?;
         }
-        let return_ty = decl
-            .return_ty_expr
-            .as_ref()
-            .map(|return_ty_expr| self.ty_checker().ty_check_ty_expr(return_ty_expr))
-            .transpose()?
-            .unwrap_or(Ty::Void);
+        let return_ty = self.ty_checker().ty_check_return_ty_expr(&decl.return_ty_expr)?;
         *decl.return_ty.borrow_mut() = Some(return_ty);
         *decl.hidden_args.borrow_mut() = Some(BTreeSet::new());
         Ok(())
@@ -125,34 +139,54 @@ impl<'a> DrawShaderAnalyser<'a> {
     pub fn analyse_shader(&mut self) -> Result<(), LiveError> {
         self.scopes.push_scope();
 
-        //let mut var_inputs = DrawShaderVarInputs::default();
+        // fields and decls don't depend on each other, report all of them
+        // but don't look at the methods if any of them failed
+        let error_count = self.shader_registry.error_count();
         for field in &self.shader_registry.draw_shader_def.fields {
-            self.analyse_field_decl(field)?;
+            if let Err(err) = self.analyse_field_decl(field) {
+                self.shader_registry.push_error(err);
+            }
         }
 
         // first analyse decls
         for fn_node_ptr in &self.shader_registry.draw_shader_def.methods {
             let fn_def = self.shader_registry.all_fns.get(fn_node_ptr).unwrap();
-            self.analyse_method_def(fn_def)?;
+            if let Err(err) = self.analyse_method_def(fn_def) {
+                self.shader_registry.push_error(err);
+            }
+        }
+        if self.shader_registry.error_count() != error_count {
+            self.scopes.pop_scope();
+            return Ok(());
         }
 
         // now analyse the methods
         for fn_node_ptr in &self.shader_registry.draw_shader_def.methods {
             let fn_def = self.shader_registry.all_fns.get(fn_node_ptr).unwrap();
-            FnDefAnalyser {
+            let result = FnDefAnalyser {
                 file: self.file,
                 shader_registry: self.shader_registry,
                 closure_return_ty: None,
                 fn_def,
                 scopes: &mut self.scopes,
                 options: self.options,
-                is_inside_loop: false,
+                loop_labels: Vec::new(),
+                unused_locals: Vec::new(),
+                failed_lets: Vec::new(),
+            }
+            .analyse_fn_def();
+            if let Err(err) = result {
+                self.shader_registry.push_error(err);
             }
-            .analyse_fn_def()?;
         }
 
         self.scopes.pop_scope();
 
+        // the call trees and hidden args need every method to be analysed
+        if self.shader_registry.error_count() > 0 {
+            return Ok(());
+        }
+
         let mut all_fns = Vec::new();
         let mut vertex_fns = Vec::new();
         // we should insert our vertex call
@@ -182,6 +216,36 @@ impl<'a> DrawShaderAnalyser<'a> {
             &mut all_fns,
         )?;
 
+        // a fn called from both stages can't use the builtins of either
+        for (stage_fns, is_pixel) in [(&vertex_fns, false), (&pixel_fns, true)].iter() {
+            for stage_fn in stage_fns.iter() {
+                let fn_def = self.shader_registry.all_fns.get(stage_fn).unwrap();
+                for (stage_builtin, span) in fn_def.stage_builtins.borrow().as_ref().unwrap() {
+                    if stage_builtin.is_pixel() != *is_pixel {
+                        return Err(LiveError {
+                            origin: live_error_origin!(),
+                            span: (*span).into(),
+                            message: format!(
+                                "`self.{}` can only be used in the {} shader",
+                                stage_builtin.name(),
+                                if stage_builtin.is_pixel() {"pixel"} else {"vertex"}
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        for vertex_fn in &vertex_fns {
+            let fn_def = self.shader_registry.all_fns.get(vertex_fn).unwrap();
+            if let Some(span) = fn_def.discard_span.get() {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: span.into(),
+                    message: String::from("`discard` can only be used in the pixel shader"),
+                });
+            }
+        }
+
         // mark all the draw_shader_refs we reference in pixelshaders.
         for pixel_fn in &pixel_fns {
             // if we run into a DrawShaderMethod mark it as
@@ -215,10 +279,30 @@ impl<'a> DrawShaderAnalyser<'a> {
             }
         }
 
+        for field in &self.shader_registry.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Varying { .. } = field.kind {
+                let is_read = pixel_fns.iter().any(|pixel_fn| {
+                    let fn_def = self.shader_registry.all_fns.get(pixel_fn).unwrap();
+                    fn_def
+                        .draw_shader_refs
+                        .borrow()
+                        .as_ref()
+                        .map_or(false, |refs| refs.contains(&field.ident))
+                });
+                if !is_read {
+                    self.shader_registry.push_warning(
+                        field.span,
+                        format!("varying `{}` is never read by the pixel shader", field.ident),
+                    );
+                }
+            }
+        }
+
         let mut all_structs = Vec::new();
         let mut pixel_structs = Vec::new();
         let mut vertex_structs = Vec::new();
         let mut all_live_refs = BTreeMap::new();
+        let mut all_const_refs = BTreeMap::new();
 
         for pixel_fn in &pixel_fns {
             let fn_decl = self.shader_registry.all_fns.get(pixel_fn).unwrap();
@@ -252,6 +336,7 @@ impl<'a> DrawShaderAnalyser<'a> {
         for any_fn in all_fns.iter().rev() {
             let fn_def = self.shader_registry.all_fns.get(any_fn).unwrap();
             all_live_refs.extend(fn_def.live_refs.borrow().as_ref().cloned().unwrap());
+            all_const_refs.extend(fn_def.const_refs.borrow().as_ref().cloned().unwrap());
             // fill in fns where hidden args is none
             // if fn_def.hidden_args.borrow().is_none() {
             self.analyse_hidden_args(fn_def);
@@ -263,6 +348,11 @@ impl<'a> DrawShaderAnalyser<'a> {
             .draw_shader_def
             .all_live_refs
             .borrow_mut() = all_live_refs;
+        *self
+            .shader_registry
+            .draw_shader_def
+            .all_const_refs
+            .borrow_mut() = all_const_refs;
 
         *self.shader_registry.draw_shader_def.all_fns.borrow_mut() = all_fns;
         *self.shader_registry.draw_shader_def.vertex_fns.borrow_mut() = vertex_fns;
@@ -332,6 +422,9 @@ impl<'a> DrawShaderAnalyser<'a> {
         if fn_def.live_refs.borrow().as_ref().unwrap().len() > 0 {
             hidden_args.insert(HiddenArgKind::LiveUniforms);
         }
+        if !fn_def.stage_builtins.borrow().as_ref().unwrap().is_empty() {
+            hidden_args.insert(HiddenArgKind::Builtins);
+        }
         // merge in the others
         for callee in fn_def.callees.borrow().as_ref().unwrap().iter() {
             let other_fn_def = self.shader_registry.all_fns.get(callee).unwrap();
@@ -388,20 +481,26 @@ impl<'a> DrawShaderAnalyser<'a> {
             }
             DrawShaderFieldKind::Texture { .. } => {
                 let ty = self.ty_checker().ty_check_ty_expr(&decl.ty_expr)?;
-                match ty {
-                    Ty::Texture2D => {}
-                    _ => {
-                        return Err(LiveError {
-                            origin: live_error_origin!(),
-                            span: decl.span.into(),
-                            message: String::from("texture must be a texture2D"),
-                        })
-                    }
+                if !ty.is_texture() {
+                    return Err(LiveError {
+                        origin: live_error_origin!(),
+                        span: decl.span.into(),
+                        message: String::from("texture must be a texture2d, textureCube, texture3d, texture2d_array or texture2d_depth"),
+                    })
                 }
                 ty
             }
             DrawShaderFieldKind::Uniform { .. } => {
                 let ty = self.ty_checker().ty_check_ty_expr(&decl.ty_expr)?;
+                if let Ty::Array { elem_ty, .. } = &ty {
+                    if let Ty::Struct(_) = **elem_ty {
+                        return Err(LiveError {
+                            origin: live_error_origin!(),
+                            span: decl.span.into(),
+                            message: String::from("uniform arrays can't hold structs"),
+                        });
+                    }
+                }
                 ty
             }
             DrawShaderFieldKind::Varying { .. } => {
@@ -427,14 +526,9 @@ impl<'a> DrawShaderAnalyser<'a> {
 
     fn analyse_method_def(&mut self, def: &FnDef) -> Result<(), LiveError> {
         for param in &def.params {
-            self.ty_checker().ty_check_ty_expr(&param.ty_expr)?;
+            self.ty_checker().ty_check_param(param)?;
         }
-        let return_ty = def
-            .return_ty_expr
-            .as_ref()
-            .map(|return_ty_expr| self.ty_checker().ty_check_ty_expr(return_ty_expr))
-            .transpose()?
-            .unwrap_or(Ty::Void);
+        let return_ty = self.ty_checker().ty_check_return_ty_expr(&def.return_ty_expr)?;
 
         if def.ident == Ident(id!(vertex)) {
             match return_ty {
@@ -450,14 +544,31 @@ impl<'a> DrawShaderAnalyser<'a> {
                 }
             }
         } else if def.ident == Ident(id!(pixel)) {
-            match return_ty {
+            match &return_ty {
                 Ty::Vec4 => {}
+                // one render target per field
+                Ty::Struct(struct_ptr) => {
+                    let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+                    let is_target = |field: &StructFieldDef| {
+                        matches!(field.ty_expr.ty.borrow().as_ref(), Some(Ty::Float | Ty::Vec2 | Ty::Vec3 | Ty::Vec4))
+                    };
+                    if struct_def.fields.is_empty() || struct_def.fields.len() > MAX_PIXEL_OUTPUTS || !struct_def.fields.iter().all(is_target) {
+                        return Err(LiveError {
+                            origin: live_error_origin!(),
+                            span: def.span.into(),
+                            message: format!(
+                                "function `pixel` can only return a struct of 1 to {} `float`, `vec2`, `vec3` or `vec4` fields",
+                                MAX_PIXEL_OUTPUTS
+                            ),
+                        });
+                    }
+                }
                 _ => {
                     return Err(LiveError {
                         origin: live_error_origin!(),
                         span: def.span.into(),
                         message: String::from(
-                            "function `fragment` must return a value of type `vec4`",
+                            "function `pixel` must return a value of type `vec4` or a struct",
                         ),
                     })
                 }
@@ -612,7 +723,12 @@ pub struct FnDefAnalyser<'a> {
     pub file: &'a LiveFile,
     pub shader_registry: &'a Shader,
     pub options: ShaderAnalyseOptions,
-    pub is_inside_loop: bool,
+    // the labels of the loops around the statement being analysed, innermost last
+    pub loop_labels: Vec<Option<Ident>>,
+    // lets that were never read, closures may still read them
+    pub unused_locals: Vec<(TokenSpan, Sym)>,
+    // variables whose `let` failed, their uses are not reported again
+    pub failed_lets: Vec<Ident>,
 }
 
 impl<'a> FnDefAnalyser<'a> {
@@ -646,15 +762,9 @@ impl<'a> FnDefAnalyser<'a> {
 
     pub fn analyse_fn_decl(&mut self) -> Result<(), LiveError> {
         for param in &self.fn_def.params {
-            self.ty_checker().ty_check_ty_expr(&param.ty_expr)?;
+            self.ty_checker().ty_check_param(param)?;
         }
-        let return_ty = self
-            .fn_def
-            .return_ty_expr
-            .as_ref()
-            .map(|return_ty_expr| self.ty_checker().ty_check_ty_expr(return_ty_expr))
-            .transpose()?
-            .unwrap_or(Ty::Void);
+        let return_ty = self.ty_checker().ty_check_return_ty_expr(&self.fn_def.return_ty_expr)?;
         *self.fn_def.return_ty.borrow_mut() = Some(return_ty);
         Ok(())
     }
@@ -696,13 +806,22 @@ impl<'a> FnDefAnalyser<'a> {
                 .unwrap_or(Ty::Void),
         );
         self.fn_def.init_analysis();
+        let error_count = self.shader_registry.error_count();
+        // the body gets its own scope so its lets aren't mixed up with the params
+        self.scopes.push_scope();
         self.analyse_block(&self.fn_def.block)?;
+        self.pop_block_scope();
         self.scopes.pop_scope();
+        // statements failed, so the closures and returns can't be trusted
+        if self.shader_registry.error_count() != error_count {
+            return Ok(());
+        }
         // alright we have closures to analyse
         // let closure_isntances = self.
         // lets move the closures from env to
         // then analyse it
         self.analyse_closures()?;
+        self.report_unused_locals();
 
         // lets build up our fn_args_hidden and combine it
         // with our callees
@@ -841,25 +960,100 @@ impl<'a> FnDefAnalyser<'a> {
         Ok(())
     }
 
+    // pops a scope pushed for a block, remembering the lets that were never read
+    fn pop_block_scope(&mut self) {
+        let scope = self.scopes.scopes.pop().unwrap();
+        for scope_sym in scope.values() {
+            if let ScopeSymKind::MutLocal = scope_sym.kind {
+                if !scope_sym.referenced.get() {
+                    self.unused_locals.push((scope_sym.span, scope_sym.sym.clone()));
+                }
+            }
+        }
+    }
+
+    fn report_unused_locals(&mut self) {
+        let closure_sites = self.fn_def.closure_sites.borrow();
+        let mut unused_locals: Vec<_> = self
+            .unused_locals
+            .drain(..)
+            .filter(|(_, sym)| {
+                !closure_sites
+                    .iter()
+                    .flatten()
+                    .any(|closure_site| closure_site.all_closed_over.contains(sym))
+            })
+            .collect();
+        unused_locals.sort_by_key(|(span, _)| span.token_id);
+        for (span, sym) in unused_locals {
+            self.shader_registry
+                .push_warning(span, format!("unused variable `{}`", sym.ident));
+        }
+    }
+
+    // statements that fail are reported and skipped, the rest of the block is
+    // still analysed
     fn analyse_block(&mut self, block: &Block) -> Result<(), LiveError> {
+        let failed_lets_len = self.failed_lets.len();
+        let mut after_return = false;
         for stmt in &block.stmts {
-            self.analyse_stmt(stmt)?;
+            if after_return {
+                self.shader_registry
+                    .push_warning(stmt.span(), String::from("unreachable statement"));
+                after_return = false;
+            }
+            if let Err(err) = self.analyse_stmt(stmt) {
+                // the variable of a failed `let` is missing, that was reported already
+                let follow_on = self
+                    .failed_lets
+                    .iter()
+                    .any(|ident| err.message == undefined_var_message(*ident));
+                if !follow_on {
+                    self.shader_registry.push_error(err);
+                }
+                if let Stmt::Let { ident, .. } = stmt {
+                    self.failed_lets.push(*ident);
+                }
+            }
+            if let Stmt::Return { .. } = stmt {
+                after_return = true;
+            }
         }
+        self.failed_lets.truncate(failed_lets_len);
         Ok(())
     }
 
     fn analyse_stmt(&mut self, stmt: &Stmt) -> Result<(), LiveError> {
         match *stmt {
-            Stmt::Break { span } => self.analyse_break_stmt(span),
-            Stmt::Continue { span } => self.analyse_continue_stmt(span),
+            Stmt::Break { span, label } => self.analyse_jump_stmt(span, "break", label),
+            Stmt::Continue { span, label } => self.analyse_jump_stmt(span, "continue", label),
+            Stmt::Discard { span } => {
+                if self.fn_def.discard_span.get().is_none() {
+                    self.fn_def.discard_span.set(Some(span));
+                }
+                Ok(())
+            }
             Stmt::For {
                 span,
+                label,
                 ident,
+                ref shadow,
                 ref from_expr,
                 ref to_expr,
                 ref step_expr,
                 ref block,
-            } => self.analyse_for_stmt(span, ident, from_expr, to_expr, step_expr, block),
+            } => self.analyse_for_stmt(span, label, ident, shadow, from_expr, to_expr, step_expr, block),
+            Stmt::While {
+                span,
+                label,
+                ref expr,
+                ref block,
+            } => self.analyse_while_stmt(span, label, expr, block),
+            Stmt::Loop {
+                label,
+                ref block,
+                ..
+            } => self.analyse_loop_block(label, block),
             Stmt::If {
                 span,
                 ref expr,
@@ -885,26 +1079,24 @@ impl<'a> FnDefAnalyser<'a> {
         }
     }
 
-    fn analyse_break_stmt(&self, span: TokenSpan) -> Result<(), LiveError> {
-        if !self.is_inside_loop {
+    fn analyse_jump_stmt(&self, span: TokenSpan, what: &str, label: Option<Ident>) -> Result<(), LiveError> {
+        if self.loop_labels.is_empty() {
             return Err(LiveError {
                 origin: live_error_origin!(),
                 span: span.into(),
-                message: String::from("break outside loop"),
+                message: format!("{} outside loop", what),
             }
             .into());
         }
-        Ok(())
-    }
-
-    fn analyse_continue_stmt(&self, span: TokenSpan) -> Result<(), LiveError> {
-        if !self.is_inside_loop {
-            return Err(LiveError {
-                origin: live_error_origin!(),
-                span: span.into(),
-                message: String::from("continue outside loop"),
+        if let Some(label) = label {
+            if !self.loop_labels.contains(&Some(label)) {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: span.into(),
+                    message: format!("use of undeclared label `'{}`", label),
+                }
+                .into());
             }
-            .into());
         }
         Ok(())
     }
@@ -912,7 +1104,9 @@ impl<'a> FnDefAnalyser<'a> {
     fn analyse_for_stmt(
         &mut self,
         span: TokenSpan,
+        label: Option<Ident>,
         ident: Ident,
+        shadow: &Cell<Option<ScopeSymShadow >>,
         from_expr: &Expr,
         to_expr: &Expr,
         step_expr: &Option<Expr>,
@@ -969,13 +1163,38 @@ impl<'a> FnDefAnalyser<'a> {
             self.dep_analyser().dep_analyse_expr(step_expr);
         }
         self.scopes.push_scope();
-        self.scopes
-            .insert_sym(span, ident, Ty::Int, ScopeSymKind::Local);
-        let was_inside_loop = self.is_inside_loop;
-        self.is_inside_loop = true;
-        self.analyse_block(block)?;
-        self.is_inside_loop = was_inside_loop;
-        self.scopes.pop_scope();
+        shadow.set(Some(self.scopes
+            .insert_sym(span, ident, Ty::Int, ScopeSymKind::Local)));
+        self.loop_labels.push(label);
+        let result = self.analyse_block(block);
+        self.loop_labels.pop();
+        result?;
+        self.pop_block_scope();
+        Ok(())
+    }
+
+    fn analyse_while_stmt(
+        &mut self,
+        span: TokenSpan,
+        label: Option<Ident>,
+        expr: &Expr,
+        block: &Block,
+    ) -> Result<(), LiveError> {
+        self.ty_checker()
+            .ty_check_expr_with_expected_ty(span, expr, &Ty::Bool)?;
+        self.const_evaluator().try_const_eval_expr(expr);
+        self.const_gatherer().const_gather_expr(expr);
+        self.dep_analyser().dep_analyse_expr(expr);
+        self.analyse_loop_block(label, block)
+    }
+
+    fn analyse_loop_block(&mut self, label: Option<Ident>, block: &Block) -> Result<(), LiveError> {
+        self.scopes.push_scope();
+        self.loop_labels.push(label);
+        let result = self.analyse_block(block);
+        self.loop_labels.pop();
+        result?;
+        self.pop_block_scope();
         Ok(())
     }
 
@@ -993,11 +1212,11 @@ impl<'a> FnDefAnalyser<'a> {
         self.dep_analyser().dep_analyse_expr(expr);
         self.scopes.push_scope();
         self.analyse_block(block_if_true)?;
-        self.scopes.pop_scope();
+        self.pop_block_scope();
         if let Some(block_if_false) = block_if_false {
             self.scopes.push_scope();
             self.analyse_block(block_if_false)?;
-            self.scopes.pop_scope();
+            self.pop_block_scope();
         }
         Ok(())
     }
@@ -1010,19 +1229,19 @@ impl<'a> FnDefAnalyser<'a> {
     ) -> Result<(), LiveError> {
         let ty = self.ty_checker().ty_check_expr(expr)?;
         // ok so the ty MUST be an Enum
-        if let Ty::Enum(live_type) = ty {
+        if let Ty::Enum(enum_name) = ty {
             self.const_evaluator().try_const_eval_expr(expr);
             self.const_gatherer().const_gather_expr(expr);
             self.dep_analyser().dep_analyse_expr(expr);
 
             for match_item in matches {
                 // lets fetch our Enum + Variant and see if its the same live_type
-                let shader_enum = self.shader_registry.enums.get(&live_type).unwrap();
+                let shader_enum = self.shader_registry.enums.get(&enum_name).unwrap();
                 // ok so.. our match_item
                 if match_item.enum_name.0 != shader_enum.enum_name {
                     return Err(LiveError {
                         origin: live_error_origin!(),
-                        span: span.into(),
+                        span: match_item.span.into(),
                         message: format!(
                             "Enum name mismatched, expected {} got {}",
                             shader_enum.enum_name, match_item.enum_name.0
@@ -1040,7 +1259,7 @@ impl<'a> FnDefAnalyser<'a> {
                 } else {
                     return Err(LiveError {
                         origin: live_error_origin!(),
-                        span: span.into(),
+                        span: match_item.span.into(),
                         message: format!(
                             "Variant not found on enum {}::{}",
                             match_item.enum_name.0, match_item.enum_variant.0
@@ -1051,7 +1270,7 @@ impl<'a> FnDefAnalyser<'a> {
                 // lets see if we have the right name
                 self.scopes.push_scope();
                 self.analyse_block(&match_item.block)?;
-                self.scopes.pop_scope();
+                self.pop_block_scope();
             }
             Ok(())
         } else {
@@ -1083,16 +1302,25 @@ impl<'a> FnDefAnalyser<'a> {
             }
             let expected_ty = self.ty_checker().ty_check_ty_expr(ty_expr)?;
             if let Some(expr) = expr {
-                let actual_ty =
-                    self.ty_checker()
-                        .ty_check_expr_with_expected_ty(span, expr, &expected_ty)?;
+                let actual_ty = self.ty_checker().ty_check_let_init_expr(expr)?;
+                if actual_ty != expected_ty {
+                    return Err(LiveError {
+                        origin: live_error_origin!(),
+                        span: span.into(),
+                        message: format!(
+                            "can't match expected type `{}` with actual type `{}`",
+                            expected_ty,
+                            actual_ty
+                        ),
+                    });
+                }
                 self.dep_analyser().dep_analyse_expr(expr);
                 actual_ty
             } else {
                 expected_ty
             }
         } else if let Some(expr) = expr {
-            let ty = self.ty_checker().ty_check_expr(expr)?;
+            let ty = self.ty_checker().ty_check_let_init_expr(expr)?;
             if ty == Ty::Void {
                 return Err(LiveError {
                     origin: live_error_origin!(),
@@ -1111,6 +1339,13 @@ impl<'a> FnDefAnalyser<'a> {
                 message: format!("can't infer type of variable `{}`", ident),
             });
         });
+        if ty.borrow().as_ref().unwrap().is_texture() {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: format!("`{}` can't hold a texture, use the texture directly", ident),
+            });
+        }
         let new_shadow = self.scopes.insert_sym(
             span,
             ident,
@@ -1159,7 +1394,7 @@ impl<'a> FnDefAnalyser<'a> {
     fn analyse_block_stmt(&mut self, _span: TokenSpan, block: &Block) -> Result<(), LiveError> {
         self.scopes.push_scope();
         self.analyse_block(block)?;
-        self.scopes.pop_scope();
+        self.pop_block_scope();
         Ok(())
     }
 
@@ -342,9 +342,57 @@ pub fn generate_builtins()-> HashMap<Ident, Builtin>{
         builtin!(sample2d, [
             (Ty::Texture2D, Ty::Vec2) -> Ty::Vec4
         ]),
+        builtin!(sample2d_array, [
+            (Ty::Texture2DArray, Ty::Vec2, Ty::Int) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_array_grad, [
+            (Ty::Texture2DArray, Ty::Vec2, Ty::Int, Ty::Vec2, Ty::Vec2) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_array_lod, [
+            (Ty::Texture2DArray, Ty::Vec2, Ty::Int, Ty::Float) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_array_offset, [
+            (Ty::Texture2DArray, Ty::Vec2, Ty::Int, Ty::Ivec2) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_compare, [
+            (Ty::Texture2DDepth, Ty::Vec2, Ty::Float) -> Ty::Float
+        ]),
+        builtin!(sample2d_compare_offset, [
+            (Ty::Texture2DDepth, Ty::Vec2, Ty::Float, Ty::Ivec2) -> Ty::Float
+        ]),
+        builtin!(sample2d_grad, [
+            (Ty::Texture2D, Ty::Vec2, Ty::Vec2, Ty::Vec2) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_lod, [
+            (Ty::Texture2D, Ty::Vec2, Ty::Float) -> Ty::Vec4
+        ]),
+        builtin!(sample2d_offset, [
+            (Ty::Texture2D, Ty::Vec2, Ty::Ivec2) -> Ty::Vec4
+        ]),
         builtin!(sample2d_rt, [
             (Ty::Texture2D, Ty::Vec2) -> Ty::Vec4
         ]),
+        builtin!(sample3d, [
+            (Ty::Texture3D, Ty::Vec3) -> Ty::Vec4
+        ]),
+        builtin!(sample3d_grad, [
+            (Ty::Texture3D, Ty::Vec3, Ty::Vec3, Ty::Vec3) -> Ty::Vec4
+        ]),
+        builtin!(sample3d_lod, [
+            (Ty::Texture3D, Ty::Vec3, Ty::Float) -> Ty::Vec4
+        ]),
+        builtin!(sample3d_offset, [
+            (Ty::Texture3D, Ty::Vec3, Ty::Ivec3) -> Ty::Vec4
+        ]),
+        builtin!(sample_cube, [
+            (Ty::TextureCube, Ty::Vec3) -> Ty::Vec4
+        ]),
+        builtin!(sample_cube_grad, [
+            (Ty::TextureCube, Ty::Vec3, Ty::Vec3, Ty::Vec3) -> Ty::Vec4
+        ]),
+        builtin!(sample_cube_lod, [
+            (Ty::TextureCube, Ty::Vec3, Ty::Float) -> Ty::Vec4
+        ]),
         builtin!(sign, [
             (Ty::Float) -> Ty::Float,
             (Ty::Vec2) -> Ty::Vec2,
@@ -387,6 +435,18 @@ pub fn generate_builtins()-> HashMap<Ident, Builtin>{
             (Ty::Vec3) -> Ty::Vec3,
             (Ty::Vec4) -> Ty::Vec4
         ]),
+        builtin!(texel_fetch, [
+            (Ty::Texture2D, Ty::Ivec2, Ty::Int) -> Ty::Vec4,
+            (Ty::Texture3D, Ty::Ivec3, Ty::Int) -> Ty::Vec4,
+            (Ty::Texture2DArray, Ty::Ivec3, Ty::Int) -> Ty::Vec4
+        ]),
+        builtin!(texture_size, [
+            (Ty::Texture2D, Ty::Int) -> Ty::Ivec2,
+            (Ty::TextureCube, Ty::Int) -> Ty::Ivec2,
+            (Ty::Texture2DDepth, Ty::Int) -> Ty::Ivec2,
+            (Ty::Texture3D, Ty::Int) -> Ty::Ivec3,
+            (Ty::Texture2DArray, Ty::Int) -> Ty::Ivec3
+        ]),
         builtin!(transpose, [
             (Ty::Mat4) -> Ty::Mat4,
             (Ty::Mat3) -> Ty::Mat3
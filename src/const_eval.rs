@@ -81,6 +81,10 @@ impl ConstEvaluator {
                 span,
                 ref args
             } => self.try_const_eval_struct_cons(struct_ptr, span, args),
+            ExprKind::ArrayCons {
+                ref elem_exprs,
+                ..
+            } => self.try_const_eval_all_call_expr(elem_exprs),
             ExprKind::Lit { span, lit } => self.try_const_eval_lit_expr(span, lit),
         };
         *expr.const_val.borrow_mut() = Some(const_val.clone());
@@ -95,9 +99,11 @@ impl ConstEvaluator {
         expr_if_true: &Expr,
         expr_if_false: &Expr,
     ) -> Option<Val> {
-        let val = self.try_const_eval_expr(expr)?;
-        let val_if_true = self.try_const_eval_expr(expr_if_true)?;
-        let val_if_false = self.try_const_eval_expr(expr_if_false)?;
+        // all three are evaluated, so each of them has its const val set
+        let val = self.try_const_eval_expr(expr);
+        let val_if_true = self.try_const_eval_expr(expr_if_true);
+        let val_if_false = self.try_const_eval_expr(expr_if_false);
+        let (val, val_if_true, val_if_false) = (val?, val_if_true?, val_if_false?);
         Some(if val.to_bool().unwrap() {
             val_if_true
         } else {
@@ -132,55 +138,101 @@ impl ConstEvaluator {
             BinOp::Eq => match (&left_val, &right_val) {
                 (Val::Bool(x), Val::Bool(y)) => Some(Val::Bool(x == y)),
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x == y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x == y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x == y)),
                 _ => None,
             },
             BinOp::Ne => match (&left_val, &right_val) {
                 (Val::Bool(x), Val::Bool(y)) => Some(Val::Bool(x != y)),
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x != y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x != y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x != y)),
                 _ => None,
             },
             BinOp::Lt => match (&left_val, &right_val) {
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x < y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x < y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x < y)),
                 _ => None,
             },
             BinOp::Le => match (&left_val, &right_val) {
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x <= y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x <= y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x <= y)),
                 _ => None,
             },
             BinOp::Gt => match (&left_val, &right_val) {
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x > y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x > y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x > y)),
                 _ => None,
             },
             BinOp::Ge => match (&left_val, &right_val) {
                 (Val::Int(x), Val::Int(y)) => Some(Val::Bool(x >= y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Bool(x >= y)),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Bool(x >= y)),
                 _ => None,
             },
             BinOp::Add => match (&left_val, &right_val) {
-                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x + y)),
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x.wrapping_add(*y))),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x.wrapping_add(*y))),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Float(x + y)),
                 _ => None,
             },
             BinOp::Sub => match (&left_val, &right_val) {
-                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x - y)),
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x.wrapping_sub(*y))),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x.wrapping_sub(*y))),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Float(x - y)),
                 _ => None,
             },
             BinOp::Mul => match (&left_val, &right_val) {
-                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x * y)),
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x.wrapping_mul(*y))),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x.wrapping_mul(*y))),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Float(x * y)),
                 _ => None,
             },
             BinOp::Div => match (&left_val, &right_val) {
-                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x / y)),
+                // a division by zero is left to the GPU
+                (Val::Int(x), Val::Int(y)) => x.checked_div(*y).map(Val::Int),
+                (Val::Uint(x), Val::Uint(y)) => x.checked_div(*y).map(Val::Uint),
                 (Val::Float(x), Val::Float(y)) => Some(Val::Float(x / y)),
                 _ => None,
             },
+            BinOp::Rem => match (&left_val, &right_val) {
+                (Val::Int(x), Val::Int(y)) => x.checked_rem(*y).map(Val::Int),
+                (Val::Uint(x), Val::Uint(y)) => x.checked_rem(*y).map(Val::Uint),
+                _ => None,
+            },
+            BinOp::BitAnd => match (&left_val, &right_val) {
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x & y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x & y)),
+                _ => None,
+            },
+            BinOp::BitOr => match (&left_val, &right_val) {
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x | y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x | y)),
+                _ => None,
+            },
+            BinOp::BitXor => match (&left_val, &right_val) {
+                (Val::Int(x), Val::Int(y)) => Some(Val::Int(x ^ y)),
+                (Val::Uint(x), Val::Uint(y)) => Some(Val::Uint(x ^ y)),
+                _ => None,
+            },
+            // shifting by the bit width or more is undefined on a GPU, so it isn't folded
+            BinOp::Shl | BinOp::Shr => {
+                let amount = match right_val {
+                    Val::Int(y) if (0..32).contains(&y) => y as u32,
+                    Val::Uint(y) if y < 32 => y,
+                    _ => return None
+                };
+                match (left_val, op) {
+                    (Val::Int(x), BinOp::Shl) => Some(Val::Int(x << amount)),
+                    (Val::Int(x), _) => Some(Val::Int(x >> amount)),
+                    (Val::Uint(x), BinOp::Shl) => Some(Val::Uint(x << amount)),
+                    (Val::Uint(x), _) => Some(Val::Uint(x >> amount)),
+                    _ => None,
+                }
+            }
             _ => None,
         }
     }
@@ -194,10 +246,12 @@ impl ConstEvaluator {
         match op {
             UnOp::Not => match val {
                 Val::Bool(x) => Some(Val::Bool(!x)),
+                Val::Int(x) => Some(Val::Int(!x)),
+                Val::Uint(x) => Some(Val::Uint(!x)),
                 _ => None,
             },
             UnOp::Neg => match val {
-                Val::Int(x) => Some(Val::Int(-x)),
+                Val::Int(x) => Some(Val::Int(x.wrapping_neg())),
                 Val::Float(x) => Some(Val::Float(-x)),
                 _ => None,
             },
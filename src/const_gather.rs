@@ -96,6 +96,10 @@ impl<'a> ConstGatherer<'a> {
                 span,
                 ref args
             } => self.const_gather_struct_cons(struct_ptr, span, args),
+            ExprKind::ArrayCons {
+                ref elem_exprs,
+                ..
+            } => self.const_gather_all_call_expr(elem_exprs),
             ExprKind::Lit { span, lit } => self.const_gather_lit_expr(span, lit),
         }
     }
@@ -75,6 +75,14 @@ impl<'a> DepAnalyser<'a> {
                 span,
                 ref args
             } => self.dep_analyse_struct_cons(struct_ptr, span, args),
+            ExprKind::ArrayCons {
+                ref elem_exprs,
+                ..
+            } => {
+                for elem_expr in elem_exprs {
+                    self.dep_analyse_expr(elem_expr);
+                }
+            }
             ExprKind::Var {
                 span,
                 ref kind,
@@ -131,13 +139,22 @@ impl<'a> DepAnalyser<'a> {
                 }
                 //panic!("IMPL")
             }
+            Ty::DrawShader => {
+                for arg_expr in arg_exprs {
+                    self.dep_analyse_expr(arg_expr);
+                }
+                let mut set = self.fn_def.callees.borrow_mut();
+                if let Some(fn_node_ptr) = self.shader_registry.draw_shader_method_ptr_from_ident(&self.shader_registry.draw_shader_def, method_ident){
+                    set.as_mut().unwrap().insert(fn_node_ptr);
+                }
+            }
             _ => panic!(),
         }
     }
     
     fn dep_analyse_builtin_call_expr(
         &mut self,
-        _span: TokenSpan,
+        span: TokenSpan,
         ident: Ident,
         arg_exprs: &[Expr],
     ) {
@@ -149,7 +166,8 @@ impl<'a> DepAnalyser<'a> {
             .borrow_mut()
             .as_mut()
             .unwrap()
-            .insert(ident);
+            .entry(ident)
+            .or_insert(span);
     }
     
     
@@ -169,9 +187,16 @@ impl<'a> DepAnalyser<'a> {
         set.as_mut().unwrap().insert(fn_ptr);
     }
     
-    fn dep_analyse_field_expr(&mut self, _span: TokenSpan, expr: &Expr, field_ident: Ident) {
+    fn dep_analyse_field_expr(&mut self, span: TokenSpan, expr: &Expr, field_ident: Ident) {
         // so we have to store which 'shader props' we use
         match expr.ty.borrow().as_ref().unwrap(){
+            Ty::DrawShader=>{
+                if let Some(stage_builtin) = self.shader_registry.draw_shader_def.find_stage_builtin(field_ident) {
+                    self.fn_def.stage_builtins.borrow_mut().as_mut().unwrap().entry(stage_builtin).or_insert(span);
+                    return
+                }
+                self.fn_def.draw_shader_refs.borrow_mut().as_mut().unwrap().insert(field_ident);
+            }
             _=>{
                   self.dep_analyse_expr(expr)
             }
@@ -221,13 +246,18 @@ impl<'a> DepAnalyser<'a> {
             VarKind::LiveValue(value_ptr)=>{
                 self.fn_def.live_refs.borrow_mut().as_mut().unwrap().insert(value_ptr, ty.unwrap().clone());
             }
+            VarKind::Const(const_ptr)=>{
+                self.fn_def.const_refs.borrow_mut().as_mut().unwrap().insert(const_ptr, ty.unwrap().clone());
+            }
             VarKind::Local{..} | VarKind::MutLocal{..}=>{ // we need to store the type
                 match ty{
                     Some(Ty::Struct(struct_ptr))=>{
                         self.fn_def.struct_refs.borrow_mut().as_mut().unwrap().insert(*struct_ptr);
                     }
-                    Some(Ty::Array{..})=>{
-                        todo!();
+                    Some(Ty::Array{elem_ty, ..})=>{
+                        if let Ty::Struct(struct_ptr) = **elem_ty {
+                            self.fn_def.struct_refs.borrow_mut().as_mut().unwrap().insert(struct_ptr);
+                        }
                     }
                     _=>()
                 }
@@ -0,0 +1,32 @@
+use {
+    crate::makepad_live_compiler::LiveFileError,
+    std::fmt,
+};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ShaderSeverity {
+    Warning,
+    Error,
+}
+
+/// A single error or warning reported by `Shader::compile`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderDiagnostic {
+    pub severity: ShaderSeverity,
+    pub error: LiveFileError,
+}
+
+impl ShaderDiagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == ShaderSeverity::Error
+    }
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            ShaderSeverity::Warning => self.error.fmt_with_label(f, "warning"),
+            ShaderSeverity::Error => self.error.fmt_with_label(f, "error"),
+        }
+    }
+}
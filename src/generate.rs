@@ -4,6 +4,7 @@ use{
         fmt,
         fmt::Write,
         collections::BTreeSet,
+        rc::Rc,
     },
     crate::{
         makepad_live_id::{LiveId, id},
@@ -12,7 +13,8 @@ use{
             TokenSpan
         },
         shader_ast::*,
-        shader::Shader
+        shader::Shader,
+        source_map::{write_start_mark, write_end_mark},
     }
 };
 
@@ -46,7 +48,26 @@ pub trait BackendWriter {
     fn needs_cstyle_struct_cons(&self)->bool;
     fn needs_mul_fn_for_matrix_multiplication(&self) -> bool;
     fn needs_unpack_for_matrix_multiplication(&self) -> bool;
+    // textures are passed along with a separate sampler, `<texture>_sampler`
+    fn needs_texture_samplers(&self) -> bool;
     fn const_table_is_vec4(&self) -> bool;
+    // `select(f, t, c)` instead of `c ? t : f`
+    fn needs_select_for_cond_expr(&self) -> bool;
+    // scalar arguments of builtins are splatted to the vector type of the other arguments
+    fn needs_splat_for_builtin_args(&self) -> bool;
+    // inout parameters are pointers, the call site passes `&x`
+    fn needs_pointer_for_inout(&self) -> bool;
+    // shift amounts are unsigned, a signed one is converted
+    fn needs_unsigned_shift_amount(&self) -> bool;
+    // uniforms live in a struct or buffer with the layout of their own language, the items of uniform
+    // arrays are declared as in `padded_uniform_ty` to keep the `layout(std140)` stride
+    fn needs_padded_uniform_arrays(&self) -> bool;
+    // no unsigned types, `%` or bitwise operators, they're emulated on `int` with `use_int_op_polyfill`
+    fn needs_int_emulation(&self) -> bool;
+    // name of the fn emulating `%`, a bitwise or a shift operator on the given operand types
+    fn use_int_op_polyfill(&self, op: BinOp, left_ty: &Ty, right_ty: &Ty) -> String;
+    // only bounded `for` loops, `while` and `loop` are lowered to ones of at most this many iterations
+    fn max_loop_iterations(&self) -> Option<u32>;
     
     fn enum_is_float(&self)->bool;
     
@@ -61,18 +82,192 @@ pub trait BackendWriter {
         ident: &dyn fmt::Display,
         ty: &Ty,
     ) -> bool;
+    
+    // everything up to and including the open paren of the params
+    fn write_fn_def_start(&self, string: &mut String, ident: &dyn fmt::Display, return_ty: &Ty);
+    fn write_fn_def_end(&self, string: &mut String, return_ty: &Ty);
+    // written at the top of the outermost block of a fn, after its params have been declared
+    fn write_fn_block_prologue(&self, string: &mut String);
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty);
+    fn write_discard_stmt(&self, string: &mut String);
+    fn write_var_expr(&self, string: &mut String, ident: &dyn fmt::Display);
 
+    // appended to the name a fn is defined and called by, a backend that emits both stages in one
+    // module tells the copies of a fn for each stage apart by it
+    fn fn_ident_suffix(&self, fn_ptr: FnPtr) -> &'static str;
+    // the name a fn is called by when some of its inout args are draw shader fields or vector components
+    fn write_call_ident_with_inout_args(&self, string: &mut String, fn_def: &FnDef, inout_args: &[InoutArg]);
     fn write_call_expr_hidden_args(&self, string: &mut String, hidden_args:&BTreeSet<HiddenArgKind >, sep: &str);
     fn write_fn_def_hidden_params(&self, string: &mut String, hidden_args:&BTreeSet<HiddenArgKind >, sep: &str);
     
     fn generate_live_value_prefix(&self, string: &mut String);
+    // `palette[i]` on a `const` array calls its accessor, this writes up to where the index goes
+    fn write_const_array_access_start(&self, string: &mut String, const_ptr: ConstPtr);
     fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, ty:&Ty);
+    fn write_stage_builtin(&self, string: &mut String, stage_builtin: StageBuiltin);
     
     fn write_ty_lit(&self, string: &mut String, ty_lit: TyLit);
     fn write_builtin_call_ident(&self, string: &mut String, ident: Ident, arg_exprs: &[Expr]);
+    // the builtins of `TextureOp`, which don't map to a single call on every backend
+    fn write_texture_call(&self, string: &mut String, call: &TextureCall);
     
 }
 
+/// The `const` arrays a shader indexes, with their element type and their offset into the const table.
+/// Every item takes 4 floats in the table, so a backend with a table of `vec4`s reads one at a time.
+pub fn const_arrays(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader) -> Vec<(ConstPtr, Ty, usize)> {
+    draw_shader_def.all_const_refs.borrow().iter().map( | (const_ptr, ty) | {
+        let elem_ty = match ty {
+            Ty::Array {elem_ty, ..} => (**elem_ty).clone(),
+            _ => panic!()
+        };
+        let offset = const_table.table_index.get(&shader_registry.const_token_id(*const_ptr)).unwrap().offset;
+        (*const_ptr, elem_ty, offset)
+    }).collect()
+}
+
+// the components of a `vec4` of the const table that hold an item of `slots` floats
+pub fn const_item_swizzle(slots: usize) -> &'static str {
+    match slots {
+        1 => ".x",
+        2 => ".xy",
+        3 => ".xyz",
+        _ => ""
+    }
+}
+
+/// The type a uniform is declared with on a backend that `needs_padded_uniform_arrays`. Items of an
+/// array narrower than a `vec4` are padded to one, so that the array has the 16 byte stride of
+/// `layout(std140)`, and are read back with a `const_item_swizzle`. Bools take 4 bytes there, so
+/// their items are `uvec4`s converted back to bools.
+pub fn padded_uniform_ty(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Array {elem_ty, len} => Ty::Array {
+            elem_ty: Rc::new(match **elem_ty {
+                Ty::Float | Ty::Vec2 | Ty::Vec3 | Ty::Enum(_) => Ty::Vec4,
+                Ty::Int | Ty::Ivec2 | Ty::Ivec3 => Ty::Ivec4,
+                Ty::Uint | Ty::Uvec2 | Ty::Uvec3 => Ty::Uvec4,
+                Ty::Bool | Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => Ty::Uvec4,
+                ref elem_ty => elem_ty.clone(),
+            }),
+            len: *len,
+        },
+        ty => ty.clone(),
+    }
+}
+
+// how an item of `array_expr` is read when it is a padded uniform array, the type it is converted
+// back to and the swizzle that goes after it
+fn padded_uniform_item(shader_registry: &Shader, backend_writer: &dyn BackendWriter, array_expr: &Expr) -> (Option<TyLit>, &'static str) {
+    if !backend_writer.needs_padded_uniform_arrays() {
+        return (None, "")
+    }
+    let field = match &array_expr.kind {
+        ExprKind::Field {expr, field_ident, ..} if *expr.ty.borrow() == Some(Ty::DrawShader) => {
+            shader_registry.draw_shader_def.find_field(*field_ident)
+        }
+        _ => None
+    };
+    match field {
+        Some(field) if matches!(field.kind, DrawShaderFieldKind::Uniform {..}) => {
+            let ty = field.ty_expr.ty.borrow().clone().unwrap();
+            match (padded_uniform_ty(&ty), ty) {
+                (Ty::Array {elem_ty: padded_elem_ty, ..}, Ty::Array {elem_ty, ..}) if padded_elem_ty != elem_ty => {
+                    let is_bool = matches!(*elem_ty, Ty::Bool | Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4);
+                    (if is_bool {elem_ty.maybe_ty_lit()} else {None}, const_item_swizzle(elem_ty.slots()))
+                }
+                _ => (None, "")
+            }
+        }
+        _ => (None, "")
+    }
+}
+
+/// A texture builtin call with its arguments already generated, the texture first.
+/// `args` are the other arguments in the order of the builtin, for a `texture2d_array`
+/// sampling op the coordinate is followed by the layer.
+pub struct TextureCall {
+    pub op: TextureOp,
+    pub ty: Ty,
+    pub texture: String,
+    /// empty if the backend doesn't need texture samplers
+    pub sampler: String,
+    pub args: Vec<String>,
+}
+
+impl TextureCall {
+    /// The arguments after the coordinate and the layer, the lod, gradients, offset or depth reference.
+    pub fn rest(&self) -> &[String] {
+        match self.op {
+            TextureOp::Size => &self.args,
+            TextureOp::Fetch => &self.args[1..],
+            _ if self.ty == Ty::Texture2DArray => &self.args[2..],
+            _ => &self.args[1..]
+        }
+    }
+}
+
+// the sampler that travels along with a texture argument, the analyser only
+// lets textures through as `self` fields and function parameters
+fn texture_sampler_name(arg_expr: &Expr) -> String {
+    match &arg_expr.kind {
+        ExprKind::Field {field_ident, ..} => format!("{}_sampler", DisplayDsIdent(*field_ident)),
+        ExprKind::Var {kind, ..} => match kind.get().unwrap() {
+            VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => {
+                format!("{}_sampler", DisplayVarName(ident, shadow))
+            }
+            VarKind::LiveValue(_) | VarKind::Const(_) => unreachable!("textures can't be consts")
+        }
+        _ => unreachable!("a texture expression that isn't a field or a parameter")
+    }
+}
+
+/// How an argument is passed to an `inout` parameter on a backend that takes them by pointer.
+#[derive(Clone, PartialEq)]
+pub enum InoutArg {
+    // by value, or a pointer to a local
+    Direct,
+    // a pointer to a `self` field, which lives in another address space than the locals
+    DrawShaderField,
+    // a component or a swizzle of a vector, there are no pointers to those so
+    // the pointer is to the vector and the components are copied in and out
+    Swizzle {is_draw_shader_field: bool, vector_ty: Ty, swizzle: Ident},
+}
+
+impl InoutArg {
+    fn from_arg_expr(is_inout: bool, arg_expr: &Expr) -> InoutArg {
+        if !is_inout {
+            return InoutArg::Direct
+        }
+        if let ExprKind::Field {expr, field_ident, ..} = &arg_expr.kind {
+            let vector_ty = expr.ty.borrow().clone().unwrap();
+            if vector_ty.is_vector() {
+                return InoutArg::Swizzle {
+                    is_draw_shader_field: is_draw_shader_field(expr),
+                    vector_ty,
+                    swizzle: *field_ident
+                }
+            }
+        }
+        if is_draw_shader_field(arg_expr) {
+            InoutArg::DrawShaderField
+        }
+        else {
+            InoutArg::Direct
+        }
+    }
+}
+
+// `self.x` or `self.x.y` in a draw shader method
+fn is_draw_shader_field(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Field {expr, ..} | ExprKind::Index {expr, ..} => {
+            *expr.ty.borrow() == Some(Ty::DrawShader) || is_draw_shader_field(expr)
+        }
+        _ => false
+    }
+}
+
 pub struct BlockGenerator<'a> {
     pub fn_def: &'a FnDef,
     pub closure_site_info: Option<ClosureSiteInfo<'a >>,
@@ -82,9 +277,17 @@ pub struct BlockGenerator<'a> {
     pub const_table_offset: Option<usize>,
     //pub use_generated_cons_fns: bool,
     pub indent_level: usize,
+    pub loops: Vec<LoopFrame>,
     pub string: &'a mut String,
 }
 
+// a loop around the statement being generated, labelled jumps out of the loops nested in it
+// set `loop_break_<label>` or `loop_continue_<label>` and are carried out with a plain `break`
+pub struct LoopFrame {
+    label: Option<Ident>,
+    jumps: LabelledJumps,
+}
+
     
 pub fn generate_cons_fn(backend_writer:&dyn BackendWriter, string: &mut String, ty_lit: TyLit, param_tys: &[Ty]) {
     let mut cons_name = format!("consfn_{}", ty_lit);
@@ -95,27 +298,43 @@ pub fn generate_cons_fn(backend_writer:&dyn BackendWriter, string: &mut String,
         return
     }
     
-    backend_writer.write_ty_lit(string, ty_lit);
-    write!(string, " {}(", cons_name).unwrap();
+    let ty = ty_lit.to_ty();
+    backend_writer.write_fn_def_start(string, &cons_name, &ty);
     let mut sep = "";
     if param_tys.len() == 1 {
         backend_writer.write_var_decl(string, sep, false, false, &Ident(id!(x)), &param_tys[0]);
     } else {
         for (index, param_ty) in param_tys.iter().enumerate() {
-            write!(string, "{}", sep).unwrap();
             backend_writer.write_var_decl(string, sep, false, false,&DisplaConstructorArg(index), param_ty);
             sep = ", ";
         }
     }
-    writeln!(string, ") {{").unwrap();
+    backend_writer.write_fn_def_end(string, &ty);
+    write!(string, " {{\n").unwrap();
+    backend_writer.write_fn_block_prologue(string);
     write!(string, "    return ").unwrap();
     backend_writer.write_ty_lit(string, ty_lit);
     write!(string, "(").unwrap();
-    let ty = ty_lit.to_ty();
     if param_tys.len() == 1 {
         let param_ty = &param_tys[0];
         match param_ty {
-            Ty::Bool | Ty::Int | Ty::Float => {
+            Ty::Bool | Ty::Int | Ty::Uint | Ty::Float if ty.is_matrix() => {
+                // a matrix from a scalar has it on the diagonal
+                let size = match ty {
+                    Ty::Mat2 => 2,
+                    Ty::Mat3 => 3,
+                    Ty::Mat4 => 4,
+                    _ => panic!(),
+                };
+                let mut sep = "";
+                for col_index in 0..size {
+                    for row_index in 0..size {
+                        write!(string, "{}{}", sep, if col_index == row_index {"x"} else {"0.0"}).unwrap();
+                        sep = ", ";
+                    }
+                }
+            }
+            Ty::Bool | Ty::Int | Ty::Uint | Ty::Float => {
                 let mut sep = "";
                 for _ in 0..ty.slots() {
                     write!(string, "{}x", sep).unwrap();
@@ -174,9 +393,43 @@ pub fn generate_cons_fn(backend_writer:&dyn BackendWriter, string: &mut String,
     writeln!(string, "}}").unwrap();
 }
 
+// the polyfill `op` goes through when the backend emulates the integer operators
+fn int_op_polyfill(backend_writer: &dyn BackendWriter, op: BinOp, left_expr: &Expr, right_expr: &Expr) -> Option<String> {
+    let int_op = op.assign_op().unwrap_or(op);
+    match int_op {
+        BinOp::Rem | BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Shl | BinOp::Shr if backend_writer.needs_int_emulation() => {
+            Some(backend_writer.use_int_op_polyfill(int_op, left_expr.ty.borrow().as_ref().unwrap(), right_expr.ty.borrow().as_ref().unwrap()))
+        }
+        _ => None
+    }
+}
+
+// the unsigned type a signed shift amount is converted to, when the backend needs that
+fn unsigned_shift_amount(backend_writer: &dyn BackendWriter, op: BinOp, right_expr: &Expr) -> Option<TyLit> {
+    if !matches!(op.assign_op().unwrap_or(op), BinOp::Shl | BinOp::Shr) || !backend_writer.needs_unsigned_shift_amount() {
+        return None
+    }
+    match right_expr.ty.borrow().as_ref().unwrap() {
+        Ty::Int => Some(TyLit::Uint),
+        Ty::Ivec2 => Some(TyLit::Uvec2),
+        Ty::Ivec3 => Some(TyLit::Uvec3),
+        Ty::Ivec4 => Some(TyLit::Uvec4),
+        _ => None
+    }
+}
+
 impl<'a> BlockGenerator<'a> {
     pub fn generate_block(&mut self, block: &Block) {
+        self.generate_block_with_prologue(block, | _ | ())
+    }
+    
+    pub fn generate_fn_block(&mut self, block: &Block) {
+        self.generate_block_with_prologue(block, | this | this.backend_writer.write_fn_block_prologue(this.string))
+    }
+    
+    fn generate_block_with_prologue(&mut self, block: &Block, prologue: impl FnOnce(&mut Self)) {
         write!(self.string, "{{\n").unwrap();
+        prologue(self);
         self.write_indent();
         if !block.stmts.is_empty() {
             self.indent_level += 1;
@@ -192,17 +445,35 @@ impl<'a> BlockGenerator<'a> {
     
     fn generate_stmt(&mut self, stmt: &Stmt) {
         self.write_indent();
+        write_start_mark(self.string, stmt.span());
         match *stmt {
-            Stmt::Break {span} => self.generate_break_stmt(span),
-            Stmt::Continue {span} => self.generate_continue_stmt(span),
+            Stmt::Break {span, label} => self.generate_jump_stmt(span, "break", label),
+            Stmt::Continue {span, label} => self.generate_jump_stmt(span, "continue", label),
+            Stmt::Discard {..} => {
+                self.backend_writer.write_discard_stmt(self.string);
+                writeln!(self.string).unwrap();
+            }
             Stmt::For {
                 span,
+                label,
                 ident,
+                ref shadow,
                 ref from_expr,
                 ref to_expr,
                 ref step_expr,
                 ref block,
-            } => self.generate_for_stmt(span, ident, from_expr, to_expr, step_expr, block),
+            } => self.generate_for_stmt(span, label, DisplayVarName(ident, shadow.get().unwrap()), from_expr, to_expr, step_expr, block),
+            Stmt::While {
+                span,
+                label,
+                ref expr,
+                ref block,
+            } => self.generate_while_stmt(span, label, Some(expr), block),
+            Stmt::Loop {
+                span,
+                label,
+                ref block,
+            } => self.generate_while_stmt(span, label, None, block),
             Stmt::If {
                 span,
                 ref expr,
@@ -228,18 +499,20 @@ impl<'a> BlockGenerator<'a> {
         }
     }
     
-    fn generate_break_stmt(&mut self, _span: TokenSpan) {
-        writeln!(self.string, "break;").unwrap();
-    }
-    
-    fn generate_continue_stmt(&mut self, _span: TokenSpan) {
-        writeln!(self.string, "continue;").unwrap();
+    fn generate_jump_stmt(&mut self, _span: TokenSpan, what: &str, label: Option<Ident>) {
+        match label {
+            Some(label) if self.loops.last().unwrap().label != Some(label) => {
+                writeln!(self.string, "loop_{}_{} = true; break;", what, label).unwrap();
+            }
+            _ => writeln!(self.string, "{};", what).unwrap()
+        }
     }
     
     fn generate_for_stmt(
         &mut self,
         _span: TokenSpan,
-        ident: Ident,
+        label: Option<Ident>,
+        ident: DisplayVarName,
         from_expr: &Expr,
         to_expr: &Expr,
         step_expr: &Option<Expr>,
@@ -278,9 +551,12 @@ impl<'a> BlockGenerator<'a> {
         } else {
             -1
         };
+        self.begin_loop(label, block);
+        write!(self.string, "for (").unwrap();
+        self.backend_writer.write_let_decl(self.string, &ident, &Ty::Int);
         write!(
             self.string,
-            "for (int {0} = {1}; {0} {2} {3}; {0} {4} {5}) ",
+            " = {1}; {0} {2} {3}; {0} {4} {5}) ",
             ident,
             if from <= to {from} else {from - 1},
             if from <= to {"<"} else {">="},
@@ -289,10 +565,107 @@ impl<'a> BlockGenerator<'a> {
             step.abs()
         )
             .unwrap();
-        self.generate_block(block);
+        self.generate_loop_block(None, block);
+        self.end_loop(label, block);
+    }
+    
+    // `loop` is a `while` without a condition
+    fn generate_while_stmt(
+        &mut self,
+        _span: TokenSpan,
+        label: Option<Ident>,
+        expr: Option<&Expr>,
+        block: &Block,
+    ) {
+        self.begin_loop(label, block);
+        if let Some(max_loop_iterations) = self.backend_writer.max_loop_iterations() {
+            let index = format!("loop_index_{}", self.loops.len());
+            write!(self.string, "for (").unwrap();
+            self.backend_writer.write_let_decl(self.string, &index, &Ty::Int);
+            write!(self.string, " = 0; {0} < {1}; {0}++) ", index, max_loop_iterations).unwrap();
+            self.generate_loop_block(expr, block);
+        }
+        else {
+            write!(self.string, "while (").unwrap();
+            match expr {
+                Some(expr) => self.generate_expr(expr),
+                None => write!(self.string, "true").unwrap(),
+            }
+            write!(self.string, ") ").unwrap();
+            self.generate_loop_block(None, block);
+        }
+        self.end_loop(label, block);
+    }
+    
+    fn begin_loop(&mut self, label: Option<Ident>, block: &Block) {
+        let jumps = match label {
+            Some(label) => block.jumps_out_of_inner_loops(label),
+            None => LabelledJumps::default()
+        };
+        if jumps.breaks {
+            self.write_flag_decl("break", label.unwrap());
+            self.write_indent();
+        }
+        self.loops.push(LoopFrame {label, jumps});
+    }
+    
+    // `break_if_not` is the condition of a `while` lowered to a bounded `for`
+    fn generate_loop_block(&mut self, break_if_not: Option<&Expr>, block: &Block) {
+        let frame = self.loops.last().unwrap();
+        let (label, continues) = (frame.label, frame.jumps.continues);
+        self.generate_block_with_prologue(block, | this | {
+            this.indent_level += 1;
+            if let Some(expr) = break_if_not {
+                this.write_indent();
+                write!(this.string, "if (!").unwrap();
+                this.generate_expr(expr);
+                write!(this.string, ") {{break;}}\n").unwrap();
+            }
+            if continues {
+                this.write_indent();
+                this.write_flag_decl("continue", label.unwrap());
+            }
+            this.indent_level -= 1;
+        });
+    }
+    
+    // carries the labelled jumps that left the loop just generated on to the loops around it
+    fn end_loop(&mut self, label: Option<Ident>, block: &Block) {
+        self.loops.pop();
+        let mut seen = Vec::new();
+        let mut checks = Vec::new();
+        for (depth, frame) in self.loops.iter().enumerate().rev() {
+            let frame_label = match frame.label {
+                Some(frame_label) if Some(frame_label) != label && !seen.contains(&frame_label) => frame_label,
+                _ => continue
+            };
+            seen.push(frame_label);
+            let jumps = block.jumps_to(frame_label);
+            if frame.jumps.breaks && jumps.breaks {
+                checks.push(format!("if (loop_break_{}) {{break;}}", frame_label));
+            }
+            if frame.jumps.continues && jumps.continues {
+                let is_innermost = depth + 1 == self.loops.len();
+                checks.push(format!(
+                    "if (loop_continue_{}) {{{}}}",
+                    frame_label,
+                    if is_innermost {"continue;"} else {"break;"}
+                ));
+            }
+        }
+        for check in checks {
+            writeln!(self.string).unwrap();
+            self.write_indent();
+            write!(self.string, "{}", check).unwrap();
+        }
         writeln!(self.string).unwrap();
     }
     
+    fn write_flag_decl(&mut self, what: &str, label: Ident) {
+        self.backend_writer.write_let_decl(self.string, &format!("loop_{}_{}", what, label), &Ty::Bool);
+        write!(self.string, " = false;\n").unwrap();
+    }
+    
     fn generate_if_stmt(
         &mut self,
         _span: TokenSpan,
@@ -301,7 +674,15 @@ impl<'a> BlockGenerator<'a> {
         block_if_false: &Option<Box<Block >>,
     ) {
         write!(self.string, "if").unwrap();
-        self.generate_expr(expr);
+        // binary exprs bring their own parens, `if self.front_facing` doesn't
+        if let ExprKind::Bin {..} = expr.kind {
+            self.generate_expr(expr);
+        }
+        else {
+            write!(self.string, "(").unwrap();
+            self.generate_expr(expr);
+            write!(self.string, ")").unwrap();
+        }
         write!(self.string, " ").unwrap();
         self.generate_block(block_if_true);
         if let Some(block_if_false) = block_if_false {
@@ -347,14 +728,37 @@ impl<'a> BlockGenerator<'a> {
         expr: &Option<Expr>,
         shadow: &Cell<Option<ScopeSymShadow >>
     ) {
-        self.backend_writer.write_var_decl(
+        self.backend_writer.write_let_decl(
             &mut self.string,
-            "",
-            false, 
-            false,
             &DisplayVarName(ident, shadow.get().unwrap()),
             ty.borrow().as_ref().unwrap()
         );
+        // not every backend has array initializers, so arrays are filled in one item at a time
+        if let Some(Ty::Array {len, ..}) = ty.borrow().as_ref() {
+            write!(self.string, ";").unwrap();
+            for index in 0..*len {
+                writeln!(self.string).unwrap();
+                self.write_indent();
+                write!(self.string, "{}[{}] = ", DisplayVarName(ident, shadow.get().unwrap()), index).unwrap();
+                match &expr.as_ref().unwrap().kind {
+                    ExprKind::ArrayCons {elem_exprs, ..} => self.generate_expr(&elem_exprs[index]),
+                    _ => {
+                        let (ty_lit, swizzle) = padded_uniform_item(self.shader_registry, self.backend_writer, expr.as_ref().unwrap());
+                        if let Some(ty_lit) = ty_lit {
+                            self.backend_writer.write_ty_lit(self.string, ty_lit);
+                            write!(self.string, "(").unwrap();
+                        }
+                        self.generate_expr(expr.as_ref().unwrap());
+                        write!(self.string, "[{}]{}", index, swizzle).unwrap();
+                        if ty_lit.is_some() {
+                            write!(self.string, ")").unwrap();
+                        }
+                    }
+                }
+                write!(self.string, ";").unwrap();
+            }
+            return
+        }
         if let Some(expr) = expr {
             write!(self.string, " = ").unwrap();
             self.generate_expr(expr);
@@ -377,7 +781,35 @@ impl<'a> BlockGenerator<'a> {
     }
     
     fn generate_expr_stmt(&mut self, _span: TokenSpan, expr: &Expr) {
-        self.generate_expr(expr);
+        match &expr.kind {
+            // not every backend allows an assignment in parens,
+            // `*=` on matrices goes through `mul()` where that is needed
+            ExprKind::Bin {op: BinOp::MulAssign, ..} if self.backend_writer.needs_mul_fn_for_matrix_multiplication() => {
+                self.generate_expr(expr)
+            }
+            ExprKind::Bin {op, left_expr, right_expr, ..} if op.is_assign() => {
+                self.generate_expr(left_expr);
+                if let Some(polyfill) = int_op_polyfill(self.backend_writer, *op, left_expr, right_expr) {
+                    write!(self.string, " = {}(", polyfill).unwrap();
+                    self.generate_expr(left_expr);
+                    write!(self.string, ", ").unwrap();
+                    self.generate_expr(right_expr);
+                    write!(self.string, ")").unwrap();
+                }
+                else if let Some(ty_lit) = unsigned_shift_amount(self.backend_writer, *op, right_expr) {
+                    write!(self.string, " {} ", op).unwrap();
+                    self.backend_writer.write_ty_lit(self.string, ty_lit);
+                    write!(self.string, "(").unwrap();
+                    self.generate_expr(right_expr);
+                    write!(self.string, ")").unwrap();
+                }
+                else {
+                    write!(self.string, " {} ", op).unwrap();
+                    self.generate_expr(right_expr);
+                }
+            }
+            _ => self.generate_expr(expr)
+        }
         writeln!(self.string, ";").unwrap();
     }
     
@@ -475,6 +907,9 @@ impl<'a> ExprGenerator<'a> {
             (Some(Some(Val::Float(val))), _) => {
                 write!(self.string, "{}", PrettyPrintedF32(*val)).unwrap();
             },
+            (Some(Some(Val::Uint(val))), _) => {
+                self.write_uint(*val);
+            },
             (Some(Some(val)), _) => {
                 write!(self.string, "{}", val).unwrap();
             },
@@ -538,6 +973,8 @@ impl<'a> ExprGenerator<'a> {
                     span,
                     ref args
                 } => self.generate_struct_cons(struct_ptr, span, args),
+                // written item by item by `generate_let_stmt`
+                ExprKind::ArrayCons {..} => panic!(),
                 ExprKind::Var {
                     span,
                     ref kind,
@@ -555,6 +992,16 @@ impl<'a> ExprGenerator<'a> {
         expr_if_true: &Expr,
         expr_if_false: &Expr,
     ) {
+        if self.backend_writer.needs_select_for_cond_expr() {
+            write!(self.string, "select(").unwrap();
+            self.generate_expr(expr_if_false);
+            write!(self.string, ", ").unwrap();
+            self.generate_expr(expr_if_true);
+            write!(self.string, ", ").unwrap();
+            self.generate_expr(expr);
+            write!(self.string, ")").unwrap();
+            return
+        }
         write!(self.string, "(").unwrap();
         self.generate_expr(expr);
         write!(self.string, " ? ").unwrap();
@@ -566,6 +1013,31 @@ impl<'a> ExprGenerator<'a> {
     
     fn generate_bin_expr(&mut self, _span: TokenSpan, op: BinOp, left_expr: &Expr, right_expr: &Expr) {
         
+        if let Some(polyfill) = int_op_polyfill(self.backend_writer, op, left_expr, right_expr) {
+            // `a %= b` becomes `a = polyfill(a, b)`
+            write!(self.string, "(").unwrap();
+            if op.is_assign() {
+                self.generate_expr(left_expr);
+                write!(self.string, " = ").unwrap();
+            }
+            write!(self.string, "{}(", polyfill).unwrap();
+            self.generate_expr(left_expr);
+            write!(self.string, ", ").unwrap();
+            self.generate_expr(right_expr);
+            write!(self.string, "))").unwrap();
+            return
+        }
+        if let Some(ty_lit) = unsigned_shift_amount(self.backend_writer, op, right_expr) {
+            write!(self.string, "(").unwrap();
+            self.generate_expr(left_expr);
+            write!(self.string, " {} ", op).unwrap();
+            self.write_ty_lit(ty_lit);
+            write!(self.string, "(").unwrap();
+            self.generate_expr(right_expr);
+            write!(self.string, "))").unwrap();
+            return
+        }
+        
         // if left_expr or right_expr is a matrix, HLSL needs to use mul()
         let left_is_mat = match left_expr.ty.borrow().as_ref().unwrap() {
             Ty::Mat2 | Ty::Mat3 | Ty::Mat4 => true,
@@ -647,38 +1119,26 @@ impl<'a> ExprGenerator<'a> {
     }
     
     fn generate_un_expr(&mut self, _span: TokenSpan, op: UnOp, expr: &Expr) {
+        if let (UnOp::Not, true) = (op, expr.ty.borrow().as_ref().unwrap().is_integer()) {
+            if self.backend_writer.needs_int_emulation() {
+                // the two's complement identity `!x == -1 - x`
+                write!(self.string, "(-1 - ").unwrap();
+                self.generate_expr(expr);
+                write!(self.string, ")").unwrap();
+            }
+            else {
+                write!(self.string, "~").unwrap();
+                self.generate_expr(expr);
+            }
+            return
+        }
         write!(self.string, "{}", op).unwrap();
         self.generate_expr(expr);
     }
     
     fn generate_method_call_expr(&mut self, _span: TokenSpan, ident: Ident, arg_exprs: &[Expr], closure_site_index: &Cell<Option<usize >>) {
-        // alright so. what if we have
-        // lets check if this is a call with closure args
-        
-        // ok so. what is expr
-        match arg_exprs[0].ty.borrow().as_ref().unwrap() {
-            Ty::Struct(struct_ptr) => {
-                let fn_def = self.shader_registry.struct_method_decl_from_ident(
-                    self.shader_registry.structs.get(struct_ptr).unwrap(),
-                    ident
-                ).unwrap();
-                
-                self.generate_call_body(_span, fn_def, arg_exprs, closure_site_index);
-            }
-            Ty::DrawShader => {
-                let fn_def = self.shader_registry.draw_shader_method_decl_from_ident(
-                    &self.shader_registry.draw_shader_def,
-                    ident
-                ).unwrap();
-                
-                if fn_def.has_closure_args() {
-                    // ok so..
-                    
-                }
-                self.generate_call_body(_span, fn_def, &arg_exprs[1..], closure_site_index);
-            }
-            _ => panic!(),
-        }
+        let (fn_def, arg_exprs) = self.shader_registry.method_call_fn_def(ident, arg_exprs);
+        self.generate_call_body(_span, fn_def, arg_exprs, closure_site_index);
     }
     
     
@@ -693,14 +1153,15 @@ impl<'a> ExprGenerator<'a> {
             // ok our function name is different now:
             
             // and then our args
-            write!(self.string, "{} (", DisplayFnNameWithClosureArgs(
+            write!(self.string, "{}{} (", DisplayFnNameWithClosureArgs(
                 closure_site_index,
                 call_def.fn_ptr,
                 fn_def.ident
-            )).unwrap();
+            ), self.backend_writer.fn_ident_suffix(fn_def.fn_ptr)).unwrap();
             
             let mut sep = "";
-            for arg_expr in arg_exprs {
+            let params = fn_def.params_for_args(arg_exprs.len());
+            for (param, arg_expr) in params.iter().zip(arg_exprs) {
                 // check if the args is a closure, ifso skip it
                 match arg_expr.ty.borrow().as_ref().unwrap(){
                     Ty::ClosureDef(_)=>{
@@ -710,7 +1171,8 @@ impl<'a> ExprGenerator<'a> {
                 }
                 
                 write!(self.string, "{}", sep).unwrap();
-                self.generate_expr(arg_expr);
+                self.generate_arg_expr(param.is_inout, arg_expr);
+                self.generate_texture_sampler_arg(arg_expr);
                 sep = ", ";
             }
             // and now the closed over values
@@ -724,6 +1186,9 @@ impl<'a> ExprGenerator<'a> {
                 }
                 write!(self.string, "{}", sep).unwrap();
                 write!(self.string, "{}", DisplayVarName(sym.ident, sym.shadow)).unwrap();
+                if sym.ty.is_texture() && self.backend_writer.needs_texture_samplers() {
+                    write!(self.string, ", {}_sampler", DisplayVarName(sym.ident, sym.shadow)).unwrap();
+                }
                 sep = ", ";
             }
 
@@ -735,11 +1200,28 @@ impl<'a> ExprGenerator<'a> {
             write!(self.string, ")").unwrap();
         }
         else {
-            write!(self.string, "{}_{} (", fn_def.fn_ptr, fn_def.ident).unwrap();
+            let params = fn_def.params_for_args(arg_exprs.len());
+            let inout_args: Vec<InoutArg> = params.iter().zip(arg_exprs).map( | (param, arg_expr) | {
+                InoutArg::from_arg_expr(param.is_inout, arg_expr)
+            }).collect();
+            let needs_pointer_for_inout = self.backend_writer.needs_pointer_for_inout();
+            if inout_args.iter().any( | inout_arg | *inout_arg != InoutArg::Direct) {
+                self.backend_writer.write_call_ident_with_inout_args(self.string, fn_def, &inout_args);
+                write!(self.string, " (").unwrap();
+            }
+            else {
+                write!(self.string, "{}_{}{} (", fn_def.fn_ptr, fn_def.ident, self.backend_writer.fn_ident_suffix(fn_def.fn_ptr)).unwrap();
+            }
             let mut sep = "";
-            for arg_expr in arg_exprs {
+            for ((param, arg_expr), inout_arg) in params.iter().zip(arg_exprs).zip(&inout_args) {
                 write!(self.string, "{}", sep).unwrap();
-                self.generate_expr(arg_expr);
+                match (&arg_expr.kind, inout_arg) {
+                    (ExprKind::Field {expr, ..}, InoutArg::Swizzle {..}) if needs_pointer_for_inout => {
+                        self.generate_arg_expr(true, expr);
+                    }
+                    _ => self.generate_arg_expr(param.is_inout, arg_expr)
+                }
+                self.generate_texture_sampler_arg(arg_expr);
                 sep = ", ";
             }
 
@@ -749,9 +1231,19 @@ impl<'a> ExprGenerator<'a> {
         }
     }
     
+    fn generate_arg_expr(&mut self, is_inout: bool, arg_expr: &Expr) {
+        if is_inout && self.backend_writer.needs_pointer_for_inout() {
+            write!(self.string, "&").unwrap();
+        }
+        self.generate_expr(arg_expr);
+    }
+    
     fn generate_field_expr(&mut self, _span: TokenSpan, expr: &Expr, field_ident: Ident, ty:&Ty) {
         match expr.ty.borrow().as_ref() {
             Some(Ty::DrawShader) => {
+                if let Some(stage_builtin) = self.shader_registry.draw_shader_def.find_stage_builtin(field_ident) {
+                    return self.backend_writer.write_stage_builtin(&mut self.string, stage_builtin);
+                }
                 self.backend_writer.generate_draw_shader_field_expr(&mut self.string, field_ident, ty);
                 //write!(self.string, "{}", &DisplayDsIdent(field_ident)).unwrap();
             }
@@ -787,24 +1279,65 @@ impl<'a> ExprGenerator<'a> {
     }
     
     fn generate_index_expr(&mut self, _span: TokenSpan, expr: &Expr, index_expr: &Expr) {
+        if let ExprKind::Var {kind, ..} = &expr.kind {
+            if let Some(VarKind::Const(const_ptr)) = kind.get() {
+                self.backend_writer.write_const_array_access_start(self.string, const_ptr);
+                self.generate_expr(index_expr);
+                write!(self.string, ")").unwrap();
+                return
+            }
+        }
+        let (ty_lit, swizzle) = padded_uniform_item(self.shader_registry, self.backend_writer, expr);
+        if let Some(ty_lit) = ty_lit {
+            self.backend_writer.write_ty_lit(self.string, ty_lit);
+            write!(self.string, "(").unwrap();
+        }
         self.generate_expr(expr);
         write!(self.string, "[").unwrap();
         self.generate_expr(index_expr);
-        write!(self.string, "]").unwrap();
+        write!(self.string, "]{}", swizzle).unwrap();
+        if ty_lit.is_some() {
+            write!(self.string, ")").unwrap();
+        }
     }
     
     
     fn generate_builtin_call_expr(&mut self, _span: TokenSpan, ident: Ident, arg_exprs: &[Expr]) {
+        if let Some(op) = TextureOp::from_builtin(ident) {
+            return self.generate_texture_call_expr(op, arg_exprs);
+        }
         // lets create a fn name for this thing.
         
         self.backend_writer.write_builtin_call_ident(&mut self.string, ident, arg_exprs);
         
+        // the eta of refract stays a scalar
+        let splat_ty_lit = if self.backend_writer.needs_splat_for_builtin_args() && ident != Ident(id!(refract)) {
+            arg_exprs.iter().find_map( | arg_expr | match arg_expr.ty.borrow().as_ref().unwrap() {
+                Ty::Vec2 => Some(TyLit::Vec2),
+                Ty::Vec3 => Some(TyLit::Vec3),
+                Ty::Vec4 => Some(TyLit::Vec4),
+                _ => None
+            })
+        }
+        else {
+            None
+        };
+        
         write!(self.string, "(").unwrap();
         let mut sep = "";
         for arg_expr in arg_exprs {
             write!(self.string, "{}", sep).unwrap();
             
-            self.generate_expr(arg_expr);
+            match splat_ty_lit {
+                Some(ty_lit) if arg_expr.ty.borrow().as_ref() == Some(&Ty::Float) => {
+                    self.write_ty_lit(ty_lit);
+                    write!(self.string, "(").unwrap();
+                    self.generate_expr(arg_expr);
+                    write!(self.string, ")").unwrap();
+                }
+                _ => self.generate_expr(arg_expr)
+            }
+            self.generate_texture_sampler_arg(arg_expr);
             
             sep = ", ";
         }
@@ -813,6 +1346,39 @@ impl<'a> ExprGenerator<'a> {
     }
     
     
+    fn generate_texture_call_expr(&mut self, op: TextureOp, arg_exprs: &[Expr]) {
+        let mut args = Vec::new();
+        for arg_expr in arg_exprs {
+            let start = self.string.len();
+            self.generate_expr(arg_expr);
+            args.push(self.string.split_off(start));
+        }
+        // written out from its components, some backends don't take anything but literals
+        if let Some(offset) = arg_exprs.last().unwrap().const_offset().filter( | _ | op == TextureOp::Offset || op == TextureOp::CompareOffset) {
+            let mut string = String::new();
+            self.backend_writer.write_ty_lit(&mut string, if offset.len() == 2 {TyLit::Ivec2} else {TyLit::Ivec3});
+            let components: Vec<String> = offset.iter().map( | value | value.to_string()).collect();
+            write!(string, "({})", components.join(", ")).unwrap();
+            *args.last_mut().unwrap() = string;
+        }
+        let ty = arg_exprs[0].ty.borrow().clone().unwrap();
+        let sampler = if self.backend_writer.needs_texture_samplers() {
+            texture_sampler_name(&arg_exprs[0])
+        }
+        else {
+            String::new()
+        };
+        let texture = args.remove(0);
+        self.backend_writer.write_texture_call(&mut self.string, &TextureCall {op, ty, texture, sampler, args});
+    }
+    
+    fn generate_texture_sampler_arg(&mut self, arg_expr: &Expr) {
+        if !self.backend_writer.needs_texture_samplers() || !arg_expr.ty.borrow().as_ref().map_or(false, | ty | ty.is_texture()) {
+            return
+        }
+        write!(self.string, ", {}", texture_sampler_name(arg_expr)).unwrap();
+    }
+    
     fn generate_plain_call_expr(&mut self, _span: TokenSpan, _ident: Option<Ident>, fn_ptr: Option<FnPtr>, arg_exprs: &[Expr], closure_site_index: &Cell<Option<usize >>, param_index: &Cell<Option<usize >>) {
         // lets create a fn name for this thing.
         if param_index.get().is_some(){ // its a closure
@@ -837,11 +1403,16 @@ impl<'a> ExprGenerator<'a> {
         
         write!(self.string, "{}", DisplayClosureName(closure_site_info.call_ptr, closure_def_index)).unwrap();
         
+        let params = match &self.fn_def.unwrap().params[param_index].ty_expr.kind {
+            TyExprKind::ClosureDecl {params, ..} => params,
+            _ => panic!()
+        };
         write!(self.string, "(").unwrap();
         let mut sep = "";
-        for arg_expr in arg_exprs {
+        for (param, arg_expr) in params.iter().zip(arg_exprs) {
             write!(self.string, "{}", sep).unwrap();
-            self.generate_expr(arg_expr);
+            self.generate_arg_expr(param.is_inout, arg_expr);
+            self.generate_texture_sampler_arg(arg_expr);
             sep = ", ";
         }
         // alright now we have to pass in the closed over syms IN order
@@ -851,6 +1422,9 @@ impl<'a> ExprGenerator<'a> {
             }
             write!(self.string, "{}", sep).unwrap();
             write!(self.string, "{}", DisplayClosedOverArg(sym.ident, sym.shadow)).unwrap();
+            if sym.ty.is_texture() && self.backend_writer.needs_texture_samplers() {
+                write!(self.string, ", {}_sampler", DisplayClosedOverArg(sym.ident, sym.shadow)).unwrap();
+            }
             sep = ", ";
         }
         
@@ -899,21 +1473,35 @@ impl<'a> ExprGenerator<'a> {
         // ok so we have a few varkinds
         match kind.get().unwrap() {
             VarKind::Local {ident, shadow} => {
-                write!(self.string, "{}", DisplayVarName(ident, shadow)).unwrap();
+                self.backend_writer.write_var_expr(self.string, &DisplayVarName(ident, shadow));
             }
             VarKind::MutLocal {ident, shadow} => {
-                write!(self.string, "{}", DisplayVarName(ident, shadow)).unwrap();
+                self.backend_writer.write_var_expr(self.string, &DisplayVarName(ident, shadow));
             }
             VarKind::LiveValue(value_node_ptr) => {
                 // this is a live value.. also prefix needed
                 self.backend_writer.generate_live_value_prefix(self.string);
                 write!(self.string, "{}", value_node_ptr).unwrap();
             }
+            // only ever indexed, see `generate_index_expr`
+            VarKind::Const(_) => panic!()
         }
     }
     
     fn generate_lit_expr(&mut self, _span: TokenSpan, lit: Lit) {
-        write!(self.string, "{}", lit).unwrap();
+        match lit {
+            Lit::Uint(val) => self.write_uint(val),
+            _ => write!(self.string, "{}", lit).unwrap()
+        }
+    }
+    
+    fn write_uint(&mut self, val: u32) {
+        if self.backend_writer.needs_int_emulation() {
+            write!(self.string, "{}", val as i32).unwrap();
+        }
+        else {
+            write!(self.string, "{}u", val).unwrap();
+        }
     }
     
     //fn write_ident(&mut self, ident: Ident) {
@@ -935,16 +1523,16 @@ pub struct FnDefGenerator<'a> {
 
 impl<'a> FnDefGenerator<'a> {
     pub fn generate_fn_def(&mut self) {
-        
-        self.backend_writer.write_var_decl(
+        write_start_mark(self.string, self.fn_def.span);
+        self.backend_writer.write_fn_def_start(
             &mut self.string,
-            "",
-            false,
-            false,
-            &DisplayFnName(self.fn_def.fn_ptr, self.fn_def.ident), // here we must expand IdentPath to something
+            &format_args!(
+                "{}{}",
+                DisplayFnName(self.fn_def.fn_ptr, self.fn_def.ident),
+                self.backend_writer.fn_ident_suffix(self.fn_def.fn_ptr)
+            ),
             self.fn_def.return_ty.borrow().as_ref().unwrap()
         );
-        write!(self.string, "(").unwrap();
         let mut sep = "";
         for param in &self.fn_def.params {
             if !param.shadow.get().is_none() {
@@ -961,9 +1549,11 @@ impl<'a> FnDefGenerator<'a> {
             }
         }
         self.backend_writer.write_fn_def_hidden_params(self.string, self.fn_def.hidden_args.borrow().as_ref().unwrap(), sep);
-        write!(self.string, ") ").unwrap();
+        self.backend_writer.write_fn_def_end(self.string, self.fn_def.return_ty.borrow().as_ref().unwrap());
+        write!(self.string, " ").unwrap();
         self.generate_block(&self.fn_def.block);
         writeln!(self.string).unwrap();
+        write_end_mark(self.string);
         //self.visited.insert(self.decl.ident_path);
     }
     
@@ -976,9 +1566,10 @@ impl<'a> FnDefGenerator<'a> {
             backend_writer: self.backend_writer,
             const_table_offset: self.const_table_offset,
             indent_level: 0,
+            loops: Vec::new(),
             string: self.string,
         }
-        .generate_block(block)
+        .generate_fn_block(block)
     }
 }
 
@@ -1052,20 +1643,16 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
     }
     
     pub fn generate_fn_def_with_closure_args(&mut self) {
-        
-        self.backend_writer.write_var_decl(
+        write_start_mark(self.string, self.fn_def.span);
+        self.backend_writer.write_fn_def_start(
             &mut self.string,
-            "",
-            false,
-            false,
-            &DisplayFnNameWithClosureArgs(
-                self.closure_site_info.site_index,
-                self.call_def.fn_ptr,
-                self.fn_def.ident
-            ), // here we must expand IdentPath to something
+            &format_args!(
+                "{}{}",
+                DisplayFnNameWithClosureArgs(self.closure_site_info.site_index, self.call_def.fn_ptr, self.fn_def.ident),
+                self.backend_writer.fn_ident_suffix(self.fn_def.fn_ptr)
+            ),
             self.fn_def.return_ty.borrow().as_ref().unwrap()
         );
-        write!(self.string, "(").unwrap();
         let mut sep = "";
         for param in &self.fn_def.params {
             if !param.shadow.get().is_none() {
@@ -1101,13 +1688,15 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
         merged_hidden_args.extend(self.call_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         self.backend_writer.write_fn_def_hidden_params(self.string, &merged_hidden_args, sep);
         
-        write!(self.string, ") ").unwrap();
+        self.backend_writer.write_fn_def_end(self.string, self.fn_def.return_ty.borrow().as_ref().unwrap());
+        write!(self.string, " ").unwrap();
         // alright so here the block is generated.. however
         // we need to know the names and the closed-over-args passthrough
         self.generate_block(&self.fn_def.block);
         
         
         writeln!(self.string).unwrap();
+        write_end_mark(self.string);
         //self.visited.insert(self.decl.ident_path);
     }
     
@@ -1120,9 +1709,10 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
             backend_writer: self.backend_writer,
             const_table_offset: self.const_table_offset,
             indent_level: 0,
+            loops: Vec::new(),
             string: self.string,
         }
-        .generate_block(block)
+        .generate_fn_block(block)
     }
 }
 
@@ -1139,22 +1729,18 @@ pub struct ClosureDefGenerator<'a> {
 
 impl<'a> ClosureDefGenerator<'a> {
     pub fn generate_fn_def(&mut self) {
-        
+        write_start_mark(self.string, self.closure_def.span);
         let fn_param = &self.fn_def.params[self.closure_site_arg.param_index];
         
         let mut sep = "";
         
-        if let TyExprKind::ClosureDecl {params, return_ty, ..} = &fn_param.ty_expr.kind {
+        let return_ty = if let TyExprKind::ClosureDecl {params, return_ty, ..} = &fn_param.ty_expr.kind {
             
-            self.backend_writer.write_var_decl(
+            self.backend_writer.write_fn_def_start(
                 &mut self.string,
-                "",
-                false,
-                false,
                 &DisplayClosureName(self.call_def.fn_ptr, self.closure_site_arg.closure_def_index), // here we must expand IdentPath to something
                 return_ty.borrow().as_ref().unwrap(),
             );
-            write!(self.string, "(").unwrap();
             
             // ok we have now params and names
             for (param_index, param) in params.iter().enumerate() {
@@ -1172,10 +1758,11 @@ impl<'a> ClosureDefGenerator<'a> {
                     sep = ", ";
                 }
             }
+            return_ty.borrow().clone().unwrap()
         }
         else {
             panic!()
-        }
+        };
         
         for sym in self.closure_def.closed_over_syms.borrow().as_ref().unwrap() {
             if self.backend_writer.write_var_decl(
@@ -1195,10 +1782,13 @@ impl<'a> ClosureDefGenerator<'a> {
         merged_hidden_args.extend(self.call_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         self.backend_writer.write_fn_def_hidden_params(self.string, &merged_hidden_args, sep);
         
-        writeln!(self.string, ") {{").unwrap();
+        self.backend_writer.write_fn_def_end(self.string, &return_ty);
+        write!(self.string, " ").unwrap();
         
         match &self.closure_def.kind {
             ClosureDefKind::Expr(expr) => {
+                write!(self.string, "{{\n").unwrap();
+                self.backend_writer.write_fn_block_prologue(self.string);
                 write!(self.string, "    return ").unwrap();
                 self.generate_expr(expr);
                 writeln!(self.string, ";").unwrap();
@@ -1209,6 +1799,7 @@ impl<'a> ClosureDefGenerator<'a> {
                 writeln!(self.string).unwrap();
             }
         }
+        write_end_mark(self.string);
         //self.visited.insert(self.decl.ident_path);
     }
     
@@ -1221,9 +1812,10 @@ impl<'a> ClosureDefGenerator<'a> {
             backend_writer: self.backend_writer,
             const_table_offset: self.const_table_offset,
             indent_level: 0,
+            loops: Vec::new(),
             string: self.string,
         }
-        .generate_block(block)
+        .generate_fn_block(block)
     }
     
     
@@ -1265,6 +1857,26 @@ impl fmt::Display for DisplayStructField {
     }
 }
 
+/// The render targets of the pixel shader as `(output, ty, value)`, where `value` reads the
+/// target from `pixel_output`, the value `pixel` returned.
+pub fn pixel_targets(shader_registry: &Shader, draw_shader_def: &DrawShaderDef) -> Vec<(String, Ty, String)> {
+    match shader_registry.pixel_outputs(draw_shader_def) {
+        Some(pixel_outputs) => pixel_outputs.into_iter().map( | (ident, ty) | {
+            (DisplayPixelOutput(ident).to_string(), ty, format!("pixel_output.{}", DisplayStructField(ident)))
+        }).collect(),
+        None => vec![(String::from("frag_color"), Ty::Vec4, String::from("pixel_output"))]
+    }
+}
+
+/// The output a field of the struct returned by `pixel` is written to, `frag_albedo`.
+pub struct DisplayPixelOutput(pub Ident);
+impl fmt::Display for DisplayPixelOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frag_{}", self.0);
+        fmt::Result::Ok(())
+    }
+}
+
 pub struct DisplayFnName(pub FnPtr, pub Ident);
 impl fmt::Display for DisplayFnName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
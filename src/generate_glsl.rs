@@ -2,9 +2,11 @@ use {
     std::{
         fmt,
         fmt::Write,
+        cell::RefCell,
         collections::BTreeSet,
     },
     crate::{
+        makepad_live_compiler::{LiveError, LiveErrorOrigin, live_error_origin},
         makepad_live_id::{
             id,
             LiveId,
@@ -12,31 +14,266 @@ use {
         generate::*,
         swizzle::Swizzle,
         shader_ast::*,
-        shader::Shader
+        shader::Shader,
+        source_map::{LineDirectives, ShaderLineMarkers},
     }
 };
 
-pub fn generate_vertex_shader(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader) -> String {
+/// The GLSL dialect to generate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GlslTarget {
+    /// GLSL ES 1.00 without a `#version` line, for hosts that prepend their own
+    Glsl100,
+    /// GLSL ES 1.00, WebGL1 and GLES2
+    Glsl100Es,
+    /// GLSL ES 3.00, WebGL2 and GLES3
+    Glsl300Es,
+    /// GLSL 3.30 core
+    Glsl330,
+    /// GLSL 4.50 for Vulkan, with explicit locations and descriptor set bindings,
+    /// see `Shader::generate_glsl_with_bindings`
+    Glsl450,
+}
+
+impl GlslTarget {
+    fn version_header(&self) -> Option<&'static str> {
+        match self {
+            GlslTarget::Glsl100 => None,
+            GlslTarget::Glsl100Es => Some("#version 100"),
+            GlslTarget::Glsl300Es => Some("#version 300 es"),
+            GlslTarget::Glsl330 => Some("#version 330"),
+            GlslTarget::Glsl450 => Some("#version 450"),
+        }
+    }
+    
+    pub(crate) fn line_directives(&self) -> LineDirectives {
+        match self {
+            GlslTarget::Glsl100 | GlslTarget::Glsl100Es => LineDirectives::Glsl100,
+            GlslTarget::Glsl300Es | GlslTarget::Glsl330 | GlslTarget::Glsl450 => LineDirectives::Glsl,
+        }
+    }
+    
+    // attribute/varying, texture2D and gl_FragColor
+    fn is_legacy(&self) -> bool {
+        match self {
+            GlslTarget::Glsl100 | GlslTarget::Glsl100Es => true,
+            GlslTarget::Glsl300Es | GlslTarget::Glsl330 | GlslTarget::Glsl450 => false,
+        }
+    }
+    
+    // desktop GLSL has no default precision, `Glsl100` is also fed to GLES2 and WebGL1
+    fn has_precision(&self) -> bool {
+        match self {
+            GlslTarget::Glsl100 | GlslTarget::Glsl100Es | GlslTarget::Glsl300Es => true,
+            GlslTarget::Glsl330 | GlslTarget::Glsl450 => false,
+        }
+    }
+    
+    // every input and output has a location, uniforms live in blocks with a set and binding
+    fn is_vulkan(&self) -> bool {
+        *self == GlslTarget::Glsl450
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GlslPrecision {
+    Lowp,
+    Mediump,
+    Highp,
+}
+
+impl fmt::Display for GlslPrecision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GlslPrecision::Lowp => write!(f, "lowp"),
+            GlslPrecision::Mediump => write!(f, "mediump"),
+            GlslPrecision::Highp => write!(f, "highp"),
+        }
+    }
+}
+
+/// Options for `Shader::generate_glsl_with_options`.
+#[derive(Clone, Copy, Debug)]
+pub struct GlslOptions {
+    pub target: GlslTarget,
+    /// default float precision of the vertex shader
+    pub vertex_precision: GlslPrecision,
+    /// default float precision of the pixel shader
+    pub pixel_precision: GlslPrecision,
+    /// declare uniforms by name instead of unpacking them from `<block>_table` float arrays,
+    /// as plain uniforms on GLSL ES 1.00 and as `layout(std140)` blocks otherwise,
+    /// always on for `GlslTarget::Glsl450`
+    pub named_uniforms: bool,
+    /// `#line` directives or comments pointing back at the `.shader` file,
+    /// see `Shader::generate_glsl_with_source_map`
+    pub line_markers: ShaderLineMarkers,
+    /// GLSL ES 1.00 only has `for` loops with a constant bound, `while` and `loop` are lowered
+    /// to ones of at most this many iterations
+    pub max_loop_iterations: u32,
+}
+
+impl Default for GlslOptions {
+    fn default() -> Self {
+        Self {
+            target: GlslTarget::Glsl100,
+            vertex_precision: GlslPrecision::Lowp,
+            pixel_precision: GlslPrecision::Lowp,
+            named_uniforms: false,
+            line_markers: ShaderLineMarkers::None,
+            max_loop_iterations: 1024,
+        }
+    }
+}
+
+/// A uniform block or texture that needs a descriptor set and binding on `GlslTarget::Glsl450`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GlslResource {
+    /// a uniform block by name, `pass`, `user`, ..., the live values are in the `live` block
+    UniformBlock(String),
+    /// a texture field by name, textures are combined image samplers
+    Texture(String),
+}
+
+/// Where a resource was bound, as reported by `Shader::generate_glsl_with_bindings`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GlslBinding {
+    pub resource: GlslResource,
+    /// name of the block or sampler in the generated GLSL
+    pub ident: String,
+    pub set: usize,
+    pub binding: usize,
+}
+
+/// Picks the descriptor set and binding of every resource, called once per resource
+/// in declaration order, uniform blocks first. Closures returning `(set, binding)` are policies too.
+pub trait GlslBindingPolicy {
+    fn bind(&mut self, resource: &GlslResource) -> (usize, usize);
+}
+
+impl<F: FnMut(&GlslResource) -> (usize, usize)> GlslBindingPolicy for F {
+    fn bind(&mut self, resource: &GlslResource) -> (usize, usize) {
+        self(resource)
+    }
+}
+
+/// Uniform blocks in set 0 and textures in set 1, each numbered from 0 in declaration order.
+#[derive(Clone, Debug, Default)]
+pub struct GlslDefaultBindingPolicy {
+    uniform_blocks: usize,
+    textures: usize,
+}
+
+impl GlslBindingPolicy for GlslDefaultBindingPolicy {
+    fn bind(&mut self, resource: &GlslResource) -> (usize, usize) {
+        match resource {
+            GlslResource::UniformBlock(_) => {
+                self.uniform_blocks += 1;
+                (0, self.uniform_blocks - 1)
+            }
+            GlslResource::Texture(_) => {
+                self.textures += 1;
+                (1, self.textures - 1)
+            }
+        }
+    }
+}
+
+pub fn generate_bindings(draw_shader_def: &DrawShaderDef, policy: &mut dyn GlslBindingPolicy) -> Vec<GlslBinding> {
+    let mut bindings = Vec::new();
+    let mut bind = | resource: GlslResource, ident: String | {
+        let (set, binding) = policy.bind(&resource);
+        bindings.push(GlslBinding {resource, ident, set, binding});
+    };
+    for (ident, _) in draw_shader_def.fields_as_uniform_blocks() {
+        bind(GlslResource::UniformBlock(ident.to_string()), format!("Uniforms_{}", ident));
+    }
+    if !draw_shader_def.all_live_refs.borrow().is_empty() {
+        bind(GlslResource::UniformBlock("live".to_string()), "Uniforms_live".to_string());
+    }
+    for field in &draw_shader_def.fields {
+        if let DrawShaderFieldKind::Texture {..} = field.kind {
+            bind(GlslResource::Texture(field.ident.to_string()), DisplayDsIdent(field.ident).to_string());
+        }
+    }
+    bindings
+}
+
+/// GLSL ES 1.00 has no 3D, array or depth textures, no `textureSize`, `texelFetch` or `textureOffset`,
+/// and no vertex or instance ids.
+pub fn check_target(draw_shader_def: &DrawShaderDef, shader_registry: &Shader, target: GlslTarget) -> Result<(), LiveError> {
+    if !target.is_legacy() {
+        return Ok(())
+    }
+    for field in &draw_shader_def.fields {
+        if let Some(ty @ Ty::Texture3D) | Some(ty @ Ty::Texture2DArray) | Some(ty @ Ty::Texture2DDepth) = field.ty_expr.ty.borrow().as_ref() {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: field.span.into(),
+                message: format!("`{}` needs GLSL ES 3.00, GLSL ES 1.00 only has `texture2d` and `textureCube`", ty),
+            })
+        }
+    }
+    for fn_ptr in draw_shader_def.all_fns.borrow().iter() {
+        let fn_def = shader_registry.all_fns.get(fn_ptr).unwrap();
+        for (ident, span) in fn_def.builtin_deps.borrow().as_ref().unwrap() {
+            if let Some(TextureOp::Size) | Some(TextureOp::Fetch) | Some(TextureOp::Offset) | Some(TextureOp::CompareOffset) = TextureOp::from_builtin(*ident) {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: (*span).into(),
+                    message: format!("`{}` needs GLSL ES 3.00", ident),
+                })
+            }
+        }
+    }
+    if let Some((stage_builtin, span)) = shader_registry.first_stage_builtin(&[StageBuiltin::VertexId, StageBuiltin::InstanceId]) {
+        return Err(LiveError {
+            origin: live_error_origin!(),
+            span: span.into(),
+            message: format!("`self.{}` needs GLSL ES 3.00", stage_builtin.name()),
+        })
+    }
+    Ok(())
+}
+
+pub fn generate_vertex_shader(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader, options: GlslOptions, bindings: &[GlslBinding]) -> String {
     let mut string = String::new();
     DrawShaderGenerator {
         draw_shader_def,
         const_table,
         shader_registry,
+        options,
+        bindings,
         string: &mut string,
-        backend_writer: &GlslBackendWriter {shader_registry, const_table}
+        backend_writer: &GlslBackendWriter {
+            shader_registry,
+            const_table,
+            target: options.target,
+            max_loop_iterations: if options.target.is_legacy() {Some(options.max_loop_iterations)} else {None},
+            polyfills: RefCell::new(Vec::new()),
+            is_pixel_shader: false,
+        }
     }
     .generate_vertex_shader();
     string
 }
 
-pub fn generate_pixel_shader(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader) -> String {
+pub fn generate_pixel_shader(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader, options: GlslOptions, bindings: &[GlslBinding]) -> String {
     let mut string = String::new();
     DrawShaderGenerator {
         draw_shader_def,
         const_table,
         shader_registry,
+        options,
+        bindings,
         string: &mut string,
-        backend_writer: &GlslBackendWriter {shader_registry, const_table}
+        backend_writer: &GlslBackendWriter {
+            shader_registry,
+            const_table,
+            target: options.target,
+            max_loop_iterations: if options.target.is_legacy() {Some(options.max_loop_iterations)} else {None},
+            polyfills: RefCell::new(Vec::new()),
+            is_pixel_shader: true,
+        }
     }
     .generate_pixel_shader();
     string
@@ -45,9 +282,11 @@ pub fn generate_pixel_shader(draw_shader_def: &DrawShaderDef, const_table: &Draw
 struct DrawShaderGenerator<'a> {
     draw_shader_def: &'a DrawShaderDef,
     shader_registry: &'a Shader,
+    options: GlslOptions,
+    bindings: &'a [GlslBinding],
     string: &'a mut String,
     const_table: &'a DrawShaderConstTable,
-    backend_writer: &'a dyn BackendWriter
+    backend_writer: &'a GlslBackendWriter<'a>
 }
 
 impl<'a> DrawShaderGenerator<'a> {
@@ -65,6 +304,14 @@ impl<'a> DrawShaderGenerator<'a> {
                 Ty::Ivec2 => "ivec2(0)",
                 Ty::Ivec3 => "ivec3(0)",
                 Ty::Ivec4 => "ivec4(0)",
+                Ty::Uint if self.options.target.is_legacy() => "0",
+                Ty::Uvec2 if self.options.target.is_legacy() => "ivec2(0)",
+                Ty::Uvec3 if self.options.target.is_legacy() => "ivec3(0)",
+                Ty::Uvec4 if self.options.target.is_legacy() => "ivec4(0)",
+                Ty::Uint => "0u",
+                Ty::Uvec2 => "uvec2(0u)",
+                Ty::Uvec3 => "uvec3(0u)",
+                Ty::Uvec4 => "uvec4(0u)",
                 Ty::Vec2 => "vec2(0.0)",
                 Ty::Vec3 => "vec3(0.0)",
                 Ty::Vec4 => "vec4(0.0)",
@@ -79,8 +326,79 @@ impl<'a> DrawShaderGenerator<'a> {
     }
     
     
+    fn generate_header(&mut self, precision: GlslPrecision, fn_deps: &Vec<FnPtr>) {
+        if let Some(version_header) = self.options.target.version_header() {
+            writeln!(self.string, "{}", version_header).unwrap();
+        }
+        if self.options.target.is_legacy() && self.shader_registry.uses_builtin(fn_deps, &[Ident(id!(dFdx)), Ident(id!(dFdy))]) {
+            writeln!(self.string, "#extension GL_OES_standard_derivatives : enable").unwrap();
+        }
+        if self.options.target.is_legacy() && self.backend_writer.is_pixel_shader && self.shader_registry.uses_builtin(fn_deps, &[
+            Ident(id!(sample2d_lod)),
+            Ident(id!(sample2d_grad)),
+            Ident(id!(sample_cube_lod)),
+            Ident(id!(sample_cube_grad))
+        ]) {
+            writeln!(self.string, "#extension GL_EXT_shader_texture_lod : enable").unwrap();
+        }
+        // `gl_FragData` past index 0
+        if self.options.target.is_legacy() && self.backend_writer.is_pixel_shader && self.shader_registry.pixel_outputs(self.draw_shader_def).is_some() {
+            writeln!(self.string, "#extension GL_EXT_draw_buffers : require").unwrap();
+        }
+        if self.options.target.is_legacy() && self.backend_writer.is_pixel_shader && self.shader_registry.stage_builtins(fn_deps).contains(&StageBuiltin::FragDepth) {
+            writeln!(self.string, "#extension GL_EXT_frag_depth : enable").unwrap();
+        }
+        if self.options.target.has_precision() {
+            writeln!(self.string, "precision {} float;", precision).unwrap();
+        }
+        // GLSL ES 3.00 has no default precision for these
+        if self.options.target == GlslTarget::Glsl300Es {
+            for (ty, sampler) in [(Ty::Texture3D, "sampler3D"), (Ty::Texture2DArray, "sampler2DArray"), (Ty::Texture2DDepth, "sampler2DShadow")].iter() {
+                if self.draw_shader_def.fields.iter().any( | field | field.ty_expr.ty.borrow().as_ref() == Some(ty)) {
+                    writeln!(self.string, "precision {} {};", precision, sampler).unwrap();
+                }
+            }
+        }
+    }
+    
+    // GLSL ES 1.00 has no inverse or transpose
+    fn generate_polyfills(&mut self, fn_deps: &Vec<FnPtr>) {
+        if !self.options.target.is_legacy() {
+            return
+        }
+        if self.shader_registry.uses_builtin(fn_deps, &[Ident(id!(transpose))]) {
+            writeln!(self.string, "mat3 polyfill_transpose(mat3 m) {{").unwrap();
+            writeln!(self.string, "    return mat3(m[0][0], m[1][0], m[2][0], m[0][1], m[1][1], m[2][1], m[0][2], m[1][2], m[2][2]);").unwrap();
+            writeln!(self.string, "}}").unwrap();
+            writeln!(self.string, "mat4 polyfill_transpose(mat4 m) {{").unwrap();
+            writeln!(self.string, "    return mat4(m[0][0], m[1][0], m[2][0], m[3][0], m[0][1], m[1][1], m[2][1], m[3][1], m[0][2], m[1][2], m[2][2], m[3][2], m[0][3], m[1][3], m[2][3], m[3][3]);").unwrap();
+            writeln!(self.string, "}}").unwrap();
+        }
+        if self.shader_registry.uses_builtin(fn_deps, &[Ident(id!(inverse))]) {
+            writeln!(self.string, "mat4 polyfill_inverse(mat4 m) {{").unwrap();
+            writeln!(self.string, "    float a00 = m[0][0], a01 = m[0][1], a02 = m[0][2], a03 = m[0][3];").unwrap();
+            writeln!(self.string, "    float a10 = m[1][0], a11 = m[1][1], a12 = m[1][2], a13 = m[1][3];").unwrap();
+            writeln!(self.string, "    float a20 = m[2][0], a21 = m[2][1], a22 = m[2][2], a23 = m[2][3];").unwrap();
+            writeln!(self.string, "    float a30 = m[3][0], a31 = m[3][1], a32 = m[3][2], a33 = m[3][3];").unwrap();
+            writeln!(self.string, "    float b00 = a00 * a11 - a01 * a10, b01 = a00 * a12 - a02 * a10;").unwrap();
+            writeln!(self.string, "    float b02 = a00 * a13 - a03 * a10, b03 = a01 * a12 - a02 * a11;").unwrap();
+            writeln!(self.string, "    float b04 = a01 * a13 - a03 * a11, b05 = a02 * a13 - a03 * a12;").unwrap();
+            writeln!(self.string, "    float b06 = a20 * a31 - a21 * a30, b07 = a20 * a32 - a22 * a30;").unwrap();
+            writeln!(self.string, "    float b08 = a20 * a33 - a23 * a30, b09 = a21 * a32 - a22 * a31;").unwrap();
+            writeln!(self.string, "    float b10 = a21 * a33 - a23 * a31, b11 = a22 * a33 - a23 * a32;").unwrap();
+            writeln!(self.string, "    float det = b00 * b11 - b01 * b10 + b02 * b09 + b03 * b08 - b04 * b07 + b05 * b06;").unwrap();
+            writeln!(self.string, "    return mat4(").unwrap();
+            writeln!(self.string, "        a11 * b11 - a12 * b10 + a13 * b09, a02 * b10 - a01 * b11 - a03 * b09, a31 * b05 - a32 * b04 + a33 * b03, a22 * b04 - a21 * b05 - a23 * b03,").unwrap();
+            writeln!(self.string, "        a12 * b08 - a10 * b11 - a13 * b07, a00 * b11 - a02 * b08 + a03 * b07, a32 * b02 - a30 * b05 - a33 * b01, a20 * b05 - a22 * b02 + a23 * b01,").unwrap();
+            writeln!(self.string, "        a10 * b10 - a11 * b08 + a13 * b06, a01 * b08 - a00 * b10 - a03 * b06, a30 * b04 - a31 * b02 + a33 * b00, a21 * b02 - a20 * b04 - a23 * b00,").unwrap();
+            writeln!(self.string, "        a11 * b07 - a10 * b09 - a12 * b06, a00 * b09 - a01 * b07 + a02 * b06, a31 * b01 - a30 * b03 - a32 * b00, a20 * b03 - a21 * b01 + a22 * b00").unwrap();
+            writeln!(self.string, "    ) / det;").unwrap();
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+    
     fn generate_vertex_shader(&mut self) {
-        write!(self.string, "precision lowp float;");
+        self.generate_header(self.options.vertex_precision, &self.draw_shader_def.vertex_fns.borrow());
         let packed_geometries_slots = self.compute_packed_geometries_slots();
         let packed_instances_slots = self.compute_packed_instances_slots();
         let packed_varyings_slots = self.compute_packed_varyings_slots();
@@ -88,6 +406,7 @@ impl<'a> DrawShaderGenerator<'a> {
             Some(packed_geometries_slots),
             Some(packed_instances_slots),
             packed_varyings_slots,
+            if self.options.target.is_legacy() {"varying"} else {"out"},
         );
         for field in &self.draw_shader_def.fields {
             match field.kind {
@@ -130,6 +449,10 @@ impl<'a> DrawShaderGenerator<'a> {
         
         self.generate_uniform_block_unpack();
         self.generate_live_unpack();
+        // only written when `vertex` asks for it, which it may do conditionally
+        if self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow()).contains(&StageBuiltin::PointSize) {
+            writeln!(self.string, "    gl_PointSize = 1.0;").unwrap();
+        }
         
         let mut geometry_unpacker = VarUnpacker::new(
             "packed_geometry",
@@ -204,6 +527,8 @@ impl<'a> DrawShaderGenerator<'a> {
         //    self.generate_live_decl(*live_ref, ty);
         //}
         
+        self.generate_polyfills(fn_deps);
+        
         // we have all the structs already from analyse
         for struct_ptr in struct_deps.iter().rev() {
             let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
@@ -213,7 +538,10 @@ impl<'a> DrawShaderGenerator<'a> {
         for (ty_lit, param_tys) in all_constructor_fns {
             generate_cons_fn(self.backend_writer, self.string, ty_lit, &param_tys);
         }
+        self.generate_const_array_fns();
         write!(self.string, "\n").unwrap();
+        // the fns decide which integer op polyfills they need, those go in front of them
+        let polyfills_pos = self.string.len();
         for fn_iter in fn_deps.iter().rev() {
             let const_table_offset = self.const_table.offsets.get(fn_iter).cloned();
             let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
@@ -244,12 +572,32 @@ impl<'a> DrawShaderGenerator<'a> {
             .generate_fn_def();
             write!(self.string, "\n").unwrap();
         }
+        let polyfills: String = self.backend_writer.polyfills.borrow().iter().map( | (_, source) | source.as_str()).collect();
+        self.string.insert_str(polyfills_pos, &polyfills);
     }
     
     pub fn generate_pixel_shader(&mut self) {
-        write!(self.string, "precision lowp float;");
+        self.generate_header(self.options.pixel_precision, &self.draw_shader_def.pixel_fns.borrow());
         let packed_varyings_slots = self.compute_packed_varyings_slots();
-        self.generate_decls(None, None, packed_varyings_slots);
+        self.generate_decls(
+            None,
+            None,
+            packed_varyings_slots,
+            if self.options.target.is_legacy() {"varying"} else {"in"},
+        );
+        let pixel_outputs = self.shader_registry.pixel_outputs(self.draw_shader_def);
+        if !self.options.target.is_legacy() {
+            if let Some(pixel_outputs) = &pixel_outputs {
+                for (index, (ident, ty)) in pixel_outputs.iter().enumerate() {
+                    write!(self.string, "layout(location = {}) out ", index).unwrap();
+                    self.write_var_decl(&DisplayPixelOutput(*ident), ty);
+                    writeln!(self.string, ";").unwrap();
+                }
+            }
+            else {
+                writeln!(self.string, "layout(location = 0) out vec4 frag_color;").unwrap();
+            }
+        }
         for field in &self.draw_shader_def.fields {
             match &field.kind {
                 DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
@@ -289,6 +637,10 @@ impl<'a> DrawShaderGenerator<'a> {
         
         self.generate_uniform_block_unpack();
         self.generate_live_unpack();
+        // once written, every path of the shader has to write it
+        if self.shader_registry.stage_builtins(&self.draw_shader_def.pixel_fns.borrow()).contains(&StageBuiltin::FragDepth) {
+            writeln!(self.string, "    {} = gl_FragCoord.z;", if self.options.target.is_legacy() {"gl_FragDepthEXT"} else {"gl_FragDepth"}).unwrap();
+        }
         
         let mut varying_unpacker = VarUnpacker::new(
             "packed_varying",
@@ -316,13 +668,80 @@ impl<'a> DrawShaderGenerator<'a> {
         // we need to collect all consts
         let pixel_decl = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
         write!(self.string, "\n").unwrap();
-        writeln!(self.string, "    gl_FragColor = {}();", DisplayFnName(pixel_decl.fn_ptr, pixel_decl.ident)).unwrap();
+        if let Some(pixel_outputs) = &pixel_outputs {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(&"pixel_output", pixel_decl.return_ty.borrow().as_ref().unwrap());
+            writeln!(self.string, " = {}();", DisplayFnName(pixel_decl.fn_ptr, pixel_decl.ident)).unwrap();
+            for (index, (ident, ty)) in pixel_outputs.iter().enumerate() {
+                if self.options.target.is_legacy() {
+                    // `gl_FragData` is a `vec4`, missing components are filled in like attributes
+                    let padding = match ty {
+                        Ty::Float => ", 0.0, 0.0, 1.0",
+                        Ty::Vec2 => ", 0.0, 1.0",
+                        Ty::Vec3 => ", 1.0",
+                        _ => "",
+                    };
+                    if padding.is_empty() {
+                        writeln!(self.string, "    gl_FragData[{}] = pixel_output.{};", index, DisplayStructField(*ident)).unwrap();
+                    }
+                    else {
+                        writeln!(self.string, "    gl_FragData[{}] = vec4(pixel_output.{}{});", index, DisplayStructField(*ident), padding).unwrap();
+                    }
+                }
+                else {
+                    writeln!(self.string, "    {} = pixel_output.{};", DisplayPixelOutput(*ident), DisplayStructField(*ident)).unwrap();
+                }
+            }
+        }
+        else {
+            writeln!(
+                self.string,
+                "    {} = {}();",
+                if self.options.target.is_legacy() {"gl_FragColor"} else {"frag_color"},
+                DisplayFnName(pixel_decl.fn_ptr, pixel_decl.ident)
+            ).unwrap();
+        }
         writeln!(self.string, "}}").unwrap();
     }
     
+    fn named_uniforms(&self) -> bool {
+        self.options.named_uniforms || self.options.target.is_vulkan()
+    }
+    
+    // `set = 0, binding = 1`, for the layout qualifier on Vulkan
+    fn binding_layout(&self, ident: &str) -> String {
+        let binding = self.bindings.iter().find( | binding | binding.ident == ident).unwrap();
+        format!("set = {}, binding = {}", binding.set, binding.binding)
+    }
+    
+    // an item of a `const` array takes 4 floats of `const_table`
+    fn generate_const_array_fns(&mut self) {
+        for (const_ptr, elem_ty, offset) in const_arrays(self.draw_shader_def, self.const_table, self.shader_registry) {
+            self.write_var_decl(&const_ptr, &elem_ty);
+            writeln!(self.string, "(int index) {{").unwrap();
+            writeln!(self.string, "    int slot = {} + index * 4;", offset).unwrap();
+            let components: Vec<String> = (0..elem_ty.slots()).map( | i | match i {
+                0 => String::from("const_table[slot]"),
+                _ => format!("const_table[slot + {}]", i)
+            }).collect();
+            if components.len() == 1 {
+                writeln!(self.string, "    return {};", components[0]).unwrap();
+            }
+            else {
+                write!(self.string, "    return ").unwrap();
+                self.backend_writer.write_ty_lit(self.string, elem_ty.maybe_ty_lit().unwrap());
+                writeln!(self.string, "({});", components.join(", ")).unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+    
     fn generate_uniform_block_unpack(
         &mut self,
     ) {
+        if self.named_uniforms() {
+            return
+        }
         for (ident, vec) in self.draw_shader_def.fields_as_uniform_blocks() {
             
             let mut slots = 0;
@@ -332,12 +751,21 @@ impl<'a> DrawShaderGenerator<'a> {
             for (index, _item) in vec {
                 let field = &self.draw_shader_def.fields[index];
                 
-                write!(self.string, "    {} = ", &DisplayDsIdent(field.ident)).unwrap();
-                
                 let ty_expr = field.ty_expr.ty.borrow();
                 
-                self.write_uniform_ty_unpack(ty_expr.as_ref().unwrap(), &table, slots);
-                write!(self.string, ";\n").unwrap();
+                // arrays are unpacked item by item, GLSL ES 1.00 has no array constructors
+                if let Some(Ty::Array {elem_ty, len}) = ty_expr.as_ref() {
+                    for elem_index in 0..*len {
+                        write!(self.string, "    {}[{}] = ", &DisplayDsIdent(field.ident), elem_index).unwrap();
+                        self.write_uniform_ty_unpack(elem_ty, &table, slots + elem_index * elem_ty.slots());
+                        write!(self.string, ";\n").unwrap();
+                    }
+                }
+                else {
+                    write!(self.string, "    {} = ", &DisplayDsIdent(field.ident)).unwrap();
+                    self.write_uniform_ty_unpack(ty_expr.as_ref().unwrap(), &table, slots);
+                    write!(self.string, ";\n").unwrap();
+                }
                 slots += ty_expr.as_ref().unwrap().slots();
             }
             write!(self.string, "\n").unwrap();
@@ -347,6 +775,9 @@ impl<'a> DrawShaderGenerator<'a> {
     fn generate_live_unpack(
         &mut self,
     ) {
+        if self.options.target.is_vulkan() {
+            return
+        }
         let mut slots = 0;
         for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
             
@@ -362,24 +793,53 @@ impl<'a> DrawShaderGenerator<'a> {
         packed_attributes_size: Option<usize>,
         packed_instances_size: Option<usize>,
         packed_varyings_size: usize,
+        packed_varying_qualifier: &str,
     ) {
+        let attribute_qualifier = if self.options.target.is_legacy() {"attribute"} else {"in"};
         
         if self.const_table.table.len()>0 {
-            writeln!(self.string, "uniform float const_table[{}];", self.const_table.table.len()).unwrap();
+            if self.options.target.is_vulkan() {
+                // Vulkan has no loose uniforms, the table is baked in
+                write!(self.string, "const float const_table[{0}] = float[{0}](", self.const_table.table.len()).unwrap();
+                for (index, value) in self.const_table.table.iter().enumerate() {
+                    write!(self.string, "{}{:?}", if index == 0 {""} else {", "}, value).unwrap();
+                }
+                writeln!(self.string, ");").unwrap();
+            }
+            else {
+                writeln!(self.string, "uniform float const_table[{}];", self.const_table.table.len()).unwrap();
+            }
         }
         write!(self.string, "\n").unwrap();
         
-        let live_slots = self.calc_live_slots();
-        if live_slots >0 {
-            writeln!(self.string, "uniform float live_table[{}];", live_slots).unwrap();
+        if self.options.target.is_vulkan() {
+            if !self.draw_shader_def.all_live_refs.borrow().is_empty() {
+                writeln!(self.string, "layout(std140, {}) uniform Uniforms_live {{", self.binding_layout("Uniforms_live")).unwrap();
+                for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+                    write!(self.string, "    ").unwrap();
+                    self.write_var_decl(live_ref, ty);
+                    writeln!(self.string, ";").unwrap();
+                }
+                writeln!(self.string, "}};").unwrap();
+            }
         }
-        
-        for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
-            self.write_var_decl(live_ref, ty);
-            writeln!(self.string, ";").unwrap();
+        else {
+            let live_slots = self.calc_live_slots();
+            if live_slots >0 {
+                writeln!(self.string, "uniform float live_table[{}];", live_slots).unwrap();
+            }
+            
+            for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+                self.write_var_decl(live_ref, ty);
+                writeln!(self.string, ";").unwrap();
+            }
         }
         
         for (ident, vec) in self.draw_shader_def.fields_as_uniform_blocks() {
+            if self.named_uniforms() {
+                self.generate_named_uniform_block(ident, &vec);
+                continue;
+            }
             let mut slots = 0;
             
             for (index, _item) in &vec {
@@ -409,23 +869,27 @@ impl<'a> DrawShaderGenerator<'a> {
         }
         write!(self.string, "\n").unwrap();
         
+        // instances are numbered after the geometries
+        let mut location = 0;
         if let Some(packed_attributes_size) = packed_attributes_size {
-            self.generate_packed_var_decls(
-                "attribute",
+            location = self.generate_packed_var_decls(
+                attribute_qualifier,
                 "packed_geometry",
                 packed_attributes_size,
+                location,
             );
         }
         write!(self.string, "\n").unwrap();
         if let Some(packed_instances_size) = packed_instances_size {
             self.generate_packed_var_decls(
-                "attribute",
+                attribute_qualifier,
                 "packed_instance",
                 packed_instances_size,
+                location,
             );
         }
         write!(self.string, "\n").unwrap();
-        self.generate_packed_var_decls("varying", "packed_varying", packed_varyings_size);
+        self.generate_packed_var_decls(packed_varying_qualifier, "packed_varying", packed_varyings_size, 0);
         write!(self.string, "\n").unwrap();
     }
     
@@ -445,6 +909,30 @@ impl<'a> DrawShaderGenerator<'a> {
         writeln!(self.string, "}};").unwrap();
     }
     
+    fn generate_named_uniform_block(&mut self, ident: Ident, fields: &[(usize, Ident)]) {
+        if self.options.target.is_legacy() {
+            for (index, _item) in fields {
+                write!(self.string, "uniform ").unwrap();
+                self.generate_uniform_decl(&self.draw_shader_def.fields[*index]);
+            }
+        }
+        else {
+            let block_ident = format!("Uniforms_{}", ident);
+            if self.options.target.is_vulkan() {
+                writeln!(self.string, "layout(std140, {}) uniform {} {{", self.binding_layout(&block_ident), block_ident).unwrap();
+            }
+            else {
+                writeln!(self.string, "layout(std140) uniform {} {{", block_ident).unwrap();
+            }
+            for (index, _item) in fields {
+                write!(self.string, "    ").unwrap();
+                self.generate_uniform_decl(&self.draw_shader_def.fields[*index]);
+            }
+            writeln!(self.string, "}};").unwrap();
+        }
+        write!(self.string, "\n").unwrap();
+    }
+    
     fn generate_uniform_decl(&mut self, decl: &DrawShaderFieldDef) {
         //write!(self.string, "uniform ").unwrap();
         self.write_var_decl(
@@ -464,6 +952,9 @@ impl<'a> DrawShaderGenerator<'a> {
     }
     
     fn generate_texture_decl(&mut self, decl: &DrawShaderFieldDef) {
+        if self.options.target.is_vulkan() {
+            write!(self.string, "layout({}) ", self.binding_layout(&DisplayDsIdent(decl.ident).to_string())).unwrap();
+        }
         write!(self.string, "uniform ").unwrap();
         self.write_var_decl(
             &DisplayDsIdent(decl.ident),
@@ -513,12 +1004,17 @@ impl<'a> DrawShaderGenerator<'a> {
     
     fn generate_packed_var_decls(
         &mut self,
-        packed_var_qualifier: &'a str,
-        packed_var_name: &'a str,
+        packed_var_qualifier: &str,
+        packed_var_name: &str,
         mut packed_vars_size: usize,
-    ) {
+        mut location: usize,
+    ) -> usize {
         let mut packed_var_index = 0;
         loop {
+            if self.options.target.is_vulkan() && packed_vars_size > 0 {
+                write!(self.string, "layout(location = {}) ", location).unwrap();
+                location += 1;
+            }
             let packed_var_size = packed_vars_size.min(4);
             writeln!(
                 self.string,
@@ -539,6 +1035,7 @@ impl<'a> DrawShaderGenerator<'a> {
             packed_vars_size -= packed_var_size;
             packed_var_index += 1;
         }
+        location
     }
     
     fn write_uniform_ty_unpack(&mut self, ty: &Ty, prefix: &str, s: usize) {
@@ -552,6 +1049,11 @@ impl<'a> DrawShaderGenerator<'a> {
             Ty::Ivec2 => write!(self.string, "ivec2(int({0}[{1}]), int({0}[{2}]))", prefix, s, s + 1),
             Ty::Ivec3 => write!(self.string, "ivec3(int({0}[{1}]), int({0}[{2}]), int({0}[{3}]))", prefix, s, s + 1, s + 2),
             Ty::Ivec4 => write!(self.string, "ivec4(int({0}[{1}]), int({0}[{2}]), int({0}[{3}]), int({0}[{4}]))", prefix, s, s + 1, s + 2, s + 3),
+            Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => {
+                let ty_name = self.backend_writer.ty_name(ty);
+                let components: Vec<String> = (s..s + ty.slots()).map( | i | format!("{}[{}]", prefix, i)).collect();
+                write!(self.string, "{}({})", ty_name, components.join(", "))
+            }
             Ty::Vec2 => write!(self.string, "vec2({0}[{1}], {0}[{2}])", prefix, s, s + 1),
             Ty::Vec3 => write!(self.string, "vec3({0}[{1}], {0}[{2}], {0}[{3}])", prefix, s, s + 1, s + 2),
             Ty::Vec4 => write!(self.string, "vec4({0}[{1}], {0}[{2}], {0}[{3}], {0}[{4}])", prefix, s, s + 1, s + 2, s + 3),
@@ -767,7 +1269,68 @@ impl<'a> VarUnpacker<'a> {
 
 struct GlslBackendWriter<'a> {
     pub shader_registry: &'a Shader,
-    const_table: &'a DrawShaderConstTable
+    const_table: &'a DrawShaderConstTable,
+    target: GlslTarget,
+    max_loop_iterations: Option<u32>,
+    // signature and source of the integer op polyfills used by the generated fns
+    polyfills: RefCell<Vec<(String, String)>>,
+    // GLSL ES 1.00 only has explicit lods in the pixel shader through `GL_EXT_shader_texture_lod`
+    is_pixel_shader: bool,
+}
+
+impl<'a> GlslBackendWriter<'a> {
+    fn ty_name(&self, ty: &Ty) -> String {
+        let mut string = String::new();
+        self.write_ty_lit(&mut string, ty.maybe_ty_lit().unwrap());
+        string
+    }
+    
+    // GLSL ES 1.00 `int`s are only guaranteed 16 bits, the bitwise polyfills walk the bits
+    // of non-negative values and stop as soon as the rest is zero
+    fn int_op_polyfill_body(&self, op: BinOp, left_ty: &Ty) -> String {
+        let size = left_ty.slots();
+        match op {
+            BinOp::Rem => "    return a - (a / b) * b;\n".to_string(),
+            BinOp::Shl | BinOp::Shr => format!(
+                "    return a {} {}(exp2({}(b)));\n",
+                if let BinOp::Shl = op {"*"} else {"/"},
+                self.ty_name(left_ty),
+                ["float", "vec2", "vec3", "vec4"][size - 1]
+            ),
+            _ if size > 1 => {
+                let scalar = self.use_int_op_polyfill(op, &Ty::Int, &Ty::Int);
+                let components: Vec<String> = ["x", "y", "z", "w"][..size].iter()
+                    .map( | c | format!("{}(a.{1}, b.{1})", scalar, c))
+                    .collect();
+                format!("    return {}({});\n", self.ty_name(left_ty), components.join(", "))
+            }
+            _ => {
+                let combine = match op {
+                    BinOp::BitAnd => "&&",
+                    BinOp::BitOr => "||",
+                    _ => "!=",
+                };
+                let mut body = String::new();
+                writeln!(body, "    int result = 0;").unwrap();
+                writeln!(body, "    int bit = 1;").unwrap();
+                writeln!(body, "    for (int i = 0; i < 31; i++) {{").unwrap();
+                writeln!(body, "        if (a == 0 && b == 0) {{").unwrap();
+                writeln!(body, "            break;").unwrap();
+                writeln!(body, "        }}").unwrap();
+                writeln!(body, "        bool x = a - (a / 2) * 2 == 1;").unwrap();
+                writeln!(body, "        bool y = b - (b / 2) * 2 == 1;").unwrap();
+                writeln!(body, "        if (x {} y) {{", combine).unwrap();
+                writeln!(body, "            result += bit;").unwrap();
+                writeln!(body, "        }}").unwrap();
+                writeln!(body, "        a /= 2;").unwrap();
+                writeln!(body, "        b /= 2;").unwrap();
+                writeln!(body, "        bit *= 2;").unwrap();
+                writeln!(body, "    }}").unwrap();
+                writeln!(body, "    return result;").unwrap();
+                body
+            }
+        }
+    }
 }
 
 impl<'a> BackendWriter for GlslBackendWriter<'a> {
@@ -788,10 +1351,63 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
         false
     }
     
+    fn needs_texture_samplers(&self) -> bool {
+        false
+    }
+    
     fn const_table_is_vec4(&self) -> bool {
         false
     }
     
+    fn needs_select_for_cond_expr(&self) -> bool {
+        false
+    }
+    
+    fn needs_splat_for_builtin_args(&self) -> bool {
+        false
+    }
+    
+    fn needs_pointer_for_inout(&self) -> bool {
+        false
+    }
+    
+    fn needs_unsigned_shift_amount(&self) -> bool {
+        false
+    }
+    
+    fn max_loop_iterations(&self) -> Option<u32> {
+        self.max_loop_iterations
+    }
+    
+    fn needs_padded_uniform_arrays(&self) -> bool {
+        false
+    }
+    
+    fn needs_int_emulation(&self) -> bool {
+        self.target.is_legacy()
+    }
+    
+    fn use_int_op_polyfill(&self, op: BinOp, left_ty: &Ty, right_ty: &Ty) -> String {
+        let name = match op {
+            BinOp::Rem => "polyfill_rem",
+            BinOp::BitAnd => "polyfill_bit_and",
+            BinOp::BitOr => "polyfill_bit_or",
+            BinOp::BitXor => "polyfill_bit_xor",
+            BinOp::Shl => "polyfill_shl",
+            BinOp::Shr => "polyfill_shr",
+            _ => panic!("no polyfill for `{}`", op)
+        };
+        // `uint` is spelled `int` here, so the overloads are by spelling
+        let left = self.ty_name(left_ty);
+        let signature = format!("{} {}({} a, {} b)", left, name, left, self.ty_name(right_ty));
+        if !self.polyfills.borrow().iter().any( | (polyfill, _) | *polyfill == signature) {
+            let body = self.int_op_polyfill_body(op, left_ty);
+            let source = format!("{} {{\n{}}}\n", signature, body);
+            self.polyfills.borrow_mut().push((signature, source));
+        }
+        name.to_string()
+    }
+    
     fn use_cons_fn(&self, _what: &str) -> bool {
         false
     }
@@ -860,6 +1476,11 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Ivec4);
                 write!(string, " {}", ident).unwrap();
             }
+            Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => {
+                prefix(string, sep, is_inout);
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+                write!(string, " {}", ident).unwrap();
+            }
             Ty::Vec2 => {
                 prefix(string, sep, is_inout);
                 self.write_ty_lit(string, TyLit::Vec2);
@@ -890,9 +1511,9 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Mat4);
                 write!(string, " {}", ident).unwrap();
             }
-            Ty::Texture2D => {
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => {
                 write!(string, "{}", sep).unwrap();
-                self.write_ty_lit(string, TyLit::Texture2D);
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
                 write!(string, " {}", ident).unwrap();
             }
             Ty::Array {ref elem_ty, len} => {
@@ -921,6 +1542,38 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
         true
     }
     
+    fn write_fn_def_start(&self, string: &mut String, ident: &dyn fmt::Display, return_ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, return_ty);
+        write!(string, "(").unwrap();
+    }
+    
+    fn write_fn_def_end(&self, string: &mut String, _return_ty: &Ty) {
+        write!(string, ")").unwrap();
+    }
+    
+    fn write_fn_block_prologue(&self, _string: &mut String) {
+    }
+    
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, ty);
+    }
+    
+    fn write_discard_stmt(&self, string: &mut String) {
+        write!(string, "discard;").unwrap();
+    }
+    
+    fn write_var_expr(&self, string: &mut String, ident: &dyn fmt::Display) {
+        write!(string, "{}", ident).unwrap();
+    }
+    
+    fn fn_ident_suffix(&self, _fn_ptr: FnPtr) -> &'static str {
+        ""
+    }
+
+    fn write_call_ident_with_inout_args(&self, string: &mut String, fn_def: &FnDef, _inout_args: &[InoutArg]) {
+        write!(string, "{}_{}", fn_def.fn_ptr, fn_def.ident).unwrap();
+    }
+
     fn write_call_expr_hidden_args(&self, _string: &mut String, _hidden_args: &BTreeSet<HiddenArgKind >, _sep: &str) {
     }
     
@@ -930,6 +1583,27 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
     fn generate_live_value_prefix(&self, _string: &mut String) {
     }
     
+    fn write_const_array_access_start(&self, string: &mut String, const_ptr: ConstPtr) {
+        write!(string, "{}(", const_ptr).unwrap();
+    }
+    
+    fn write_stage_builtin(&self, string: &mut String, stage_builtin: StageBuiltin) {
+        let legacy = self.target.is_legacy();
+        write!(string, "{}", match stage_builtin {
+            // GLSL ES 1.00 can't tell vertices apart
+            StageBuiltin::VertexId if self.target.is_vulkan() => "gl_VertexIndex",
+            StageBuiltin::InstanceId if self.target.is_vulkan() => "gl_InstanceIndex",
+            StageBuiltin::VertexId => "gl_VertexID",
+            StageBuiltin::InstanceId => "gl_InstanceID",
+            StageBuiltin::PointSize => "gl_PointSize",
+            StageBuiltin::FragCoord => "gl_FragCoord",
+            StageBuiltin::FrontFacing => "gl_FrontFacing",
+            StageBuiltin::PointCoord => "gl_PointCoord",
+            StageBuiltin::FragDepth if legacy => "gl_FragDepthEXT",
+            StageBuiltin::FragDepth => "gl_FragDepth",
+        }).unwrap();
+    }
+    
     fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, _ty: &Ty) {
         write!(string, "{}", &DisplayDsIdent(field_ident)).unwrap();
     }
@@ -948,6 +1622,15 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
                 TyLit::Ivec2 => "ivec2",
                 TyLit::Ivec3 => "ivec3",
                 TyLit::Ivec4 => "ivec4",
+                // GLSL ES 1.00 has no unsigned types, they're emulated with `int`
+                TyLit::Uint if self.target.is_legacy() => "int",
+                TyLit::Uvec2 if self.target.is_legacy() => "ivec2",
+                TyLit::Uvec3 if self.target.is_legacy() => "ivec3",
+                TyLit::Uvec4 if self.target.is_legacy() => "ivec4",
+                TyLit::Uint => "uint",
+                TyLit::Uvec2 => "uvec2",
+                TyLit::Uvec3 => "uvec3",
+                TyLit::Uvec4 => "uvec4",
                 TyLit::Vec2 => "vec2",
                 TyLit::Vec3 => "vec3",
                 TyLit::Vec4 => "vec4",
@@ -955,13 +1638,67 @@ impl<'a> BackendWriter for GlslBackendWriter<'a> {
                 TyLit::Mat3 => "mat3",
                 TyLit::Mat4 => "mat4",
                 TyLit::Texture2D => "sampler2D",
+                TyLit::TextureCube => "samplerCube",
+                TyLit::Texture3D => "sampler3D",
+                TyLit::Texture2DArray => "sampler2DArray",
+                TyLit::Texture2DDepth => "sampler2DShadow",
             }
         )
             .unwrap();
     }
     
     fn write_builtin_call_ident(&self, string: &mut String, ident: Ident, _arg_exprs: &[Expr]) {
-        write!(string, "{}", ident).unwrap();
+        match ident {
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) => {
+                write!(string, "{}", if self.target.is_legacy() {"texture2D"} else {"texture"}).unwrap();
+            }
+            Ident(id!(inverse)) | Ident(id!(transpose)) if self.target.is_legacy() => {
+                write!(string, "polyfill_{}", ident).unwrap();
+            }
+            _ => {
+                write!(string, "{}", ident).unwrap()
+            }
+        }
+    }
+    
+    fn write_texture_call(&self, string: &mut String, call: &TextureCall) {
+        // GLSL ES 1.00 only has 2D and cube textures
+        let legacy = self.target.is_legacy() && (call.ty == Ty::Texture2D || call.ty == Ty::TextureCube);
+        let ident = match call.op {
+            TextureOp::Sample if legacy && call.ty == Ty::TextureCube => "textureCube",
+            TextureOp::Sample | TextureOp::Compare => "texture",
+            TextureOp::Lod if legacy => match (&call.ty, self.is_pixel_shader) {
+                (Ty::TextureCube, false) => "textureCubeLod",
+                (Ty::TextureCube, true) => "textureCubeLodEXT",
+                (_, false) => "texture2DLod",
+                (_, true) => "texture2DLodEXT",
+            },
+            TextureOp::Lod => "textureLod",
+            TextureOp::Grad if legacy => if call.ty == Ty::TextureCube {"textureCubeGradEXT"} else {"texture2DGradEXT"},
+            TextureOp::Grad => "textureGrad",
+            TextureOp::Offset | TextureOp::CompareOffset => "textureOffset",
+            TextureOp::Size => "textureSize",
+            TextureOp::Fetch => "texelFetch",
+        };
+        write!(string, "{}({}", ident, call.texture).unwrap();
+        let mut rest = call.rest();
+        match call.op {
+            TextureOp::Size => (),
+            // the depth reference is the last coordinate of a shadow sampler
+            TextureOp::Compare | TextureOp::CompareOffset => {
+                write!(string, ", vec3({}, {})", call.args[0], rest[0]).unwrap();
+                rest = &rest[1..];
+            }
+            // as is the layer of an array
+            _ if call.ty == Ty::Texture2DArray && call.op != TextureOp::Fetch => {
+                write!(string, ", vec3({}, float({}))", call.args[0], call.args[1]).unwrap();
+            }
+            _ => write!(string, ", {}", call.args[0]).unwrap()
+        }
+        for arg in rest {
+            write!(string, ", {}", arg).unwrap();
+        }
+        write!(string, ")").unwrap();
     }
     
 }
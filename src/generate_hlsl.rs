@@ -5,6 +5,7 @@ use{
         collections::{BTreeSet,BTreeMap},
     },
     crate::{
+        makepad_live_compiler::{LiveError, LiveErrorOrigin, live_error_origin},
         makepad_live_id::{
             id,
             LiveId,
@@ -19,6 +20,18 @@ pub fn index_to_char(index: usize) -> char {
     std::char::from_u32(index as u32 + 65).unwrap()
 }
 
+/// D3D11 has no point sprites, so no `point_size` or `point_coord`.
+pub fn check_shader(shader_registry: &Shader) -> Result<(), LiveError> {
+    if let Some((stage_builtin, span)) = shader_registry.first_stage_builtin(&[StageBuiltin::PointSize, StageBuiltin::PointCoord]) {
+        return Err(LiveError {
+            origin: live_error_origin!(),
+            span: span.into(),
+            message: format!("HLSL doesn't support `self.{}`", stage_builtin.name()),
+        })
+    }
+    Ok(())
+}
+
 pub fn generate_shader(draw_shader_def: &DrawShaderDef, const_table:&DrawShaderConstTable, shader_registry: &Shader) -> String {
     let mut string = String::new();
     DrawShaderGenerator {
@@ -43,14 +56,12 @@ struct DrawShaderGenerator<'a> {
 impl<'a> DrawShaderGenerator<'a> {
     fn generate_shader(&mut self) {
         
-        for fn_iter in self.draw_shader_def.all_fns.borrow().iter() {
-            let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
-            if fn_def.builtin_deps.borrow().as_ref().unwrap().contains(&Ident(id!(sample2d))) {
-                writeln!(self.string, "SamplerState default_texture_sampler{{Filter=MIN_MAX_MIP_LINEAR;AddressU = Wrap;AddressV=Wrap;}};").unwrap();
-                writeln!(self.string, "float4 sample2d(Texture2D tex, float2 pos){{return tex.Sample(default_texture_sampler,pos);}}").unwrap();
-                break;
-            }
-        };
+        if self.shader_registry.uses_builtin(&self.draw_shader_def.all_fns.borrow(), &[Ident(id!(sample2d))]) {
+            writeln!(self.string, "float4 sample2d(Texture2D tex, SamplerState smp, float2 pos){{return tex.Sample(smp,pos);}}").unwrap();
+        }
+        if self.shader_registry.uses_builtin(&self.draw_shader_def.all_fns.borrow(), &[Ident(id!(texture_size))]) {
+            self.generate_texture_size_fns();
+        }
         
         self.generate_struct_decls();
         let fields_as_uniform_blocks = self.draw_shader_def.fields_as_uniform_blocks();
@@ -59,6 +70,7 @@ impl<'a> DrawShaderGenerator<'a> {
         self.generate_geometry_struct();
         self.generate_instance_struct();
         self.generate_varying_struct();
+        self.generate_builtins_struct();
         
         let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(vertex))).unwrap();
         let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
@@ -72,6 +84,7 @@ impl<'a> DrawShaderGenerator<'a> {
         {
             generate_cons_fn(self.backend_writer, self.string, ty_lit, &param_tys);
         }
+        self.generate_const_array_fns();
         
         let all_fns = self.draw_shader_def.all_fns.borrow();
         for fn_iter in all_fns.iter().rev() {
@@ -107,7 +120,7 @@ impl<'a> DrawShaderGenerator<'a> {
         self.generate_pixel_main();
     }
     
-    fn generate_struct_decls(&mut self) {
+   fn generate_struct_decls(&mut self) {
         for struct_ptr in self.draw_shader_def.all_structs.borrow().iter().rev() {
             let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
             write!(self.string, "struct {} {{", struct_ptr).unwrap();
@@ -146,7 +159,7 @@ impl<'a> DrawShaderGenerator<'a> {
             for (index, _item) in vec {
                 let field = &self.draw_shader_def.fields[*index];
                 write!(self.string, "    ").unwrap();
-                self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
+                self.write_var_decl(&DisplayDsIdent(field.ident), &padded_uniform_ty(field.ty_expr.ty.borrow().as_ref().unwrap()));
                 writeln!(self.string, ";").unwrap();
             }
             writeln!(self.string, "}};").unwrap();
@@ -154,14 +167,49 @@ impl<'a> DrawShaderGenerator<'a> {
         }
     }
     
+    // an item of a `const` array takes a `float4` of `const_table`
+    fn generate_const_array_fns(&mut self) {
+        for (const_ptr, elem_ty, offset) in const_arrays(self.draw_shader_def, self.const_table, self.shader_registry) {
+            self.write_var_decl(&const_ptr, &elem_ty);
+            writeln!(self.string, "(int index) {{").unwrap();
+            writeln!(self.string, "    return const_table[{} + index]{};", offset / 4, const_item_swizzle(elem_ty.slots())).unwrap();
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+    
+    // `GetDimensions` only has out parameters, so `texture_size` calls one of these
+    fn generate_texture_size_fns(&mut self) {
+        let mut tys: Vec<Ty> = Vec::new();
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {..} = field.kind {
+                let ty = field.ty_expr.ty.borrow().clone().unwrap();
+                if !tys.contains(&ty) {
+                    tys.push(ty);
+                }
+            }
+        }
+        for ty in tys {
+            let (ret_ty, dims) = match ty {
+                Ty::Texture3D | Ty::Texture2DArray => ("int3", "w, h, d"),
+                _ => ("int2", "w, h"),
+            };
+            write!(self.string, "{} texture_size_{}(", ret_ty, texture_size_suffix(&ty)).unwrap();
+            self.backend_writer.write_ty_lit(self.string, ty.maybe_ty_lit().unwrap());
+            writeln!(self.string, " tex, int lod){{uint {0}, levels; tex.GetDimensions(lod, {0}, levels); return {1}({0});}}", dims, ret_ty).unwrap();
+        }
+    }
+    
     fn generate_texture_defs(&mut self) {
         let mut index = 0;
         //writeln!(self.string, "struct mpsc_Textures {{").unwrap();
         for field in &self.draw_shader_def.fields {
             match field.kind {
                 DrawShaderFieldKind::Texture {..} => {
-                    assert_eq!(*field.ty_expr.ty.borrow().as_ref().unwrap(), Ty::Texture2D);
-                    write!(self.string, "Texture2D {}: register(t{});", DisplayDsIdent(field.ident), index).unwrap();
+                    let ty = field.ty_expr.ty.borrow().clone().unwrap();
+                    self.backend_writer.write_ty_lit(self.string, ty.maybe_ty_lit().unwrap());
+                    writeln!(self.string, " {}: register(t{});", DisplayDsIdent(field.ident), index).unwrap();
+                    // D3D11 samplers have no state in the shader, it is created from the reflection
+                    writeln!(self.string, "{} {}_sampler: register(s{});", sampler_ty(&ty), DisplayDsIdent(field.ident), index).unwrap();
                     index += 1;
                 }
                 _ => {}
@@ -194,7 +242,7 @@ impl<'a> DrawShaderGenerator<'a> {
             match field.kind {
                 DrawShaderFieldKind::Instance {..} => {
                     match field.ty_expr.ty.borrow().as_ref().unwrap() {
-                        Ty::Float | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
+                        Ty::Float | Ty::Enum(_) | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
                             write!(self.string, "    ").unwrap();
                             self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
                             writeln!(self.string, ": INST{};", index_to_char(index)).unwrap();
@@ -248,7 +296,7 @@ impl<'a> DrawShaderGenerator<'a> {
                 }
                 DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
                     match field.ty_expr.ty.borrow().as_ref().unwrap() {
-                        Ty::Float | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
+                        Ty::Float | Ty::Enum(_) | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
                             write!(self.string, "    ").unwrap();
                             self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
                             writeln!(self.string, ": VARY{};", index_to_char(index)).unwrap();
@@ -344,9 +392,14 @@ impl<'a> DrawShaderGenerator<'a> {
         write!(self.string, "Geometries geometries").unwrap();
         write!(self.string, ", Instances instances").unwrap();
         write!(self.string, ", uint inst_id: SV_InstanceID").unwrap();
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow());
+        if stage_builtins.contains(&StageBuiltin::VertexId) {
+            write!(self.string, ", uint vertex_id: SV_VertexID").unwrap();
+        }
         writeln!(self.string, ") {{").unwrap();
         write!(self.string, "    Varyings varyings = ").unwrap();
         self.generate_varying_init();
+        self.generate_builtins_init(&stage_builtins);
         
         for decl in &self.draw_shader_def.fields {
             match &decl.kind {
@@ -388,13 +441,43 @@ impl<'a> DrawShaderGenerator<'a> {
     }
     
     fn generate_pixel_main(&mut self) {
+        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.pixel_fns.borrow());
+        let writes_depth = stage_builtins.contains(&StageBuiltin::FragDepth);
+        let has_output_struct = writes_depth || self.shader_registry.pixel_outputs(self.draw_shader_def).is_some();
+        if has_output_struct {
+            self.generate_pixel_output_struct(writes_depth);
+            write!(self.string, "PixelOutput pixel_main(Varyings varyings").unwrap();
+        }
+        else {
+            write!(self.string, "float4 pixel_main(").unwrap();
+            write!(self.string, "Varyings varyings").unwrap();
+        }
+        if stage_builtins.contains(&StageBuiltin::FrontFacing) {
+            write!(self.string, ", bool front_facing: SV_IsFrontFace").unwrap();
+        }
+        writeln!(self.string, "){}{{", if has_output_struct {" "} else {" : SV_TARGET"}).unwrap();
+        self.generate_builtins_init(&stage_builtins);
         
-        write!(self.string, "float4 pixel_main(").unwrap();
-        write!(self.string, "Varyings varyings").unwrap();
-        writeln!(self.string, ") : SV_TARGET{{").unwrap();
+        if has_output_struct {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(&"pixel_output", pixel_def.return_ty.borrow().as_ref().unwrap());
+            write!(self.string, " = {}(", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+            self.backend_writer.write_call_expr_hidden_args(self.string, pixel_def.hidden_args.borrow().as_ref().unwrap(), "");
+            writeln!(self.string, ");").unwrap();
+            writeln!(self.string, "    PixelOutput output;").unwrap();
+            for (output, _, value) in pixel_targets(self.shader_registry, self.draw_shader_def) {
+                writeln!(self.string, "    output.{} = {};", output, value).unwrap();
+            }
+            if writes_depth {
+                writeln!(self.string, "    output.depth = builtins.frag_depth;").unwrap();
+            }
+            writeln!(self.string, "    return output;").unwrap();
+            writeln!(self.string, "}}").unwrap();
+            return
+        }
         
         write!(self.string, "    return ").unwrap();
-        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
         write!(self.string, "    {}", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
         write!(self.string, "(").unwrap();
         self.backend_writer.write_call_expr_hidden_args(self.string, pixel_def.hidden_args.borrow().as_ref().unwrap(), "");
@@ -403,6 +486,55 @@ impl<'a> DrawShaderGenerator<'a> {
         writeln!(self.string, "}}").unwrap();
     }
     
+    // a render target per field of the struct `pixel` returns, or `frag_color`
+    fn generate_pixel_output_struct(&mut self, writes_depth: bool) {
+        writeln!(self.string, "struct PixelOutput {{").unwrap();
+        for (index, (output, ty, _)) in pixel_targets(self.shader_registry, self.draw_shader_def).iter().enumerate() {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(output, ty);
+            writeln!(self.string, ": SV_Target{};", index).unwrap();
+        }
+        if writes_depth {
+            writeln!(self.string, "    float depth: SV_Depth;").unwrap();
+        }
+        writeln!(self.string, "}};").unwrap();
+    }
+    
+    // the stage builtins used anywhere, passed to the fns that use them
+    fn generate_builtins_struct(&mut self) {
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.all_fns.borrow());
+        if stage_builtins.is_empty() {
+            return
+        }
+        writeln!(self.string, "struct Builtins {{").unwrap();
+        for stage_builtin in stage_builtins {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(&stage_builtin.name(), &stage_builtin.ty());
+            writeln!(self.string, ";").unwrap();
+        }
+        writeln!(self.string, "}};").unwrap();
+    }
+    
+    fn generate_builtins_init(&mut self, stage_builtins: &BTreeSet<StageBuiltin>) {
+        if stage_builtins.is_empty() {
+            return
+        }
+        writeln!(self.string, "    Builtins builtins;").unwrap();
+        for stage_builtin in stage_builtins {
+            let value = match stage_builtin {
+                StageBuiltin::VertexId => "int(vertex_id)",
+                StageBuiltin::InstanceId => "int(inst_id)",
+                StageBuiltin::PointSize => "1.0",
+                StageBuiltin::FragCoord => "varyings.position",
+                StageBuiltin::FrontFacing => "front_facing",
+                // rejected by `check_shader`
+                StageBuiltin::PointCoord => "float2(0.5, 0.5)",
+                StageBuiltin::FragDepth => "varyings.position.z",
+            };
+            writeln!(self.string, "    builtins.{} = {};", stage_builtin.name(), value).unwrap();
+        }
+    }
+    
     fn generate_expr(&mut self, expr: &Expr) {
         ExprGenerator {
             fn_def: None,
@@ -420,13 +552,17 @@ impl<'a> DrawShaderGenerator<'a> {
         match ty {
             Ty::Bool => write!(self.string, "false").unwrap(),
             Ty::Int => write!(self.string, "0").unwrap(),
-            Ty::Float => write!(self.string, "0.0").unwrap(),
+            Ty::Float | Ty::Enum(_) => write!(self.string, "0.0").unwrap(),
             Ty::Bvec2 => write!(self.string, "bool2(0,0)").unwrap(),
             Ty::Bvec3 => write!(self.string, "bool3(0,0,0)").unwrap(),
             Ty::Bvec4 => write!(self.string, "bool4(0,0,0,0)").unwrap(),
             Ty::Ivec2 => write!(self.string, "int2(0,0)").unwrap(),
             Ty::Ivec3 => write!(self.string, "int3(0,0,0)").unwrap(),
             Ty::Ivec4 => write!(self.string, "int4(0,0,0,0)").unwrap(),
+            Ty::Uint => write!(self.string, "0u").unwrap(),
+            Ty::Uvec2 => write!(self.string, "uint2(0u,0u)").unwrap(),
+            Ty::Uvec3 => write!(self.string, "uint3(0u,0u,0u)").unwrap(),
+            Ty::Uvec4 => write!(self.string, "uint4(0u,0u,0u,0u)").unwrap(),
             Ty::Vec2 => write!(self.string, "float2(0.0,0.0)").unwrap(),
             Ty::Vec3 => write!(self.string, "float3(0.0,0.0,0.0)").unwrap(),
             Ty::Vec4 => write!(self.string, "float4(0.0,0.0,0.0,0.0)").unwrap(),
@@ -459,7 +595,7 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
     }
     
     fn enum_is_float(&self)->bool{
-        false
+        true
     }
         
     fn needs_mul_fn_for_matrix_multiplication(&self) -> bool {
@@ -470,10 +606,46 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
         false
     }
     
+    fn needs_texture_samplers(&self) -> bool {
+        true
+    }
+    
     fn const_table_is_vec4(&self) -> bool {
         true
     }
     
+    fn needs_select_for_cond_expr(&self) -> bool {
+        false
+    }
+    
+    fn needs_splat_for_builtin_args(&self) -> bool {
+        false
+    }
+    
+    fn needs_pointer_for_inout(&self) -> bool {
+        false
+    }
+    
+    fn needs_unsigned_shift_amount(&self) -> bool {
+        false
+    }
+    
+    fn max_loop_iterations(&self) -> Option<u32> {
+        None
+    }
+    
+    fn needs_padded_uniform_arrays(&self) -> bool {
+        true
+    }
+    
+    fn needs_int_emulation(&self) -> bool {
+        false
+    }
+    
+    fn use_int_op_polyfill(&self, op: BinOp, _left_ty: &Ty, _right_ty: &Ty) -> String {
+        panic!("no polyfill for `{}`", op)
+    }
+    
     fn use_cons_fn(&self, what: &str) -> bool {
         match what {
             "mpsc_vec4_float_float_float_float" => false,
@@ -547,6 +719,11 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Ivec4);
                 write!(string, " {}", ident).unwrap();
             }
+            Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => {
+                prefix(string, sep, is_inout);
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+                write!(string, " {}", ident).unwrap();
+            }
             Ty::Vec2 => {
                 prefix(string, sep, is_inout);
                 self.write_ty_lit(string, TyLit::Vec2);
@@ -577,7 +754,12 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Mat4);
                 write!(string, " {}", ident).unwrap();
             }
-            Ty::Texture2D => panic!(), // TODO
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => {
+                // the sampler travels along with the texture, see `needs_texture_samplers`
+                write!(string, "{}", sep).unwrap();
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+                write!(string, " {0}, {1} {0}_sampler", ident, sampler_ty(ty)).unwrap();
+            }
             Ty::Array {ref elem_ty, len} => {
                 self.write_var_decl(string, sep, is_inout, is_packed, ident, elem_ty);
                 write!(string, "[{}]", len).unwrap();
@@ -588,7 +770,7 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
             }
             Ty::Enum(_) => {
                 prefix(string, sep, is_inout);
-                write!(string, "int {}", ident).unwrap();
+                write!(string, "float {}", ident).unwrap();
             }
             Ty::DrawShader => {
                 return false
@@ -603,27 +785,57 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
         true
     }
     
+    // textures, uniforms and the const table are globals in HLSL, only the structs are passed along
+    fn write_fn_def_start(&self, string: &mut String, ident: &dyn fmt::Display, return_ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, return_ty);
+        write!(string, "(").unwrap();
+    }
+    
+    fn write_fn_def_end(&self, string: &mut String, _return_ty: &Ty) {
+        write!(string, ")").unwrap();
+    }
+    
+    fn write_fn_block_prologue(&self, _string: &mut String) {
+    }
+    
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, ty);
+    }
+    
+    fn write_discard_stmt(&self, string: &mut String) {
+        write!(string, "discard;").unwrap();
+    }
+    
+    fn write_var_expr(&self, string: &mut String, ident: &dyn fmt::Display) {
+        write!(string, "{}", ident).unwrap();
+    }
+    
+    fn fn_ident_suffix(&self, _fn_ptr: FnPtr) -> &'static str {
+        ""
+    }
+
+    fn write_call_ident_with_inout_args(&self, string: &mut String, fn_def: &FnDef, _inout_args: &[InoutArg]) {
+        write!(string, "{}_{}", fn_def.fn_ptr, fn_def.ident).unwrap();
+    }
+
     fn write_call_expr_hidden_args(&self, string: &mut String, hidden_args: &BTreeSet<HiddenArgKind >, sep: &str) {
         let mut sep = sep;
-        if self.const_table.table.len()>0 {
-            write!(string, "{}", sep).unwrap();
-            sep = ", ";
-            write!(string, "const_table").unwrap();
-        }
-        
         for hidden_arg in hidden_args {
-            write!(string, "{}", sep).unwrap();
             match hidden_arg {
                 HiddenArgKind::Geometries => {
-                    write!(string, "geometries").unwrap();
+                    write!(string, "{}geometries", sep).unwrap();
                     sep = ", ";
                 }
                 HiddenArgKind::Instances => {
-                    write!(string, "instances").unwrap();
+                    write!(string, "{}instances", sep).unwrap();
                     sep = ", ";
                 }
                 HiddenArgKind::Varyings => {
-                    write!(string, "varyings").unwrap();
+                    write!(string, "{}varyings", sep).unwrap();
+                    sep = ", ";
+                }
+                HiddenArgKind::Builtins => {
+                    write!(string, "{}builtins", sep).unwrap();
                     sep = ", ";
                 }
                 _ => ()
@@ -633,20 +845,22 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
     
     fn write_fn_def_hidden_params(&self, string: &mut String, hidden_args: &BTreeSet<HiddenArgKind >, sep: &str) {
         let mut sep = sep;
-        
         for hidden_arg in hidden_args {
-            write!(string, "{}", sep).unwrap();
             match hidden_arg {
                 HiddenArgKind::Geometries => {
-                    write!(string, "in Geometries geometries").unwrap();
+                    write!(string, "{}in Geometries geometries", sep).unwrap();
                     sep = ", ";
                 }
                 HiddenArgKind::Instances => {
-                    write!(string, "in Instances instances").unwrap();
+                    write!(string, "{}in Instances instances", sep).unwrap();
                     sep = ", ";
                 }
                 HiddenArgKind::Varyings => {
-                    write!(string, "inout Varyings varyings").unwrap();
+                    write!(string, "{}inout Varyings varyings", sep).unwrap();
+                    sep = ", ";
+                }
+                HiddenArgKind::Builtins => {
+                    write!(string, "{}inout Builtins builtins", sep).unwrap();
                     sep = ", ";
                 }
                 _ => ()
@@ -657,6 +871,14 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
     fn generate_live_value_prefix(&self, _string: &mut String) {
     }
     
+    fn write_const_array_access_start(&self, string: &mut String, const_ptr: ConstPtr) {
+        write!(string, "{}(", const_ptr).unwrap();
+    }
+    
+    fn write_stage_builtin(&self, string: &mut String, stage_builtin: StageBuiltin) {
+        write!(string, "builtins.{}", stage_builtin.name()).unwrap();
+    }
+    
     fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, ty: &Ty) {
         let field_def = self.draw_shader_def.find_field(field_ident).unwrap();
         
@@ -749,13 +971,21 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
                 TyLit::Ivec2 => "int2",
                 TyLit::Ivec3 => "int3",
                 TyLit::Ivec4 => "int4",
+                TyLit::Uint => "uint",
+                TyLit::Uvec2 => "uint2",
+                TyLit::Uvec3 => "uint3",
+                TyLit::Uvec4 => "uint4",
                 TyLit::Vec2 => "float2",
                 TyLit::Vec3 => "float3",
                 TyLit::Vec4 => "float4",
                 TyLit::Mat2 => "float2x2",
                 TyLit::Mat3 => "float3x3",
                 TyLit::Mat4 => "float4x4",
-                TyLit::Texture2D => panic!(), // TODO
+                TyLit::Texture2D => "Texture2D",
+                TyLit::TextureCube => "TextureCube",
+                TyLit::Texture3D => "Texture3D",
+                TyLit::Texture2DArray => "Texture2DArray",
+                TyLit::Texture2DDepth => "Texture2D<float>",
             }
         )
             .unwrap();
@@ -792,4 +1022,51 @@ impl<'a> BackendWriter for HlslBackendWriter<'a> {
             }
         }
     }
+    
+    fn write_texture_call(&self, string: &mut String, call: &TextureCall) {
+        let rest = call.rest();
+        if call.op == TextureOp::Size {
+            write!(string, "texture_size_{}({}, {})", texture_size_suffix(&call.ty), call.texture, rest[0]).unwrap();
+            return
+        }
+        let coord = if call.ty == Ty::Texture2DArray && call.op != TextureOp::Fetch {
+            format!("float3({}, {})", call.args[0], call.args[1])
+        }
+        else {
+            call.args[0].clone()
+        };
+        match call.op {
+            TextureOp::Size => unreachable!(),
+            // the lod is the last coordinate
+            TextureOp::Fetch => {
+                let coord_ty = if call.ty == Ty::Texture2D {"int3"} else {"int4"};
+                write!(string, "{}.Load({}({}, {}))", call.texture, coord_ty, coord, rest[0]).unwrap();
+                return
+            }
+            TextureOp::Sample | TextureOp::Offset => write!(string, "{}.Sample(", call.texture).unwrap(),
+            TextureOp::Lod => write!(string, "{}.SampleLevel(", call.texture).unwrap(),
+            TextureOp::Grad => write!(string, "{}.SampleGrad(", call.texture).unwrap(),
+            TextureOp::Compare | TextureOp::CompareOffset => write!(string, "{}.SampleCmp(", call.texture).unwrap(),
+        }
+        write!(string, "{}, {}", call.sampler, coord).unwrap();
+        for arg in rest {
+            write!(string, ", {}", arg).unwrap();
+        }
+        write!(string, ")").unwrap();
+    }
+}
+
+// depth textures are sampled with a comparison sampler
+fn sampler_ty(ty: &Ty) -> &'static str {
+    if *ty == Ty::Texture2DDepth {"SamplerComparisonState"} else {"SamplerState"}
+}
+
+fn texture_size_suffix(ty: &Ty) -> &'static str {
+    match ty {
+        Ty::TextureCube => "cube",
+        Ty::Texture3D => "3d",
+        Ty::Texture2DArray => "2d_array",
+        Ty::Texture2DDepth => "2d_depth",
+        _ => "2d",
+    }
 }
@@ -50,16 +50,13 @@ impl<'a> DrawShaderGenerator<'a> {
         writeln!(self.string, "#include <metal_stdlib>").unwrap();
         writeln!(self.string, "using namespace metal;").unwrap();
         
-        for fn_iter in self.draw_shader_def.all_fns.borrow().iter() {
-            let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
-            if fn_def.builtin_deps.borrow().as_ref().unwrap().contains(&Ident(id!(sample2d))) {
-                writeln!(self.string, "float4 sample2d(texture2d<float> tex, float2 pos){{return tex.sample(sampler(mag_filter::nearest,min_filter::nearest),pos);}}").unwrap();
-            }
-            if fn_def.builtin_deps.borrow().as_ref().unwrap().contains(&Ident(id!(sample2d_rt))) {
-                writeln!(self.string, "float4 sample2d_rt(texture2d<float> tex, float2 pos){{return tex.sample(sampler(mag_filter::nearest,min_filter::nearest),pos);}}").unwrap();
-                break;
-            }
-        };
+        self.generate_samplers();
+        if self.shader_registry.uses_builtin(&self.draw_shader_def.all_fns.borrow(), &[Ident(id!(sample2d))]) {
+            writeln!(self.string, "float4 sample2d(texture2d<float> tex, sampler smp, float2 pos){{return tex.sample(smp,pos);}}").unwrap();
+        }
+        if self.shader_registry.uses_builtin(&self.draw_shader_def.all_fns.borrow(), &[Ident(id!(sample2d_rt))]) {
+            writeln!(self.string, "float4 sample2d_rt(texture2d<float> tex, sampler smp, float2 pos){{return tex.sample(smp,pos);}}").unwrap();
+        }
         
         self.generate_struct_defs();
         //let fields_as_uniform_blocks = self.draw_shader_def.fields_as_uniform_blocks();
@@ -68,6 +65,7 @@ impl<'a> DrawShaderGenerator<'a> {
         self.generate_geometry_struct();
         self.generate_instance_struct();
         self.generate_varying_struct();
+        self.generate_builtins_struct();
         
         let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(vertex))).unwrap();
         let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
@@ -81,6 +79,7 @@ impl<'a> DrawShaderGenerator<'a> {
         {
             generate_cons_fn(self.backend_writer, self.string, ty_lit, &param_tys);
         }
+        self.generate_const_array_fns();
         
         let all_fns = self.draw_shader_def.all_fns.borrow();
         for fn_iter in all_fns.iter().rev() {
@@ -116,6 +115,38 @@ impl<'a> DrawShaderGenerator<'a> {
         self.generate_pixel_main();
     }
     
+   fn generate_samplers(&mut self) {
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {sampler, ..} = &field.kind {
+                let filter = match sampler.filter {
+                    SamplerFilter::Nearest => "nearest",
+                    SamplerFilter::Linear => "linear",
+                };
+                let address = match sampler.wrap {
+                    SamplerWrap::Repeat => "repeat",
+                    SamplerWrap::Clamp => "clamp_to_edge",
+                    SamplerWrap::Mirror => "mirrored_repeat",
+                };
+                let mip_filter = match sampler.mip {
+                    SamplerMip::None => "none",
+                    SamplerMip::Nearest => "nearest",
+                    SamplerMip::Linear => "linear",
+                };
+                // depth textures pass where the reference is at most the stored depth
+                let compare_func = if *field.ty_expr.ty.borrow() == Some(Ty::Texture2DDepth) {", compare_func::less_equal"} else {""};
+                writeln!(
+                    self.string,
+                    "constexpr sampler {}_sampler(mag_filter::{1}, min_filter::{1}, address::{2}, mip_filter::{3}{4});",
+                    DisplayDsIdent(field.ident),
+                    filter,
+                    address,
+                    mip_filter,
+                    compare_func
+                ).unwrap();
+            }
+        }
+    }
+    
     fn generate_struct_defs(&mut self) {
         // we have all the structs already from analyse
         for struct_ptr in self.draw_shader_def.all_structs.borrow().iter().rev() {
@@ -133,6 +164,28 @@ impl<'a> DrawShaderGenerator<'a> {
         }
     }
     
+    // an item of a `const` array takes 4 floats of `const_table`
+    fn generate_const_array_fns(&mut self) {
+        for (const_ptr, elem_ty, offset) in const_arrays(self.draw_shader_def, self.const_table, self.shader_registry) {
+            self.write_var_decl(&const_ptr, &elem_ty);
+            writeln!(self.string, "(constant const float *const_table, int index) {{").unwrap();
+            writeln!(self.string, "    int slot = {} + index * 4;", offset).unwrap();
+            let components: Vec<String> = (0..elem_ty.slots()).map( | i | match i {
+                0 => String::from("const_table[slot]"),
+                _ => format!("const_table[slot + {}]", i)
+            }).collect();
+            if components.len() == 1 {
+                writeln!(self.string, "    return {};", components[0]).unwrap();
+            }
+            else {
+                write!(self.string, "    return ").unwrap();
+                self.write_ty_lit(elem_ty.maybe_ty_lit().unwrap());
+                writeln!(self.string, "({});", components.join(", ")).unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+    
     fn generate_uniform_structs(&mut self,) {
         writeln!(self.string, "struct LiveUniforms {{").unwrap();
         for (value_node_ptr, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
@@ -151,7 +204,7 @@ impl<'a> DrawShaderGenerator<'a> {
             for (index, _item) in vec {
                 let field = &self.draw_shader_def.fields[*index];
                 write!(self.string, "    ").unwrap();
-                self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
+                self.write_var_decl(&DisplayDsIdent(field.ident), &padded_uniform_ty(field.ty_expr.ty.borrow().as_ref().unwrap()));
                 writeln!(self.string, ";").unwrap();
             }
             writeln!(self.string, "}};").unwrap();
@@ -164,10 +217,11 @@ impl<'a> DrawShaderGenerator<'a> {
         for field in &self.draw_shader_def.fields {
             match field.kind {
                 DrawShaderFieldKind::Texture {..} => {
-                    assert_eq!(*field.ty_expr.ty.borrow().as_ref().unwrap(), Ty::Texture2D);
-                    write!(self.string, "    texture2d<float> ").unwrap();
+                    write!(self.string, "    ").unwrap();
+                    self.backend_writer.write_ty_lit(self.string, field.ty_expr.ty.borrow().as_ref().unwrap().maybe_ty_lit().unwrap());
+                    write!(self.string, " ").unwrap();
                     write!(self.string, "{}", &DisplayDsIdent(field.ident)).unwrap();
-                    write!(self.string, " [[texture({})]];", index).unwrap();
+                    writeln!(self.string, " [[texture({})]];", index).unwrap();
                     index += 1;
                 }
                 _ => {}
@@ -257,6 +311,9 @@ impl<'a> DrawShaderGenerator<'a> {
     fn generate_varying_struct(&mut self) {
         writeln!(self.string, "struct Varyings {{").unwrap();
         writeln!(self.string, "    float4 position [[position]];").unwrap();
+        if self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow()).contains(&StageBuiltin::PointSize) {
+            writeln!(self.string, "    float point_size [[point_size]];").unwrap();
+        }
         for field in &self.draw_shader_def.fields {
             match &field.kind {
                 DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
@@ -345,6 +402,8 @@ impl<'a> DrawShaderGenerator<'a> {
             "    Instances instances = in_instances[inst_id];"
         ).unwrap();
         writeln!(self.string, "    Varyings varyings;").unwrap();
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow());
+        self.generate_builtins_init(&stage_builtins);
         
         for decl in &self.draw_shader_def.fields {
             match &decl.kind {
@@ -379,14 +438,23 @@ impl<'a> DrawShaderGenerator<'a> {
         self.backend_writer.write_call_expr_hidden_args(self.string, vertex_def.hidden_args.borrow().as_ref().unwrap(), "");
         
         writeln!(self.string, ");").unwrap();
+        if stage_builtins.contains(&StageBuiltin::PointSize) {
+            writeln!(self.string, "    varyings.point_size = builtins.point_size;").unwrap();
+        }
         
         writeln!(self.string, "    return varyings;").unwrap();
         writeln!(self.string, "}}").unwrap();
     }
     
     fn generate_pixel_main(&mut self) {
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.pixel_fns.borrow());
+        let writes_depth = stage_builtins.contains(&StageBuiltin::FragDepth);
+        let has_output_struct = writes_depth || self.shader_registry.pixel_outputs(self.draw_shader_def).is_some();
+        if has_output_struct {
+            self.generate_pixel_output_struct(writes_depth);
+        }
         
-        write!(self.string, "fragment float4 fragment_main(").unwrap();
+        write!(self.string, "fragment {} fragment_main(", if has_output_struct {"PixelOutput"} else {"float4"}).unwrap();
         writeln!(self.string, "Varyings varyings[[stage_in]]").unwrap();
         writeln!(self.string, ", Textures textures").unwrap();
         writeln!(self.string, ", constant LiveUniforms &live_uniforms [[buffer(2)]]").unwrap();
@@ -396,22 +464,94 @@ impl<'a> DrawShaderGenerator<'a> {
             writeln!(self.string, ", constant Uniforms_{0} &uniforms_{0} [[buffer({1})]]", field, buffer_id).unwrap();
             buffer_id += 1;
         }
+        if stage_builtins.contains(&StageBuiltin::FrontFacing) {
+            writeln!(self.string, ", bool front_facing [[front_facing]]").unwrap();
+        }
+        if stage_builtins.contains(&StageBuiltin::PointCoord) {
+            writeln!(self.string, ", float2 point_coord [[point_coord]]").unwrap();
+        }
         
         writeln!(self.string, ") {{").unwrap();
-        
-        write!(self.string, "    return ").unwrap();
+        self.generate_builtins_init(&stage_builtins);
         
         let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
-        write!(self.string, "    {}", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        if has_output_struct {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(&"pixel_output", pixel_def.return_ty.borrow().as_ref().unwrap());
+            write!(self.string, " = {}", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        }
+        else {
+            write!(self.string, "    return ").unwrap();
+            write!(self.string, "    {}", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        }
         
         write!(self.string, "(").unwrap();
         self.backend_writer.write_call_expr_hidden_args(self.string, pixel_def.hidden_args.borrow().as_ref().unwrap(), "");
         
         writeln!(self.string, ");").unwrap();
         
+        if has_output_struct {
+            writeln!(self.string, "    PixelOutput output;").unwrap();
+            for (output, _, value) in pixel_targets(self.shader_registry, self.draw_shader_def) {
+                writeln!(self.string, "    output.{} = {};", output, value).unwrap();
+            }
+            if writes_depth {
+                writeln!(self.string, "    output.depth = builtins.frag_depth;").unwrap();
+            }
+            writeln!(self.string, "    return output;").unwrap();
+        }
+        
         writeln!(self.string, "}}").unwrap();
     }
     
+    // a color attachment per field of the struct `pixel` returns, or `frag_color`
+    fn generate_pixel_output_struct(&mut self, writes_depth: bool) {
+        writeln!(self.string, "struct PixelOutput {{").unwrap();
+        for (index, (output, ty, _)) in pixel_targets(self.shader_registry, self.draw_shader_def).iter().enumerate() {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(output, ty);
+            writeln!(self.string, " [[color({})]];", index).unwrap();
+        }
+        if writes_depth {
+            writeln!(self.string, "    float depth [[depth(any)]];").unwrap();
+        }
+        writeln!(self.string, "}};").unwrap();
+    }
+    
+    // the stage builtins used anywhere, passed to the fns that use them
+    fn generate_builtins_struct(&mut self) {
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.all_fns.borrow());
+        if stage_builtins.is_empty() {
+            return
+        }
+        writeln!(self.string, "struct Builtins {{").unwrap();
+        for stage_builtin in stage_builtins {
+            write!(self.string, "    ").unwrap();
+            self.write_var_decl(&stage_builtin.name(), &stage_builtin.ty());
+            writeln!(self.string, ";").unwrap();
+        }
+        writeln!(self.string, "}};").unwrap();
+    }
+    
+    fn generate_builtins_init(&mut self, stage_builtins: &BTreeSet<StageBuiltin>) {
+        if stage_builtins.is_empty() {
+            return
+        }
+        writeln!(self.string, "    Builtins builtins;").unwrap();
+        for stage_builtin in stage_builtins {
+            let value = match stage_builtin {
+                StageBuiltin::VertexId => "int(vtx_id)",
+                StageBuiltin::InstanceId => "int(inst_id)",
+                StageBuiltin::PointSize => "1.0",
+                StageBuiltin::FragCoord => "varyings.position",
+                StageBuiltin::FrontFacing => "front_facing",
+                StageBuiltin::PointCoord => "point_coord",
+                StageBuiltin::FragDepth => "varyings.position.z",
+            };
+            writeln!(self.string, "    builtins.{} = {};", stage_builtin.name(), value).unwrap();
+        }
+    }
+    
     fn generate_expr(&mut self, expr: &Expr) {
         ExprGenerator {
             fn_def: None,
@@ -458,15 +598,51 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
     fn needs_unpack_for_matrix_multiplication(&self) -> bool {
         true
     }
+    
+    fn needs_texture_samplers(&self) -> bool {
+        true
+    }
 
     fn enum_is_float(&self)->bool{
-        false
+        true
     }
     
     fn const_table_is_vec4(&self) -> bool {
         false
     }
     
+    fn needs_select_for_cond_expr(&self) -> bool {
+        false
+    }
+    
+    fn needs_splat_for_builtin_args(&self) -> bool {
+        false
+    }
+    
+    fn needs_pointer_for_inout(&self) -> bool {
+        false
+    }
+    
+    fn needs_unsigned_shift_amount(&self) -> bool {
+        false
+    }
+    
+    fn max_loop_iterations(&self) -> Option<u32> {
+        None
+    }
+    
+    fn needs_padded_uniform_arrays(&self) -> bool {
+        true
+    }
+    
+    fn needs_int_emulation(&self) -> bool {
+        false
+    }
+    
+    fn use_int_op_polyfill(&self, op: BinOp, _left_ty: &Ty, _right_ty: &Ty) -> String {
+        panic!("no polyfill for `{}`", op)
+    }
+    
     
     fn use_cons_fn(&self, what: &str) -> bool {
         match what {
@@ -553,6 +729,17 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Ivec4);
                 write!(string, " {}{}", ref_prefix, ident).unwrap();
             }
+            Ty::Uint => {
+                prefix(string, sep, is_inout);
+                self.write_ty_lit(string, TyLit::Uint);
+                write!(string, " {}{}", ref_prefix, ident).unwrap();
+            }
+            Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => {
+                prefix(string, sep, is_inout);
+                write!(string, "{}", packed_prefix).unwrap();
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+                write!(string, " {}{}", ref_prefix, ident).unwrap();
+            }
             Ty::Vec2 => {
                 prefix(string, sep, is_inout);
                 write!(string, "{}", packed_prefix).unwrap();
@@ -588,7 +775,12 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
                 self.write_ty_lit(string, TyLit::Mat4);
                 write!(string, " {}{}", ref_prefix, ident).unwrap();
             }
-            Ty::Texture2D => panic!(), // TODO
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => {
+                // the sampler travels along with the texture, see `needs_texture_samplers`
+                write!(string, "{}", sep).unwrap();
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+                write!(string, " {0}, sampler {0}_sampler", ident).unwrap();
+            }
             Ty::Array {ref elem_ty, len} => {
                 self.write_var_decl(string, sep, is_inout, is_packed, ident, elem_ty);
                 write!(string, "[{}]", len).unwrap();
@@ -599,7 +791,7 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
             }
             Ty::Enum(_) => {
                 prefix(string, sep, is_inout);
-                write!(string, "uint32_t {} {}", ref_prefix, ident).unwrap();
+                write!(string, "float {} {}", ref_prefix, ident).unwrap();
             }
             Ty::DrawShader => {
                 return false
@@ -614,6 +806,38 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
         true
     }
     
+    fn write_fn_def_start(&self, string: &mut String, ident: &dyn fmt::Display, return_ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, return_ty);
+        write!(string, "(").unwrap();
+    }
+    
+    fn write_fn_def_end(&self, string: &mut String, _return_ty: &Ty) {
+        write!(string, ")").unwrap();
+    }
+    
+    fn write_fn_block_prologue(&self, _string: &mut String) {
+    }
+    
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, ty);
+    }
+    
+    fn write_discard_stmt(&self, string: &mut String) {
+        write!(string, "discard_fragment();").unwrap();
+    }
+    
+    fn write_var_expr(&self, string: &mut String, ident: &dyn fmt::Display) {
+        write!(string, "{}", ident).unwrap();
+    }
+    
+    fn fn_ident_suffix(&self, _fn_ptr: FnPtr) -> &'static str {
+        ""
+    }
+
+    fn write_call_ident_with_inout_args(&self, string: &mut String, fn_def: &FnDef, _inout_args: &[InoutArg]) {
+        write!(string, "{}_{}", fn_def.fn_ptr, fn_def.ident).unwrap();
+    }
+
     fn write_call_expr_hidden_args(&self, string: &mut String, hidden_args: &BTreeSet<HiddenArgKind >, sep: &str) {
         let mut sep = sep;
         if self.const_table.table.len()>0 {
@@ -643,6 +867,9 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
                 HiddenArgKind::LiveUniforms => {
                     write!(string, "live_uniforms").unwrap();
                 }
+                HiddenArgKind::Builtins => {
+                    write!(string, "builtins").unwrap();
+                }
             }
             sep = ", ";
         }
@@ -677,15 +904,26 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
                 HiddenArgKind::LiveUniforms => {
                     write!(string, "constant LiveUniforms &live_uniforms").unwrap();
                 }
+                HiddenArgKind::Builtins => {
+                    write!(string, "thread Builtins &builtins").unwrap();
+                }
             }
             sep = ", ";
         }
     }
     
+    fn write_stage_builtin(&self, string: &mut String, stage_builtin: StageBuiltin) {
+        write!(string, "builtins.{}", stage_builtin.name()).unwrap();
+    }
+    
     fn generate_live_value_prefix(&self, string: &mut String) {
         write!(string, "live_uniforms.").unwrap();
     }
     
+    fn write_const_array_access_start(&self, string: &mut String, const_ptr: ConstPtr) {
+        write!(string, "{}(const_table, ", const_ptr).unwrap();
+    }
+    
     fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, ty: &Ty) {
         let field_def = self.draw_shader_def.find_field(field_ident).unwrap();
         
@@ -782,13 +1020,21 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
                 TyLit::Ivec2 => "int2",
                 TyLit::Ivec3 => "int3",
                 TyLit::Ivec4 => "int4",
+                TyLit::Uint => "uint",
+                TyLit::Uvec2 => "uint2",
+                TyLit::Uvec3 => "uint3",
+                TyLit::Uvec4 => "uint4",
                 TyLit::Vec2 => "float2",
                 TyLit::Vec3 => "float3",
                 TyLit::Vec4 => "float4",
                 TyLit::Mat2 => "float2x2",
                 TyLit::Mat3 => "float3x3",
                 TyLit::Mat4 => "float4x4",
-                TyLit::Texture2D => panic!(), // TODO
+                TyLit::Texture2D => "texture2d<float>",
+                TyLit::TextureCube => "texturecube<float>",
+                TyLit::Texture3D => "texture3d<float>",
+                TyLit::Texture2DArray => "texture2d_array<float>",
+                TyLit::Texture2DDepth => "depth2d<float>",
             }
         )
             .unwrap();
@@ -818,4 +1064,50 @@ impl<'a> BackendWriter for MetalBackendWriter<'a> {
             }
         }
     }
+    
+    fn write_texture_call(&self, string: &mut String, call: &TextureCall) {
+        let tex = &call.texture;
+        let args = &call.args;
+        match call.op {
+            TextureOp::Size => {
+                let lod = &args[0];
+                match call.ty {
+                    Ty::Texture3D => write!(string, "int3({0}.get_width({1}), {0}.get_height({1}), {0}.get_depth({1}))", tex, lod).unwrap(),
+                    Ty::Texture2DArray => write!(string, "int3({0}.get_width({1}), {0}.get_height({1}), {0}.get_array_size())", tex, lod).unwrap(),
+                    _ => write!(string, "int2({0}.get_width({1}), {0}.get_height({1}))", tex, lod).unwrap(),
+                }
+                return
+            }
+            TextureOp::Fetch => {
+                match call.ty {
+                    Ty::Texture3D => write!(string, "{}.read(uint3({}), uint({}))", tex, args[0], args[1]).unwrap(),
+                    Ty::Texture2DArray => write!(string, "{0}.read(uint2(({1}).xy), uint(({1}).z), uint({2}))", tex, args[0], args[1]).unwrap(),
+                    _ => write!(string, "{}.read(uint2({}), uint({}))", tex, args[0], args[1]).unwrap(),
+                }
+                return
+            }
+            TextureOp::Compare | TextureOp::CompareOffset => write!(string, "{}.sample_compare(", tex).unwrap(),
+            _ => write!(string, "{}.sample(", tex).unwrap(),
+        }
+        write!(string, "{}, {}", call.sampler, args[0]).unwrap();
+        if call.ty == Ty::Texture2DArray {
+            write!(string, ", uint({})", args[1]).unwrap();
+        }
+        let rest = call.rest();
+        match call.op {
+            TextureOp::Lod => write!(string, ", level({})", rest[0]).unwrap(),
+            TextureOp::Grad => {
+                let gradient = match call.ty {
+                    Ty::TextureCube => "gradientcube",
+                    Ty::Texture3D => "gradient3d",
+                    _ => "gradient2d",
+                };
+                write!(string, ", {}({}, {})", gradient, rest[0], rest[1]).unwrap();
+            }
+            _ => for arg in rest {
+                write!(string, ", {}", arg).unwrap();
+            }
+        }
+        write!(string, ")").unwrap();
+    }
 }
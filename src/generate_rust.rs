@@ -0,0 +1,1138 @@
+use {
+    std::fmt::Write,
+    crate::{
+        makepad_live_compiler::{LiveError, LiveErrorOrigin, LiveEval, live_error_origin, TokenSpan},
+        makepad_live_id::{id, LiveId},
+        generate::{DisplayFnName, DisplayStructField, DisplayVarName},
+        shader_ast::*,
+        shader::Shader,
+        swizzle::Swizzle,
+    }
+};
+
+/// Lowers the draw shader to a Rust module on top of `makepad_math`, with a `vertex` and a `pixel` fn.
+///
+/// The fields come in through the `Geometry`, `Instance`, `Uniforms` and `Varyings` structs of the module,
+/// textures through a `Textures` struct of sampling closures. Everything is a copy, inout args are copied
+/// back after the call and closures are Rust closures. Only the types `makepad_math` has are supported,
+/// that is `bool`, `int`, `uint`, `float`, `vec2`, `vec3`, `vec4`, `mat2`, `mat3` and `mat4`, and arrays of them.
+pub fn generate_shader(draw_shader_def: &DrawShaderDef, shader_registry: &Shader) -> Result<String, LiveError> {
+    let mut generator = RustGenerator {
+        draw_shader_def,
+        shader_registry,
+        string: String::new(),
+        indent: 0,
+        fn_def: None,
+        inout_params: Vec::new(),
+        next_temp: 0,
+    };
+    generator.generate_module() ?;
+    Ok(generator.string)
+}
+
+// a value that can be assigned to, `components` are the picked components of a vector and `binds`
+// the lets of the indices in `base`, which have to come first
+struct Place {
+    base: String,
+    components: Option<Vec<usize>>,
+    binds: Vec<String>,
+}
+
+struct RustGenerator<'a> {
+    draw_shader_def: &'a DrawShaderDef,
+    shader_registry: &'a Shader,
+    string: String,
+    indent: usize,
+    fn_def: Option<&'a FnDef>,
+    // returned along with the return value of the current fn or closure
+    inout_params: Vec<String>,
+    next_temp: usize,
+}
+
+impl<'a> RustGenerator<'a> {
+    fn generate_module(&mut self) -> Result<(), LiveError> {
+        writeln!(self.string, "// Generated by nanoshredder, do not edit.").unwrap();
+        writeln!(self.string, "#![allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals)]").unwrap();
+        writeln!(self.string, "#![allow(unreachable_code, unused_labels, unused_mut, unused_parens, unused_variables)]").unwrap();
+        writeln!(self.string).unwrap();
+        writeln!(self.string, "use makepad_math::*;").unwrap();
+        self.generate_io_structs() ?;
+        self.generate_live_values() ?;
+        self.generate_const_arrays() ?;
+        self.generate_structs() ?;
+        self.generate_fns() ?;
+        self.generate_entry_points();
+        Ok(())
+    }
+
+    fn has_textures(&self) -> bool {
+        self.draw_shader_def.fields.iter().any( | field | matches!(field.kind, DrawShaderFieldKind::Texture {..}))
+    }
+
+    fn generate_io_structs(&mut self) -> Result<(), LiveError> {
+        let kinds: [(&str, fn(&DrawShaderFieldKind) -> bool); 4] = [
+            ("Geometry", | kind | matches!(kind, DrawShaderFieldKind::Geometry {..})),
+            ("Instance", | kind | matches!(kind, DrawShaderFieldKind::Instance {..})),
+            ("Uniforms", | kind | matches!(kind, DrawShaderFieldKind::Uniform {..})),
+            ("Varyings", is_varying),
+        ];
+        for (name, filter) in kinds.iter() {
+            writeln!(self.string).unwrap();
+            writeln!(self.string, "#[derive(Clone, Copy, Debug, Default, PartialEq)]").unwrap();
+            writeln!(self.string, "pub struct {} {{", name).unwrap();
+            for field in &self.draw_shader_def.fields {
+                if filter(&field.kind) {
+                    let ty = self.ty(field.span, field.ty_expr.ty.borrow().as_ref().unwrap()) ?;
+                    writeln!(self.string, "    pub {}: {},", field.ident, ty).unwrap();
+                }
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+        if let Some(pixel_outputs) = self.shader_registry.pixel_outputs(self.draw_shader_def) {
+            writeln!(self.string).unwrap();
+            writeln!(self.string, "// the render targets `pixel` writes").unwrap();
+            writeln!(self.string, "#[derive(Clone, Copy, Debug, Default, PartialEq)]").unwrap();
+            writeln!(self.string, "pub struct PixelOutput {{").unwrap();
+            for (ident, ty) in &pixel_outputs {
+                writeln!(self.string, "    pub {}: {},", ident, self.ty(TokenSpan::default(), ty) ?).unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+        if self.has_textures() {
+            writeln!(self.string).unwrap();
+            writeln!(self.string, "// samples a texture at a uv coordinate").unwrap();
+            writeln!(self.string, "pub struct Textures<'a> {{").unwrap();
+            for field in &self.draw_shader_def.fields {
+                if let DrawShaderFieldKind::Texture {..} = field.kind {
+                    writeln!(self.string, "    pub {}: &'a dyn Fn(Vec2) -> Vec4,", field.ident).unwrap();
+                }
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+
+        // all the fields, for the draw shader methods to read from and write to
+        writeln!(self.string).unwrap();
+        if self.has_textures() {
+            writeln!(self.string, "struct DrawShader<'a> {{").unwrap();
+        }
+        else {
+            writeln!(self.string, "struct DrawShader {{").unwrap();
+        }
+        for field in &self.draw_shader_def.fields {
+            let ty = match field.kind {
+                DrawShaderFieldKind::Texture {..} => String::from("&'a dyn Fn(Vec2) -> Vec4"),
+                _ => self.ty(field.span, field.ty_expr.ty.borrow().as_ref().unwrap()) ?,
+            };
+            writeln!(self.string, "    {}: {},", field.ident, ty).unwrap();
+        }
+        writeln!(self.string, "}}").unwrap();
+        Ok(())
+    }
+
+    fn generate_live_values(&mut self) -> Result<(), LiveError> {
+        let live_refs = self.draw_shader_def.all_live_refs.borrow().clone();
+        if !live_refs.is_empty() {
+            writeln!(self.string).unwrap();
+        }
+        for (value_ptr, ty) in live_refs {
+            let span = TokenSpan::default();
+            let value = match self.shader_registry.live_value_eval(value_ptr) ? {
+                LiveEval::Bool(value) => Val::Bool(value),
+                LiveEval::Int(value) if ty == Ty::Int => Val::Int(value as i32),
+                LiveEval::Int(value) => Val::Float(value as f32),
+                LiveEval::Float(value) if ty == Ty::Int => Val::Int(value as i32),
+                LiveEval::Float(value) => Val::Float(value as f32),
+                LiveEval::Vec2(value) => {
+                    writeln!(self.string, "const {}: Vec2 = {};", value_ptr, vector_lit(&[value.x, value.y])).unwrap();
+                    continue;
+                }
+                LiveEval::Vec3(value) => {
+                    writeln!(self.string, "const {}: Vec3 = {};", value_ptr, vector_lit(&[value.x, value.y, value.z])).unwrap();
+                    continue;
+                }
+                LiveEval::Vec4(value) => Val::Vec4(value),
+                LiveEval::String(_) => return Err(error(span, String::from("can't use a string in a shader"))),
+            };
+            let rust_ty = self.ty(span, &ty) ?;
+            let value = match value {
+                Val::Vec4(value) => vector_lit(&[value.x, value.y, value.z, value.w]),
+                value => self.val(&value, &ty),
+            };
+            writeln!(self.string, "const {}: {} = {};", value_ptr, rust_ty, value).unwrap();
+        }
+        Ok(())
+    }
+
+    fn generate_const_arrays(&mut self) -> Result<(), LiveError> {
+        let const_refs = self.draw_shader_def.all_const_refs.borrow().clone();
+        if !const_refs.is_empty() {
+            writeln!(self.string).unwrap();
+        }
+        for (const_ptr, ty) in const_refs {
+            let rust_ty = self.ty(TokenSpan::default(), &ty) ?;
+            let (_, items) = self.shader_registry.const_array(const_ptr);
+            let items: Vec<String> = items.iter().map( | item | {
+                if item.len() == 1 {f32_lit(item[0])} else {vector_lit(item)}
+            }).collect();
+            writeln!(self.string, "const {}: {} = [{}];", const_ptr, rust_ty, items.join(", ")).unwrap();
+        }
+        Ok(())
+    }
+
+    fn generate_structs(&mut self) -> Result<(), LiveError> {
+        for struct_ptr in self.draw_shader_def.all_structs.borrow().iter() {
+            let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+            writeln!(self.string).unwrap();
+            writeln!(self.string, "#[derive(Clone, Copy, Debug, Default, PartialEq)]").unwrap();
+            writeln!(self.string, "struct {} {{", struct_ptr).unwrap();
+            for field in &struct_def.fields {
+                let ty = self.ty(field.ty_expr.span, field.ty_expr.ty.borrow().as_ref().unwrap()) ?;
+                writeln!(self.string, "    {}: {},", DisplayStructField(field.ident), ty).unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+        Ok(())
+    }
+
+    fn generate_entry_points(&mut self) {
+        let textures_param = if self.has_textures() {", textures: &Textures"} else {""};
+        writeln!(self.string).unwrap();
+        writeln!(
+            self.string,
+            "pub fn vertex(geometry: &Geometry, instance: &Instance, uniforms: &Uniforms{}) -> (Vec4, Varyings) {{",
+            textures_param
+        ).unwrap();
+        self.generate_draw_shader_init( | kind | match kind {
+            DrawShaderFieldKind::Geometry {..} => Some("geometry"),
+            DrawShaderFieldKind::Instance {..} => Some("instance"),
+            DrawShaderFieldKind::Uniform {..} => Some("uniforms"),
+            DrawShaderFieldKind::Texture {..} => Some("textures"),
+            DrawShaderFieldKind::Varying {..} => None,
+        });
+        let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(vertex))).unwrap();
+        writeln!(self.string, "    let position = {}(&mut ds);", DisplayFnName(vertex_def.fn_ptr, vertex_def.ident)).unwrap();
+        write!(self.string, "    (position, Varyings {{").unwrap();
+        let mut sep = "";
+        for field in &self.draw_shader_def.fields {
+            if is_varying(&field.kind) {
+                write!(self.string, "{}{}: ds.{}", sep, field.ident, field.ident).unwrap();
+                sep = ", ";
+            }
+        }
+        writeln!(self.string, "}})").unwrap();
+        writeln!(self.string, "}}").unwrap();
+
+        let pixel_outputs = self.shader_registry.pixel_outputs(self.draw_shader_def);
+        writeln!(self.string).unwrap();
+        writeln!(
+            self.string,
+            "pub fn pixel(varyings: &Varyings, uniforms: &Uniforms{}) -> {} {{",
+            textures_param,
+            if pixel_outputs.is_some() {"PixelOutput"} else {"Vec4"}
+        ).unwrap();
+        self.generate_draw_shader_init( | kind | match kind {
+            DrawShaderFieldKind::Uniform {..} => Some("uniforms"),
+            DrawShaderFieldKind::Texture {..} => Some("textures"),
+            kind if is_varying(kind) => Some("varyings"),
+            _ => None,
+        });
+        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
+        if let Some(pixel_outputs) = pixel_outputs {
+            writeln!(self.string, "    let output = {}(&mut ds);", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+            let fields: Vec<String> = pixel_outputs.iter().map( | (ident, _) | format!("{}: output.{}", ident, DisplayStructField(*ident))).collect();
+            writeln!(self.string, "    PixelOutput {{{}}}", fields.join(", ")).unwrap();
+        }
+        else {
+            writeln!(self.string, "    {}(&mut ds)", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        }
+        writeln!(self.string, "}}").unwrap();
+    }
+
+    // fields without a source start out zeroed
+    fn generate_draw_shader_init(&mut self, source: fn(&DrawShaderFieldKind) -> Option<&'static str>) {
+        writeln!(self.string, "    let mut ds = DrawShader {{").unwrap();
+        for field in &self.draw_shader_def.fields {
+            match source(&field.kind) {
+                Some(source) => writeln!(self.string, "        {}: {}.{},", field.ident, source, field.ident).unwrap(),
+                None => writeln!(self.string, "        {}: Default::default(),", field.ident).unwrap(),
+            }
+        }
+        writeln!(self.string, "    }};").unwrap();
+    }
+
+    // types
+
+    fn ty(&self, span: TokenSpan, ty: &Ty) -> Result<String, LiveError> {
+        Ok(match ty {
+            Ty::Void => String::from("()"),
+            Ty::Bool => String::from("bool"),
+            Ty::Int | Ty::Enum(_) => String::from("i32"),
+            Ty::Uint => String::from("u32"),
+            Ty::Float => String::from("f32"),
+            Ty::Vec2 => String::from("Vec2"),
+            Ty::Vec3 => String::from("Vec3"),
+            Ty::Vec4 => String::from("Vec4"),
+            Ty::Mat2 => String::from("Mat2"),
+            Ty::Mat3 => String::from("Mat3"),
+            Ty::Mat4 => String::from("Mat4"),
+            Ty::Texture2D => String::from("&dyn Fn(Vec2) -> Vec4"),
+            Ty::Struct(struct_ptr) => struct_ptr.to_string(),
+            // the structs holding the fields derive `Default`, which arrays only have up to 32 items
+            Ty::Array {len, ..} if *len > 32 => {
+                return Err(error(span, String::from("the Rust backend only supports arrays of up to 32 items")))
+            }
+            Ty::Array {elem_ty, len} => format!("[{}; {}]", self.ty(span, elem_ty) ?, len),
+            _ => return Err(error(span, format!("the Rust backend doesn't support `{}`", ty))),
+        })
+    }
+
+    // `(R, inout..)` for a fn or closure with inout params
+    fn return_ty(&self, span: TokenSpan, return_ty: &Ty, params: &[(bool, Ty)]) -> Result<String, LiveError> {
+        let mut tys = vec![self.ty(span, return_ty) ?];
+        for (is_inout, ty) in params {
+            if *is_inout {
+                tys.push(self.ty(span, ty) ?);
+            }
+        }
+        Ok(if tys.len() == 1 {tys.pop().unwrap()} else {format!("({})", tys.join(", "))})
+    }
+
+    fn closure_ty(&self, span: TokenSpan, params: &[Param], return_ty: &Ty) -> Result<String, LiveError> {
+        let mut param_tys = Vec::new();
+        let mut inout_tys = Vec::new();
+        for param in params {
+            let ty = param.ty_expr.ty.borrow().clone().unwrap();
+            param_tys.push(self.ty(span, &ty) ?);
+            inout_tys.push((param.is_inout, ty));
+        }
+        param_tys.push(String::from("&mut DrawShader"));
+        Ok(format!("&dyn Fn({}) -> {}", param_tys.join(", "), self.return_ty(span, return_ty, &inout_tys) ?))
+    }
+
+    fn zero(&self, span: TokenSpan, ty: &Ty) -> Result<String, LiveError> {
+        Ok(match ty {
+            Ty::Bool => String::from("false"),
+            Ty::Int | Ty::Uint | Ty::Enum(_) => String::from("0"),
+            Ty::Float => String::from("0.0f32"),
+            Ty::Array {elem_ty, len} => format!("[{}; {}]", self.zero(span, elem_ty) ?, len),
+            _ => format!("{}::default()", self.ty(span, ty) ?),
+        })
+    }
+
+    fn val(&self, val: &Val, ty: &Ty) -> String {
+        match val {
+            Val::Bool(value) => value.to_string(),
+            Val::Int(value) if *ty == Ty::Float => f32_lit(*value as f32),
+            Val::Int(value) => value.to_string(),
+            Val::Uint(value) => format!("{}u32", value),
+            Val::Float(value) => f32_lit(*value),
+            Val::Vec4(value) => format!("vec4({}, {}, {}, {})", f32_lit(value.x), f32_lit(value.y), f32_lit(value.z), f32_lit(value.w)),
+        }
+    }
+
+    // functions
+
+    fn generate_fns(&mut self) -> Result<(), LiveError> {
+        let all_fns = self.draw_shader_def.all_fns.borrow().clone();
+        for fn_ptr in all_fns.iter().rev() {
+            let fn_def = self.shader_registry.all_fns.get(fn_ptr).unwrap();
+            self.generate_fn(fn_def) ?;
+        }
+        Ok(())
+    }
+
+    fn generate_fn(&mut self, fn_def: &'a FnDef) -> Result<(), LiveError> {
+        self.fn_def = Some(fn_def);
+        self.inout_params.clear();
+        self.next_temp = 0;
+
+        let mut params = Vec::new();
+        let mut inout_tys = Vec::new();
+        for param in &fn_def.params {
+            let ty = param.ty_expr.ty.borrow().clone().unwrap();
+            let rust_ty = match (&ty, &param.ty_expr.kind) {
+                (Ty::DrawShader, _) => continue,
+                (_, TyExprKind::ClosureDecl {params, return_ty, ..}) => {
+                    self.closure_ty(param.span, params, return_ty.borrow().as_ref().unwrap()) ?
+                }
+                _ => self.ty(param.span, &ty) ?,
+            };
+            let name = param_name(param);
+            params.push(format!("mut {}: {}", name, rust_ty));
+            if param.is_inout {
+                self.inout_params.push(name);
+            }
+            inout_tys.push((param.is_inout, ty));
+        }
+        params.push(String::from("ds: &mut DrawShader"));
+        let return_ty = fn_def.return_ty.borrow().clone().unwrap();
+        let rust_return_ty = self.return_ty(fn_def.span, &return_ty, &inout_tys) ?;
+
+        writeln!(self.string).unwrap();
+        write!(self.string, "fn {}({})", DisplayFnName(fn_def.fn_ptr, fn_def.ident), params.join(", ")).unwrap();
+        if rust_return_ty != "()" {
+            write!(self.string, " -> {}", rust_return_ty).unwrap();
+        }
+        writeln!(self.string, " {{").unwrap();
+        self.indent = 1;
+        self.generate_stmts(&fn_def.block) ?;
+        self.generate_fn_end(&fn_def.block, &return_ty);
+        self.indent = 0;
+        writeln!(self.string, "}}").unwrap();
+        Ok(())
+    }
+
+    // the inout params still have to be returned when falling off the end of a block
+    fn generate_fn_end(&mut self, block: &Block, return_ty: &Ty) {
+        if let Some(Stmt::Return {..}) = block.stmts.last() {
+            return
+        }
+        if *return_ty != Ty::Void {
+            self.line("unreachable!()");
+        }
+        else if !self.inout_params.is_empty() {
+            let value = self.return_value(String::from("()"));
+            self.line(&value);
+        }
+    }
+
+    fn return_value(&self, value: String) -> String {
+        if self.inout_params.is_empty() {
+            return value
+        }
+        format!("({}, {})", value, self.inout_params.join(", "))
+    }
+
+    fn temp(&mut self) -> String {
+        let temp = format!("t{}", self.next_temp);
+        self.next_temp += 1;
+        temp
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.string.push_str("    ");
+        }
+        self.string.push_str(line);
+        self.string.push('\n');
+    }
+
+    // statements
+
+    fn generate_stmts(&mut self, block: &'a Block) -> Result<(), LiveError> {
+        for stmt in &block.stmts {
+            self.generate_stmt(stmt) ?;
+        }
+        Ok(())
+    }
+
+    fn generate_block(&mut self, block: &'a Block) -> Result<(), LiveError> {
+        self.indent += 1;
+        self.generate_stmts(block) ?;
+        self.indent -= 1;
+        Ok(())
+    }
+
+    fn generate_stmt(&mut self, stmt: &'a Stmt) -> Result<(), LiveError> {
+        match stmt {
+            Stmt::Break {label, ..} => self.line(&format!("break{};", jump_label(*label))),
+            Stmt::Continue {label, ..} => self.line(&format!("continue{};", jump_label(*label))),
+            Stmt::Discard {span} => return Err(error(*span, String::from("the Rust backend doesn't support `discard`"))),
+            Stmt::For {label, ident, shadow, from_expr, to_expr, step_expr, block, ..} => {
+                let const_int = | expr: &Expr | expr.const_val.borrow().as_ref().unwrap().as_ref().unwrap().to_int().unwrap();
+                let from = const_int(from_expr);
+                let to = const_int(to_expr);
+                let step = match step_expr {
+                    Some(step_expr) => const_int(step_expr),
+                    None => if from < to {1} else {-1}
+                };
+                // the same bounds as the generated `for (int i = ..; i < to; i += step)`, stepped
+                // before the body so that `continue` steps too
+                let (start, cond) = if from <= to {(from, "<")} else {(from - 1, ">=")};
+                self.line(&format!("let mut for_{} = {};", ident, start));
+                self.line(&format!("{}while for_{} {} {} {{", loop_label(*label), ident, cond, to));
+                self.indent += 1;
+                self.line(&format!("let mut {} = for_{};", DisplayVarName(*ident, shadow.get().unwrap()), ident));
+                self.line(&format!("for_{} += {};", ident, step));
+                self.generate_stmts(block) ?;
+                self.indent -= 1;
+                self.line("}");
+            }
+            Stmt::While {label, expr, block, ..} => {
+                let cond = self.expr(expr) ?;
+                self.line(&format!("{}while {} {{", loop_label(*label), cond));
+                self.generate_block(block) ?;
+                self.line("}");
+            }
+            Stmt::Loop {label, block, ..} => {
+                self.line(&format!("{}loop {{", loop_label(*label)));
+                self.generate_block(block) ?;
+                self.line("}");
+            }
+            Stmt::If {expr, block_if_true, block_if_false, ..} => {
+                let cond = self.expr(expr) ?;
+                self.line(&format!("if {} {{", cond));
+                self.generate_block(block_if_true) ?;
+                if let Some(block_if_false) = block_if_false {
+                    self.line("} else {");
+                    self.generate_block(block_if_false) ?;
+                }
+                self.line("}");
+            }
+            Stmt::Match {expr, matches, ..} => {
+                let value = self.expr(expr) ?;
+                self.line(&format!("match {} {{", value));
+                self.indent += 1;
+                for match_item in matches {
+                    self.line(&format!("{} => {{", match_item.enum_value.get().unwrap()));
+                    self.generate_block(&match_item.block) ?;
+                    self.line("}");
+                }
+                self.line("_ => {}");
+                self.indent -= 1;
+                self.line("}");
+            }
+            Stmt::Let {span, ty, ident, expr, shadow, ..} => {
+                let ty = ty.borrow().clone().unwrap();
+                let value = match expr {
+                    Some(expr) => self.expr(expr) ?,
+                    None => self.zero(*span, &ty) ?,
+                };
+                let shadow = shadow.get().unwrap();
+                let rust_ty = self.ty(*span, &ty) ?;
+                self.line(&format!("let mut {}: {} = {};", DisplayVarName(*ident, shadow), rust_ty, value));
+            }
+            Stmt::Return {expr, ..} => {
+                let value = match expr {
+                    Some(expr) => self.expr(expr) ?,
+                    None => String::from("()"),
+                };
+                if value == "()" && self.inout_params.is_empty() {
+                    self.line("return;");
+                }
+                else {
+                    let value = self.return_value(value);
+                    self.line(&format!("return {};", value));
+                }
+            }
+            Stmt::Block {block, ..} => {
+                self.line("{");
+                self.generate_block(block) ?;
+                self.line("}");
+            }
+            Stmt::Expr {expr, ..} => match &expr.kind {
+                ExprKind::Bin {op, left_expr, right_expr, ..} if op.is_assign() => {
+                    self.generate_assign(*op, left_expr, right_expr) ?;
+                }
+                _ => {
+                    let value = self.expr(expr) ?;
+                    self.line(&format!("{};", value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_assign(&mut self, op: BinOp, left_expr: &'a Expr, right_expr: &'a Expr) -> Result<(), LiveError> {
+        let place = self.place(left_expr) ?;
+        for bind in &place.binds {
+            self.line(bind);
+        }
+        let mut value = self.expr(right_expr) ?;
+        let left_ty = left_expr.ty.borrow().clone().unwrap();
+        let right_ty = right_expr.ty.borrow().clone().unwrap();
+        if let Some(op) = op.assign_op() {
+            let left = read_place(&place, &left_ty);
+            value = self.arith(left_expr.span, op, left, &left_ty, value, &right_ty) ?;
+        }
+        let lines = write_place(&place, value, &mut | | String::from("v"));
+        for line in lines {
+            self.line(&line);
+        }
+        Ok(())
+    }
+
+    // expressions
+
+    fn expr(&mut self, expr: &'a Expr) -> Result<String, LiveError> {
+        let ty = expr.ty.borrow().clone().unwrap();
+        if let Some(Some(val)) = expr.const_val.borrow().as_ref() {
+            if !matches!(ty, Ty::Vec2 | Ty::Vec3) {
+                return Ok(self.val(val, &ty))
+            }
+        }
+        match &expr.kind {
+            ExprKind::Cond {expr: cond_expr, expr_if_true, expr_if_false, ..} => {
+                let cond = self.expr(cond_expr) ?;
+                let value_if_true = self.expr(expr_if_true) ?;
+                let value_if_false = self.expr(expr_if_false) ?;
+                Ok(format!("(if {} {{{}}} else {{{}}})", cond, value_if_true, value_if_false))
+            }
+            ExprKind::Bin {span, op, left_expr, right_expr} => {
+                let left_ty = left_expr.ty.borrow().clone().unwrap();
+                let right_ty = right_expr.ty.borrow().clone().unwrap();
+                let left = self.expr(left_expr) ?;
+                let right = self.expr(right_expr) ?;
+                Ok(match op {
+                    _ if op.is_assign() => {
+                        return Err(error(*span, String::from("the Rust backend only supports assignments as statements")))
+                    }
+                    BinOp::Or => format!("({} || {})", left, right),
+                    BinOp::And => format!("({} && {})", left, right),
+                    BinOp::Eq => format!("({} == {})", left, right),
+                    BinOp::Ne => format!("({} != {})", left, right),
+                    BinOp::Lt => format!("({} < {})", left, right),
+                    BinOp::Le => format!("({} <= {})", left, right),
+                    BinOp::Gt => format!("({} > {})", left, right),
+                    BinOp::Ge => format!("({} >= {})", left, right),
+                    _ => self.arith(*span, *op, left, &left_ty, right, &right_ty) ?,
+                })
+            }
+            ExprKind::Un {op, expr: value_expr, ..} => {
+                let value = self.expr(value_expr) ?;
+                Ok(match (op, &ty) {
+                    (UnOp::Not, _) => format!("(!{})", value),
+                    (UnOp::Neg, Ty::Int) => format!("i32::wrapping_neg({})", value),
+                    (UnOp::Neg, _) => format!("(-{})", value),
+                })
+            }
+            ExprKind::Field {span, expr: base_expr, field_ident} => {
+                match base_expr.ty.borrow().as_ref().unwrap() {
+                    Ty::DrawShader => match self.draw_shader_def.find_stage_builtin(*field_ident) {
+                        Some(_) => Err(error(*span, format!("the Rust backend doesn't support `self.{}`", field_ident))),
+                        None => Ok(format!("ds.{}", field_ident)),
+                    },
+                    Ty::Struct(_) => Ok(format!("{}.{}", self.expr(base_expr) ?, DisplayStructField(*field_ident))),
+                    _ => {
+                        let base = self.expr(base_expr) ?;
+                        let swizzle: Vec<usize> = Swizzle::parse(*field_ident).unwrap().iter().cloned().collect();
+                        if swizzle.len() == 1 {
+                            return Ok(format!("{}.{}", base, COMPONENTS[swizzle[0]]))
+                        }
+                        self.ty(*span, &ty) ?;
+                        Ok(self.with_simple(base, | base | read_place(&Place {base, components: Some(swizzle), binds: Vec::new()}, &ty)))
+                    }
+                }
+            }
+            ExprKind::Index {expr: base_expr, index_expr, ..} => {
+                let base = self.expr(base_expr) ?;
+                let index = self.expr(index_expr) ?;
+                Ok(format!("{}[{}]", base, usize_index(&index)))
+            }
+            ExprKind::MethodCall {span, ident, arg_exprs, ..} => {
+                let (fn_def, arg_exprs) = self.shader_registry.method_call_fn_def(*ident, arg_exprs);
+                self.fn_call(*span, fn_def, arg_exprs)
+            }
+            ExprKind::PlainCall {span, fn_ptr, param_index, arg_exprs, ..} => {
+                if let Some(param_index) = param_index.get() {
+                    let param = &self.fn_def.unwrap().params[param_index];
+                    let params = match &param.ty_expr.kind {
+                        TyExprKind::ClosureDecl {params, ..} => params,
+                        _ => panic!()
+                    };
+                    let name = param_name(param);
+                    let params: Vec<(bool, Ty)> = params.iter().map( | param | {
+                        (param.is_inout, param.ty_expr.ty.borrow().clone().unwrap())
+                    }).collect();
+                    self.call(*span, name, &params, arg_exprs, &[])
+                }
+                else {
+                    let fn_def = self.shader_registry.all_fns.get(&fn_ptr.unwrap()).unwrap();
+                    self.fn_call(*span, fn_def, arg_exprs)
+                }
+            }
+            ExprKind::BuiltinCall {span, ident, arg_exprs} => self.builtin_call(*span, *ident, arg_exprs, &ty),
+            ExprKind::ClosureDef(_) => panic!(),
+            ExprKind::ConsCall {span, arg_exprs, ..} => self.cons_call(*span, arg_exprs, &ty),
+            ExprKind::StructCons {struct_ptr, args, ..} => {
+                let mut fields = Vec::new();
+                for (ident, arg_expr) in args {
+                    fields.push(format!("{}: {}", DisplayStructField(*ident), self.expr(arg_expr) ?));
+                }
+                Ok(format!("({} {{{}}})", struct_ptr, fields.join(", ")))
+            }
+            ExprKind::ArrayCons {elem_exprs, ..} => {
+                let mut elems = Vec::new();
+                for elem_expr in elem_exprs {
+                    elems.push(self.expr(elem_expr) ?);
+                }
+                Ok(format!("[{}]", elems.join(", ")))
+            }
+            ExprKind::Var {kind, ..} => Ok(match kind.get().unwrap() {
+                VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => DisplayVarName(ident, shadow).to_string(),
+                VarKind::LiveValue(value_ptr) => value_ptr.to_string(),
+                VarKind::Const(const_ptr) => const_ptr.to_string(),
+            }),
+            ExprKind::Lit {lit, ..} => Ok(self.val(&lit.to_val(), &ty)),
+        }
+    }
+
+    // hands `value` to `f` as something that can be repeated, binding it to a temp if it has to be
+    fn with_simple(&mut self, value: String, f: impl FnOnce(String) -> String) -> String {
+        if is_simple(&value) {
+            return f(value)
+        }
+        let temp = self.temp();
+        format!("({{let {} = {}; {}}})", temp, value, f(temp.clone()))
+    }
+
+    fn arith(&mut self, span: TokenSpan, op: BinOp, left: String, left_ty: &Ty, right: String, right_ty: &Ty) -> Result<String, LiveError> {
+        let symbol = match op {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            _ => "/",
+        };
+        Ok(match (left_ty, right_ty) {
+            // the shift amount can have the other signedness
+            (Ty::Int | Ty::Uint, Ty::Int | Ty::Uint) if matches!(op, BinOp::Shl | BinOp::Shr) => {
+                let method = if matches!(op, BinOp::Shl) {"wrapping_shl"} else {"wrapping_shr"};
+                format!("{}::{}({}, ({}) as u32)", self.ty(span, left_ty) ?, method, left, right)
+            }
+            (Ty::Int, Ty::Int) | (Ty::Uint, Ty::Uint) => {
+                let method = match op {
+                    BinOp::Add => "wrapping_add",
+                    BinOp::Sub => "wrapping_sub",
+                    BinOp::Mul => "wrapping_mul",
+                    BinOp::Rem => "wrapping_rem",
+                    BinOp::BitAnd => return Ok(format!("({} & {})", left, right)),
+                    BinOp::BitOr => return Ok(format!("({} | {})", left, right)),
+                    BinOp::BitXor => return Ok(format!("({} ^ {})", left, right)),
+                    _ => "wrapping_div",
+                };
+                // the type is spelled out, the methods can't be called on an unsuffixed literal
+                format!("{}::{}({}, {})", self.ty(span, left_ty) ?, method, left, right)
+            }
+            // the analyser made sure the sizes match
+            (Ty::Mat2 | Ty::Mat3 | Ty::Mat4, Ty::Mat2 | Ty::Mat3 | Ty::Mat4) if symbol == "*" => format!("mat_mul({}, {})", left, right),
+            (Ty::Mat2 | Ty::Mat3 | Ty::Mat4, _) if symbol == "*" && !is_scalar(right_ty) => format!("mat_mul_vec({}, {})", left, right),
+            (_, Ty::Mat2 | Ty::Mat3 | Ty::Mat4) if symbol == "*" && !is_scalar(left_ty) => format!("vec_mul_mat({}, {})", left, right),
+            (Ty::Mat2 | Ty::Mat3 | Ty::Mat4, _) | (_, Ty::Mat2 | Ty::Mat3 | Ty::Mat4) => {
+                return Err(error(span, format!("the Rust backend can't apply `{}` to a `{}` and a `{}`", symbol, left_ty, right_ty)))
+            }
+            _ => format!("({} {} {})", left, symbol, right),
+        })
+    }
+
+    // places
+
+    fn place(&mut self, expr: &'a Expr) -> Result<Place, LiveError> {
+        match &expr.kind {
+            ExprKind::Var {kind, ..} => Ok(Place {
+                base: match kind.get().unwrap() {
+                    VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => DisplayVarName(ident, shadow).to_string(),
+                    VarKind::LiveValue(value_ptr) => value_ptr.to_string(),
+                    VarKind::Const(_) => panic!(),
+                },
+                components: None,
+                binds: Vec::new(),
+            }),
+            ExprKind::Field {span, expr: base_expr, field_ident} => match base_expr.ty.borrow().as_ref().unwrap() {
+                Ty::DrawShader if self.draw_shader_def.find_stage_builtin(*field_ident).is_some() => {
+                    Err(error(*span, format!("the Rust backend doesn't support `self.{}`", field_ident)))
+                }
+                Ty::DrawShader => Ok(Place {base: format!("ds.{}", field_ident), components: None, binds: Vec::new()}),
+                Ty::Struct(_) => {
+                    let mut place = self.place(base_expr) ?;
+                    place.base = format!("{}.{}", place.base, DisplayStructField(*field_ident));
+                    Ok(place)
+                }
+                _ => {
+                    let mut place = self.place(base_expr) ?;
+                    let swizzle: Vec<usize> = Swizzle::parse(*field_ident).unwrap().iter().cloned().collect();
+                    place.components = Some(match place.components {
+                        Some(components) => swizzle.iter().map( | index | components[*index]).collect(),
+                        None => swizzle,
+                    });
+                    Ok(place)
+                }
+            },
+            ExprKind::Index {span, expr: base_expr, index_expr} => {
+                let mut place = self.place(base_expr) ?;
+                if place.components.is_some() {
+                    return Err(error(*span, String::from("the Rust backend can't assign to an index of a swizzle")))
+                }
+                // the index is read for an inout arg and written back after the call, it is only evaluated once
+                let mut index = self.expr(index_expr) ?;
+                if !is_simple(&index) {
+                    let temp = self.temp();
+                    place.binds.push(format!("let {} = {};", temp, index));
+                    index = temp;
+                }
+                place.base = format!("{}[{}]", place.base, usize_index(&index));
+                Ok(place)
+            }
+            _ => panic!()
+        }
+    }
+
+    // calls
+
+    fn fn_call(&mut self, span: TokenSpan, fn_def: &'a FnDef, arg_exprs: &'a [Expr]) -> Result<String, LiveError> {
+        let params = fn_def.params_for_args(arg_exprs.len());
+        let param_tys: Vec<(bool, Ty)> = params.iter().map( | param | {
+            (param.is_inout, param.ty_expr.ty.borrow().clone().unwrap())
+        }).collect();
+        self.call(span, DisplayFnName(fn_def.fn_ptr, fn_def.ident).to_string(), &param_tys, arg_exprs, params)
+    }
+
+    // `ds` goes last, so that the args are done with it by the time it is borrowed for the call
+    fn call(
+        &mut self,
+        span: TokenSpan,
+        name: String,
+        params: &[(bool, Ty)],
+        arg_exprs: &'a [Expr],
+        decl_params: &'a [Param],
+    ) -> Result<String, LiveError> {
+        let mut args = Vec::new();
+        let mut write_backs = Vec::new();
+        for (index, ((is_inout, ty), arg_expr)) in params.iter().zip(arg_exprs).enumerate() {
+            if let ExprKind::ClosureDef(closure_def_index) = arg_expr.kind {
+                let (closure_params, return_ty) = match &decl_params[index].ty_expr.kind {
+                    TyExprKind::ClosureDecl {params, return_ty, ..} => (params, return_ty.borrow().clone().unwrap()),
+                    _ => panic!()
+                };
+                args.push(self.closure(closure_def_index, closure_params, &return_ty) ?);
+            }
+            else if *is_inout {
+                let place = self.place(arg_expr) ?;
+                args.push(read_place(&place, ty));
+                write_backs.push(place);
+            }
+            else {
+                args.push(self.expr(arg_expr) ?);
+            }
+        }
+        args.push(String::from("ds"));
+        let call = format!("{}({})", name, args.join(", "));
+        if write_backs.is_empty() {
+            return Ok(call)
+        }
+        let temps: Vec<String> = write_backs.iter().map( | _ | self.temp()).collect();
+        let mut string = String::from("({");
+        for bind in write_backs.iter().flat_map( | place | &place.binds) {
+            write!(string, "{} ", bind).unwrap();
+        }
+        write!(string, "let (r, {}) = {};", temps.join(", "), call).unwrap();
+        for (place, temp) in write_backs.iter().zip(&temps) {
+            let mut next_temp = | | self.temp();
+            for line in write_place(place, temp.clone(), &mut next_temp) {
+                write!(string, " {}", line).unwrap();
+            }
+        }
+        string.push_str(" r})");
+        Ok(string)
+    }
+
+    fn closure(&mut self, closure_def_index: ClosureDefIndex, params: &'a [Param], return_ty: &Ty) -> Result<String, LiveError> {
+        let closure_def = &self.fn_def.unwrap().closure_defs[closure_def_index.0];
+        let span = closure_def.span;
+        let mut rust_params = Vec::new();
+        let mut inout_params = Vec::new();
+        let mut inout_tys = Vec::new();
+        for (param, closure_param) in params.iter().zip(&closure_def.params) {
+            let ty = param.ty_expr.ty.borrow().clone().unwrap();
+            let shadow = closure_param.shadow.get().unwrap();
+            let name = DisplayVarName(closure_param.ident, shadow).to_string();
+            rust_params.push(format!("mut {}: {}", name, self.ty(span, &ty) ?));
+            if param.is_inout {
+                inout_params.push(name);
+            }
+            inout_tys.push((param.is_inout, ty));
+        }
+        rust_params.push(String::from("ds: &mut DrawShader"));
+        let rust_return_ty = self.return_ty(span, return_ty, &inout_tys) ?;
+
+        // the closure is a block of lines of its own, which goes inline
+        let outer_string = std::mem::take(&mut self.string);
+        let outer_indent = self.indent;
+        let outer_inout_params = std::mem::replace(&mut self.inout_params, inout_params);
+        self.indent = outer_indent + 1;
+        let result = match &closure_def.kind {
+            ClosureDefKind::Expr(expr) => self.expr(expr).map( | value | {
+                let value = self.return_value(value);
+                self.line(&value);
+            }),
+            ClosureDefKind::Block(block) => self.generate_stmts(block).map( | _ | self.generate_fn_end(block, return_ty)),
+        };
+        self.indent = outer_indent;
+        self.inout_params = outer_inout_params;
+        let body = std::mem::replace(&mut self.string, outer_string);
+        result ?;
+
+        let mut string = format!("&move |{}| -> {} {{\n", rust_params.join(", "), rust_return_ty);
+        string.push_str(&body);
+        for _ in 0..self.indent {
+            string.push_str("    ");
+        }
+        string.push('}');
+        Ok(string)
+    }
+
+    fn builtin_call(&mut self, span: TokenSpan, ident: Ident, arg_exprs: &'a [Expr], ty: &Ty) -> Result<String, LiveError> {
+        let mut args = Vec::new();
+        for arg_expr in arg_exprs {
+            let arg_ty = arg_expr.ty.borrow().clone().unwrap();
+            let arg = self.expr(arg_expr) ?;
+            // the float args of the component wise builtins go to every component
+            let splat = matches!(
+                ident,
+                Ident(id!(clamp)) | Ident(id!(max)) | Ident(id!(min)) | Ident(id!(mix)) | Ident(id!(mod)) |
+                Ident(id!(pow)) | Ident(id!(smoothstep)) | Ident(id!(step))
+            );
+            if splat && arg_ty == Ty::Float && matches!(ty, Ty::Vec2 | Ty::Vec3 | Ty::Vec4) {
+                args.push(format!("{}::all({})", self.ty(span, ty) ?, arg));
+            }
+            else {
+                args.push(arg);
+            }
+        }
+        let name = match ident {
+            Ident(id!(abs)) if *ty == Ty::Int => return Ok(format!("i32::wrapping_abs({})", args[0])),
+            Ident(id!(atan)) if args.len() == 2 => "atan2",
+            // there are no neighbouring invocations to take a difference with
+            Ident(id!(dFdx)) | Ident(id!(dFdy)) => return self.zero(span, ty),
+            Ident(id!(log)) => "ln",
+            Ident(id!(mod)) => "modulo",
+            // `f32::fract` rounds towards zero, GLSL towards negative infinity
+            Ident(id!(fract)) => {
+                self.ty(span, ty) ?;
+                return Ok(self.with_simple(args[0].clone(), | arg | format!("({} - floor({}))", arg, arg)))
+            }
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) => return Ok(format!("({})({})", args[0], args[1])),
+            // a texture is a plain fn of the uv, without mipmaps
+            Ident(id!(sample2d_lod)) | Ident(id!(sample2d_grad)) => return Ok(format!("({})({})", args[0], args[1])),
+            Ident(id!(abs)) | Ident(id!(acos)) | Ident(id!(asin)) | Ident(id!(atan)) | Ident(id!(ceil)) | Ident(id!(clamp)) |
+            Ident(id!(cos)) | Ident(id!(cross)) | Ident(id!(degrees)) | Ident(id!(distance)) | Ident(id!(dot)) |
+            Ident(id!(exp)) | Ident(id!(exp2)) | Ident(id!(faceforward)) | Ident(id!(floor)) |
+            Ident(id!(inverse)) | Ident(id!(inversesqrt)) | Ident(id!(length)) | Ident(id!(log2)) | Ident(id!(max)) |
+            Ident(id!(min)) | Ident(id!(mix)) | Ident(id!(normalize)) | Ident(id!(pow)) | Ident(id!(radians)) |
+            Ident(id!(reflect)) | Ident(id!(refract)) | Ident(id!(sign)) | Ident(id!(sin)) | Ident(id!(smoothstep)) |
+            Ident(id!(sqrt)) | Ident(id!(step)) | Ident(id!(tan)) | Ident(id!(transpose)) => {
+                for arg_expr in arg_exprs {
+                    self.ty(span, arg_expr.ty.borrow().as_ref().unwrap()) ?;
+                }
+                return Ok(format!("{}({})", ident, args.join(", ")))
+            }
+            _ => return Err(error(span, format!("the Rust backend doesn't support `{}`", ident))),
+        };
+        Ok(format!("{}({})", name, args.join(", ")))
+    }
+
+    fn cons_call(&mut self, span: TokenSpan, arg_exprs: &'a [Expr], ty: &Ty) -> Result<String, LiveError> {
+        let rust_ty = self.ty(span, ty) ?;
+        let mut args = Vec::new();
+        for arg_expr in arg_exprs {
+            let arg_ty = arg_expr.ty.borrow().clone().unwrap();
+            self.ty(span, &arg_ty) ?;
+            args.push((self.expr(arg_expr) ?, arg_ty));
+        }
+        if let [(arg, arg_ty)] = args.as_slice() {
+            if arg_ty == ty {
+                return Ok(arg.clone())
+            }
+            match ty {
+                Ty::Bool | Ty::Int | Ty::Uint | Ty::Float => {
+                    let scalar_ty = if matches!(arg_ty, Ty::Bool | Ty::Int | Ty::Uint) {arg_ty.clone()} else {Ty::Float};
+                    let scalar = if scalar_ty == *arg_ty {arg.clone()} else {format!("{}.x", arg)};
+                    return Ok(convert_scalar(scalar, &scalar_ty, ty))
+                }
+                Ty::Vec2 | Ty::Vec3 | Ty::Vec4 if matches!(arg_ty, Ty::Bool | Ty::Int | Ty::Uint | Ty::Float) => {
+                    return Ok(format!("{}::all({})", rust_ty, convert_scalar(arg.clone(), arg_ty, &Ty::Float)))
+                }
+                // a scalar goes on the diagonal, a matrix is cut down or filled up from the identity
+                Ty::Mat2 | Ty::Mat3 | Ty::Mat4 if is_scalar(arg_ty) || matrix_size(arg_ty).is_some() => {
+                    let size = matrix_size(ty).unwrap();
+                    let arg_size = matrix_size(arg_ty);
+                    let arg = if arg_size.is_some() {arg.clone()} else {convert_scalar(arg.clone(), arg_ty, &Ty::Float)};
+                    return Ok(self.with_simple(arg, | arg | {
+                        let components: Vec<String> = (0..size * size).map( | index | {
+                            let (column, row) = (index / size, index % size);
+                            match arg_size {
+                                Some(arg_size) if column < arg_size && row < arg_size => format!("{}.v[{}]", arg, column * arg_size + row),
+                                _ if column != row => String::from("0.0"),
+                                Some(_) => String::from("1.0"),
+                                None => arg.clone(),
+                            }
+                        }).collect();
+                        format!("{} {{v: [{}]}}", rust_ty, components.join(", "))
+                    }))
+                }
+                _ => ()
+            }
+        }
+        // everything else takes the components of its args in order
+        let mut binds = Vec::new();
+        let mut components = Vec::new();
+        for (arg, arg_ty) in args {
+            let arg_components = match arg_ty {
+                Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
+                    let len = match arg_ty {Ty::Vec2 => 2, Ty::Vec3 => 3, _ => 4};
+                    COMPONENTS[..len].iter().map( | component | component.to_string()).collect::<Vec<_>>()
+                }
+                Ty::Mat2 | Ty::Mat3 | Ty::Mat4 => {
+                    let size = matrix_size(&arg_ty).unwrap();
+                    (0..size * size).map( | index | format!("v[{}]", index)).collect()
+                }
+                _ => {
+                    components.push(convert_scalar(arg, &arg_ty, &Ty::Float));
+                    continue;
+                }
+            };
+            let base = if is_simple(&arg) {
+                arg
+            }
+            else {
+                let temp = self.temp();
+                binds.push(format!("let {} = {};", temp, arg));
+                temp
+            };
+            for component in arg_components {
+                components.push(format!("{}.{}", base, component));
+            }
+        }
+        let value = match matrix_size(ty) {
+            Some(size) => {
+                components.truncate(size * size);
+                format!("{} {{v: [{}]}}", rust_ty, components.join(", "))
+            }
+            None => {
+                components.truncate(match ty {Ty::Vec2 => 2, Ty::Vec3 => 3, _ => 4});
+                format!("{}({})", rust_ty.to_lowercase(), components.join(", "))
+            }
+        };
+        if binds.is_empty() {
+            return Ok(value)
+        }
+        Ok(format!("({{{} {}}})", binds.join(" "), value))
+    }
+}
+
+const COMPONENTS: [&str; 4] = ["x", "y", "z", "w"];
+
+fn error(span: TokenSpan, message: String) -> LiveError {
+    LiveError {
+        origin: live_error_origin!(),
+        span: span.into(),
+        message,
+    }
+}
+
+// closure params don't get a shadow
+fn param_name(param: &Param) -> String {
+    match param.shadow.get() {
+        Some(shadow) => DisplayVarName(param.ident, shadow).to_string(),
+        None => format!("param_{}", param.ident),
+    }
+}
+
+// `'label_outer: ` in front of a loop
+fn loop_label(label: Option<Ident>) -> String {
+    label.map_or(String::new(), | label | format!("'label_{}: ", label))
+}
+
+// ` 'label_outer` after a `break` or `continue`
+fn jump_label(label: Option<Ident>) -> String {
+    label.map_or(String::new(), | label | format!(" 'label_{}", label))
+}
+
+fn is_varying(kind: &DrawShaderFieldKind) -> bool {
+    match kind {
+        DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Varying {..} => true,
+        _ => false
+    }
+}
+
+// a path that can be repeated without evaluating anything twice
+fn is_simple(value: &str) -> bool {
+    value.chars().all( | c | c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn is_scalar(ty: &Ty) -> bool {
+    matches!(ty, Ty::Bool | Ty::Int | Ty::Uint | Ty::Float)
+}
+
+fn matrix_size(ty: &Ty) -> Option<usize> {
+    match ty {
+        Ty::Mat2 => Some(2),
+        Ty::Mat3 => Some(3),
+        Ty::Mat4 => Some(4),
+        _ => None
+    }
+}
+
+// an int or uint index of a Rust array or vector
+fn usize_index(index: &str) -> String {
+    if is_simple(index) {format!("{} as usize", index)} else {format!("({}) as usize", index)}
+}
+
+// a `Vec2`, `Vec3` or `Vec4` that can be used in a `const`
+fn vector_lit(components: &[f32]) -> String {
+    let fields: Vec<String> = components.iter().enumerate().map( | (index, component) | {
+        format!("{}: {}", COMPONENTS[index], f32_lit(*component))
+    }).collect();
+    format!("Vec{} {{{}}}", components.len(), fields.join(", "))
+}
+
+fn f32_lit(value: f32) -> String {
+    if value.is_nan() {
+        String::from("f32::NAN")
+    }
+    else if value.is_infinite() {
+        String::from(if value > 0.0 {"f32::INFINITY"} else {"f32::NEG_INFINITY"})
+    }
+    else if value < 0.0 {
+        format!("({:?}f32)", value)
+    }
+    else {
+        format!("{:?}f32", value)
+    }
+}
+
+fn convert_scalar(value: String, from_ty: &Ty, to_ty: &Ty) -> String {
+    match (from_ty, to_ty) {
+        (from_ty, to_ty) if from_ty == to_ty => value,
+        (Ty::Bool, Ty::Float) => format!("({} as i32 as f32)", value),
+        (Ty::Float, Ty::Bool) => format!("({} != 0.0)", value),
+        (Ty::Int | Ty::Uint, Ty::Bool) => format!("({} != 0)", value),
+        (_, Ty::Int) => format!("({} as i32)", value),
+        (_, Ty::Uint) => format!("({} as u32)", value),
+        _ => format!("({} as f32)", value),
+    }
+}
+
+fn read_place(place: &Place, ty: &Ty) -> String {
+    match &place.components {
+        None => place.base.clone(),
+        Some(components) if components.len() == 1 => format!("{}.{}", place.base, COMPONENTS[components[0]]),
+        Some(components) => {
+            let components: Vec<String> = components.iter().map( | index | format!("{}.{}", place.base, COMPONENTS[*index])).collect();
+            let constructor = match ty {Ty::Vec2 => "vec2", Ty::Vec3 => "vec3", _ => "vec4"};
+            format!("{}({})", constructor, components.join(", "))
+        }
+    }
+}
+
+// the statements that write `value` to `place`
+fn write_place(place: &Place, value: String, temp: &mut dyn FnMut() -> String) -> Vec<String> {
+    match &place.components {
+        None => vec![format!("{} = {};", place.base, value)],
+        Some(components) if components.len() == 1 => vec![format!("{}.{} = {};", place.base, COMPONENTS[components[0]], value)],
+        Some(components) => {
+            let value = if is_simple(&value) {value} else {
+                let temp = temp();
+                let mut lines = vec![format!("let {} = {};", temp, value)];
+                for (index, component) in components.iter().enumerate() {
+                    lines.push(format!("{}.{} = {}.{};", place.base, COMPONENTS[*component], temp, COMPONENTS[index]));
+                }
+                return vec![format!("{{{}}}", lines.join(" "))]
+            };
+            components.iter().enumerate().map( | (index, component) | {
+                format!("{}.{} = {}.{};", place.base, COMPONENTS[*component], value, COMPONENTS[index])
+            }).collect()
+        }
+    }
+}
@@ -0,0 +1,2314 @@
+use {
+    std::collections::{BTreeSet, HashMap},
+    crate::{
+        makepad_live_id::{id, LiveId},
+        generate::{
+            DisplayClosedOverArg, DisplayClosureName, DisplayDsIdent, DisplayFnName, DisplayFnNameWithClosureArgs,
+            DisplayPixelOutput, DisplayStructField, DisplayVarName,
+        },
+        generate_glsl::GlslBinding,
+        shader_ast::*,
+        shader::Shader,
+        spirv::{
+            built_in, capability, decoration, dim, execution_mode, execution_model, glsl_std_450, image_operands,
+            push_inst, storage_class, string_words, Op, MAGIC, VERSION_1_0,
+        },
+        swizzle::Swizzle,
+    }
+};
+
+/// Lowers the draw shader straight to a SPIR-V 1.0 module with a `vertex_main` and a `fragment_main`
+/// entry point, see `crate::disassemble_spirv` for the text form.
+///
+/// The interface matches the `GlslTarget::Glsl450` shaders: the same packed attributes and varyings at
+/// the same locations, `layout(std140)` uniform blocks and combined image samplers at `bindings`.
+/// Constants are inlined and `const` arrays are baked into private vars, so there is no const table.
+pub fn generate_shader(draw_shader_def: &DrawShaderDef, shader_registry: &Shader, bindings: &[GlslBinding]) -> Vec<u32> {
+    let mut generator = SpirvGenerator {
+        draw_shader_def,
+        shader_registry,
+        bindings,
+        next_id: 1,
+        capabilities: Vec::new(),
+        ext_inst_imports: Vec::new(),
+        memory_model: Vec::new(),
+        entry_points: Vec::new(),
+        execution_modes: Vec::new(),
+        debug_names: Vec::new(),
+        annotations: Vec::new(),
+        globals: Vec::new(),
+        functions: Vec::new(),
+        glsl_std_450: 0,
+        unique_ids: HashMap::new(),
+        struct_ids: HashMap::new(),
+        field_vars: HashMap::new(),
+        builtin_vars: HashMap::new(),
+        live_vars: HashMap::new(),
+        const_vars: HashMap::new(),
+        uniform_blocks: Vec::new(),
+        fn_ids: HashMap::new(),
+        fn_def: None,
+        closure_site: None,
+        sample_level: false,
+        vertex_copy_fns: shader_registry.vertex_copy_fns(),
+        f: FnState::default(),
+    };
+    generator.generate_module();
+    generator.finish()
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+enum FnKey {
+    Plain(FnPtr),
+    // a fn with closure args, specialised for a closure site in the calling fn
+    Site(FnPtr, usize),
+    Closure(FnPtr, ClosureDefIndex),
+}
+
+enum FnBody<'a> {
+    Block(&'a Block),
+    Expr(&'a Expr),
+}
+
+#[derive(Clone, Copy)]
+enum ParamKind {
+    Local(Ident, ScopeSymShadow),
+    // only passed on to the closures of a site
+    ClosedOver(Ident, ScopeSymShadow),
+}
+
+struct FnParam {
+    kind: ParamKind,
+    ty: Ty,
+    is_inout: bool,
+}
+
+// a pointer to a value, or to some components of a vector
+#[derive(Clone)]
+struct Place {
+    ptr: u32,
+    storage_class: u32,
+    ty: Ty,
+    swizzle: Vec<u32>,
+}
+
+// a uniform block and the private vars its members are copied into
+struct UniformBlock {
+    var: u32,
+    members: Vec<(Ty, u32)>,
+}
+
+// packed attributes and varyings, with their number of floats
+type PackedVars = Vec<(u32, usize)>;
+
+// an enclosing loop, branches can only leave the innermost loop so labelled jumps out of
+// the loops nested in it set a flag var and are carried on after each of those loops
+struct LoopTargets {
+    label: Option<Ident>,
+    merge_label: u32,
+    continue_label: u32,
+    break_flag: Option<u32>,
+    continue_flag: Option<u32>,
+}
+
+#[derive(Default)]
+struct FnState {
+    header: Vec<u32>,
+    entry_label: u32,
+    // the function vars, which have to go first in the entry block
+    vars: Vec<u32>,
+    body: Vec<u32>,
+    label: u32,
+    is_terminated: bool,
+    loops: Vec<LoopTargets>,
+    locals: HashMap<(Ident, ScopeSymShadow), u32>,
+    closed_over: HashMap<(Ident, ScopeSymShadow), u32>,
+}
+
+struct SpirvGenerator<'a> {
+    draw_shader_def: &'a DrawShaderDef,
+    shader_registry: &'a Shader,
+    bindings: &'a [GlslBinding],
+    next_id: u32,
+    // the sections of the module, in order
+    capabilities: Vec<u32>,
+    ext_inst_imports: Vec<u32>,
+    memory_model: Vec<u32>,
+    entry_points: Vec<u32>,
+    execution_modes: Vec<u32>,
+    debug_names: Vec<u32>,
+    annotations: Vec<u32>,
+    // types, constants and global vars
+    globals: Vec<u32>,
+    functions: Vec<u32>,
+    glsl_std_450: u32,
+    // types and constants are declared once, keyed on their opcode and operands
+    unique_ids: HashMap<Vec<u32>, u32>,
+    struct_ids: HashMap<StructPtr, u32>,
+    // private copies of the fields, and the texture vars
+    field_vars: HashMap<Ident, u32>,
+    // private copies of the stage builtins, the fns can't reach the stage inputs and outputs
+    builtin_vars: HashMap<StageBuiltin, u32>,
+    live_vars: HashMap<ValuePtr, u32>,
+    const_vars: HashMap<ConstPtr, u32>,
+    uniform_blocks: Vec<UniformBlock>,
+    // keyed on whether it is the vertex stage copy too
+    fn_ids: HashMap<(FnKey, bool), u32>,
+    fn_def: Option<&'a FnDef>,
+    closure_site: Option<(FnPtr, ClosureSite)>,
+    // there are no implicit derivatives in the vertex stage
+    sample_level: bool,
+    vertex_copy_fns: BTreeSet<FnPtr>,
+    f: FnState,
+}
+
+impl<'a> SpirvGenerator<'a> {
+    fn generate_module(&mut self) {
+        push_inst(&mut self.capabilities, Op::Capability, &[capability::SHADER]);
+        self.glsl_std_450 = self.alloc();
+        let mut operands = vec![self.glsl_std_450];
+        operands.extend(string_words("GLSL.std.450"));
+        push_inst(&mut self.ext_inst_imports, Op::ExtInstImport, &operands);
+        // logical addressing, GLSL450 memory model
+        push_inst(&mut self.memory_model, Op::MemoryModel, &[0, 1]);
+
+        self.generate_private_vars();
+        self.generate_uniform_blocks();
+        self.generate_textures();
+        self.generate_fns();
+        self.generate_vertex_main();
+        self.generate_fragment_main();
+    }
+
+    fn finish(self) -> Vec<u32> {
+        let mut words = vec![MAGIC, VERSION_1_0, 0, self.next_id, 0];
+        for section in [
+            &self.capabilities,
+            &self.ext_inst_imports,
+            &self.memory_model,
+            &self.entry_points,
+            &self.execution_modes,
+            &self.debug_names,
+            &self.annotations,
+            &self.globals,
+            &self.functions,
+        ] {
+            words.extend_from_slice(section);
+        }
+        words
+    }
+
+    fn alloc(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn name(&mut self, id: u32, name: &str) {
+        let mut operands = vec![id];
+        operands.extend(string_words(name));
+        push_inst(&mut self.debug_names, Op::Name, &operands);
+    }
+
+    fn member_name(&mut self, id: u32, member: usize, name: &str) {
+        let mut operands = vec![id, member as u32];
+        operands.extend(string_words(name));
+        push_inst(&mut self.debug_names, Op::MemberName, &operands);
+    }
+
+    fn decorate(&mut self, id: u32, operands: &[u32]) {
+        let mut words = vec![id];
+        words.extend_from_slice(operands);
+        push_inst(&mut self.annotations, Op::Decorate, &words);
+    }
+
+    fn member_decorate(&mut self, id: u32, member: usize, operands: &[u32]) {
+        let mut words = vec![id, member as u32];
+        words.extend_from_slice(operands);
+        push_inst(&mut self.annotations, Op::MemberDecorate, &words);
+    }
+
+    fn binding(&mut self, id: u32, ident: &str) {
+        let binding = self.bindings.iter().find( | binding | binding.ident == ident).unwrap();
+        let (set, binding) = (binding.set as u32, binding.binding as u32);
+        self.decorate(id, &[decoration::DESCRIPTOR_SET, set]);
+        self.decorate(id, &[decoration::BINDING, binding]);
+    }
+
+    // types
+
+    fn unique_type(&mut self, op: Op, operands: &[u32]) -> u32 {
+        let mut key = vec![op as u32];
+        key.extend_from_slice(operands);
+        if let Some(id) = self.unique_ids.get(&key) {
+            return *id
+        }
+        let id = self.alloc();
+        let mut words = vec![id];
+        words.extend_from_slice(operands);
+        push_inst(&mut self.globals, op, &words);
+        self.unique_ids.insert(key, id);
+        id
+    }
+
+    fn void_ty(&mut self) -> u32 {
+        self.unique_type(Op::TypeVoid, &[])
+    }
+
+    fn bool_ty(&mut self) -> u32 {
+        self.unique_type(Op::TypeBool, &[])
+    }
+
+    fn int_ty(&mut self) -> u32 {
+        self.unique_type(Op::TypeInt, &[32, 1])
+    }
+
+    fn uint_ty(&mut self) -> u32 {
+        self.unique_type(Op::TypeInt, &[32, 0])
+    }
+
+    fn float_ty(&mut self) -> u32 {
+        self.unique_type(Op::TypeFloat, &[32])
+    }
+
+    fn vector_ty(&mut self, elem_ty: u32, len: usize) -> u32 {
+        self.unique_type(Op::TypeVector, &[elem_ty, len as u32])
+    }
+
+    fn pointer_ty(&mut self, storage_class: u32, ty: u32) -> u32 {
+        self.unique_type(Op::TypePointer, &[storage_class, ty])
+    }
+
+    fn image_ty(&mut self, ty: &Ty) -> u32 {
+        let float_ty = self.float_ty();
+        let (dim, depth, arrayed) = match ty {
+            Ty::TextureCube => (dim::DIM_CUBE, 0, 0),
+            Ty::Texture3D => (dim::DIM_3D, 0, 0),
+            Ty::Texture2DArray => (dim::DIM_2D, 0, 1),
+            Ty::Texture2DDepth => (dim::DIM_2D, 1, 0),
+            _ => (dim::DIM_2D, 0, 0),
+        };
+        // single sampled, used with a sampler, unknown format
+        self.unique_type(Op::TypeImage, &[float_ty, dim, depth, arrayed, 0, 1, 0])
+    }
+
+    fn sampled_image_ty(&mut self, ty: &Ty) -> u32 {
+        let image_ty = self.image_ty(ty);
+        self.unique_type(Op::TypeSampledImage, &[image_ty])
+    }
+
+    fn require_capability(&mut self, capability: u32) {
+        if !self.capabilities.chunks(2).any( | inst | inst[1] == capability) {
+            push_inst(&mut self.capabilities, Op::Capability, &[capability]);
+        }
+    }
+
+    fn ty(&mut self, ty: &Ty) -> u32 {
+        match ty {
+            Ty::Void => self.void_ty(),
+            Ty::Bool => self.bool_ty(),
+            Ty::Int => self.int_ty(),
+            Ty::Uint => self.uint_ty(),
+            // enums are passed around as floats, like on the GLSL side
+            Ty::Float | Ty::Enum(_) => self.float_ty(),
+            Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4
+                | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => {
+                let elem_ty = self.ty(&elem_ty(ty));
+                self.vector_ty(elem_ty, vector_len(ty))
+            }
+            Ty::Mat2 | Ty::Mat3 | Ty::Mat4 => {
+                let column_ty = self.ty(&elem_ty(ty));
+                self.unique_type(Op::TypeMatrix, &[column_ty, vector_len(ty) as u32])
+            }
+            // a texture is only ever handled by pointer
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => {
+                let sampled_image_ty = self.sampled_image_ty(ty);
+                self.pointer_ty(storage_class::UNIFORM_CONSTANT, sampled_image_ty)
+            }
+            Ty::Array {elem_ty, len} => {
+                let elem_ty = self.ty(elem_ty);
+                let len = self.const_u32(*len as u32);
+                self.unique_type(Op::TypeArray, &[elem_ty, len])
+            }
+            Ty::Struct(struct_ptr) => self.struct_ty(*struct_ptr),
+            Ty::DrawShader | Ty::ClosureDecl | Ty::ClosureDef(_) => panic!(),
+        }
+    }
+
+    fn struct_ty(&mut self, struct_ptr: StructPtr) -> u32 {
+        if let Some(id) = self.struct_ids.get(&struct_ptr) {
+            return *id
+        }
+        let struct_def = self.shader_registry.structs.get(&struct_ptr).unwrap();
+        let mut operands = Vec::new();
+        for field in &struct_def.fields {
+            let field_ty = field.ty_expr.ty.borrow().clone().unwrap();
+            operands.push(self.ty(&field_ty));
+        }
+        let id = self.alloc();
+        operands.insert(0, id);
+        push_inst(&mut self.globals, Op::TypeStruct, &operands);
+        self.name(id, &struct_ptr.to_string());
+        for (index, field) in struct_def.fields.iter().enumerate() {
+            self.member_name(id, index, &DisplayStructField(field.ident).to_string());
+        }
+        self.struct_ids.insert(struct_ptr, id);
+        id
+    }
+
+    fn fn_ty(&mut self, return_ty: u32, param_tys: &[u32]) -> u32 {
+        let mut operands = vec![return_ty];
+        operands.extend_from_slice(param_tys);
+        self.unique_type(Op::TypeFunction, &operands)
+    }
+
+    // constants
+
+    fn unique_constant(&mut self, op: Op, ty: u32, operands: &[u32]) -> u32 {
+        let mut key = vec![op as u32, ty];
+        key.extend_from_slice(operands);
+        if let Some(id) = self.unique_ids.get(&key) {
+            return *id
+        }
+        let id = self.alloc();
+        let mut words = vec![ty, id];
+        words.extend_from_slice(operands);
+        push_inst(&mut self.globals, op, &words);
+        self.unique_ids.insert(key, id);
+        id
+    }
+
+    fn const_f32(&mut self, value: f32) -> u32 {
+        let float_ty = self.float_ty();
+        self.unique_constant(Op::Constant, float_ty, &[value.to_bits()])
+    }
+
+    fn const_i32(&mut self, value: i32) -> u32 {
+        let int_ty = self.int_ty();
+        self.unique_constant(Op::Constant, int_ty, &[value as u32])
+    }
+
+    fn const_u32(&mut self, value: u32) -> u32 {
+        let uint_ty = self.uint_ty();
+        self.unique_constant(Op::Constant, uint_ty, &[value])
+    }
+
+    fn const_bool(&mut self, value: bool) -> u32 {
+        let bool_ty = self.bool_ty();
+        self.unique_constant(if value {Op::ConstantTrue} else {Op::ConstantFalse}, bool_ty, &[])
+    }
+
+    fn const_val(&mut self, val: &Val, ty: &Ty) -> u32 {
+        match val {
+            Val::Bool(value) => self.const_bool(*value),
+            Val::Int(value) if is_enum(ty) => self.const_f32(*value as f32),
+            Val::Int(value) => self.const_i32(*value),
+            Val::Uint(value) => self.const_u32(*value),
+            Val::Float(value) => self.const_f32(*value),
+            Val::Vec4(value) => {
+                let components = [
+                    self.const_f32(value.x),
+                    self.const_f32(value.y),
+                    self.const_f32(value.z),
+                    self.const_f32(value.w),
+                ];
+                let vec4_ty = self.ty(&Ty::Vec4);
+                self.unique_constant(Op::ConstantComposite, vec4_ty, &components)
+            }
+        }
+    }
+
+    // global vars
+
+    fn global_var(&mut self, storage_class: u32, ty: u32, name: &str) -> u32 {
+        let pointer_ty = self.pointer_ty(storage_class, ty);
+        let id = self.alloc();
+        push_inst(&mut self.globals, Op::Variable, &[pointer_ty, id, storage_class]);
+        self.name(id, name);
+        id
+    }
+
+    fn generate_private_vars(&mut self) {
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {..} = field.kind {
+                continue;
+            }
+            let ty = self.ty(field.ty_expr.ty.borrow().as_ref().unwrap());
+            let var = self.global_var(storage_class::PRIVATE, ty, &DisplayDsIdent(field.ident).to_string());
+            self.field_vars.insert(field.ident, var);
+        }
+        for stage_builtin in self.shader_registry.stage_builtins(&self.draw_shader_def.all_fns.borrow()) {
+            let ty = self.ty(&stage_builtin.ty());
+            let var = self.global_var(storage_class::PRIVATE, ty, &format!("builtin_{}", stage_builtin.name()));
+            self.builtin_vars.insert(stage_builtin, var);
+        }
+        for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+            let ty = self.ty(ty);
+            let var = self.global_var(storage_class::PRIVATE, ty, &live_ref.to_string());
+            self.live_vars.insert(*live_ref, var);
+        }
+        for (const_ptr, ty) in self.draw_shader_def.all_const_refs.borrow().iter() {
+            let (_, items) = self.shader_registry.const_array(*const_ptr);
+            let elem_ty_id = self.ty(&elem_ty(ty));
+            let mut values = Vec::new();
+            for item in items {
+                let components: Vec<u32> = item.into_iter().map( | value | self.const_f32(value)).collect();
+                values.push(if components.len() == 1 {
+                    components[0]
+                }
+                else {
+                    self.unique_constant(Op::ConstantComposite, elem_ty_id, &components)
+                });
+            }
+            let ty_id = self.ty(ty);
+            let initializer = self.unique_constant(Op::ConstantComposite, ty_id, &values);
+            let pointer_ty = self.pointer_ty(storage_class::PRIVATE, ty_id);
+            let var = self.alloc();
+            push_inst(&mut self.globals, Op::Variable, &[pointer_ty, var, storage_class::PRIVATE, initializer]);
+            self.name(var, &const_ptr.to_string());
+            self.const_vars.insert(*const_ptr, var);
+        }
+    }
+
+    fn generate_uniform_blocks(&mut self) {
+        for (ident, fields) in self.draw_shader_def.fields_as_uniform_blocks() {
+            let members = fields.iter().map( | (index, _) | {
+                let field = &self.draw_shader_def.fields[*index];
+                (
+                    DisplayDsIdent(field.ident).to_string(),
+                    field.ty_expr.ty.borrow().clone().unwrap(),
+                    self.field_vars[&field.ident],
+                )
+            }).collect();
+            self.generate_uniform_block(&format!("Uniforms_{}", ident), members);
+        }
+        let members: Vec<_> = self.draw_shader_def.all_live_refs.borrow().iter().map( | (live_ref, ty) | {
+            (live_ref.to_string(), ty.clone(), self.live_vars[live_ref])
+        }).collect();
+        if !members.is_empty() {
+            self.generate_uniform_block("Uniforms_live", members);
+        }
+    }
+
+    fn generate_uniform_block(&mut self, ident: &str, members: Vec<(String, Ty, u32)>) {
+        let mut operands = Vec::new();
+        for (_, ty, _) in &members {
+            operands.push(self.uniform_member_ty(ty));
+        }
+        let struct_id = self.alloc();
+        operands.insert(0, struct_id);
+        push_inst(&mut self.globals, Op::TypeStruct, &operands);
+        self.name(struct_id, ident);
+        self.decorate(struct_id, &[decoration::BLOCK]);
+
+        let mut offset = 0;
+        for (index, (name, ty, _)) in members.iter().enumerate() {
+            self.member_name(struct_id, index, name);
+            let (size, align) = ty.std140_size_align();
+            offset = (offset + align - 1) / align * align;
+            self.member_decorate(struct_id, index, &[decoration::OFFSET, offset as u32]);
+            // the layout of a matrix in an array is also decorated on the member
+            if ty.is_matrix() || matches!(ty, Ty::Array {elem_ty, ..} if elem_ty.is_matrix()) {
+                self.member_decorate(struct_id, index, &[decoration::COL_MAJOR]);
+                self.member_decorate(struct_id, index, &[decoration::MATRIX_STRIDE, 16]);
+            }
+            offset += size;
+        }
+
+        let var = self.global_var(storage_class::UNIFORM, struct_id, &ident.to_lowercase());
+        self.binding(var, ident);
+        self.uniform_blocks.push(UniformBlock {
+            var,
+            members: members.into_iter().map( | (_, ty, private_var) | (ty, private_var)).collect(),
+        });
+    }
+
+    // the type of a value in a uniform block, where there are no bools and arrays have a stride
+    fn uniform_member_ty(&mut self, ty: &Ty) -> u32 {
+        match ty {
+            Ty::Bool => self.uint_ty(),
+            Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => {
+                let uint_ty = self.uint_ty();
+                self.vector_ty(uint_ty, vector_len(ty))
+            }
+            Ty::Array {elem_ty, len} => {
+                let elem_ty_id = self.uniform_member_ty(elem_ty);
+                let len_id = self.const_u32(*len as u32);
+                // not shared with the array type outside of the block, which has no stride
+                let id = self.alloc();
+                push_inst(&mut self.globals, Op::TypeArray, &[id, elem_ty_id, len_id]);
+                let stride = ty.std140_size_align().0 / len;
+                self.decorate(id, &[decoration::ARRAY_STRIDE, stride as u32]);
+                id
+            }
+            _ => self.ty(ty)
+        }
+    }
+
+    fn generate_textures(&mut self) {
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {..} = field.kind {
+                let sampled_image_ty = self.sampled_image_ty(field.ty_expr.ty.borrow().as_ref().unwrap());
+                let name = DisplayDsIdent(field.ident).to_string();
+                let var = self.global_var(storage_class::UNIFORM_CONSTANT, sampled_image_ty, &name);
+                self.binding(var, &name);
+                self.field_vars.insert(field.ident, var);
+            }
+        }
+    }
+
+    // packed vars are filled 4 floats at a time
+    fn packed_vars(&mut self, storage_class: u32, name: &str, slots: usize, location: &mut u32) -> PackedVars {
+        let mut packed_vars = Vec::new();
+        let mut slots_left = slots;
+        while slots_left > 0 {
+            let size = slots_left.min(4);
+            let float_ty = self.float_ty();
+            let ty = if size == 1 {float_ty} else {self.vector_ty(float_ty, size)};
+            let var = self.global_var(storage_class, ty, &format!("{}_{}", name, packed_vars.len()));
+            self.decorate(var, &[decoration::LOCATION, *location]);
+            *location += 1;
+            packed_vars.push((var, size));
+            slots_left -= size;
+        }
+        packed_vars
+    }
+
+    fn packed_slots(&self, filter: fn(&DrawShaderFieldKind) -> bool) -> usize {
+        self.draw_shader_def.fields.iter()
+            .filter( | field | filter(&field.kind))
+            .map( | field | field.ty_expr.ty.borrow().as_ref().unwrap().slots())
+            .sum()
+    }
+
+    // functions
+
+    // `fn_ptr` is the fn whose body it is, a fn that samples in both stages has a vertex stage copy
+    fn fn_id(&mut self, key: FnKey, fn_ptr: FnPtr) -> u32 {
+        let key = (key, self.is_vertex_copy(fn_ptr));
+        if let Some(id) = self.fn_ids.get(&key) {
+            return *id
+        }
+        let id = self.alloc();
+        self.fn_ids.insert(key, id);
+        id
+    }
+
+    fn is_vertex_copy(&self, fn_ptr: FnPtr) -> bool {
+        self.sample_level && self.vertex_copy_fns.contains(&fn_ptr)
+    }
+
+    fn fn_name_suffix(&self, fn_ptr: FnPtr) -> &'static str {
+        if self.is_vertex_copy(fn_ptr) {"_vertex"} else {""}
+    }
+
+    fn generate_fns(&mut self) {
+        let shader_registry = self.shader_registry;
+        let all_fns = self.draw_shader_def.all_fns.borrow().clone();
+        let vertex_fns = self.draw_shader_def.vertex_fns.borrow().clone();
+        for fn_iter in all_fns.iter().rev() {
+            // a fn that samples with implicit levels in both stages gets a copy for each,
+            // `OpImageSampleImplicitLod` is fragment only and the entry points share the module
+            let sample_levels: &[bool] = if self.vertex_copy_fns.contains(fn_iter) {
+                &[false, true]
+            } else if vertex_fns.contains(fn_iter) {
+                &[true]
+            } else {
+                &[false]
+            };
+            for sample_level in sample_levels {
+                self.sample_level = *sample_level;
+                self.generate_fn_def(shader_registry.all_fns.get(fn_iter).unwrap());
+            }
+        }
+    }
+
+    fn generate_fn_def(&mut self, fn_def: &'a FnDef) {
+        let shader_registry = self.shader_registry;
+        if fn_def.has_closure_args() {
+            let all_fns = self.draw_shader_def.all_fns.borrow().clone();
+            for call_iter in all_fns.iter().rev() {
+                // any function that depends on us, will have the closures we need
+                let call_def = shader_registry.all_fns.get(call_iter).unwrap();
+                if call_def.callees.borrow().as_ref().unwrap().contains(&fn_def.fn_ptr) {
+                    self.generate_fn_def_with_all_closures(fn_def, call_def);
+                }
+            }
+            return
+        }
+        self.fn_def = Some(fn_def);
+        self.closure_site = None;
+        let id = self.fn_id(FnKey::Plain(fn_def.fn_ptr), fn_def.fn_ptr);
+        let return_ty = fn_def.return_ty.borrow().clone().unwrap();
+        self.generate_fn(
+            id,
+            &format!("{}{}", DisplayFnName(fn_def.fn_ptr, fn_def.ident), self.fn_name_suffix(fn_def.fn_ptr)),
+            fn_def_params(fn_def),
+            &return_ty,
+            FnBody::Block(&fn_def.block),
+        );
+    }
+
+    fn generate_fn_def_with_all_closures(&mut self, fn_def: &'a FnDef, call_def: &'a FnDef) {
+        let closure_sites = call_def.closure_sites.borrow().clone().unwrap();
+        for (closure_def_index, closure_def) in call_def.closure_defs.iter().enumerate() {
+            let closure_def_index = ClosureDefIndex(closure_def_index);
+            for closure_site in &closure_sites {
+                if closure_site.call_to != fn_def.fn_ptr {
+                    continue;
+                }
+                for closure_site_arg in &closure_site.closure_args {
+                    if closure_site_arg.closure_def_index != closure_def_index {
+                        continue;
+                    }
+                    let (params, return_ty) = match &fn_def.params[closure_site_arg.param_index].ty_expr.kind {
+                        TyExprKind::ClosureDecl {params, return_ty, ..} => (params, return_ty.borrow().clone().unwrap()),
+                        _ => panic!()
+                    };
+                    let mut fn_params = Vec::new();
+                    for (param, closure_param) in params.iter().zip(&closure_def.params) {
+                        fn_params.push(FnParam {
+                            kind: ParamKind::Local(closure_param.ident, closure_param.shadow.get().unwrap()),
+                            ty: param.ty_expr.ty.borrow().clone().unwrap(),
+                            is_inout: param.is_inout,
+                        });
+                    }
+                    for sym in closure_def.closed_over_syms.borrow().as_ref().unwrap() {
+                        if sym.ty == Ty::DrawShader {
+                            continue;
+                        }
+                        fn_params.push(FnParam {
+                            kind: ParamKind::Local(sym.ident, sym.shadow),
+                            ty: sym.ty.clone(),
+                            is_inout: false,
+                        });
+                    }
+                    self.fn_def = Some(fn_def);
+                    self.closure_site = None;
+                    let id = self.fn_id(FnKey::Closure(call_def.fn_ptr, closure_def_index), fn_def.fn_ptr);
+                    let body = match &closure_def.kind {
+                        ClosureDefKind::Expr(expr) => FnBody::Expr(expr),
+                        ClosureDefKind::Block(block) => FnBody::Block(block),
+                    };
+                    self.generate_fn(
+                        id,
+                        &format!("{}{}", DisplayClosureName(call_def.fn_ptr, closure_def_index), self.fn_name_suffix(fn_def.fn_ptr)),
+                        fn_params,
+                        &return_ty,
+                        body,
+                    );
+                }
+            }
+        }
+
+        for (site_index, closure_site) in closure_sites.into_iter().enumerate() {
+            if closure_site.call_to != fn_def.fn_ptr {
+                continue;
+            }
+            let mut fn_params = fn_def_params(fn_def);
+            for sym in &closure_site.all_closed_over {
+                if sym.ty == Ty::DrawShader {
+                    continue;
+                }
+                fn_params.push(FnParam {
+                    kind: ParamKind::ClosedOver(sym.ident, sym.shadow),
+                    ty: sym.ty.clone(),
+                    is_inout: false,
+                });
+            }
+            self.fn_def = Some(fn_def);
+            self.closure_site = Some((call_def.fn_ptr, closure_site));
+            let id = self.fn_id(FnKey::Site(call_def.fn_ptr, site_index), fn_def.fn_ptr);
+            let return_ty = fn_def.return_ty.borrow().clone().unwrap();
+            self.generate_fn(
+                id,
+                &format!("{}{}", DisplayFnNameWithClosureArgs(site_index, call_def.fn_ptr, fn_def.ident), self.fn_name_suffix(fn_def.fn_ptr)),
+                fn_params,
+                &return_ty,
+                FnBody::Block(&fn_def.block),
+            );
+        }
+    }
+
+    fn begin_fn(&mut self, id: u32, return_ty: u32, param_tys: &[u32]) -> Vec<u32> {
+        self.f = FnState::default();
+        let fn_ty = self.fn_ty(return_ty, param_tys);
+        push_inst(&mut self.f.header, Op::Function, &[return_ty, id, 0, fn_ty]);
+        let mut param_ids = Vec::new();
+        for param_ty in param_tys {
+            let param_id = self.alloc();
+            push_inst(&mut self.f.header, Op::FunctionParameter, &[*param_ty, param_id]);
+            param_ids.push(param_id);
+        }
+        self.f.entry_label = self.alloc();
+        self.f.label = self.f.entry_label;
+        param_ids
+    }
+
+    fn end_fn(&mut self, return_ty: &Ty) {
+        if !self.f.is_terminated {
+            if *return_ty == Ty::Void {
+                self.emit(Op::Return, &[]);
+            }
+            else {
+                self.emit(Op::Unreachable, &[]);
+            }
+        }
+        self.functions.extend_from_slice(&self.f.header);
+        push_inst(&mut self.functions, Op::Label, &[self.f.entry_label]);
+        self.functions.extend_from_slice(&self.f.vars);
+        self.functions.extend_from_slice(&self.f.body);
+        push_inst(&mut self.functions, Op::FunctionEnd, &[]);
+    }
+
+    fn generate_fn(&mut self, id: u32, name: &str, params: Vec<FnParam>, return_ty: &Ty, body: FnBody) {
+        self.name(id, name);
+        let param_tys: Vec<u32> = params.iter().map( | param | {
+            let ty = self.ty(&param.ty);
+            if param.is_inout {self.pointer_ty(storage_class::FUNCTION, ty)} else {ty}
+        }).collect();
+        let return_ty_id = self.ty(return_ty);
+        let param_ids = self.begin_fn(id, return_ty_id, &param_tys);
+        for (param, param_id) in params.iter().zip(param_ids) {
+            match param.kind {
+                ParamKind::Local(ident, shadow) => {
+                    self.name(param_id, &DisplayVarName(ident, shadow).to_string());
+                    // params can be assigned to, so they get a var of their own
+                    let ptr = if param.is_inout || param.ty.is_texture() {
+                        param_id
+                    }
+                    else {
+                        let ty = self.ty(&param.ty);
+                        let var = self.local_var(ty);
+                        self.emit(Op::Store, &[var, param_id]);
+                        var
+                    };
+                    self.f.locals.insert((ident, shadow), ptr);
+                }
+                ParamKind::ClosedOver(ident, shadow) => {
+                    self.name(param_id, &DisplayClosedOverArg(ident, shadow).to_string());
+                    self.f.closed_over.insert((ident, shadow), param_id);
+                }
+            }
+        }
+        match body {
+            FnBody::Block(block) => self.generate_block(block),
+            FnBody::Expr(expr) => {
+                let value = self.generate_expr(expr);
+                self.emit(Op::ReturnValue, &[value]);
+                self.f.is_terminated = true;
+            }
+        }
+        self.end_fn(return_ty);
+    }
+
+    fn emit(&mut self, op: Op, operands: &[u32]) {
+        push_inst(&mut self.f.body, op, operands);
+    }
+
+    fn emit_value(&mut self, op: Op, ty: u32, operands: &[u32]) -> u32 {
+        let id = self.alloc();
+        let mut words = vec![ty, id];
+        words.extend_from_slice(operands);
+        push_inst(&mut self.f.body, op, &words);
+        id
+    }
+
+    fn local_var(&mut self, ty: u32) -> u32 {
+        let pointer_ty = self.pointer_ty(storage_class::FUNCTION, ty);
+        let id = self.alloc();
+        push_inst(&mut self.f.vars, Op::Variable, &[pointer_ty, id, storage_class::FUNCTION]);
+        id
+    }
+
+    fn label(&mut self, label: u32) {
+        self.emit(Op::Label, &[label]);
+        self.f.label = label;
+        self.f.is_terminated = false;
+    }
+
+    fn branch(&mut self, target: u32) {
+        if !self.f.is_terminated {
+            self.emit(Op::Branch, &[target]);
+            self.f.is_terminated = true;
+        }
+    }
+
+    // a structured `if`, with the current block as the header
+    fn branch_conditional(&mut self, cond: u32, merge_label: u32, true_label: u32, false_label: u32) {
+        self.emit(Op::SelectionMerge, &[merge_label, 0]);
+        self.emit(Op::BranchConditional, &[cond, true_label, false_label]);
+        self.f.is_terminated = true;
+    }
+
+    fn ext_inst(&mut self, ty: u32, inst: u32, args: &[u32]) -> u32 {
+        let mut operands = vec![self.glsl_std_450, inst];
+        operands.extend_from_slice(args);
+        self.emit_value(Op::ExtInst, ty, &operands)
+    }
+
+    // entry points
+
+    fn generate_entry_point(&mut self, execution_model: u32, name: &str, interface: &[u32]) -> u32 {
+        let id = self.alloc();
+        let mut operands = vec![execution_model, id];
+        operands.extend(string_words(name));
+        operands.extend_from_slice(interface);
+        push_inst(&mut self.entry_points, Op::EntryPoint, &operands);
+        self.name(id, name);
+        let void_ty = self.void_ty();
+        self.begin_fn(id, void_ty, &[]);
+        self.generate_uniform_unpack();
+        id
+    }
+
+    fn generate_vertex_main(&mut self) {
+        let mut location = 0;
+        let geometry_slots = self.packed_slots( | kind | matches!(kind, DrawShaderFieldKind::Geometry {..}));
+        let packed_geometries = self.packed_vars(storage_class::INPUT, "packed_geometry", geometry_slots, &mut location);
+        // instances are numbered after the geometries
+        let instance_slots = self.packed_slots( | kind | matches!(kind, DrawShaderFieldKind::Instance {..}));
+        let packed_instances = self.packed_vars(storage_class::INPUT, "packed_instance", instance_slots, &mut location);
+        let mut location = 0;
+        let varying_slots = self.packed_slots(is_varying);
+        let packed_varyings = self.packed_vars(storage_class::OUTPUT, "packed_varying", varying_slots, &mut location);
+        let vec4_ty = self.ty(&Ty::Vec4);
+        let position = self.global_var(storage_class::OUTPUT, vec4_ty, "position");
+        self.decorate(position, &[decoration::BUILT_IN, built_in::POSITION]);
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow());
+        let mut builtin_inputs = Vec::new();
+        for (stage_builtin, built_in) in [(StageBuiltin::VertexId, built_in::VERTEX_INDEX), (StageBuiltin::InstanceId, built_in::INSTANCE_INDEX)] {
+            if stage_builtins.contains(&stage_builtin) {
+                builtin_inputs.push((stage_builtin, self.builtin_var(storage_class::INPUT, &stage_builtin.ty(), stage_builtin.name(), built_in)));
+            }
+        }
+        let point_size = if stage_builtins.contains(&StageBuiltin::PointSize) {
+            Some(self.builtin_var(storage_class::OUTPUT, &Ty::Float, "point_size", built_in::POINT_SIZE))
+        }
+        else {
+            None
+        };
+
+        let mut interface: Vec<u32> = packed_geometries.iter().chain(&packed_instances).map( | (var, _) | *var).collect();
+        interface.push(position);
+        interface.extend(packed_varyings.iter().map( | (var, _) | *var));
+        interface.extend(builtin_inputs.iter().map( | (_, var) | *var));
+        interface.extend(point_size);
+        self.generate_entry_point(execution_model::VERTEX, "vertex_main", &interface);
+        self.generate_builtin_copy_in(&builtin_inputs);
+        if point_size.is_some() {
+            let one = self.const_f32(1.0);
+            self.emit(Op::Store, &[self.builtin_vars[&StageBuiltin::PointSize], one]);
+        }
+
+        self.generate_packed_unpack(&packed_geometries, | kind | matches!(kind, DrawShaderFieldKind::Geometry {..}));
+        self.generate_packed_unpack(&packed_instances, | kind | matches!(kind, DrawShaderFieldKind::Instance {..}));
+        let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(vertex))).unwrap();
+        let vertex_fn = self.fn_id(FnKey::Plain(vertex_def.fn_ptr), vertex_def.fn_ptr);
+        let value = self.emit_value(Op::FunctionCall, vec4_ty, &[vertex_fn]);
+        self.emit(Op::Store, &[position, value]);
+
+        let mut components = Vec::new();
+        for field in &self.draw_shader_def.fields {
+            if is_varying(&field.kind) {
+                let ty = field.ty_expr.ty.borrow().clone().unwrap();
+                let ty_id = self.ty(&ty);
+                let value = self.emit_value(Op::Load, ty_id, &[self.field_vars[&field.ident]]);
+                let scalars = self.scalars(value, &ty);
+                for scalar in scalars {
+                    components.push(self.convert_scalar(scalar, &elem_ty(&ty), &Ty::Float));
+                }
+            }
+        }
+        let mut components = components.into_iter();
+        for (var, size) in packed_varyings {
+            let scalars: Vec<u32> = components.by_ref().take(size).collect();
+            let value = self.from_scalars(&packed_ty(size), &scalars);
+            self.emit(Op::Store, &[var, value]);
+        }
+        if let Some(point_size) = point_size {
+            self.generate_builtin_copy_out(StageBuiltin::PointSize, point_size);
+        }
+        self.end_fn(&Ty::Void);
+    }
+
+    fn generate_fragment_main(&mut self) {
+        let mut location = 0;
+        let varying_slots = self.packed_slots(is_varying);
+        let packed_varyings = self.packed_vars(storage_class::INPUT, "packed_varying", varying_slots, &mut location);
+        // a `vec4` return is written to `frag_color`, a struct to an output per field
+        let pixel_outputs: Vec<(String, Ty)> = match self.shader_registry.pixel_outputs(self.draw_shader_def) {
+            Some(pixel_outputs) => pixel_outputs.into_iter().map( | (ident, ty) | (DisplayPixelOutput(ident).to_string(), ty)).collect(),
+            None => vec![(String::from("frag_color"), Ty::Vec4)]
+        };
+        let mut output_vars = Vec::new();
+        for (index, (name, ty)) in pixel_outputs.iter().enumerate() {
+            let ty_id = self.ty(ty);
+            let var = self.global_var(storage_class::OUTPUT, ty_id, name);
+            self.decorate(var, &[decoration::LOCATION, index as u32]);
+            output_vars.push(var);
+        }
+
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.pixel_fns.borrow());
+        let mut builtin_inputs = Vec::new();
+        for (stage_builtin, built_in) in [
+            (StageBuiltin::FragCoord, built_in::FRAG_COORD),
+            (StageBuiltin::FrontFacing, built_in::FRONT_FACING),
+            (StageBuiltin::PointCoord, built_in::POINT_COORD)
+        ] {
+            if stage_builtins.contains(&stage_builtin) {
+                builtin_inputs.push((stage_builtin, self.builtin_var(storage_class::INPUT, &stage_builtin.ty(), stage_builtin.name(), built_in)));
+            }
+        }
+        // the depth starts out as `frag_coord.z`, which needs the input even when `frag_coord` isn't used
+        let frag_depth = if stage_builtins.contains(&StageBuiltin::FragDepth) {
+            let frag_coord = match builtin_inputs.iter().find( | (stage_builtin, _) | *stage_builtin == StageBuiltin::FragCoord) {
+                Some((_, var)) => *var,
+                None => self.builtin_var(storage_class::INPUT, &Ty::Vec4, "frag_coord", built_in::FRAG_COORD)
+            };
+            Some((frag_coord, self.builtin_var(storage_class::OUTPUT, &Ty::Float, "frag_depth", built_in::FRAG_DEPTH)))
+        }
+        else {
+            None
+        };
+
+        let mut interface: Vec<u32> = packed_varyings.iter().map( | (var, _) | *var).collect();
+        interface.extend(&output_vars);
+        interface.extend(builtin_inputs.iter().map( | (_, var) | *var));
+        if let Some((frag_coord, frag_depth)) = frag_depth {
+            if !stage_builtins.contains(&StageBuiltin::FragCoord) {
+                interface.push(frag_coord);
+            }
+            interface.push(frag_depth);
+        }
+        let id = self.generate_entry_point(execution_model::FRAGMENT, "fragment_main", &interface);
+        push_inst(&mut self.execution_modes, Op::ExecutionMode, &[id, execution_mode::ORIGIN_UPPER_LEFT]);
+        if frag_depth.is_some() {
+            push_inst(&mut self.execution_modes, Op::ExecutionMode, &[id, execution_mode::DEPTH_REPLACING]);
+        }
+        self.generate_builtin_copy_in(&builtin_inputs);
+        if let Some((frag_coord, _)) = frag_depth {
+            let float_ty = self.ty(&Ty::Float);
+            let vec4_ty = self.ty(&Ty::Vec4);
+            let value = self.emit_value(Op::Load, vec4_ty, &[frag_coord]);
+            let z = self.emit_value(Op::CompositeExtract, float_ty, &[value, 2]);
+            self.emit(Op::Store, &[self.builtin_vars[&StageBuiltin::FragDepth], z]);
+        }
+
+        self.generate_packed_unpack(&packed_varyings, is_varying);
+        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
+        let pixel_fn = self.fn_id(FnKey::Plain(pixel_def.fn_ptr), pixel_def.fn_ptr);
+        let return_ty = pixel_def.return_ty.borrow().clone().unwrap();
+        let return_ty_id = self.ty(&return_ty);
+        let value = self.emit_value(Op::FunctionCall, return_ty_id, &[pixel_fn]);
+        if let Ty::Struct(_) = return_ty {
+            for (index, ((_, ty), var)) in pixel_outputs.iter().zip(output_vars).enumerate() {
+                let ty_id = self.ty(ty);
+                let field = self.emit_value(Op::CompositeExtract, ty_id, &[value, index as u32]);
+                self.emit(Op::Store, &[var, field]);
+            }
+        }
+        else {
+            self.emit(Op::Store, &[output_vars[0], value]);
+        }
+        if let Some((_, frag_depth)) = frag_depth {
+            self.generate_builtin_copy_out(StageBuiltin::FragDepth, frag_depth);
+        }
+        self.end_fn(&Ty::Void);
+    }
+
+    fn builtin_var(&mut self, storage_class: u32, ty: &Ty, name: &str, built_in: u32) -> u32 {
+        let ty_id = self.ty(ty);
+        let var = self.global_var(storage_class, ty_id, name);
+        self.decorate(var, &[decoration::BUILT_IN, built_in]);
+        var
+    }
+
+    fn generate_builtin_copy_in(&mut self, builtin_inputs: &[(StageBuiltin, u32)]) {
+        for (stage_builtin, var) in builtin_inputs {
+            let ty_id = self.ty(&stage_builtin.ty());
+            let value = self.emit_value(Op::Load, ty_id, &[*var]);
+            self.emit(Op::Store, &[self.builtin_vars[stage_builtin], value]);
+        }
+    }
+
+    fn generate_builtin_copy_out(&mut self, stage_builtin: StageBuiltin, var: u32) {
+        let ty_id = self.ty(&stage_builtin.ty());
+        let value = self.emit_value(Op::Load, ty_id, &[self.builtin_vars[&stage_builtin]]);
+        self.emit(Op::Store, &[var, value]);
+    }
+
+    fn generate_uniform_unpack(&mut self) {
+        for block_index in 0..self.uniform_blocks.len() {
+            for member_index in 0..self.uniform_blocks[block_index].members.len() {
+                let block_var = self.uniform_blocks[block_index].var;
+                let (ty, private_var) = self.uniform_blocks[block_index].members[member_index].clone();
+                let index = self.const_i32(member_index as i32);
+                if let Ty::Array {elem_ty, len} = &ty {
+                    // the array types differ in their stride, so the items are copied one by one
+                    let elem_ty_id = self.ty(elem_ty);
+                    let pointer_ty = self.pointer_ty(storage_class::PRIVATE, elem_ty_id);
+                    for item_index in 0..*len {
+                        let item_index = self.const_i32(item_index as i32);
+                        let value = self.load_uniform(&[block_var, index, item_index], elem_ty);
+                        let ptr = self.emit_value(Op::AccessChain, pointer_ty, &[private_var, item_index]);
+                        self.emit(Op::Store, &[ptr, value]);
+                    }
+                }
+                else {
+                    let value = self.load_uniform(&[block_var, index], &ty);
+                    self.emit(Op::Store, &[private_var, value]);
+                }
+            }
+        }
+    }
+
+    fn load_uniform(&mut self, access_chain: &[u32], ty: &Ty) -> u32 {
+        let member_ty = self.uniform_member_ty(ty);
+        let pointer_ty = self.pointer_ty(storage_class::UNIFORM, member_ty);
+        let ptr = self.emit_value(Op::AccessChain, pointer_ty, access_chain);
+        let value = self.emit_value(Op::Load, member_ty, &[ptr]);
+        if elem_ty(ty) != Ty::Bool {
+            return value
+        }
+        let zero = self.const_u32(0);
+        let zero = if *ty == Ty::Bool {zero} else {self.emit_value(Op::CompositeConstruct, member_ty, &vec![zero; vector_len(ty)])};
+        let bool_ty = self.ty(ty);
+        self.emit_value(Op::INotEqual, bool_ty, &[value, zero])
+    }
+
+    fn generate_packed_unpack(&mut self, packed_vars: &[(u32, usize)], filter: fn(&DrawShaderFieldKind) -> bool) {
+        let mut components = Vec::new();
+        for (var, size) in packed_vars {
+            let ty = self.ty(&packed_ty(*size));
+            let value = self.emit_value(Op::Load, ty, &[*var]);
+            components.extend(self.scalars(value, &packed_ty(*size)));
+        }
+        let mut components = components.into_iter();
+        for field in &self.draw_shader_def.fields {
+            if !filter(&field.kind) {
+                continue;
+            }
+            let ty = field.ty_expr.ty.borrow().clone().unwrap();
+            let scalars: Vec<u32> = components.by_ref().take(ty.slots()).collect();
+            let scalars: Vec<u32> = scalars.into_iter().map( | scalar | self.convert_scalar(scalar, &Ty::Float, &elem_ty(&ty))).collect();
+            let value = self.from_scalars(&ty, &scalars);
+            self.emit(Op::Store, &[self.field_vars[&field.ident], value]);
+        }
+    }
+
+    // statements
+
+    fn generate_block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            // whatever follows a `return`, `break` or `continue` is never reached
+            if self.f.is_terminated {
+                break;
+            }
+            self.generate_stmt(stmt);
+        }
+    }
+
+    fn generate_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Break {label, ..} => {
+                if let Some(break_flag) = self.jump_flag(*label, | targets | targets.break_flag) {
+                    let true_const = self.const_bool(true);
+                    self.emit(Op::Store, &[break_flag, true_const]);
+                }
+                let merge_label = self.f.loops.last().unwrap().merge_label;
+                self.branch(merge_label);
+            }
+            Stmt::Continue {label, ..} => {
+                let targets = self.f.loops.last().unwrap();
+                let (merge_label, continue_label) = (targets.merge_label, targets.continue_label);
+                if let Some(continue_flag) = self.jump_flag(*label, | targets | targets.continue_flag) {
+                    let true_const = self.const_bool(true);
+                    self.emit(Op::Store, &[continue_flag, true_const]);
+                    self.branch(merge_label);
+                }
+                else {
+                    self.branch(continue_label);
+                }
+            }
+            Stmt::For {label, ident, shadow, from_expr, to_expr, step_expr, block, ..} => {
+                self.generate_for_stmt(*label, *ident, shadow.get().unwrap(), from_expr, to_expr, step_expr, block)
+            }
+            Stmt::While {label, expr, block, ..} => self.generate_while_stmt(*label, Some(expr), block),
+            Stmt::Loop {label, block, ..} => self.generate_while_stmt(*label, None, block),
+            Stmt::If {expr, block_if_true, block_if_false, ..} => {
+                let cond = self.generate_expr(expr);
+                let true_label = self.alloc();
+                let merge_label = self.alloc();
+                let false_label = if block_if_false.is_some() {self.alloc()} else {merge_label};
+                self.branch_conditional(cond, merge_label, true_label, false_label);
+                self.label(true_label);
+                self.generate_block(block_if_true);
+                self.branch(merge_label);
+                if let Some(block_if_false) = block_if_false {
+                    self.label(false_label);
+                    self.generate_block(block_if_false);
+                    self.branch(merge_label);
+                }
+                self.label(merge_label);
+            }
+            Stmt::Match {expr, matches, ..} => {
+                let value = self.generate_expr(expr);
+                self.generate_match_arms(value, matches);
+            }
+            Stmt::Let {ty, ident, expr, shadow, ..} => {
+                let ty = ty.borrow().clone().unwrap();
+                let key = (*ident, shadow.get().unwrap());
+                if ty.is_texture() {
+                    let ptr = self.generate_expr(expr.as_ref().unwrap());
+                    self.f.locals.insert(key, ptr);
+                    return
+                }
+                let ty_id = self.ty(&ty);
+                let var = self.local_var(ty_id);
+                self.name(var, &DisplayVarName(key.0, key.1).to_string());
+                if let Some(expr) = expr {
+                    let value = self.generate_expr(expr);
+                    self.emit(Op::Store, &[var, value]);
+                }
+                self.f.locals.insert(key, var);
+            }
+            Stmt::Return {expr, ..} => {
+                if let Some(expr) = expr {
+                    let value = self.generate_expr(expr);
+                    self.emit(Op::ReturnValue, &[value]);
+                }
+                else {
+                    self.emit(Op::Return, &[]);
+                }
+                self.f.is_terminated = true;
+            }
+            Stmt::Discard {..} => {
+                self.emit(Op::Kill, &[]);
+                self.f.is_terminated = true;
+            }
+            Stmt::Block {block, ..} => self.generate_block(block),
+            Stmt::Expr {expr, ..} => {
+                self.generate_expr(expr);
+            }
+        }
+    }
+
+    fn generate_for_stmt(&mut self, label: Option<Ident>, ident: Ident, shadow: ScopeSymShadow, from_expr: &Expr, to_expr: &Expr, step_expr: &Option<Expr>, block: &Block) {
+        let const_int = | expr: &Expr | expr.const_val.borrow().as_ref().unwrap().as_ref().unwrap().to_int().unwrap();
+        let from = const_int(from_expr);
+        let to = const_int(to_expr);
+        let step = match step_expr {
+            Some(step_expr) => const_int(step_expr),
+            None => if from < to {1} else {-1}
+        };
+        let int_ty = self.int_ty();
+        let bool_ty = self.bool_ty();
+        let break_flag = self.break_flag(label, block);
+        let var = self.local_var(int_ty);
+        self.name(var, &ident.to_string());
+        let start = self.const_i32(if from <= to {from} else {from - 1});
+        self.emit(Op::Store, &[var, start]);
+
+        let header_label = self.alloc();
+        let cond_label = self.alloc();
+        let body_label = self.alloc();
+        let continue_label = self.alloc();
+        let merge_label = self.alloc();
+        self.branch(header_label);
+        self.label(header_label);
+        self.emit(Op::LoopMerge, &[merge_label, continue_label, 0]);
+        self.branch(cond_label);
+
+        self.label(cond_label);
+        let value = self.emit_value(Op::Load, int_ty, &[var]);
+        let to_const = self.const_i32(to);
+        let cond = self.emit_value(if from <= to {Op::SLessThan} else {Op::SGreaterThanEqual}, bool_ty, &[value, to_const]);
+        self.emit(Op::BranchConditional, &[cond, body_label, merge_label]);
+        self.f.is_terminated = true;
+
+        self.label(body_label);
+        self.f.locals.insert((ident, shadow), var);
+        self.generate_loop_body(label, merge_label, continue_label, break_flag, block);
+
+        self.label(continue_label);
+        let value = self.emit_value(Op::Load, int_ty, &[var]);
+        let step = self.const_i32(step);
+        let value = self.emit_value(Op::IAdd, int_ty, &[value, step]);
+        self.emit(Op::Store, &[var, value]);
+        self.branch(header_label);
+        self.label(merge_label);
+        self.carry_jumps_out_of_loop(label, block);
+    }
+
+    // `loop` is a `while` without a condition
+    fn generate_while_stmt(&mut self, label: Option<Ident>, expr: Option<&Expr>, block: &Block) {
+        let break_flag = self.break_flag(label, block);
+        let header_label = self.alloc();
+        let cond_label = self.alloc();
+        let body_label = self.alloc();
+        let continue_label = self.alloc();
+        let merge_label = self.alloc();
+        self.branch(header_label);
+        self.label(header_label);
+        self.emit(Op::LoopMerge, &[merge_label, continue_label, 0]);
+        self.branch(cond_label);
+
+        self.label(cond_label);
+        if let Some(expr) = expr {
+            let cond = self.generate_expr(expr);
+            self.emit(Op::BranchConditional, &[cond, body_label, merge_label]);
+            self.f.is_terminated = true;
+        }
+        else {
+            self.branch(body_label);
+        }
+
+        self.label(body_label);
+        self.generate_loop_body(label, merge_label, continue_label, break_flag, block);
+
+        self.label(continue_label);
+        self.branch(header_label);
+        self.label(merge_label);
+        self.carry_jumps_out_of_loop(label, block);
+    }
+
+    // the flag var set by `break 'label` in the loops nested in the loop labelled `label`
+    fn break_flag(&mut self, label: Option<Ident>, block: &Block) -> Option<u32> {
+        if !block.jumps_out_of_inner_loops(label?).breaks {
+            return None
+        }
+        let bool_ty = self.bool_ty();
+        let flag = self.local_var(bool_ty);
+        self.name(flag, &format!("loop_break_{}", label.unwrap()));
+        let false_const = self.const_bool(false);
+        self.emit(Op::Store, &[flag, false_const]);
+        Some(flag)
+    }
+
+    fn generate_loop_body(&mut self, label: Option<Ident>, merge_label: u32, continue_label: u32, break_flag: Option<u32>, block: &Block) {
+        let continue_flag = match label {
+            Some(label) if block.jumps_out_of_inner_loops(label).continues => {
+                let bool_ty = self.bool_ty();
+                let flag = self.local_var(bool_ty);
+                self.name(flag, &format!("loop_continue_{}", label));
+                let false_const = self.const_bool(false);
+                self.emit(Op::Store, &[flag, false_const]);
+                Some(flag)
+            }
+            _ => None
+        };
+        self.f.loops.push(LoopTargets {label, merge_label, continue_label, break_flag, continue_flag});
+        self.generate_block(block);
+        self.f.loops.pop();
+        self.branch(continue_label);
+    }
+
+    // the flag a labelled jump sets when it leaves more than the innermost loop
+    fn jump_flag(&self, label: Option<Ident>, flag: fn(&LoopTargets) -> Option<u32>) -> Option<u32> {
+        let label = label?;
+        if self.f.loops.last().unwrap().label == Some(label) {
+            return None
+        }
+        self.f.loops.iter().rev().find( | targets | targets.label == Some(label)).and_then(flag)
+    }
+
+    // after a loop, goes on with the labelled jumps that left it towards the loops around it
+    fn carry_jumps_out_of_loop(&mut self, label: Option<Ident>, block: &Block) {
+        let mut seen = Vec::new();
+        let mut branches = Vec::new();
+        for (depth, targets) in self.f.loops.iter().enumerate().rev() {
+            let targets_label = match targets.label {
+                Some(targets_label) if Some(targets_label) != label && !seen.contains(&targets_label) => targets_label,
+                _ => continue
+            };
+            seen.push(targets_label);
+            let jumps = block.jumps_to(targets_label);
+            let innermost = self.f.loops.last().unwrap();
+            if let (Some(break_flag), true) = (targets.break_flag, jumps.breaks) {
+                branches.push((break_flag, innermost.merge_label));
+            }
+            if let (Some(continue_flag), true) = (targets.continue_flag, jumps.continues) {
+                let is_innermost = depth + 1 == self.f.loops.len();
+                branches.push((continue_flag, if is_innermost {innermost.continue_label} else {innermost.merge_label}));
+            }
+        }
+        let bool_ty = self.bool_ty();
+        for (flag, target) in branches {
+            let cond = self.emit_value(Op::Load, bool_ty, &[flag]);
+            let true_label = self.alloc();
+            let merge_label = self.alloc();
+            self.branch_conditional(cond, merge_label, true_label, merge_label);
+            self.label(true_label);
+            self.branch(target);
+            self.label(merge_label);
+        }
+    }
+
+    // a chain of `if`s, enums are floats so they are compared within half a unit
+    fn generate_match_arms(&mut self, value: u32, matches: &[Match]) {
+        let (match_item, rest) = match matches.split_first() {
+            Some(split) => split,
+            None => return
+        };
+        let float_ty = self.float_ty();
+        let bool_ty = self.bool_ty();
+        let enum_value = self.const_f32(match_item.enum_value.get().unwrap() as f32);
+        let diff = self.emit_value(Op::FSub, float_ty, &[value, enum_value]);
+        let diff = self.ext_inst(float_ty, glsl_std_450::F_ABS, &[diff]);
+        let half = self.const_f32(0.5);
+        let cond = self.emit_value(Op::FOrdLessThan, bool_ty, &[diff, half]);
+        let arm_label = self.alloc();
+        let merge_label = self.alloc();
+        let else_label = if rest.is_empty() {merge_label} else {self.alloc()};
+        self.branch_conditional(cond, merge_label, arm_label, else_label);
+        self.label(arm_label);
+        self.generate_block(&match_item.block);
+        self.branch(merge_label);
+        if !rest.is_empty() {
+            self.label(else_label);
+            self.generate_match_arms(value, rest);
+            self.branch(merge_label);
+        }
+        self.label(merge_label);
+    }
+
+    // expressions
+
+    fn generate_expr(&mut self, expr: &Expr) -> u32 {
+        let ty = expr.ty.borrow().clone().unwrap();
+        let const_val = expr.const_val.borrow().clone();
+        if let Some(Some(val)) = const_val {
+            return self.const_val(&val, &ty)
+        }
+        match &expr.kind {
+            ExprKind::Cond {expr, expr_if_true, expr_if_false, ..} => {
+                let cond = self.generate_expr(expr);
+                self.generate_phi(&ty, cond, | this | this.generate_expr(expr_if_true), | this | this.generate_expr(expr_if_false))
+            }
+            ExprKind::Bin {op, left_expr, right_expr, ..} => self.generate_bin_expr(*op, left_expr, right_expr, &ty),
+            ExprKind::Un {op, expr, ..} => {
+                let value = self.generate_expr(expr);
+                match op {
+                    UnOp::Not => {
+                        let ty_id = self.ty(&ty);
+                        let op = if ty.is_integer() {Op::Not} else {Op::LogicalNot};
+                        self.emit_value(op, ty_id, &[value])
+                    }
+                    UnOp::Neg => self.generate_neg(value, &ty),
+                }
+            }
+            ExprKind::Field {expr, field_ident, ..} => self.generate_field_expr(expr, *field_ident, &ty),
+            ExprKind::Index {expr, index_expr, ..} => self.generate_index_expr(expr, index_expr, &ty),
+            ExprKind::MethodCall {ident, arg_exprs, closure_site_index, ..} => {
+                let (fn_def, arg_exprs) = self.shader_registry.method_call_fn_def(*ident, arg_exprs);
+                self.generate_call(fn_def, arg_exprs, closure_site_index.get())
+            }
+            ExprKind::PlainCall {fn_ptr, arg_exprs, closure_site_index, param_index, ..} => {
+                if let Some(param_index) = param_index.get() {
+                    self.generate_closure_call(arg_exprs, param_index, &ty)
+                }
+                else {
+                    let fn_def = self.shader_registry.all_fns.get(&fn_ptr.unwrap()).unwrap();
+                    self.generate_call(fn_def, arg_exprs, closure_site_index.get())
+                }
+            }
+            ExprKind::BuiltinCall {ident, arg_exprs, ..} => self.generate_builtin_call_expr(*ident, arg_exprs, &ty),
+            // only ever passed to a fn with closure args, which gets it by site
+            ExprKind::ClosureDef(_) => 0,
+            ExprKind::ConsCall {ty_lit, arg_exprs, ..} => self.generate_cons_call_expr(*ty_lit, arg_exprs),
+            ExprKind::StructCons {struct_ptr, args, ..} => {
+                let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+                let mut values = Vec::new();
+                for field in &struct_def.fields {
+                    let arg = args.iter().find( | (ident, _) | field.ident == *ident).unwrap();
+                    values.push(self.generate_expr(&arg.1));
+                }
+                let ty_id = self.ty(&ty);
+                self.emit_value(Op::CompositeConstruct, ty_id, &values)
+            }
+            ExprKind::ArrayCons {elem_exprs, ..} => {
+                let values: Vec<u32> = elem_exprs.iter().map( | elem_expr | self.generate_expr(elem_expr)).collect();
+                let ty_id = self.ty(&ty);
+                self.emit_value(Op::CompositeConstruct, ty_id, &values)
+            }
+            ExprKind::Var {kind, ..} => match kind.get().unwrap() {
+                VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => self.generate_var_value(ident, shadow, &ty),
+                VarKind::LiveValue(value_ptr) => {
+                    let ty_id = self.ty(&ty);
+                    let var = self.live_vars[&value_ptr];
+                    self.emit_value(Op::Load, ty_id, &[var])
+                }
+                VarKind::Const(const_ptr) => {
+                    let ty_id = self.ty(&ty);
+                    let var = self.const_vars[&const_ptr];
+                    self.emit_value(Op::Load, ty_id, &[var])
+                }
+            },
+            ExprKind::Lit {lit, ..} => self.const_val(&lit.to_val(), &ty),
+        }
+    }
+
+    // branches on `cond` and merges the two values, which keeps the branches lazy
+    fn generate_phi(
+        &mut self,
+        ty: &Ty,
+        cond: u32,
+        generate_if_true: impl FnOnce(&mut Self) -> u32,
+        generate_if_false: impl FnOnce(&mut Self) -> u32,
+    ) -> u32 {
+        let true_label = self.alloc();
+        let false_label = self.alloc();
+        let merge_label = self.alloc();
+        self.branch_conditional(cond, merge_label, true_label, false_label);
+        self.label(true_label);
+        let value_if_true = generate_if_true(self);
+        let true_end_label = self.f.label;
+        self.branch(merge_label);
+        self.label(false_label);
+        let value_if_false = generate_if_false(self);
+        let false_end_label = self.f.label;
+        self.branch(merge_label);
+        self.label(merge_label);
+        let ty_id = self.ty(ty);
+        self.emit_value(Op::Phi, ty_id, &[value_if_true, true_end_label, value_if_false, false_end_label])
+    }
+
+    fn generate_var_value(&mut self, ident: Ident, shadow: ScopeSymShadow, ty: &Ty) -> u32 {
+        match self.var_ptr(ident, shadow) {
+            Some(ptr) if ty.is_texture() => ptr,
+            Some(ptr) => {
+                let ty_id = self.ty(ty);
+                self.emit_value(Op::Load, ty_id, &[ptr])
+            }
+            None => self.f.closed_over[&(ident, shadow)]
+        }
+    }
+
+    fn var_ptr(&self, ident: Ident, shadow: ScopeSymShadow) -> Option<u32> {
+        self.f.locals.get(&(ident, shadow)).cloned()
+    }
+
+    fn generate_bin_expr(&mut self, op: BinOp, left_expr: &Expr, right_expr: &Expr, ty: &Ty) -> u32 {
+        let left_ty = left_expr.ty.borrow().clone().unwrap();
+        let right_ty = right_expr.ty.borrow().clone().unwrap();
+        match op {
+            BinOp::Assign => {
+                let place = self.generate_place(left_expr);
+                let value = self.generate_expr(right_expr);
+                self.store_place(&place, value);
+                value
+            }
+            BinOp::AddAssign | BinOp::SubAssign | BinOp::MulAssign | BinOp::DivAssign
+                | BinOp::RemAssign | BinOp::BitAndAssign | BinOp::BitOrAssign | BinOp::BitXorAssign
+                | BinOp::ShlAssign | BinOp::ShrAssign => {
+                let place = self.generate_place(left_expr);
+                let left = self.load_place(&place, &left_ty);
+                let right = self.generate_expr(right_expr);
+                let op = op.assign_op().unwrap();
+                let value = self.generate_arith(op, left, &left_ty, right, &right_ty, &left_ty);
+                self.store_place(&place, value);
+                value
+            }
+            BinOp::Or | BinOp::And => {
+                let left = self.generate_expr(left_expr);
+                let left_end_label = self.f.label;
+                let right_label = self.alloc();
+                let merge_label = self.alloc();
+                // the right side is only evaluated when it decides the outcome
+                if let BinOp::And = op {
+                    self.branch_conditional(left, merge_label, right_label, merge_label);
+                }
+                else {
+                    self.branch_conditional(left, merge_label, merge_label, right_label);
+                }
+                self.label(right_label);
+                let right = self.generate_expr(right_expr);
+                let right_end_label = self.f.label;
+                self.branch(merge_label);
+                self.label(merge_label);
+                let bool_ty = self.bool_ty();
+                self.emit_value(Op::Phi, bool_ty, &[left, left_end_label, right, right_end_label])
+            }
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                let left = self.generate_expr(left_expr);
+                let right = self.generate_expr(right_expr);
+                self.generate_compare(op, left, &left_ty, right, &right_ty)
+            }
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div
+                | BinOp::Rem | BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Shl | BinOp::Shr => {
+                let left = self.generate_expr(left_expr);
+                let right = self.generate_expr(right_expr);
+                self.generate_arith(op, left, &left_ty, right, &right_ty, ty)
+            }
+        }
+    }
+
+    fn generate_arith(&mut self, op: BinOp, left: u32, left_ty: &Ty, right: u32, right_ty: &Ty, ty: &Ty) -> u32 {
+        let ty_id = self.ty(ty);
+        if let BinOp::Mul = op {
+            let mul = match (left_ty.is_matrix(), right_ty.is_matrix()) {
+                (true, true) => Some((Op::MatrixTimesMatrix, left, right)),
+                (true, false) if right_ty.is_vector() => Some((Op::MatrixTimesVector, left, right)),
+                (false, true) if left_ty.is_vector() => Some((Op::VectorTimesMatrix, left, right)),
+                (true, false) => Some((Op::MatrixTimesScalar, left, right)),
+                (false, true) => Some((Op::MatrixTimesScalar, right, left)),
+                _ if left_ty.is_vector() && *right_ty == Ty::Float => Some((Op::VectorTimesScalar, left, right)),
+                _ if *left_ty == Ty::Float && right_ty.is_vector() => Some((Op::VectorTimesScalar, right, left)),
+                _ => None
+            };
+            if let Some((op, left, right)) = mul {
+                return self.emit_value(op, ty_id, &[left, right])
+            }
+        }
+        // the rest is component wise, matrices a column at a time
+        if ty.is_matrix() {
+            let column_ty = elem_ty(ty);
+            let mut columns = Vec::new();
+            for index in 0..vector_len(ty) {
+                let left = self.column_or_splat(left, left_ty, &column_ty, index);
+                let right = self.column_or_splat(right, right_ty, &column_ty, index);
+                columns.push(self.generate_arith(op, left, &column_ty, right, &column_ty, &column_ty));
+            }
+            return self.emit_value(Op::CompositeConstruct, ty_id, &columns)
+        }
+        let left = if left_ty.is_scalar() && !ty.is_scalar() {self.splat(left, ty)} else {left};
+        let right = if right_ty.is_scalar() && !ty.is_scalar() {self.splat(right, ty)} else {right};
+        let op = match (elem_ty(ty), op) {
+            (Ty::Float, BinOp::Add) => Op::FAdd,
+            (Ty::Float, BinOp::Sub) => Op::FSub,
+            (Ty::Float, BinOp::Mul) => Op::FMul,
+            (Ty::Float, _) => Op::FDiv,
+            (_, BinOp::Add) => Op::IAdd,
+            (_, BinOp::Sub) => Op::ISub,
+            (_, BinOp::Mul) => Op::IMul,
+            (Ty::Int, BinOp::Div) => Op::SDiv,
+            (_, BinOp::Div) => Op::UDiv,
+            // `%` truncates, the sign follows the dividend
+            (Ty::Int, BinOp::Rem) => Op::SRem,
+            (_, BinOp::Rem) => Op::UMod,
+            (_, BinOp::BitAnd) => Op::BitwiseAnd,
+            (_, BinOp::BitOr) => Op::BitwiseOr,
+            (_, BinOp::BitXor) => Op::BitwiseXor,
+            (_, BinOp::Shl) => Op::ShiftLeftLogical,
+            (Ty::Int, _) => Op::ShiftRightArithmetic,
+            (_, _) => Op::ShiftRightLogical,
+        };
+        self.emit_value(op, ty_id, &[left, right])
+    }
+
+    fn column_or_splat(&mut self, value: u32, ty: &Ty, column_ty: &Ty, index: usize) -> u32 {
+        if ty.is_matrix() {
+            let column_ty = self.ty(column_ty);
+            self.emit_value(Op::CompositeExtract, column_ty, &[value, index as u32])
+        }
+        else {
+            self.splat(value, column_ty)
+        }
+    }
+
+    fn splat(&mut self, scalar: u32, ty: &Ty) -> u32 {
+        let ty_id = self.ty(ty);
+        self.emit_value(Op::CompositeConstruct, ty_id, &vec![scalar; vector_len(ty)])
+    }
+
+    fn generate_compare(&mut self, op: BinOp, left: u32, left_ty: &Ty, right: u32, right_ty: &Ty) -> u32 {
+        let bool_ty = self.bool_ty();
+        if left_ty.is_matrix() {
+            let column_ty = elem_ty(left_ty);
+            let mut result = None;
+            for index in 0..vector_len(left_ty) {
+                let left = self.column_or_splat(left, left_ty, &column_ty, index);
+                let right = self.column_or_splat(right, right_ty, &column_ty, index);
+                let column = self.generate_compare(op, left, &column_ty, right, &column_ty);
+                result = Some(match result {
+                    None => column,
+                    Some(result) => {
+                        let op = if let BinOp::Eq = op {Op::LogicalAnd} else {Op::LogicalOr};
+                        self.emit_value(op, bool_ty, &[result, column])
+                    }
+                });
+            }
+            return result.unwrap()
+        }
+        let ty = if left_ty.is_scalar() {right_ty} else {left_ty};
+        let left = if left_ty.is_scalar() && !ty.is_scalar() {self.splat(left, ty)} else {left};
+        let right = if right_ty.is_scalar() && !ty.is_scalar() {self.splat(right, ty)} else {right};
+        let value = self.generate_compare_components(op, left, right, ty);
+        match (ty.is_vector(), op) {
+            (true, BinOp::Eq) => self.emit_value(Op::All, bool_ty, &[value]),
+            (true, _) => self.emit_value(Op::Any, bool_ty, &[value]),
+            _ => value
+        }
+    }
+
+    // compares scalars or vectors, into a bool of the same size
+    fn generate_compare_components(&mut self, op: BinOp, left: u32, right: u32, ty: &Ty) -> u32 {
+        let op = match (elem_ty(ty), op) {
+            (Ty::Bool, BinOp::Eq) => Op::LogicalEqual,
+            (Ty::Bool, _) => Op::LogicalNotEqual,
+            (Ty::Int, BinOp::Eq) => Op::IEqual,
+            (Ty::Int, BinOp::Ne) => Op::INotEqual,
+            (Ty::Int, BinOp::Lt) => Op::SLessThan,
+            (Ty::Int, BinOp::Le) => Op::SLessThanEqual,
+            (Ty::Int, BinOp::Gt) => Op::SGreaterThan,
+            (Ty::Int, _) => Op::SGreaterThanEqual,
+            (Ty::Uint, BinOp::Eq) => Op::IEqual,
+            (Ty::Uint, BinOp::Ne) => Op::INotEqual,
+            (Ty::Uint, BinOp::Lt) => Op::ULessThan,
+            (Ty::Uint, BinOp::Le) => Op::ULessThanEqual,
+            (Ty::Uint, BinOp::Gt) => Op::UGreaterThan,
+            (Ty::Uint, _) => Op::UGreaterThanEqual,
+            (_, BinOp::Eq) => Op::FOrdEqual,
+            (_, BinOp::Ne) => Op::FUnordNotEqual,
+            (_, BinOp::Lt) => Op::FOrdLessThan,
+            (_, BinOp::Le) => Op::FOrdLessThanEqual,
+            (_, BinOp::Gt) => Op::FOrdGreaterThan,
+            (_, _) => Op::FOrdGreaterThanEqual,
+        };
+        let bool_ty = self.bool_ty();
+        let result_ty = if ty.is_vector() {self.vector_ty(bool_ty, vector_len(ty))} else {bool_ty};
+        self.emit_value(op, result_ty, &[left, right])
+    }
+
+    fn generate_neg(&mut self, value: u32, ty: &Ty) -> u32 {
+        let ty_id = self.ty(ty);
+        if ty.is_matrix() {
+            let column_ty = elem_ty(ty);
+            let mut columns = Vec::new();
+            for index in 0..vector_len(ty) {
+                let column = self.column_or_splat(value, ty, &column_ty, index);
+                columns.push(self.generate_neg(column, &column_ty));
+            }
+            return self.emit_value(Op::CompositeConstruct, ty_id, &columns)
+        }
+        let op = if elem_ty(ty) == Ty::Int {Op::SNegate} else {Op::FNegate};
+        self.emit_value(op, ty_id, &[value])
+    }
+
+    fn generate_field_expr(&mut self, expr: &Expr, field_ident: Ident, ty: &Ty) -> u32 {
+        let ty_id = self.ty(ty);
+        match expr.ty.borrow().clone().unwrap() {
+            Ty::DrawShader => {
+                if let Some(stage_builtin) = self.draw_shader_def.find_stage_builtin(field_ident) {
+                    return self.emit_value(Op::Load, ty_id, &[self.builtin_vars[&stage_builtin]])
+                }
+                let var = self.field_vars[&field_ident];
+                if ty.is_texture() {
+                    return var
+                }
+                self.emit_value(Op::Load, ty_id, &[var])
+            }
+            Ty::Struct(struct_ptr) => {
+                let value = self.generate_expr(expr);
+                let index = self.struct_field_index(struct_ptr, field_ident);
+                self.emit_value(Op::CompositeExtract, ty_id, &[value, index])
+            }
+            _ => {
+                let value = self.generate_expr(expr);
+                let swizzle: Vec<u32> = Swizzle::parse(field_ident).unwrap().iter().map( | index | *index as u32).collect();
+                if swizzle.len() == 1 {
+                    self.emit_value(Op::CompositeExtract, ty_id, &[value, swizzle[0]])
+                }
+                else {
+                    let mut operands = vec![value, value];
+                    operands.extend(swizzle);
+                    self.emit_value(Op::VectorShuffle, ty_id, &operands)
+                }
+            }
+        }
+    }
+
+    fn struct_field_index(&self, struct_ptr: StructPtr, field_ident: Ident) -> u32 {
+        let struct_def = self.shader_registry.structs.get(&struct_ptr).unwrap();
+        struct_def.fields.iter().position( | field | field.ident == field_ident).unwrap() as u32
+    }
+
+    fn generate_index_expr(&mut self, expr: &Expr, index_expr: &Expr, ty: &Ty) -> u32 {
+        let base_ty = expr.ty.borrow().clone().unwrap();
+        let ty_id = self.ty(ty);
+        let value = self.generate_expr(expr);
+        if let Some(Some(val)) = index_expr.const_val.borrow().as_ref() {
+            if let Some(index) = val.to_int() {
+                return self.emit_value(Op::CompositeExtract, ty_id, &[value, index as u32])
+            }
+        }
+        let index = self.generate_expr(index_expr);
+        if base_ty.is_vector() {
+            return self.emit_value(Op::VectorExtractDynamic, ty_id, &[value, index])
+        }
+        // a dynamic index into a value goes through memory
+        let base_ty_id = self.ty(&base_ty);
+        let var = self.local_var(base_ty_id);
+        self.emit(Op::Store, &[var, value]);
+        let pointer_ty = self.pointer_ty(storage_class::FUNCTION, ty_id);
+        let ptr = self.emit_value(Op::AccessChain, pointer_ty, &[var, index]);
+        self.emit_value(Op::Load, ty_id, &[ptr])
+    }
+
+    // places, the left hand sides of assignments and inout args
+
+    fn generate_place(&mut self, expr: &Expr) -> Place {
+        let ty = expr.ty.borrow().clone().unwrap();
+        match &expr.kind {
+            ExprKind::Var {kind, ..} => match kind.get().unwrap() {
+                VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => Place {
+                    ptr: self.var_ptr(ident, shadow).unwrap(),
+                    storage_class: storage_class::FUNCTION,
+                    ty,
+                    swizzle: Vec::new(),
+                },
+                VarKind::LiveValue(value_ptr) => Place {
+                    ptr: self.live_vars[&value_ptr],
+                    storage_class: storage_class::PRIVATE,
+                    ty,
+                    swizzle: Vec::new(),
+                },
+                VarKind::Const(_) => panic!(),
+            },
+            ExprKind::Field {expr: base_expr, field_ident, ..} => match base_expr.ty.borrow().clone().unwrap() {
+                Ty::DrawShader => Place {
+                    ptr: match self.draw_shader_def.find_stage_builtin(*field_ident) {
+                        Some(stage_builtin) => self.builtin_vars[&stage_builtin],
+                        None => self.field_vars[field_ident]
+                    },
+                    storage_class: storage_class::PRIVATE,
+                    ty,
+                    swizzle: Vec::new(),
+                },
+                Ty::Struct(struct_ptr) => {
+                    let base = self.generate_place(base_expr);
+                    let index = self.struct_field_index(struct_ptr, *field_ident);
+                    let index = self.const_i32(index as i32);
+                    self.generate_access_chain(&base, index, ty)
+                }
+                _ => {
+                    let base = self.generate_place(base_expr);
+                    let swizzle = Swizzle::parse(*field_ident).unwrap().iter().map( | index | {
+                        if base.swizzle.is_empty() {*index as u32} else {base.swizzle[*index]}
+                    }).collect();
+                    Place {swizzle, ..base}
+                }
+            },
+            ExprKind::Index {expr: base_expr, index_expr, ..} => {
+                let base = self.generate_place(base_expr);
+                let index = self.generate_expr(index_expr);
+                self.generate_access_chain(&base, index, ty)
+            }
+            _ => panic!()
+        }
+    }
+
+    fn generate_access_chain(&mut self, base: &Place, index: u32, ty: Ty) -> Place {
+        let ty_id = self.ty(&ty);
+        let pointer_ty = self.pointer_ty(base.storage_class, ty_id);
+        Place {
+            ptr: self.emit_value(Op::AccessChain, pointer_ty, &[base.ptr, index]),
+            storage_class: base.storage_class,
+            ty,
+            swizzle: Vec::new(),
+        }
+    }
+
+    fn load_place(&mut self, place: &Place, ty: &Ty) -> u32 {
+        let place_ty = self.ty(&place.ty);
+        let value = self.emit_value(Op::Load, place_ty, &[place.ptr]);
+        let ty_id = self.ty(ty);
+        match place.swizzle.len() {
+            0 => value,
+            1 => self.emit_value(Op::CompositeExtract, ty_id, &[value, place.swizzle[0]]),
+            _ => {
+                let mut operands = vec![value, value];
+                operands.extend_from_slice(&place.swizzle);
+                self.emit_value(Op::VectorShuffle, ty_id, &operands)
+            }
+        }
+    }
+
+    fn store_place(&mut self, place: &Place, value: u32) {
+        if place.swizzle.is_empty() {
+            self.emit(Op::Store, &[place.ptr, value]);
+            return
+        }
+        let place_ty = self.ty(&place.ty);
+        let old_value = self.emit_value(Op::Load, place_ty, &[place.ptr]);
+        let new_value = if place.swizzle.len() == 1 {
+            self.emit_value(Op::CompositeInsert, place_ty, &[value, old_value, place.swizzle[0]])
+        }
+        else {
+            // components come from the new value where the swizzle names them
+            let len = vector_len(&place.ty) as u32;
+            let mut operands = vec![old_value, value];
+            for index in 0..len {
+                operands.push(match place.swizzle.iter().position( | component | *component == index) {
+                    Some(position) => len + position as u32,
+                    None => index
+                });
+            }
+            self.emit_value(Op::VectorShuffle, place_ty, &operands)
+        };
+        self.emit(Op::Store, &[place.ptr, new_value]);
+    }
+
+    // calls
+
+    // inout args are copied into a var of the callee, and back out after the call
+    fn generate_args(&mut self, params: &[(bool, Ty)], arg_exprs: &[Expr], args: &mut Vec<u32>, write_backs: &mut Vec<(Place, u32)>) {
+        for ((is_inout, ty), arg_expr) in params.iter().zip(arg_exprs) {
+            if !is_inout {
+                args.push(self.generate_expr(arg_expr));
+                continue;
+            }
+            let place = self.generate_place(arg_expr);
+            let value = self.load_place(&place, ty);
+            let ty_id = self.ty(ty);
+            let var = self.local_var(ty_id);
+            self.emit(Op::Store, &[var, value]);
+            args.push(var);
+            write_backs.push((place, var));
+        }
+    }
+
+    fn generate_fn_call(&mut self, fn_id: u32, return_ty: &Ty, args: Vec<u32>, write_backs: Vec<(Place, u32)>) -> u32 {
+        let return_ty_id = self.ty(return_ty);
+        let mut operands = vec![fn_id];
+        operands.extend(args);
+        let value = self.emit_value(Op::FunctionCall, return_ty_id, &operands);
+        for (place, var) in write_backs {
+            let ty_id = self.ty(&place.ty);
+            let elem_ty = if place.swizzle.len() == 1 {elem_ty(&place.ty)} else {vector_ty(&elem_ty(&place.ty), place.swizzle.len())};
+            let ty_id = if place.swizzle.is_empty() {ty_id} else {self.ty(&elem_ty)};
+            let value = self.emit_value(Op::Load, ty_id, &[var]);
+            self.store_place(&place, value);
+        }
+        value
+    }
+
+    fn generate_call(&mut self, fn_def: &'a FnDef, arg_exprs: &[Expr], closure_site_index: Option<usize>) -> u32 {
+        let params = fn_def.params_for_args(arg_exprs.len());
+        let mut value_params = Vec::new();
+        let mut value_args = Vec::new();
+        for (param, arg_expr) in params.iter().zip(arg_exprs) {
+            // closures are specialised into the site
+            if let Some(Ty::ClosureDef(_)) = arg_expr.ty.borrow().as_ref() {
+                continue;
+            }
+            value_params.push((param.is_inout, arg_expr.ty.borrow().clone().unwrap()));
+            value_args.push(arg_expr.clone());
+        }
+        let mut args = Vec::new();
+        let mut write_backs = Vec::new();
+        self.generate_args(&value_params, &value_args, &mut args, &mut write_backs);
+
+        let fn_id = if let Some(closure_site_index) = closure_site_index {
+            let call_def = self.fn_def.unwrap();
+            let closure_site = call_def.closure_sites.borrow().as_ref().unwrap()[closure_site_index].clone();
+            // and now the closed over values
+            for sym in &closure_site.all_closed_over {
+                if sym.ty == Ty::DrawShader {
+                    continue;
+                }
+                args.push(self.generate_var_value(sym.ident, sym.shadow, &sym.ty));
+            }
+            self.fn_id(FnKey::Site(call_def.fn_ptr, closure_site_index), fn_def.fn_ptr)
+        }
+        else {
+            self.fn_id(FnKey::Plain(fn_def.fn_ptr), fn_def.fn_ptr)
+        };
+        let return_ty = fn_def.return_ty.borrow().clone().unwrap();
+        self.generate_fn_call(fn_id, &return_ty, args, write_backs)
+    }
+
+    fn generate_closure_call(&mut self, arg_exprs: &[Expr], param_index: usize, ty: &Ty) -> u32 {
+        let (call_ptr, closure_site) = self.closure_site.clone().unwrap();
+        let closure_def_index = closure_site.closure_args.iter().find( | arg | arg.param_index == param_index).unwrap().closure_def_index;
+        let call_def = self.shader_registry.all_fns.get(&call_ptr).unwrap();
+        let closure_def = &call_def.closure_defs[closure_def_index.0];
+        let params: Vec<(bool, Ty)> = match &self.fn_def.unwrap().params[param_index].ty_expr.kind {
+            TyExprKind::ClosureDecl {params, ..} => params.iter().map( | param | {
+                (param.is_inout, param.ty_expr.ty.borrow().clone().unwrap())
+            }).collect(),
+            _ => panic!()
+        };
+        let mut args = Vec::new();
+        let mut write_backs = Vec::new();
+        self.generate_args(&params, arg_exprs, &mut args, &mut write_backs);
+        // the closed over syms, in order
+        for sym in closure_def.closed_over_syms.borrow().as_ref().unwrap() {
+            if sym.ty == Ty::DrawShader {
+                continue;
+            }
+            args.push(self.f.closed_over[&(sym.ident, sym.shadow)]);
+        }
+        let fn_id = self.fn_id(FnKey::Closure(call_ptr, closure_def_index), self.fn_def.unwrap().fn_ptr);
+        self.generate_fn_call(fn_id, ty, args, write_backs)
+    }
+
+    fn generate_texture_call(&mut self, op: TextureOp, arg_exprs: &[Expr], ty: &Ty) -> u32 {
+        let ty_id = self.ty(ty);
+        let texture_ty = arg_exprs[0].ty.borrow().clone().unwrap();
+        // an offset is a constant of its own, see below
+        let arg_exprs_len = match op {
+            TextureOp::Offset | TextureOp::CompareOffset => arg_exprs.len() - 1,
+            _ => arg_exprs.len()
+        };
+        let args: Vec<u32> = arg_exprs[..arg_exprs_len].iter().map( | arg_expr | self.generate_expr(arg_expr)).collect();
+        let sampled_image_ty = self.sampled_image_ty(&texture_ty);
+        let sampled_image = self.emit_value(Op::Load, sampled_image_ty, &[args[0]]);
+        match op {
+            TextureOp::Size => {
+                self.require_capability(capability::IMAGE_QUERY);
+                let image_ty = self.image_ty(&texture_ty);
+                let image = self.emit_value(Op::Image, image_ty, &[sampled_image]);
+                return self.emit_value(Op::ImageQuerySizeLod, ty_id, &[image, args[1]])
+            }
+            TextureOp::Fetch => {
+                let image_ty = self.image_ty(&texture_ty);
+                let image = self.emit_value(Op::Image, image_ty, &[sampled_image]);
+                return self.emit_value(Op::ImageFetch, ty_id, &[image, args[1], image_operands::LOD, args[2]])
+            }
+            _ => ()
+        }
+        // the layer of an array is the last coordinate
+        let (coord, rest) = if texture_ty == Ty::Texture2DArray {
+            let float_ty = self.float_ty();
+            let layer = self.emit_value(Op::ConvertSToF, float_ty, &[args[2]]);
+            let vec3_ty = self.ty(&Ty::Vec3);
+            (self.emit_value(Op::CompositeConstruct, vec3_ty, &[args[1], layer]), &args[3..])
+        }
+        else {
+            (args[1], &args[2..])
+        };
+        let const_offset = match op {
+            TextureOp::Offset | TextureOp::CompareOffset => {
+                let offset_expr = arg_exprs.last().unwrap();
+                let components: Vec<u32> = offset_expr.const_offset().unwrap().into_iter().map( | value | self.const_i32(value)).collect();
+                let offset_ty = self.ty(offset_expr.ty.borrow().as_ref().unwrap());
+                Some(self.unique_constant(Op::ConstantComposite, offset_ty, &components))
+            }
+            _ => None
+        };
+        let mut operands = vec![sampled_image, coord];
+        if let TextureOp::Compare | TextureOp::CompareOffset = op {
+            operands.push(rest[0]);
+        }
+        let explicit_lod = match op {
+            TextureOp::Lod => {
+                operands.extend_from_slice(&[image_operands::LOD, rest[0]]);
+                true
+            }
+            TextureOp::Grad => {
+                operands.extend_from_slice(&[image_operands::GRAD, rest[0], rest[1]]);
+                true
+            }
+            _ if self.sample_level => {
+                let lod = self.const_f32(0.0);
+                let mask = if const_offset.is_some() {image_operands::LOD | image_operands::CONST_OFFSET} else {image_operands::LOD};
+                operands.extend_from_slice(&[mask, lod]);
+                operands.extend(const_offset);
+                true
+            }
+            _ => {
+                if let Some(const_offset) = const_offset {
+                    operands.extend_from_slice(&[image_operands::CONST_OFFSET, const_offset]);
+                }
+                false
+            }
+        };
+        let inst = match (op, explicit_lod) {
+            (TextureOp::Compare, false) | (TextureOp::CompareOffset, false) => Op::ImageSampleDrefImplicitLod,
+            (TextureOp::Compare, true) | (TextureOp::CompareOffset, true) => Op::ImageSampleDrefExplicitLod,
+            (_, false) => Op::ImageSampleImplicitLod,
+            (_, true) => Op::ImageSampleExplicitLod,
+        };
+        self.emit_value(inst, ty_id, &operands)
+    }
+
+    fn generate_builtin_call_expr(&mut self, ident: Ident, arg_exprs: &[Expr], ty: &Ty) -> u32 {
+        if let Some(op) = TextureOp::from_builtin(ident) {
+            return self.generate_texture_call(op, arg_exprs, ty)
+        }
+        let ty_id = self.ty(ty);
+        let arg_tys: Vec<Ty> = arg_exprs.iter().map( | arg_expr | arg_expr.ty.borrow().clone().unwrap()).collect();
+        let mut args: Vec<u32> = arg_exprs.iter().map( | arg_expr | self.generate_expr(arg_expr)).collect();
+        // the extended instructions want all args of the same type, the eta of refract stays a scalar
+        if ident != Ident(id!(refract)) {
+            if let Some(vector_ty) = arg_tys.iter().find( | arg_ty | arg_ty.is_vector()).cloned() {
+                for (arg, arg_ty) in args.iter_mut().zip(&arg_tys) {
+                    if *arg_ty == Ty::Float {
+                        *arg = self.splat(*arg, &vector_ty);
+                    }
+                }
+            }
+        }
+        let compare = | ident | match ident {
+            Ident(id!(equal)) => Some(BinOp::Eq),
+            Ident(id!(notEqual)) => Some(BinOp::Ne),
+            Ident(id!(lessThan)) => Some(BinOp::Lt),
+            Ident(id!(lessThanEqual)) => Some(BinOp::Le),
+            Ident(id!(greaterThan)) => Some(BinOp::Gt),
+            Ident(id!(greaterThanEqual)) => Some(BinOp::Ge),
+            _ => None
+        };
+        if let Some(op) = compare(ident) {
+            return self.generate_compare_components(op, args[0], args[1], &arg_tys[0])
+        }
+        let inst = match ident {
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) => {
+                let sampled_image_ty = self.sampled_image_ty(&Ty::Texture2D);
+                let image = self.emit_value(Op::Load, sampled_image_ty, &[args[0]]);
+                if self.sample_level {
+                    let lod = self.const_f32(0.0);
+                    return self.emit_value(Op::ImageSampleExplicitLod, ty_id, &[image, args[1], image_operands::LOD, lod])
+                }
+                return self.emit_value(Op::ImageSampleImplicitLod, ty_id, &[image, args[1]])
+            }
+            Ident(id!(all)) => return self.emit_value(Op::All, ty_id, &args),
+            Ident(id!(any)) => return self.emit_value(Op::Any, ty_id, &args),
+            Ident(id!(not)) => return self.emit_value(Op::LogicalNot, ty_id, &args),
+            Ident(id!(dot)) if arg_tys[0] == Ty::Float => return self.emit_value(Op::FMul, ty_id, &args),
+            Ident(id!(dot)) => return self.emit_value(Op::Dot, ty_id, &args),
+            Ident(id!(dFdx)) => return self.emit_value(Op::DPdx, ty_id, &args),
+            Ident(id!(dFdy)) => return self.emit_value(Op::DPdy, ty_id, &args),
+            Ident(id!(mod)) => return self.emit_value(Op::FMod, ty_id, &args),
+            Ident(id!(transpose)) => return self.emit_value(Op::Transpose, ty_id, &args),
+            Ident(id!(matrixCompMult)) => return self.generate_arith(BinOp::Mul, args[0], &Ty::Vec4, args[1], &Ty::Vec4, ty),
+            Ident(id!(abs)) if elem_ty(ty) == Ty::Int => glsl_std_450::S_ABS,
+            Ident(id!(abs)) => glsl_std_450::F_ABS,
+            Ident(id!(acos)) => glsl_std_450::ACOS,
+            Ident(id!(asin)) => glsl_std_450::ASIN,
+            Ident(id!(atan)) if args.len() == 2 => glsl_std_450::ATAN2,
+            Ident(id!(atan)) => glsl_std_450::ATAN,
+            Ident(id!(ceil)) => glsl_std_450::CEIL,
+            Ident(id!(clamp)) => glsl_std_450::F_CLAMP,
+            Ident(id!(cos)) => glsl_std_450::COS,
+            Ident(id!(cross)) => glsl_std_450::CROSS,
+            Ident(id!(degrees)) => glsl_std_450::DEGREES,
+            Ident(id!(distance)) => glsl_std_450::DISTANCE,
+            Ident(id!(exp)) => glsl_std_450::EXP,
+            Ident(id!(exp2)) => glsl_std_450::EXP2,
+            Ident(id!(faceforward)) => glsl_std_450::FACE_FORWARD,
+            Ident(id!(floor)) => glsl_std_450::FLOOR,
+            Ident(id!(fract)) => glsl_std_450::FRACT,
+            Ident(id!(inverse)) => glsl_std_450::MATRIX_INVERSE,
+            Ident(id!(inversesqrt)) => glsl_std_450::INVERSE_SQRT,
+            Ident(id!(length)) => glsl_std_450::LENGTH,
+            Ident(id!(log)) => glsl_std_450::LOG,
+            Ident(id!(log2)) => glsl_std_450::LOG2,
+            Ident(id!(max)) => glsl_std_450::F_MAX,
+            Ident(id!(min)) => glsl_std_450::F_MIN,
+            Ident(id!(mix)) => glsl_std_450::F_MIX,
+            Ident(id!(normalize)) => glsl_std_450::NORMALIZE,
+            Ident(id!(pow)) => glsl_std_450::POW,
+            Ident(id!(radians)) => glsl_std_450::RADIANS,
+            Ident(id!(reflect)) => glsl_std_450::REFLECT,
+            Ident(id!(refract)) => glsl_std_450::REFRACT,
+            Ident(id!(sign)) => glsl_std_450::F_SIGN,
+            Ident(id!(sin)) => glsl_std_450::SIN,
+            Ident(id!(smoothstep)) => glsl_std_450::SMOOTH_STEP,
+            Ident(id!(sqrt)) => glsl_std_450::SQRT,
+            Ident(id!(step)) => glsl_std_450::STEP,
+            Ident(id!(tan)) => glsl_std_450::TAN,
+            _ => panic!("no SPIR-V for builtin {}", ident),
+        };
+        self.ext_inst(ty_id, inst, &args)
+    }
+
+    fn generate_cons_call_expr(&mut self, ty_lit: TyLit, arg_exprs: &[Expr]) -> u32 {
+        let ty = ty_lit.to_ty();
+        let arg_tys: Vec<Ty> = arg_exprs.iter().map( | arg_expr | arg_expr.ty.borrow().clone().unwrap()).collect();
+        let args: Vec<u32> = arg_exprs.iter().map( | arg_expr | self.generate_expr(arg_expr)).collect();
+        if let ([arg], [arg_ty]) = (args.as_slice(), arg_tys.as_slice()) {
+            if *arg_ty == ty {
+                return *arg
+            }
+            if ty.is_scalar() && arg_ty.is_scalar() {
+                return self.convert_scalar(*arg, arg_ty, &ty)
+            }
+            if ty.is_vector() && arg_ty.is_scalar() {
+                let scalar = self.convert_scalar(*arg, arg_ty, &elem_ty(&ty));
+                return self.splat(scalar, &ty)
+            }
+            if ty.is_matrix() && (arg_ty.is_scalar() || arg_ty.is_matrix()) {
+                return self.generate_matrix_cons(*arg, arg_ty, &ty)
+            }
+        }
+        // the components of all args, in order
+        let mut scalars = Vec::new();
+        for (arg, arg_ty) in args.iter().zip(&arg_tys) {
+            for scalar in self.scalars(*arg, arg_ty) {
+                scalars.push(self.convert_scalar(scalar, &elem_ty(arg_ty), &elem_ty(&ty)));
+            }
+        }
+        self.from_scalars(&ty, &scalars)
+    }
+
+    // a scalar goes on the diagonal, a matrix is cut down or filled up from the identity
+    fn generate_matrix_cons(&mut self, arg: u32, arg_ty: &Ty, ty: &Ty) -> u32 {
+        let float_ty = self.float_ty();
+        let size = vector_len(ty);
+        let arg = if arg_ty.is_scalar() {self.convert_scalar(arg, arg_ty, &Ty::Float)} else {arg};
+        let mut scalars = Vec::new();
+        for column in 0..size {
+            for row in 0..size {
+                scalars.push(if arg_ty.is_matrix() && column < vector_len(arg_ty) && row < vector_len(arg_ty) {
+                    self.emit_value(Op::CompositeExtract, float_ty, &[arg, column as u32, row as u32])
+                }
+                else if row != column {
+                    self.const_f32(0.0)
+                }
+                else if arg_ty.is_scalar() {
+                    arg
+                }
+                else {
+                    self.const_f32(1.0)
+                });
+            }
+        }
+        self.from_scalars(ty, &scalars)
+    }
+
+    // the scalar components of a value, matrices column by column
+    fn scalars(&mut self, value: u32, ty: &Ty) -> Vec<u32> {
+        if ty.is_scalar() || is_enum(ty) {
+            return vec![value]
+        }
+        let scalar_ty = self.ty(&elem_ty(&elem_ty(ty)));
+        let mut scalars = Vec::new();
+        if ty.is_matrix() {
+            for column in 0..vector_len(ty) {
+                for row in 0..vector_len(ty) {
+                    scalars.push(self.emit_value(Op::CompositeExtract, scalar_ty, &[value, column as u32, row as u32]));
+                }
+            }
+        }
+        else {
+            for index in 0..vector_len(ty) {
+                scalars.push(self.emit_value(Op::CompositeExtract, scalar_ty, &[value, index as u32]));
+            }
+        }
+        scalars
+    }
+
+    // builds a value from its scalar components, which are of its element type already
+    fn from_scalars(&mut self, ty: &Ty, scalars: &[u32]) -> u32 {
+        if scalars.len() == 1 {
+            return scalars[0]
+        }
+        let ty_id = self.ty(ty);
+        if ty.is_matrix() {
+            let column_ty = elem_ty(ty);
+            let columns: Vec<u32> = scalars.chunks(vector_len(ty)).map( | column | self.from_scalars(&column_ty, column)).collect();
+            return self.emit_value(Op::CompositeConstruct, ty_id, &columns)
+        }
+        self.emit_value(Op::CompositeConstruct, ty_id, scalars)
+    }
+
+    fn convert_scalar(&mut self, value: u32, from_ty: &Ty, to_ty: &Ty) -> u32 {
+        let from_ty = if is_enum(from_ty) {&Ty::Float} else {from_ty};
+        let to_ty = if is_enum(to_ty) {&Ty::Float} else {to_ty};
+        let ty_id = self.ty(to_ty);
+        match (from_ty, to_ty) {
+            (from_ty, to_ty) if from_ty == to_ty => value,
+            (Ty::Int, Ty::Float) => self.emit_value(Op::ConvertSToF, ty_id, &[value]),
+            (Ty::Float, Ty::Int) => self.emit_value(Op::ConvertFToS, ty_id, &[value]),
+            (Ty::Uint, Ty::Float) => self.emit_value(Op::ConvertUToF, ty_id, &[value]),
+            (Ty::Float, Ty::Uint) => self.emit_value(Op::ConvertFToU, ty_id, &[value]),
+            // same width, the bits stay as they are
+            (Ty::Int, Ty::Uint) | (Ty::Uint, Ty::Int) => self.emit_value(Op::Bitcast, ty_id, &[value]),
+            (Ty::Bool, _) => {
+                let (one, zero) = match to_ty {
+                    Ty::Int => (self.const_i32(1), self.const_i32(0)),
+                    Ty::Uint => (self.const_u32(1), self.const_u32(0)),
+                    _ => (self.const_f32(1.0), self.const_f32(0.0))
+                };
+                self.emit_value(Op::Select, ty_id, &[value, one, zero])
+            }
+            (Ty::Int, Ty::Bool) => {
+                let zero = self.const_i32(0);
+                self.emit_value(Op::INotEqual, ty_id, &[value, zero])
+            }
+            (Ty::Uint, Ty::Bool) => {
+                let zero = self.const_u32(0);
+                self.emit_value(Op::INotEqual, ty_id, &[value, zero])
+            }
+            (_, _) => {
+                let zero = self.const_f32(0.0);
+                self.emit_value(Op::FUnordNotEqual, ty_id, &[value, zero])
+            }
+        }
+    }
+}
+
+fn fn_def_params(fn_def: &FnDef) -> Vec<FnParam> {
+    fn_def.params.iter().filter_map( | param | {
+        let ty = param.ty_expr.ty.borrow().clone().unwrap();
+        match (param.shadow.get(), &ty) {
+            (None, _) | (_, Ty::DrawShader) | (_, Ty::ClosureDecl) | (_, Ty::ClosureDef(_)) => None,
+            (Some(shadow), _) => Some(FnParam {
+                kind: ParamKind::Local(param.ident, shadow),
+                ty,
+                is_inout: param.is_inout,
+            })
+        }
+    }).collect()
+}
+
+fn is_varying(kind: &DrawShaderFieldKind) -> bool {
+    match kind {
+        DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Varying {..} => true,
+        _ => false
+    }
+}
+
+fn is_enum(ty: &Ty) -> bool {
+    matches!(ty, Ty::Enum(_))
+}
+
+fn packed_ty(size: usize) -> Ty {
+    vector_ty(&Ty::Float, size)
+}
+
+// the component type of a vector, the column type of a matrix
+fn elem_ty(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => Ty::Bool,
+        Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => Ty::Int,
+        Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => Ty::Uint,
+        Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => Ty::Float,
+        Ty::Mat2 => Ty::Vec2,
+        Ty::Mat3 => Ty::Vec3,
+        Ty::Mat4 => Ty::Vec4,
+        Ty::Array {elem_ty, ..} => (**elem_ty).clone(),
+        ty => ty.clone()
+    }
+}
+
+fn vector_len(ty: &Ty) -> usize {
+    match ty {
+        Ty::Bvec2 | Ty::Ivec2 | Ty::Uvec2 | Ty::Vec2 | Ty::Mat2 => 2,
+        Ty::Bvec3 | Ty::Ivec3 | Ty::Uvec3 | Ty::Vec3 | Ty::Mat3 => 3,
+        Ty::Bvec4 | Ty::Ivec4 | Ty::Uvec4 | Ty::Vec4 | Ty::Mat4 => 4,
+        _ => 1
+    }
+}
+
+fn vector_ty(elem_ty: &Ty, len: usize) -> Ty {
+    match (elem_ty, len) {
+        (Ty::Bool, 2) => Ty::Bvec2,
+        (Ty::Bool, 3) => Ty::Bvec3,
+        (Ty::Bool, 4) => Ty::Bvec4,
+        (Ty::Int, 2) => Ty::Ivec2,
+        (Ty::Int, 3) => Ty::Ivec3,
+        (Ty::Int, 4) => Ty::Ivec4,
+        (Ty::Uint, 2) => Ty::Uvec2,
+        (Ty::Uint, 3) => Ty::Uvec3,
+        (Ty::Uint, 4) => Ty::Uvec4,
+        (Ty::Float, 2) => Ty::Vec2,
+        (Ty::Float, 3) => Ty::Vec3,
+        (Ty::Float, 4) => Ty::Vec4,
+        (elem_ty, _) => elem_ty.clone()
+    }
+}
@@ -0,0 +1,1024 @@
+use {
+    std::{
+        cell::{Cell, RefCell},
+        fmt,
+        fmt::Write,
+        collections::BTreeSet,
+    },
+    crate::{
+        makepad_live_compiler::{LiveError, LiveErrorOrigin, live_error_origin},
+        makepad_live_id::{
+            id,
+            LiveId,
+        },
+        generate::*,
+        shader_ast::*,
+        shader::Shader
+    }
+};
+
+/// WebGPU has no point sprites, so no `point_size` or `point_coord`.
+pub fn check_shader(shader_registry: &Shader) -> Result<(), LiveError> {
+    if let Some((stage_builtin, span)) = shader_registry.first_stage_builtin(&[StageBuiltin::PointSize, StageBuiltin::PointCoord]) {
+        return Err(LiveError {
+            origin: live_error_origin!(),
+            span: span.into(),
+            message: format!("WGSL doesn't support `self.{}`", stage_builtin.name()),
+        })
+    }
+    Ok(())
+}
+
+/// Generates a single WGSL module with a `vertex_main` and a `fragment_main` entry point.
+///
+/// Everything is bound in `@group(0)`, numbered in this order: the uniform blocks (`<block>_table`),
+/// `live_table`, `const_table`, then each texture followed by its sampler. The tables hold the same
+/// floats as the GLSL tables, as an `array<vec4<f32>, N>` to satisfy the uniform layout rules.
+pub fn generate_shader(draw_shader_def: &DrawShaderDef, const_table: &DrawShaderConstTable, shader_registry: &Shader) -> String {
+    let mut string = String::new();
+    DrawShaderGenerator {
+        draw_shader_def,
+        shader_registry,
+        const_table,
+        string: &mut string,
+        backend_writer: &WgslBackendWriter {
+            shader_registry,
+            const_table,
+            fn_params: RefCell::new(Vec::new()),
+            polyfills: RefCell::new(Vec::new()),
+            sample_level: Cell::new(false),
+            vertex_copy_fns: shader_registry.vertex_copy_fns(),
+        }
+    }
+    .generate_shader();
+    string
+}
+
+struct DrawShaderGenerator<'a> {
+    draw_shader_def: &'a DrawShaderDef,
+    shader_registry: &'a Shader,
+    string: &'a mut String,
+    const_table: &'a DrawShaderConstTable,
+    backend_writer: &'a WgslBackendWriter<'a>
+}
+
+impl<'a> DrawShaderGenerator<'a> {
+    fn generate_shader(&mut self) {
+        // the bodies go first, they decide which polyfills are needed
+        let mut body = String::new();
+        self.generate_fns(&mut body);
+
+        for (_, polyfill) in self.backend_writer.polyfills.borrow().iter() {
+            writeln!(self.string, "{}", polyfill).unwrap();
+        }
+        self.generate_struct_defs();
+        self.generate_bindings();
+        self.generate_io_structs();
+        self.generate_private_vars();
+        self.string.push_str(&body);
+        self.generate_vertex_main();
+        self.generate_fragment_main();
+    }
+
+    fn generate_fns(&mut self, string: &mut String) {
+        let all_fns = self.draw_shader_def.all_fns.borrow();
+        let vertex_fns = self.draw_shader_def.vertex_fns.borrow();
+
+        let mut all_constructor_fns = BTreeSet::new();
+        for fn_iter in all_fns.iter() {
+            let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
+            all_constructor_fns.extend(fn_def.constructor_fn_deps.borrow().as_ref().unwrap().iter().cloned());
+        }
+        for (ty_lit, param_tys) in all_constructor_fns {
+            generate_cons_fn(self.backend_writer, string, ty_lit, &param_tys);
+            writeln!(string).unwrap();
+        }
+        // an item of a `const` array takes a `vec4` of `const_table`
+        for (const_ptr, elem_ty, offset) in const_arrays(self.draw_shader_def, self.const_table, self.shader_registry) {
+            write!(string, "fn {}(index: i32) -> ", const_ptr).unwrap();
+            write_ty(string, &elem_ty);
+            writeln!(string, " {{").unwrap();
+            writeln!(string, "    return const_table[{} + index]{};", offset / 4, const_item_swizzle(elem_ty.slots())).unwrap();
+            writeln!(string, "}}").unwrap();
+            writeln!(string).unwrap();
+        }
+
+        for fn_iter in all_fns.iter().rev() {
+            // there are no implicit derivatives in the vertex stage, a fn that samples
+            // with them in both stages gets a copy for each
+            let sample_levels: &[bool] = if self.backend_writer.vertex_copy_fns.contains(fn_iter) {
+                &[false, true]
+            } else if vertex_fns.contains(fn_iter) {
+                &[true]
+            } else {
+                &[false]
+            };
+            for sample_level in sample_levels {
+                self.backend_writer.sample_level.set(*sample_level);
+                self.generate_fn(string, *fn_iter);
+            }
+        }
+    }
+
+    fn generate_fn(&mut self, string: &mut String, fn_iter: FnPtr) {
+        let all_fns = self.draw_shader_def.all_fns.borrow();
+        let const_table_offset = self.const_table.offsets.get(&fn_iter).cloned();
+        let fn_def = self.shader_registry.all_fns.get(&fn_iter).unwrap();
+        if fn_def.has_closure_args() {
+            for call_iter in all_fns.iter().rev() {
+                // any function that depends on us, will have the closures we need
+                let call_def = self.shader_registry.all_fns.get(call_iter).unwrap();
+                if call_def.callees.borrow().as_ref().unwrap().contains(&fn_iter) {
+                    FnDefWithClosureArgsGenerator::generate_fn_def_with_all_closures(
+                        string,
+                        self.shader_registry,
+                        fn_def,
+                        call_def,
+                        self.backend_writer,
+                        const_table_offset
+                    );
+                }
+            }
+            return
+        }
+        FnDefGenerator {
+            fn_def,
+            const_table_offset,
+            shader_registry: self.shader_registry,
+            backend_writer: self.backend_writer,
+            string,
+        }
+        .generate_fn_def();
+        writeln!(string).unwrap();
+    }
+
+    fn generate_struct_defs(&mut self) {
+        for struct_ptr in self.draw_shader_def.all_structs.borrow().iter().rev() {
+            let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+            writeln!(self.string, "struct {} {{", struct_ptr).unwrap();
+            for field in &struct_def.fields {
+                write!(self.string, "    {}: ", DisplayStructField(field.ident)).unwrap();
+                write_ty(self.string, field.ty_expr.ty.borrow().as_ref().unwrap());
+                writeln!(self.string, ",").unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+
+    fn generate_bindings(&mut self) {
+        let mut binding = 0;
+        for (ident, fields) in self.draw_shader_def.fields_as_uniform_blocks() {
+            let slots: usize = fields.iter().map( | (index, _) | {
+                self.draw_shader_def.fields[*index].ty_expr.ty.borrow().as_ref().unwrap().slots()
+            }).sum();
+            self.generate_table_binding(binding, &format!("{}_table", ident), slots);
+            binding += 1;
+        }
+        let live_slots = self.calc_live_slots();
+        if live_slots > 0 {
+            self.generate_table_binding(binding, "live_table", live_slots);
+            binding += 1;
+        }
+        if self.const_table.table.len() > 0 {
+            self.generate_table_binding(binding, "const_table", self.const_table.table.len());
+            binding += 1;
+        }
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {..} = field.kind {
+                let ty = field.ty_expr.ty.borrow().clone().unwrap();
+                write!(self.string, "@group(0) @binding({}) var {}: ", binding, DisplayDsIdent(field.ident)).unwrap();
+                write_ty(self.string, &ty);
+                writeln!(self.string, ";").unwrap();
+                writeln!(self.string, "@group(0) @binding({}) var {}_sampler: {};", binding + 1, DisplayDsIdent(field.ident), sampler_ty(&ty)).unwrap();
+                binding += 2;
+            }
+        }
+        writeln!(self.string).unwrap();
+    }
+
+    fn generate_table_binding(&mut self, binding: usize, name: &str, slots: usize) {
+        writeln!(
+            self.string,
+            "@group(0) @binding({}) var<uniform> {}: array<vec4<f32>, {}>;",
+            binding,
+            name,
+            (slots + 3) / 4
+        ).unwrap();
+    }
+
+    fn generate_io_structs(&mut self) {
+        let geometry_slots = self.compute_packed_slots( | kind | match kind {
+            DrawShaderFieldKind::Geometry {..} => true,
+            _ => false
+        });
+        let instance_slots = self.compute_packed_slots( | kind | match kind {
+            DrawShaderFieldKind::Instance {..} => true,
+            _ => false
+        });
+        let varying_slots = self.compute_packed_slots(is_varying);
+
+        if geometry_slots + instance_slots > 0 {
+            writeln!(self.string, "struct Attributes {{").unwrap();
+            let mut location = 0;
+            for (name, slots) in [("packed_geometry", geometry_slots), ("packed_instance", instance_slots)] {
+                for (index, size) in packed_var_sizes(slots).into_iter().enumerate() {
+                    writeln!(self.string, "    @location({}) {}_{}: {},", location, name, index, packed_var_ty(size)).unwrap();
+                    location += 1;
+                }
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+
+        writeln!(self.string, "struct Varyings {{").unwrap();
+        writeln!(self.string, "    @builtin(position) position: vec4<f32>,").unwrap();
+        for (index, size) in packed_var_sizes(varying_slots).into_iter().enumerate() {
+            writeln!(self.string, "    @location({}) packed_varying_{}: {},", index, index, packed_var_ty(size)).unwrap();
+        }
+        writeln!(self.string, "}}").unwrap();
+        writeln!(self.string).unwrap();
+    }
+
+    fn generate_private_vars(&mut self) {
+        for field in &self.draw_shader_def.fields {
+            match field.kind {
+                DrawShaderFieldKind::Texture {..} => (),
+                _ => {
+                    write!(self.string, "var<private> {}: ", DisplayDsIdent(field.ident)).unwrap();
+                    write_ty(self.string, field.ty_expr.ty.borrow().as_ref().unwrap());
+                    writeln!(self.string, ";").unwrap();
+                }
+            }
+        }
+        for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+            write!(self.string, "var<private> {}: ", live_ref).unwrap();
+            write_ty(self.string, ty);
+            writeln!(self.string, ";").unwrap();
+        }
+        for stage_builtin in self.shader_registry.stage_builtins(&self.draw_shader_def.all_fns.borrow()) {
+            write!(self.string, "var<private> builtin_{}: ", stage_builtin.name()).unwrap();
+            write_ty(self.string, &stage_builtin.ty());
+            writeln!(self.string, ";").unwrap();
+        }
+        writeln!(self.string).unwrap();
+    }
+
+    fn generate_vertex_main(&mut self) {
+        let has_attributes = self.draw_shader_def.fields.iter().any( | field | match field.kind {
+            DrawShaderFieldKind::Geometry {..} | DrawShaderFieldKind::Instance {..} => true,
+            _ => false
+        });
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.vertex_fns.borrow());
+        let mut params = Vec::new();
+        if has_attributes {
+            params.push("attributes: Attributes");
+        }
+        if stage_builtins.contains(&StageBuiltin::VertexId) {
+            params.push("@builtin(vertex_index) vertex_index: u32");
+        }
+        if stage_builtins.contains(&StageBuiltin::InstanceId) {
+            params.push("@builtin(instance_index) instance_index: u32");
+        }
+        writeln!(self.string, "@vertex").unwrap();
+        writeln!(self.string, "fn vertex_main({}) -> Varyings {{", params.join(", ")).unwrap();
+        self.generate_uniform_unpack();
+        self.generate_builtins_init(&stage_builtins);
+
+        self.generate_packed_unpack("attributes.packed_geometry", | kind | match kind {
+            DrawShaderFieldKind::Geometry {..} => true,
+            _ => false
+        });
+        self.generate_packed_unpack("attributes.packed_instance", | kind | match kind {
+            DrawShaderFieldKind::Instance {..} => true,
+            _ => false
+        });
+
+        let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(vertex))).unwrap();
+        writeln!(self.string, "    var varyings: Varyings;").unwrap();
+        writeln!(self.string, "    varyings.position = {}();", DisplayFnName(vertex_def.fn_ptr, vertex_def.ident)).unwrap();
+
+        // there is no swizzle assignment, so a packed varying is built in one go
+        let mut components = Vec::new();
+        for field in &self.draw_shader_def.fields {
+            if is_varying(&field.kind) {
+                components.extend(field_components(field.ident, field.ty_expr.ty.borrow().as_ref().unwrap()));
+            }
+        }
+        for (index, chunk) in components.chunks(4).enumerate() {
+            writeln!(
+                self.string,
+                "    varyings.packed_varying_{} = {}({});",
+                index,
+                packed_var_ty(chunk.len()),
+                chunk.join(", ")
+            ).unwrap();
+        }
+        writeln!(self.string, "    return varyings;").unwrap();
+        writeln!(self.string, "}}").unwrap();
+        writeln!(self.string).unwrap();
+    }
+
+    fn generate_fragment_main(&mut self) {
+        let stage_builtins = self.shader_registry.stage_builtins(&self.draw_shader_def.pixel_fns.borrow());
+        let writes_depth = stage_builtins.contains(&StageBuiltin::FragDepth);
+        let has_output_struct = writes_depth || self.shader_registry.pixel_outputs(self.draw_shader_def).is_some();
+        let pixel_targets = pixel_targets(self.shader_registry, self.draw_shader_def);
+        if has_output_struct {
+            writeln!(self.string, "struct PixelOutput {{").unwrap();
+            for (index, (output, ty, _)) in pixel_targets.iter().enumerate() {
+                write!(self.string, "    @location({}) {}: ", index, output).unwrap();
+                write_ty(self.string, ty);
+                writeln!(self.string, ",").unwrap();
+            }
+            if writes_depth {
+                writeln!(self.string, "    @builtin(frag_depth) depth: f32,").unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+            writeln!(self.string).unwrap();
+        }
+        let front_facing = if stage_builtins.contains(&StageBuiltin::FrontFacing) {", @builtin(front_facing) front_facing: bool"} else {""};
+        writeln!(self.string, "@fragment").unwrap();
+        if has_output_struct {
+            writeln!(self.string, "fn fragment_main(varyings: Varyings{}) -> PixelOutput {{", front_facing).unwrap();
+        }
+        else {
+            writeln!(self.string, "fn fragment_main(varyings: Varyings{}) -> @location(0) vec4<f32> {{", front_facing).unwrap();
+        }
+        self.generate_uniform_unpack();
+        self.generate_builtins_init(&stage_builtins);
+        self.generate_packed_unpack("varyings.packed_varying", is_varying);
+        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(id!(pixel))).unwrap();
+        if has_output_struct {
+            writeln!(self.string, "    let pixel_output = {}();", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+            let mut fields: Vec<String> = pixel_targets.into_iter().map( | (_, _, value) | value).collect();
+            if writes_depth {
+                fields.push(String::from("builtin_frag_depth"));
+            }
+            writeln!(self.string, "    return PixelOutput({});", fields.join(", ")).unwrap();
+        }
+        else {
+            writeln!(self.string, "    return {}();", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        }
+        writeln!(self.string, "}}").unwrap();
+    }
+    
+    // `point_size` and `point_coord` are rejected by `check_shader`
+    fn generate_builtins_init(&mut self, stage_builtins: &BTreeSet<StageBuiltin>) {
+        for stage_builtin in stage_builtins {
+            let value = match stage_builtin {
+                StageBuiltin::VertexId => "i32(vertex_index)",
+                StageBuiltin::InstanceId => "i32(instance_index)",
+                StageBuiltin::PointSize => "1.0",
+                StageBuiltin::FragCoord => "varyings.position",
+                StageBuiltin::FrontFacing => "front_facing",
+                StageBuiltin::PointCoord => "vec2<f32>(0.5, 0.5)",
+                StageBuiltin::FragDepth => "varyings.position.z",
+            };
+            writeln!(self.string, "    builtin_{} = {};", stage_builtin.name(), value).unwrap();
+        }
+    }
+
+    fn generate_uniform_unpack(&mut self) {
+        for (ident, fields) in self.draw_shader_def.fields_as_uniform_blocks() {
+            let table = format!("{}_table", ident);
+            let mut slots = 0;
+            for (index, _) in fields {
+                let field = &self.draw_shader_def.fields[index];
+                let ty = field.ty_expr.ty.borrow();
+                let ty = ty.as_ref().unwrap();
+                if let Ty::Array {elem_ty, len} = ty {
+                    for elem_index in 0..*len {
+                        let start = slots + elem_index * elem_ty.slots();
+                        let components: Vec<String> = (start..start + elem_ty.slots()).map( | slot | table_component(&table, slot)).collect();
+                        write!(self.string, "    {}[{}] = ", DisplayDsIdent(field.ident), elem_index).unwrap();
+                        write_unpacked(self.string, elem_ty, &components);
+                        writeln!(self.string, ";").unwrap();
+                    }
+                }
+                else {
+                    let components: Vec<String> = (slots..slots + ty.slots()).map( | slot | table_component(&table, slot)).collect();
+                    write!(self.string, "    {} = ", DisplayDsIdent(field.ident)).unwrap();
+                    write_unpacked(self.string, ty, &components);
+                    writeln!(self.string, ";").unwrap();
+                }
+                slots += ty.slots();
+            }
+        }
+        let mut slots = 0;
+        for (live_ref, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+            let components: Vec<String> = (slots..slots + ty.slots()).map( | slot | table_component("live_table", slot)).collect();
+            write!(self.string, "    {} = ", live_ref).unwrap();
+            write_unpacked(self.string, ty, &components);
+            writeln!(self.string, ";").unwrap();
+            slots += ty.slots();
+        }
+    }
+
+    fn generate_packed_unpack(&mut self, packed_var_name: &str, filter: fn(&DrawShaderFieldKind) -> bool) {
+        let sizes = packed_var_sizes(self.compute_packed_slots(filter));
+        let mut slot = 0;
+        for field in &self.draw_shader_def.fields {
+            if !filter(&field.kind) {
+                continue;
+            }
+            let ty = field.ty_expr.ty.borrow();
+            let ty = ty.as_ref().unwrap();
+            let components: Vec<String> = (slot..slot + ty.slots()).map( | slot | {
+                if sizes[slot / 4] == 1 {
+                    format!("{}_{}", packed_var_name, slot / 4)
+                }
+                else {
+                    format!("{}_{}.{}", packed_var_name, slot / 4, SWIZZLE[slot % 4])
+                }
+            }).collect();
+            write!(self.string, "    {} = ", DisplayDsIdent(field.ident)).unwrap();
+            write_unpacked(self.string, ty, &components);
+            writeln!(self.string, ";").unwrap();
+            slot += ty.slots();
+        }
+    }
+
+    fn compute_packed_slots(&self, filter: fn(&DrawShaderFieldKind) -> bool) -> usize {
+        self.draw_shader_def.fields.iter()
+            .filter( | field | filter(&field.kind))
+            .map( | field | field.ty_expr.ty.borrow().as_ref().unwrap().slots())
+            .sum()
+    }
+
+    fn calc_live_slots(&self) -> usize {
+        let mut slots = 0;
+        for (_, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+            slots += ty.slots();
+        }
+        slots
+    }
+}
+
+const SWIZZLE: [&str; 4] = ["x", "y", "z", "w"];
+
+fn is_varying(kind: &DrawShaderFieldKind) -> bool {
+    match kind {
+        DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+        DrawShaderFieldKind::Varying {..} => true,
+        _ => false
+    }
+}
+
+// packed vars are filled 4 slots at a time
+fn packed_var_sizes(mut slots: usize) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    while slots > 0 {
+        sizes.push(slots.min(4));
+        slots -= slots.min(4);
+    }
+    sizes
+}
+
+fn packed_var_ty(size: usize) -> &'static str {
+    match size {
+        1 => "f32",
+        2 => "vec2<f32>",
+        3 => "vec3<f32>",
+        4 => "vec4<f32>",
+        _ => panic!(),
+    }
+}
+
+fn table_component(table: &str, slot: usize) -> String {
+    format!("{}[{}].{}", table, slot / 4, SWIZZLE[slot % 4])
+}
+
+fn matrix_size(ty: &Ty) -> Option<usize> {
+    match ty {
+        Ty::Mat2 => Some(2),
+        Ty::Mat3 => Some(3),
+        Ty::Mat4 => Some(4),
+        _ => None
+    }
+}
+
+// the float slots of a value, in packing order
+fn field_components(ident: Ident, ty: &Ty) -> Vec<String> {
+    let scalar = match ty {
+        Ty::Bool | Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => "select(0.0, 1.0, {})",
+        Ty::Int | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => "f32({})",
+        Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => "f32({})",
+        _ => "{}"
+    };
+    let slots = ty.slots();
+    (0..slots).map( | slot | {
+        let component = if let Some(size) = matrix_size(ty) {
+            format!("{}[{}][{}]", DisplayDsIdent(ident), slot / size, slot % size)
+        }
+        else if slots == 1 {
+            DisplayDsIdent(ident).to_string()
+        }
+        else {
+            format!("{}.{}", DisplayDsIdent(ident), SWIZZLE[slot])
+        };
+        scalar.replace("{}", &component)
+    }).collect()
+}
+
+// builds a value from its float slots
+fn write_unpacked(string: &mut String, ty: &Ty, components: &[String]) {
+    let components: Vec<String> = components.iter().map( | component | match ty {
+        Ty::Bool | Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => format!("({} > 0.5)", component),
+        Ty::Int | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => format!("i32({})", component),
+        Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => format!("u32({})", component),
+        _ => component.clone()
+    }).collect();
+    if components.len() == 1 {
+        write!(string, "{}", components[0]).unwrap();
+    }
+    else {
+        write_ty(string, ty);
+        write!(string, "({})", components.join(", ")).unwrap();
+    }
+}
+
+fn write_ty(string: &mut String, ty: &Ty) {
+    match ty {
+        Ty::Array {elem_ty, len} => {
+            write!(string, "array<").unwrap();
+            write_ty(string, elem_ty);
+            write!(string, ", {}>", len).unwrap();
+        }
+        Ty::Struct(struct_ptr) => write!(string, "{}", struct_ptr).unwrap(),
+        Ty::Enum(_) => write!(string, "f32").unwrap(),
+        _ => write_ty_lit(string, ty.maybe_ty_lit().unwrap()),
+    }
+}
+
+fn write_ty_lit(string: &mut String, ty_lit: TyLit) {
+    write!(
+        string,
+        "{}",
+        match ty_lit {
+            TyLit::Bool => "bool",
+            TyLit::Int => "i32",
+            TyLit::Float => "f32",
+            TyLit::Bvec2 => "vec2<bool>",
+            TyLit::Bvec3 => "vec3<bool>",
+            TyLit::Bvec4 => "vec4<bool>",
+            TyLit::Ivec2 => "vec2<i32>",
+            TyLit::Ivec3 => "vec3<i32>",
+            TyLit::Ivec4 => "vec4<i32>",
+            TyLit::Uint => "u32",
+            TyLit::Uvec2 => "vec2<u32>",
+            TyLit::Uvec3 => "vec3<u32>",
+            TyLit::Uvec4 => "vec4<u32>",
+            TyLit::Vec2 => "vec2<f32>",
+            TyLit::Vec3 => "vec3<f32>",
+            TyLit::Vec4 => "vec4<f32>",
+            TyLit::Mat2 => "mat2x2<f32>",
+            TyLit::Mat3 => "mat3x3<f32>",
+            TyLit::Mat4 => "mat4x4<f32>",
+            TyLit::Texture2D => "texture_2d<f32>",
+            TyLit::TextureCube => "texture_cube<f32>",
+            TyLit::Texture3D => "texture_3d<f32>",
+            TyLit::Texture2DArray => "texture_2d_array<f32>",
+            TyLit::Texture2DDepth => "texture_depth_2d",
+        }
+    )
+        .unwrap();
+}
+
+// depth textures are sampled with a comparison sampler
+fn sampler_ty(ty: &Ty) -> &'static str {
+    if *ty == Ty::Texture2DDepth {"sampler_comparison"} else {"sampler"}
+}
+
+fn bool_vec_ty(ty: &Ty) -> &'static str {
+    match ty.slots() {
+        1 => "bool",
+        2 => "vec2<bool>",
+        3 => "vec3<bool>",
+        _ => "vec4<bool>",
+    }
+}
+
+struct WgslBackendWriter<'a> {
+    pub shader_registry: &'a Shader,
+    const_table: &'a DrawShaderConstTable,
+    // params of the fn that is being generated and whether they are inout,
+    // WGSL params are immutable so the others are copied into a var of the same name
+    fn_params: RefCell<Vec<(String, bool)>>,
+    // name and source of the polyfills used by the generated fns
+    polyfills: RefCell<Vec<(String, String)>>,
+    // the fn is used by the vertex stage, which has to pick a mip level explicitly
+    sample_level: Cell<bool>,
+    // fns both stages call that get a `_vertex` copy with explicit mip levels
+    vertex_copy_fns: BTreeSet<FnPtr>,
+}
+
+impl<'a> WgslBackendWriter<'a> {
+    fn use_polyfill(&self, name: String, source: impl FnOnce(&str) -> String) -> String {
+        let mut polyfills = self.polyfills.borrow_mut();
+        if !polyfills.iter().any( | (polyfill, _) | *polyfill == name) {
+            let source = source(&name);
+            polyfills.push((name.clone(), source));
+        }
+        name
+    }
+
+    fn polyfill_for_builtin(&self, ident: Ident, arg_exprs: &[Expr]) -> Option<String> {
+        let ty = arg_exprs.first().map( | arg_expr | arg_expr.ty.borrow().clone().unwrap())?;
+        let mut wgsl_ty = String::new();
+        write_ty(&mut wgsl_ty, &ty);
+        let op = match ident {
+            Ident(id!(equal)) => "==",
+            Ident(id!(notEqual)) => "!=",
+            Ident(id!(lessThan)) => "<",
+            Ident(id!(lessThanEqual)) => "<=",
+            Ident(id!(greaterThan)) => ">",
+            Ident(id!(greaterThanEqual)) => ">=",
+            Ident(id!(mod)) => {
+                // GLSL mod follows floor, the `%` operator truncates
+                return Some(self.use_polyfill(format!("polyfill_mod_{}", ty), | name | format!(
+                    "fn {0}(x: {1}, y: {1}) -> {1} {{\n    return x - y * floor(x / y);\n}}",
+                    name,
+                    wgsl_ty
+                )))
+            }
+            Ident(id!(not)) => {
+                return Some(self.use_polyfill(format!("polyfill_not_{}", ty), | name | format!(
+                    "fn {0}(a: {1}) -> {1} {{\n    return !a;\n}}",
+                    name,
+                    wgsl_ty
+                )))
+            }
+            Ident(id!(matrixCompMult)) => {
+                let size = matrix_size(&ty)?;
+                let columns: Vec<String> = (0..size).map( | column | format!("a[{0}] * b[{0}]", column)).collect();
+                return Some(self.use_polyfill(format!("polyfill_matrixCompMult_{}", ty), | name | format!(
+                    "fn {0}(a: {1}, b: {1}) -> {1} {{\n    return {1}({2});\n}}",
+                    name,
+                    wgsl_ty,
+                    columns.join(", ")
+                )))
+            }
+            Ident(id!(inverse)) => {
+                return Some(self.use_polyfill("polyfill_inverse".to_string(), | name | format!(
+                    "fn {}(m: mat4x4<f32>) -> mat4x4<f32> {{\n{}}}",
+                    name,
+                    POLYFILL_INVERSE
+                )))
+            }
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) if self.sample_level.get() => {
+                return Some(self.use_polyfill("polyfill_sample2d_level".to_string(), | name | format!(
+                    "fn {}(tex: texture_2d<f32>, smp: sampler, pos: vec2<f32>) -> vec4<f32> {{\n    return textureSampleLevel(tex, smp, pos, 0.0);\n}}",
+                    name
+                )))
+            }
+            _ => return None
+        };
+        // the vector relational functions are operators that work per component
+        Some(self.use_polyfill(format!("polyfill_{}_{}", ident, ty), | name | format!(
+            "fn {0}(a: {1}, b: {1}) -> {2} {{\n    return a {3} b;\n}}",
+            name,
+            wgsl_ty,
+            bool_vec_ty(&ty),
+            op
+        )))
+    }
+}
+
+const POLYFILL_INVERSE: &str = "    let a00 = m[0][0]; let a01 = m[0][1]; let a02 = m[0][2]; let a03 = m[0][3];
+    let a10 = m[1][0]; let a11 = m[1][1]; let a12 = m[1][2]; let a13 = m[1][3];
+    let a20 = m[2][0]; let a21 = m[2][1]; let a22 = m[2][2]; let a23 = m[2][3];
+    let a30 = m[3][0]; let a31 = m[3][1]; let a32 = m[3][2]; let a33 = m[3][3];
+    let b00 = a00 * a11 - a01 * a10; let b01 = a00 * a12 - a02 * a10;
+    let b02 = a00 * a13 - a03 * a10; let b03 = a01 * a12 - a02 * a11;
+    let b04 = a01 * a13 - a03 * a11; let b05 = a02 * a13 - a03 * a12;
+    let b06 = a20 * a31 - a21 * a30; let b07 = a20 * a32 - a22 * a30;
+    let b08 = a20 * a33 - a23 * a30; let b09 = a21 * a32 - a22 * a31;
+    let b10 = a21 * a33 - a23 * a31; let b11 = a22 * a33 - a23 * a32;
+    let det = b00 * b11 - b01 * b10 + b02 * b09 + b03 * b08 - b04 * b07 + b05 * b06;
+    return mat4x4<f32>(
+        a11 * b11 - a12 * b10 + a13 * b09, a02 * b10 - a01 * b11 - a03 * b09, a31 * b05 - a32 * b04 + a33 * b03, a22 * b04 - a21 * b05 - a23 * b03,
+        a12 * b08 - a10 * b11 - a13 * b07, a00 * b11 - a02 * b08 + a03 * b07, a32 * b02 - a30 * b05 - a33 * b01, a20 * b05 - a22 * b02 + a23 * b01,
+        a10 * b10 - a11 * b08 + a13 * b06, a01 * b08 - a00 * b10 - a03 * b06, a30 * b04 - a31 * b02 + a33 * b00, a21 * b02 - a20 * b04 - a23 * b00,
+        a11 * b07 - a10 * b09 - a12 * b06, a00 * b09 - a01 * b07 + a02 * b06, a31 * b01 - a30 * b03 - a32 * b00, a20 * b03 - a21 * b01 + a22 * b00
+    ) * (1.0 / det);
+";
+
+impl<'a> BackendWriter for WgslBackendWriter<'a> {
+
+    fn needs_cstyle_struct_cons(&self) -> bool {
+        true
+    }
+
+    fn enum_is_float(&self) -> bool {
+        true
+    }
+
+    fn needs_mul_fn_for_matrix_multiplication(&self) -> bool {
+        false
+    }
+
+    fn needs_unpack_for_matrix_multiplication(&self) -> bool {
+        false
+    }
+
+    fn needs_texture_samplers(&self) -> bool {
+        true
+    }
+
+    fn const_table_is_vec4(&self) -> bool {
+        true
+    }
+
+    fn needs_select_for_cond_expr(&self) -> bool {
+        true
+    }
+
+    fn needs_splat_for_builtin_args(&self) -> bool {
+        true
+    }
+
+    fn needs_pointer_for_inout(&self) -> bool {
+        true
+    }
+
+    fn needs_unsigned_shift_amount(&self) -> bool {
+        true
+    }
+    
+    fn max_loop_iterations(&self) -> Option<u32> {
+        None
+    }
+    
+    fn needs_padded_uniform_arrays(&self) -> bool {
+        false
+    }
+
+    fn needs_int_emulation(&self) -> bool {
+        false
+    }
+    
+    fn use_int_op_polyfill(&self, op: BinOp, _left_ty: &Ty, _right_ty: &Ty) -> String {
+        panic!("no polyfill for `{}`", op)
+    }
+    
+    // WGSL can't build a matrix from a scalar or from another matrix
+    fn use_cons_fn(&self, what: &str) -> bool {
+        let parts: Vec<&str> = what.split('_').collect();
+        parts.len() == 3 && parts[1].starts_with("mat") && (parts[2] == "float" || parts[2].starts_with("mat"))
+    }
+
+    fn write_var_decl(
+        &self,
+        string: &mut String,
+        sep: &'static str,
+        is_inout: bool,
+        _is_packed: bool,
+        ident: &dyn fmt::Display,
+        ty: &Ty,
+    ) -> bool {
+        match ty {
+            Ty::Void | Ty::DrawShader | Ty::ClosureDef {..} | Ty::ClosureDecl => {
+                return false
+            }
+            _ if ty.is_texture() => {
+                write!(string, "{}{}: ", sep, ident).unwrap();
+                write_ty(string, ty);
+                write!(string, ", {}_sampler: {}", ident, sampler_ty(ty)).unwrap();
+            }
+            _ if is_inout => {
+                write!(string, "{}{}: ptr<function, ", sep, ident).unwrap();
+                write_ty(string, ty);
+                write!(string, ">").unwrap();
+                self.fn_params.borrow_mut().push((ident.to_string(), true));
+            }
+            _ => {
+                write!(string, "{}param_{}: ", sep, ident).unwrap();
+                write_ty(string, ty);
+                self.fn_params.borrow_mut().push((ident.to_string(), false));
+            }
+        }
+        true
+    }
+
+    fn write_fn_def_start(&self, string: &mut String, ident: &dyn fmt::Display, _return_ty: &Ty) {
+        self.fn_params.borrow_mut().clear();
+        write!(string, "fn {}(", ident).unwrap();
+    }
+
+    fn write_fn_def_end(&self, string: &mut String, return_ty: &Ty) {
+        write!(string, ")").unwrap();
+        if *return_ty != Ty::Void {
+            write!(string, " -> ").unwrap();
+            write_ty(string, return_ty);
+        }
+    }
+
+    fn write_fn_block_prologue(&self, string: &mut String) {
+        for (ident, is_inout) in self.fn_params.borrow().iter() {
+            if !is_inout {
+                writeln!(string, "    var {0} = param_{0};", ident).unwrap();
+            }
+        }
+    }
+
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        write!(string, "var {}: ", ident).unwrap();
+        write_ty(string, ty);
+    }
+
+    fn write_discard_stmt(&self, string: &mut String) {
+        write!(string, "discard;").unwrap();
+    }
+
+    fn write_var_expr(&self, string: &mut String, ident: &dyn fmt::Display) {
+        let ident = ident.to_string();
+        if self.fn_params.borrow().iter().any( | (param, is_inout) | *is_inout && *param == ident) {
+            write!(string, "(*{})", ident).unwrap();
+        }
+        else {
+            write!(string, "{}", ident).unwrap();
+        }
+    }
+
+    // `&ds_x` is a `ptr<private, T>` where the fn takes a `ptr<function, T>`,
+    // so the fields go through a wrapper that copies them in and out of locals
+    fn fn_ident_suffix(&self, fn_ptr: FnPtr) -> &'static str {
+        if self.sample_level.get() && self.vertex_copy_fns.contains(&fn_ptr) {
+            "_vertex"
+        }
+        else {
+            ""
+        }
+    }
+
+    fn write_call_ident_with_inout_args(&self, string: &mut String, fn_def: &FnDef, inout_args: &[InoutArg]) {
+        let suffix = self.fn_ident_suffix(fn_def.fn_ptr);
+        let mut name = format!("{}_{}{}_fields_", fn_def.fn_ptr, fn_def.ident, suffix);
+        for inout_arg in inout_args {
+            match inout_arg {
+                InoutArg::Direct => name.push('0'),
+                InoutArg::DrawShaderField => name.push('1'),
+                InoutArg::Swizzle {is_draw_shader_field, swizzle, ..} => {
+                    write!(name, "{}{}", if *is_draw_shader_field {'1'} else {'0'}, swizzle).unwrap();
+                }
+            }
+        }
+        let name = self.use_polyfill(name, | name | {
+            let params = fn_def.params_for_args(inout_args.len());
+            let return_ty = fn_def.return_ty.borrow().clone().unwrap();
+            let mut decls = String::new();
+            let mut args = String::new();
+            let mut copy_in = String::new();
+            let mut copy_out = String::new();
+            for (index, (param, inout_arg)) in params.iter().zip(inout_args).enumerate() {
+                let ty = param.ty_expr.ty.borrow().clone().unwrap();
+                let sep = if index == 0 {""} else {", "};
+                if let InoutArg::Swizzle {is_draw_shader_field, vector_ty, swizzle} = inout_arg {
+                    let space = if *is_draw_shader_field {"private"} else {"function"};
+                    write!(decls, "{}p{}: ptr<{}, ", sep, index, space).unwrap();
+                    write_ty(&mut decls, vector_ty);
+                    write!(decls, ">").unwrap();
+                    write!(args, "{}&a{}", sep, index).unwrap();
+                    writeln!(copy_in, "    var a{0} = (*p{0}).{1};", index, swizzle).unwrap();
+                    // a swizzle can't be assigned to, its components can
+                    let swizzle = swizzle.to_string();
+                    if swizzle.len() == 1 {
+                        writeln!(copy_out, "    (*p{0}).{1} = a{0};", index, swizzle).unwrap();
+                    }
+                    else {
+                        for (component, c) in swizzle.chars().enumerate() {
+                            writeln!(copy_out, "    (*p{0}).{1} = a{0}.{2};", index, c, &"xyzw"[component..component + 1]).unwrap();
+                        }
+                    }
+                }
+                else if *inout_arg == InoutArg::DrawShaderField {
+                    write!(decls, "{}p{}: ptr<private, ", sep, index).unwrap();
+                    write_ty(&mut decls, &ty);
+                    write!(decls, ">").unwrap();
+                    write!(args, "{}&a{}", sep, index).unwrap();
+                    writeln!(copy_in, "    var a{0} = *p{0};", index).unwrap();
+                    writeln!(copy_out, "    *p{0} = a{0};", index).unwrap();
+                }
+                else if ty.is_texture() {
+                    write!(decls, "{}p{}: ", sep, index).unwrap();
+                    write_ty(&mut decls, &ty);
+                    write!(decls, ", p{}_sampler: {}", index, sampler_ty(&ty)).unwrap();
+                    write!(args, "{0}p{1}, p{1}_sampler", sep, index).unwrap();
+                }
+                else {
+                    write!(decls, "{}p{}: ", sep, index).unwrap();
+                    if param.is_inout {
+                        write!(decls, "ptr<function, ").unwrap();
+                        write_ty(&mut decls, &ty);
+                        write!(decls, ">").unwrap();
+                    }
+                    else {
+                        write_ty(&mut decls, &ty);
+                    }
+                    write!(args, "{}p{}", sep, index).unwrap();
+                }
+            }
+            let mut source = format!("fn {}({})", name, decls);
+            if return_ty == Ty::Void {
+                write!(source, " {{\n{}    {}_{}{}({});\n{}}}", copy_in, fn_def.fn_ptr, fn_def.ident, suffix, args, copy_out).unwrap();
+            }
+            else {
+                write!(source, " -> ").unwrap();
+                write_ty(&mut source, &return_ty);
+                write!(source, " {{\n{}    let result = {}_{}{}({});\n{}    return result;\n}}", copy_in, fn_def.fn_ptr, fn_def.ident, suffix, args, copy_out).unwrap();
+            }
+            source
+        });
+        write!(string, "{}", name).unwrap();
+    }
+
+    fn write_call_expr_hidden_args(&self, _string: &mut String, _hidden_args: &BTreeSet<HiddenArgKind >, _sep: &str) {
+    }
+
+    fn write_fn_def_hidden_params(&self, _string: &mut String, _hidden_args: &BTreeSet<HiddenArgKind >, _sep: &str) {
+    }
+
+    fn generate_live_value_prefix(&self, _string: &mut String) {
+    }
+
+    fn write_const_array_access_start(&self, string: &mut String, const_ptr: ConstPtr) {
+        write!(string, "{}(", const_ptr).unwrap();
+    }
+
+    fn write_stage_builtin(&self, string: &mut String, stage_builtin: StageBuiltin) {
+        write!(string, "builtin_{}", stage_builtin.name()).unwrap();
+    }
+
+    fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, _ty: &Ty) {
+        write!(string, "{}", &DisplayDsIdent(field_ident)).unwrap();
+    }
+
+    fn write_ty_lit(&self, string: &mut String, ty_lit: TyLit) {
+        write_ty_lit(string, ty_lit);
+    }
+
+    fn write_builtin_call_ident(&self, string: &mut String, ident: Ident, arg_exprs: &[Expr]) {
+        if let Some(polyfill) = self.polyfill_for_builtin(ident, arg_exprs) {
+            write!(string, "{}", polyfill).unwrap();
+            return
+        }
+        match ident {
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) => write!(string, "textureSample").unwrap(),
+            Ident(id!(atan)) if arg_exprs.len() == 2 => write!(string, "atan2").unwrap(),
+            Ident(id!(dFdx)) => write!(string, "dpdx").unwrap(),
+            Ident(id!(dFdy)) => write!(string, "dpdy").unwrap(),
+            Ident(id!(inversesqrt)) => write!(string, "inverseSqrt").unwrap(),
+            Ident(id!(faceforward)) => write!(string, "faceForward").unwrap(),
+            _ => write!(string, "{}", ident).unwrap()
+        }
+    }
+    
+    fn write_texture_call(&self, string: &mut String, call: &TextureCall) {
+        let tex = &call.texture;
+        let args = &call.args;
+        let rest = call.rest();
+        match call.op {
+            TextureOp::Size => {
+                match call.ty {
+                    Ty::Texture3D => write!(string, "vec3<i32>(textureDimensions({}, {}))", tex, rest[0]).unwrap(),
+                    Ty::Texture2DArray => write!(string, "vec3<i32>(vec2<i32>(textureDimensions({0}, {1})), i32(textureNumLayers({0})))", tex, rest[0]).unwrap(),
+                    _ => write!(string, "vec2<i32>(textureDimensions({}, {}))", tex, rest[0]).unwrap(),
+                }
+                return
+            }
+            TextureOp::Fetch => {
+                if call.ty == Ty::Texture2DArray {
+                    write!(string, "textureLoad({0}, ({1}).xy, ({1}).z, {2})", tex, args[0], rest[0]).unwrap();
+                }
+                else {
+                    write!(string, "textureLoad({}, {}, {})", tex, args[0], rest[0]).unwrap();
+                }
+                return
+            }
+            _ => ()
+        }
+        // implicit derivatives only exist in the fragment stage
+        let sample_level = self.sample_level.get();
+        let ident = match call.op {
+            TextureOp::Sample | TextureOp::Offset if sample_level => "textureSampleLevel",
+            TextureOp::Sample | TextureOp::Offset => "textureSample",
+            TextureOp::Lod => "textureSampleLevel",
+            TextureOp::Grad => "textureSampleGrad",
+            TextureOp::Compare | TextureOp::CompareOffset if sample_level => "textureSampleCompareLevel",
+            TextureOp::Compare | TextureOp::CompareOffset => "textureSampleCompare",
+            TextureOp::Size | TextureOp::Fetch => unreachable!(),
+        };
+        write!(string, "{}({}, {}, {}", ident, tex, call.sampler, args[0]).unwrap();
+        if call.ty == Ty::Texture2DArray {
+            write!(string, ", {}", args[1]).unwrap();
+        }
+        if sample_level && (call.op == TextureOp::Sample || call.op == TextureOp::Offset) {
+            write!(string, ", 0.0").unwrap();
+        }
+        for arg in rest {
+            write!(string, ", {}", arg).unwrap();
+        }
+        write!(string, ")").unwrap();
+    }
+
+}
@@ -0,0 +1,1406 @@
+use {
+    std::{collections::HashMap, rc::Rc},
+    crate::{
+        makepad_live_compiler::{LiveError, LiveErrorOrigin, LiveEval, live_error_origin, TokenSpan},
+        makepad_live_id::{id, LiveId},
+        makepad_math::{Mat4, Vec2, Vec3, Vec4},
+        shader::Shader,
+        shader_ast::*,
+        swizzle::Swizzle,
+    }
+};
+
+/// A value going into or coming out of `Shader::interpret_vertex` and `Shader::interpret_pixel`.
+/// Matrices are column major, like `Mat4`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    Bvec2([bool; 2]),
+    Bvec3([bool; 3]),
+    Bvec4([bool; 4]),
+    Ivec2([i32; 2]),
+    Ivec3([i32; 3]),
+    Ivec4([i32; 4]),
+    Uint(u32),
+    Uvec2([u32; 2]),
+    Uvec3([u32; 3]),
+    Uvec4([u32; 4]),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Mat2([f32; 4]),
+    Mat3([f32; 9]),
+    Mat4(Mat4),
+    /// the items of an array uniform, in order
+    Array(Vec<ShaderValue>),
+}
+
+impl From<bool> for ShaderValue {
+    fn from(value: bool) -> Self {ShaderValue::Bool(value)}
+}
+
+impl From<i32> for ShaderValue {
+    fn from(value: i32) -> Self {ShaderValue::Int(value)}
+}
+
+impl From<u32> for ShaderValue {
+    fn from(value: u32) -> Self {ShaderValue::Uint(value)}
+}
+
+impl From<f32> for ShaderValue {
+    fn from(value: f32) -> Self {ShaderValue::Float(value)}
+}
+
+impl From<Vec2> for ShaderValue {
+    fn from(value: Vec2) -> Self {ShaderValue::Vec2(value)}
+}
+
+impl From<Vec3> for ShaderValue {
+    fn from(value: Vec3) -> Self {ShaderValue::Vec3(value)}
+}
+
+impl From<Vec4> for ShaderValue {
+    fn from(value: Vec4) -> Self {ShaderValue::Vec4(value)}
+}
+
+impl From<Mat4> for ShaderValue {
+    fn from(value: Mat4) -> Self {ShaderValue::Mat4(value)}
+}
+
+impl<T: Into<ShaderValue>> From<Vec<T>> for ShaderValue {
+    fn from(values: Vec<T>) -> Self {ShaderValue::Array(values.into_iter().map(Into::into).collect())}
+}
+
+/// The texels behind a texture field, row by row with the first row at `v = 0`.
+/// It is sampled with the `ShaderSampler` of the field, without mipmaps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderImage {
+    pub width: usize,
+    pub height: usize,
+    pub texels: Vec<Vec4>,
+}
+
+/// The fields of a draw shader for a single invocation, by name.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderInputs {
+    values: HashMap<String, ShaderValue>,
+    images: HashMap<String, ShaderImage>,
+}
+
+impl ShaderInputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a geometry, instance, uniform or varying field, or a stage builtin like `frag_coord`.
+    pub fn set(&mut self, name: &str, value: impl Into<ShaderValue>) -> &mut Self {
+        self.values.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Sets the image a texture field samples from.
+    pub fn set_image(&mut self, name: &str, image: ShaderImage) -> &mut Self {
+        self.images.insert(name.to_string(), image);
+        self
+    }
+
+    /// Sets the varyings computed by `Shader::interpret_vertex`, to feed them to `Shader::interpret_pixel`.
+    pub fn set_varyings(&mut self, output: &ShaderOutput) -> &mut Self {
+        for (name, value) in &output.varyings {
+            self.set(name, value.clone());
+        }
+        self
+    }
+}
+
+/// The result of a single invocation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderOutput {
+    /// What `vertex` or `pixel` returned, the clip space position or the colour.
+    /// For a `pixel` returning a struct, the first render target padded like `gl_FragData[0]`.
+    pub value: Vec4,
+    /// The render targets of a `pixel` returning a struct, by field name, then the
+    /// `point_size` or `frag_depth` the stage writes.
+    pub outputs: Vec<(String, ShaderValue)>,
+    /// The varyings as the invocation left them, and the geometry and instance fields the pixel shader reads.
+    pub varyings: Vec<(String, ShaderValue)>,
+    /// `pixel` ran into a `discard`, nothing is written then.
+    pub discarded: bool,
+}
+
+impl ShaderOutput {
+    pub fn varying(&self, name: &str) -> Option<&ShaderValue> {
+        self.varyings.iter().find( | (varying_name, _) | varying_name == name).map( | (_, value) | value)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&ShaderValue> {
+        self.outputs.iter().find( | (output_name, _) | output_name == name).map( | (_, value) | value)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Stage {
+    Vertex,
+    Pixel,
+}
+
+pub(crate) fn interpret(shader: &Shader, inputs: &ShaderInputs, stage: Stage) -> Result<ShaderOutput, LiveError> {
+    let mut interpreter = Interpreter {
+        shader,
+        inputs,
+        stage,
+        fields: HashMap::new(),
+        builtins: HashMap::new(),
+        live_values: HashMap::new(),
+        discarded: false,
+    };
+    let ident = if stage == Stage::Vertex {Ident(id!(vertex))} else {Ident(id!(pixel))};
+    let fn_def = shader.draw_shader_method_decl_from_ident(&shader.draw_shader_def, ident).unwrap();
+    let mut frame = Frame::new(fn_def);
+    let value = interpreter.call_fn(&mut frame, fn_def, &[])?;
+    let mut outputs = Vec::new();
+    let value = match (stage, shader.pixel_outputs(&shader.draw_shader_def), value) {
+        (Stage::Pixel, Some(pixel_outputs), Value::Composite(fields)) => {
+            for ((ident, ty), field) in pixel_outputs.iter().zip(&fields) {
+                outputs.push((ident.to_string(), field.to_shader_value(ty)));
+            }
+            // missing components are filled in like attributes
+            let mut value = vec![0.0, 0.0, 0.0, 1.0];
+            let first = fields[0].floats();
+            value[..first.len()].copy_from_slice(first);
+            value
+        }
+        (_, _, value) => value.floats().to_vec(),
+    };
+    // the builtins the stage writes, even when the invocation didn't get to them
+    let fns = if stage == Stage::Vertex {&shader.draw_shader_def.vertex_fns} else {&shader.draw_shader_def.pixel_fns};
+    for fn_ptr in fns.borrow().iter() {
+        let fn_def = shader.all_fns.get(fn_ptr).unwrap();
+        for (stage_builtin, span) in fn_def.stage_builtins.borrow().as_ref().unwrap() {
+            if stage_builtin.is_output() && !outputs.iter().any( | (name, _) | name == stage_builtin.name()) {
+                let value = interpreter.builtin_value(*span, *stage_builtin) ?;
+                outputs.push((stage_builtin.name().to_string(), value.to_shader_value(&stage_builtin.ty())));
+            }
+        }
+    }
+
+    let mut varyings = Vec::new();
+    for field in &shader.draw_shader_def.fields {
+        let is_varying = match &field.kind {
+            DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+            DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} => is_used_in_pixel_shader.get(),
+            DrawShaderFieldKind::Varying {..} => true,
+            _ => false
+        };
+        if !is_varying {
+            continue;
+        }
+        let ty = field.ty_expr.ty.borrow().clone().unwrap();
+        // a varying that is never written starts out zeroed
+        let value = match interpreter.field_value(field.span, field.ident) {
+            Ok(value) => value,
+            Err(_) => interpreter.zero(&ty),
+        };
+        varyings.push((field.ident.to_string(), value.to_shader_value(&ty)));
+    }
+    Ok(ShaderOutput {
+        value: Vec4 {x: value[0], y: value[1], z: value[2], w: value[3]},
+        varyings,
+        outputs,
+        discarded: interpreter.discarded,
+    })
+}
+
+// scalars are values with a single component, matrices are column major
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Void,
+    Bools(Vec<bool>),
+    Ints(Vec<i32>),
+    Uints(Vec<u32>),
+    Floats(Vec<f32>),
+    // structs and arrays
+    Composite(Vec<Value>),
+    // a texture field
+    Texture(Ident),
+}
+
+impl Value {
+    fn from_val(val: &Val) -> Value {
+        match val {
+            Val::Bool(value) => Value::Bools(vec![*value]),
+            Val::Int(value) => Value::Ints(vec![*value]),
+            Val::Uint(value) => Value::Uints(vec![*value]),
+            Val::Float(value) => Value::Floats(vec![*value]),
+            Val::Vec4(value) => Value::Floats(vec![value.x, value.y, value.z, value.w]),
+        }
+    }
+
+    fn from_shader_value(value: &ShaderValue, ty: &Ty) -> Option<Value> {
+        Some(match (value, ty) {
+            (ShaderValue::Bool(value), Ty::Bool) => Value::Bools(vec![*value]),
+            (ShaderValue::Int(value), Ty::Int | Ty::Enum(_)) => Value::Ints(vec![*value]),
+            (ShaderValue::Float(value), Ty::Float) => Value::Floats(vec![*value]),
+            (ShaderValue::Bvec2(value), Ty::Bvec2) => Value::Bools(value.to_vec()),
+            (ShaderValue::Bvec3(value), Ty::Bvec3) => Value::Bools(value.to_vec()),
+            (ShaderValue::Bvec4(value), Ty::Bvec4) => Value::Bools(value.to_vec()),
+            (ShaderValue::Ivec2(value), Ty::Ivec2) => Value::Ints(value.to_vec()),
+            (ShaderValue::Ivec3(value), Ty::Ivec3) => Value::Ints(value.to_vec()),
+            (ShaderValue::Ivec4(value), Ty::Ivec4) => Value::Ints(value.to_vec()),
+            (ShaderValue::Uint(value), Ty::Uint) => Value::Uints(vec![*value]),
+            (ShaderValue::Uvec2(value), Ty::Uvec2) => Value::Uints(value.to_vec()),
+            (ShaderValue::Uvec3(value), Ty::Uvec3) => Value::Uints(value.to_vec()),
+            (ShaderValue::Uvec4(value), Ty::Uvec4) => Value::Uints(value.to_vec()),
+            (ShaderValue::Vec2(value), Ty::Vec2) => Value::Floats(vec![value.x, value.y]),
+            (ShaderValue::Vec3(value), Ty::Vec3) => Value::Floats(vec![value.x, value.y, value.z]),
+            (ShaderValue::Vec4(value), Ty::Vec4) => Value::Floats(vec![value.x, value.y, value.z, value.w]),
+            (ShaderValue::Mat2(value), Ty::Mat2) => Value::Floats(value.to_vec()),
+            (ShaderValue::Mat3(value), Ty::Mat3) => Value::Floats(value.to_vec()),
+            (ShaderValue::Mat4(value), Ty::Mat4) => Value::Floats(value.v.to_vec()),
+            (ShaderValue::Array(values), Ty::Array {elem_ty, len}) if values.len() == *len => {
+                Value::Composite(values.iter().map( | value | Value::from_shader_value(value, elem_ty)).collect::<Option<_>>() ?)
+            }
+            _ => return None
+        })
+    }
+
+    fn to_shader_value(&self, ty: &Ty) -> ShaderValue {
+        match (self, ty) {
+            (Value::Bools(value), Ty::Bool) => ShaderValue::Bool(value[0]),
+            (Value::Bools(value), Ty::Bvec2) => ShaderValue::Bvec2([value[0], value[1]]),
+            (Value::Bools(value), Ty::Bvec3) => ShaderValue::Bvec3([value[0], value[1], value[2]]),
+            (Value::Bools(value), _) => ShaderValue::Bvec4([value[0], value[1], value[2], value[3]]),
+            (Value::Ints(value), Ty::Ivec2) => ShaderValue::Ivec2([value[0], value[1]]),
+            (Value::Ints(value), Ty::Ivec3) => ShaderValue::Ivec3([value[0], value[1], value[2]]),
+            (Value::Ints(value), Ty::Ivec4) => ShaderValue::Ivec4([value[0], value[1], value[2], value[3]]),
+            (Value::Ints(value), _) => ShaderValue::Int(value[0]),
+            (Value::Uints(value), Ty::Uvec2) => ShaderValue::Uvec2([value[0], value[1]]),
+            (Value::Uints(value), Ty::Uvec3) => ShaderValue::Uvec3([value[0], value[1], value[2]]),
+            (Value::Uints(value), Ty::Uvec4) => ShaderValue::Uvec4([value[0], value[1], value[2], value[3]]),
+            (Value::Uints(value), _) => ShaderValue::Uint(value[0]),
+            (Value::Floats(value), Ty::Vec2) => ShaderValue::Vec2(Vec2 {x: value[0], y: value[1]}),
+            (Value::Floats(value), Ty::Vec3) => ShaderValue::Vec3(Vec3 {x: value[0], y: value[1], z: value[2]}),
+            (Value::Floats(value), Ty::Vec4) => ShaderValue::Vec4(Vec4 {x: value[0], y: value[1], z: value[2], w: value[3]}),
+            (Value::Floats(value), Ty::Mat2) => ShaderValue::Mat2([value[0], value[1], value[2], value[3]]),
+            (Value::Floats(value), Ty::Mat3) => {
+                let mut mat = [0.0; 9];
+                mat.copy_from_slice(value);
+                ShaderValue::Mat3(mat)
+            }
+            (Value::Floats(value), Ty::Mat4) => {
+                let mut mat = Mat4::default();
+                mat.v.copy_from_slice(value);
+                ShaderValue::Mat4(mat)
+            }
+            (Value::Floats(value), _) => ShaderValue::Float(value[0]),
+            _ => panic!()
+        }
+    }
+
+    fn bools(&self) -> &[bool] {
+        match self {
+            Value::Bools(value) => value,
+            _ => panic!()
+        }
+    }
+
+    fn ints(&self) -> &[i32] {
+        match self {
+            Value::Ints(value) => value,
+            _ => panic!()
+        }
+    }
+
+    fn texture(&self) -> Ident {
+        match self {
+            Value::Texture(ident) => *ident,
+            _ => panic!()
+        }
+    }
+
+    fn floats(&self) -> &[f32] {
+        match self {
+            Value::Floats(value) => value,
+            _ => panic!()
+        }
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            Value::Bools(value) => value.len(),
+            Value::Ints(value) => value.len(),
+            Value::Uints(value) => value.len(),
+            Value::Floats(value) => value.len(),
+            _ => 1
+        }
+    }
+
+    // a component as a number, to convert between scalar types
+    fn component(&self, index: usize) -> f64 {
+        match self {
+            Value::Bools(value) => if value[index] {1.0} else {0.0},
+            Value::Ints(value) => value[index] as f64,
+            Value::Uints(value) => value[index] as f64,
+            Value::Floats(value) => value[index] as f64,
+            _ => panic!()
+        }
+    }
+
+    fn select(&self, indices: &[usize]) -> Value {
+        match self {
+            Value::Bools(value) => Value::Bools(indices.iter().map( | index | value[*index]).collect()),
+            Value::Ints(value) => Value::Ints(indices.iter().map( | index | value[*index]).collect()),
+            Value::Uints(value) => Value::Uints(indices.iter().map( | index | value[*index]).collect()),
+            Value::Floats(value) => Value::Floats(indices.iter().map( | index | value[*index]).collect()),
+            _ => panic!()
+        }
+    }
+
+    fn set_components(&mut self, indices: &[usize], new_value: &Value) {
+        match (self, new_value) {
+            (Value::Bools(value), Value::Bools(new_value)) => indices.iter().zip(new_value).for_each( | (index, new) | value[*index] = *new),
+            (Value::Ints(value), Value::Ints(new_value)) => indices.iter().zip(new_value).for_each( | (index, new) | value[*index] = *new),
+            (Value::Uints(value), Value::Uints(new_value)) => indices.iter().zip(new_value).for_each( | (index, new) | value[*index] = *new),
+            (Value::Floats(value), Value::Floats(new_value)) => indices.iter().zip(new_value).for_each( | (index, new) | value[*index] = *new),
+            _ => panic!()
+        }
+    }
+}
+
+// converts numbers to the scalar type of `ty`
+fn from_components(ty: &Ty, components: impl Iterator<Item = f64>) -> Value {
+    match scalar_ty(ty) {
+        Ty::Bool => Value::Bools(components.map( | value | value != 0.0).collect()),
+        // going through `i64` keeps the bits when converting between `int` and `uint`
+        Ty::Int | Ty::Enum(_) => Value::Ints(components.map( | value | value as i64 as i32).collect()),
+        Ty::Uint => Value::Uints(components.map( | value | value as i64 as u32).collect()),
+        _ => Value::Floats(components.map( | value | value as f32).collect()),
+    }
+}
+
+fn scalar_ty(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Bool | Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => Ty::Bool,
+        Ty::Int | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => Ty::Int,
+        Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => Ty::Uint,
+        Ty::Enum(_) => ty.clone(),
+        _ => Ty::Float
+    }
+}
+
+fn components(ty: &Ty) -> usize {
+    match ty {
+        Ty::Bvec2 | Ty::Ivec2 | Ty::Uvec2 | Ty::Vec2 => 2,
+        Ty::Bvec3 | Ty::Ivec3 | Ty::Uvec3 | Ty::Vec3 => 3,
+        Ty::Bvec4 | Ty::Ivec4 | Ty::Uvec4 | Ty::Vec4 | Ty::Mat2 => 4,
+        Ty::Mat3 => 9,
+        Ty::Mat4 => 16,
+        _ => 1
+    }
+}
+
+fn matrix_size(ty: &Ty) -> usize {
+    match ty {
+        Ty::Mat2 => 2,
+        Ty::Mat3 => 3,
+        _ => 4
+    }
+}
+
+// the same default as `GlslOptions::max_loop_iterations`, a loop that never ends fails instead of hanging
+const MAX_LOOP_ITERATIONS: u32 = 1024;
+
+fn count_iteration(span: TokenSpan, keyword: &str, iterations: u32) -> Result<u32, LiveError> {
+    if iterations == MAX_LOOP_ITERATIONS {
+        return Err(error(span, format!("`{}` ran more than {} iterations", keyword, MAX_LOOP_ITERATIONS)))
+    }
+    Ok(iterations + 1)
+}
+
+fn error(span: TokenSpan, message: String) -> LiveError {
+    LiveError {
+        origin: live_error_origin!(),
+        span: span.into(),
+        message,
+    }
+}
+
+enum Flow {
+    Next,
+    Break(Option<Ident>),
+    Continue(Option<Ident>),
+    Return(Value),
+    Discard,
+}
+
+impl Flow {
+    // the flow after the body of a loop labelled `label` has run, `None` to run it again
+    fn out_of_loop(self, label: Option<Ident>) -> Option<Flow> {
+        match self {
+            Flow::Next => None,
+            Flow::Break(None) => Some(Flow::Next),
+            Flow::Continue(None) => None,
+            Flow::Break(jump_label) if jump_label == label => Some(Flow::Next),
+            Flow::Continue(jump_label) if jump_label == label => None,
+            flow => Some(flow),
+        }
+    }
+}
+
+// a closure passed to the fn of a frame, with the values it closed over when it was passed
+struct Closure<'a> {
+    def: &'a ClosureDef,
+    fn_def: &'a FnDef,
+    closed_over: Vec<((Ident, ScopeSymShadow), Value)>,
+}
+
+struct Frame<'a> {
+    fn_def: &'a FnDef,
+    locals: HashMap<(Ident, ScopeSymShadow), Value>,
+    // by param index
+    closures: HashMap<usize, Rc<Closure<'a>>>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(fn_def: &'a FnDef) -> Self {
+        Frame {
+            fn_def,
+            locals: HashMap::new(),
+            closures: HashMap::new(),
+        }
+    }
+}
+
+enum PlaceRoot {
+    Local(Ident, ScopeSymShadow),
+    Field(Ident),
+    LiveValue(ValuePtr),
+}
+
+enum PlaceStep {
+    // a field of a struct or an element of an array
+    Member(usize),
+    // components of a vector or a matrix
+    Components(Vec<usize>),
+}
+
+struct Place {
+    root: PlaceRoot,
+    steps: Vec<PlaceStep>,
+}
+
+struct Interpreter<'a> {
+    shader: &'a Shader,
+    inputs: &'a ShaderInputs,
+    stage: Stage,
+    // fields as far as they were read or written
+    fields: HashMap<Ident, Value>,
+    builtins: HashMap<StageBuiltin, Value>,
+    live_values: HashMap<ValuePtr, Value>,
+    discarded: bool,
+}
+
+impl<'a> Interpreter<'a> {
+    fn zero(&self, ty: &Ty) -> Value {
+        match ty {
+            Ty::Void | Ty::DrawShader | Ty::ClosureDecl | Ty::ClosureDef(_) => Value::Void,
+            _ if ty.is_texture() => Value::Void,
+            Ty::Struct(struct_ptr) => {
+                let struct_def = self.shader.structs.get(struct_ptr).unwrap();
+                Value::Composite(struct_def.fields.iter().map( | field | {
+                    self.zero(field.ty_expr.ty.borrow().as_ref().unwrap())
+                }).collect())
+            }
+            Ty::Array {elem_ty, len} => Value::Composite(vec![self.zero(elem_ty); *len]),
+            _ => from_components(ty, std::iter::repeat(0.0).take(components(ty))),
+        }
+    }
+
+    fn field_value(&mut self, span: TokenSpan, ident: Ident) -> Result<Value, LiveError> {
+        if let Some(stage_builtin) = self.shader.draw_shader_def.find_stage_builtin(ident) {
+            return self.builtin_value(span, stage_builtin)
+        }
+        if let Some(value) = self.fields.get(&ident) {
+            return Ok(value.clone())
+        }
+        let field = self.shader.draw_shader_def.fields.iter().find( | field | field.ident == ident).unwrap();
+        let ty = field.ty_expr.ty.borrow().clone().unwrap();
+        let value = match (&field.kind, self.inputs.values.get(&ident.to_string())) {
+            (DrawShaderFieldKind::Texture {..}, _) => Value::Texture(ident),
+            (_, Some(value)) => Value::from_shader_value(value, &ty).ok_or_else( | | {
+                error(span, format!("expected a {} value for `{}`, got {:?}", ty, ident, value))
+            }) ?,
+            (DrawShaderFieldKind::Varying {..}, None) if self.stage == Stage::Vertex => self.zero(&ty),
+            (_, None) => return Err(error(span, format!("no value for `{}` in the inputs", ident))),
+        };
+        self.fields.insert(ident, value.clone());
+        Ok(value)
+    }
+
+    // the inputs set them by name, the outputs start out like they do on the GPU
+    fn builtin_value(&mut self, span: TokenSpan, stage_builtin: StageBuiltin) -> Result<Value, LiveError> {
+        if let Some(value) = self.builtins.get(&stage_builtin) {
+            return Ok(value.clone())
+        }
+        let ty = stage_builtin.ty();
+        let value = match (stage_builtin, self.inputs.values.get(stage_builtin.name())) {
+            (_, Some(value)) => Value::from_shader_value(value, &ty).ok_or_else( | | {
+                error(span, format!("expected a {} value for `self.{}`, got {:?}", ty, stage_builtin.name(), value))
+            }) ?,
+            (StageBuiltin::PointSize, None) => Value::Floats(vec![1.0]),
+            (StageBuiltin::FragDepth, None) => self.builtin_value(span, StageBuiltin::FragCoord) ?.select(&[2]),
+            (_, None) => return Err(error(span, format!("no value for `self.{}` in the inputs", stage_builtin.name()))),
+        };
+        self.builtins.insert(stage_builtin, value.clone());
+        Ok(value)
+    }
+
+    fn live_value(&mut self, span: TokenSpan, value_ptr: ValuePtr, ty: &Ty) -> Result<Value, LiveError> {
+        if let Some(value) = self.live_values.get(&value_ptr) {
+            return Ok(value.clone())
+        }
+        let value = match self.shader.live_value_eval(value_ptr) ? {
+            LiveEval::Bool(value) => Value::Bools(vec![value]),
+            LiveEval::Int(value) => from_components(ty, std::iter::once(value as f64)),
+            LiveEval::Float(value) => from_components(ty, std::iter::once(value)),
+            LiveEval::Vec2(value) => Value::Floats(vec![value.x, value.y]),
+            LiveEval::Vec3(value) => Value::Floats(vec![value.x, value.y, value.z]),
+            LiveEval::Vec4(value) => Value::Floats(vec![value.x, value.y, value.z, value.w]),
+            LiveEval::String(_) => return Err(error(span, String::from("can't use a string in a shader"))),
+        };
+        self.live_values.insert(value_ptr, value.clone());
+        Ok(value)
+    }
+
+    // calls
+
+    fn call_fn(&mut self, frame: &mut Frame<'a>, fn_def: &'a FnDef, arg_exprs: &'a [Expr]) -> Result<Value, LiveError> {
+        let first_param = fn_def.params.len() - arg_exprs.len();
+        let mut callee = Frame::new(fn_def);
+        let mut write_backs = Vec::new();
+        for (param_index, (param, arg_expr)) in fn_def.params_for_args(arg_exprs.len()).iter().zip(arg_exprs).enumerate() {
+            if let ExprKind::ClosureDef(closure_def_index) = arg_expr.kind {
+                let def = &frame.fn_def.closure_defs[closure_def_index.0];
+                let mut closed_over = Vec::new();
+                for sym in def.closed_over_syms.borrow().as_ref().unwrap() {
+                    if sym.ty != Ty::DrawShader {
+                        closed_over.push(((sym.ident, sym.shadow), self.var_value(frame, sym.ident, sym.shadow)));
+                    }
+                }
+                callee.closures.insert(first_param + param_index, Rc::new(Closure {
+                    def,
+                    fn_def: frame.fn_def,
+                    closed_over,
+                }));
+                continue;
+            }
+            let key = (param.ident, param.shadow.get().unwrap());
+            let value = self.eval_arg(frame, param.is_inout, arg_expr, key, &mut write_backs) ?;
+            callee.locals.insert(key, value);
+        }
+        let value = match self.exec_block(&mut callee, &fn_def.block) ? {
+            Flow::Return(value) => value,
+            // the caller stops at the end of its statement, until then the value is zeroed
+            Flow::Discard => self.zero(fn_def.return_ty.borrow().as_ref().unwrap()),
+            _ => Value::Void,
+        };
+        self.write_back(frame, &callee, write_backs) ?;
+        Ok(value)
+    }
+
+    fn call_closure(&mut self, frame: &mut Frame<'a>, param_index: usize, arg_exprs: &'a [Expr]) -> Result<Value, LiveError> {
+        let closure = frame.closures.get(&param_index).unwrap().clone();
+        let (params, return_ty) = match &frame.fn_def.params[param_index].ty_expr.kind {
+            TyExprKind::ClosureDecl {params, return_ty, ..} => (params, return_ty),
+            _ => panic!()
+        };
+        let mut callee = Frame::new(closure.fn_def);
+        let mut write_backs = Vec::new();
+        for ((param, closure_param), arg_expr) in params.iter().zip(&closure.def.params).zip(arg_exprs) {
+            let key = (closure_param.ident, closure_param.shadow.get().unwrap());
+            let value = self.eval_arg(frame, param.is_inout, arg_expr, key, &mut write_backs) ?;
+            callee.locals.insert(key, value);
+        }
+        for (key, value) in &closure.closed_over {
+            callee.locals.insert(*key, value.clone());
+        }
+        let value = match &closure.def.kind {
+            ClosureDefKind::Expr(expr) => self.eval_expr(&mut callee, expr) ?,
+            ClosureDefKind::Block(block) => match self.exec_block(&mut callee, block) ? {
+                Flow::Return(value) => value,
+                Flow::Discard => self.zero(return_ty.borrow().as_ref().unwrap()),
+                _ => Value::Void,
+            }
+        };
+        self.write_back(frame, &callee, write_backs) ?;
+        Ok(value)
+    }
+
+    // inout args are written back to where they came from after the call
+    fn eval_arg(
+        &mut self,
+        frame: &mut Frame<'a>,
+        is_inout: bool,
+        arg_expr: &'a Expr,
+        key: (Ident, ScopeSymShadow),
+        write_backs: &mut Vec<(Place, (Ident, ScopeSymShadow))>,
+    ) -> Result<Value, LiveError> {
+        if !is_inout {
+            return self.eval_expr(frame, arg_expr)
+        }
+        let place = self.place(frame, arg_expr) ?;
+        let value = self.read_place(frame, arg_expr.span, &place) ?;
+        write_backs.push((place, key));
+        Ok(value)
+    }
+
+    fn write_back(
+        &mut self,
+        frame: &mut Frame<'a>,
+        callee: &Frame<'a>,
+        write_backs: Vec<(Place, (Ident, ScopeSymShadow))>,
+    ) -> Result<(), LiveError> {
+        for (place, key) in write_backs {
+            let value = callee.locals.get(&key).unwrap().clone();
+            self.write_place(frame, &place, value);
+        }
+        Ok(())
+    }
+
+    // statements
+
+    fn exec_block(&mut self, frame: &mut Frame<'a>, block: &'a Block) -> Result<Flow, LiveError> {
+        for stmt in &block.stmts {
+            // a `discard` in a called fn ends the invocation too
+            if self.discarded {
+                return Ok(Flow::Discard)
+            }
+            match self.exec_stmt(frame, stmt) ? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_stmt(&mut self, frame: &mut Frame<'a>, stmt: &'a Stmt) -> Result<Flow, LiveError> {
+        match stmt {
+            Stmt::Break {label, ..} => Ok(Flow::Break(*label)),
+            Stmt::Continue {label, ..} => Ok(Flow::Continue(*label)),
+            Stmt::Discard {..} => {
+                self.discarded = true;
+                Ok(Flow::Discard)
+            }
+            Stmt::For {label, ident, shadow, from_expr, to_expr, step_expr, block, ..} => {
+                let const_int = | expr: &Expr | expr.const_val.borrow().as_ref().unwrap().as_ref().unwrap().to_int().unwrap();
+                let from = const_int(from_expr);
+                let to = const_int(to_expr);
+                let step = match step_expr {
+                    Some(step_expr) => const_int(step_expr),
+                    None => if from < to {1} else {-1}
+                };
+                // the same bounds as the generated `for (int i = ..; i < to; i += step)`
+                let mut index = if from <= to {from} else {from - 1};
+                while if from <= to {index < to} else {index >= to} {
+                    frame.locals.insert((*ident, shadow.get().unwrap()), Value::Ints(vec![index]));
+                    let flow = self.exec_block(frame, block);
+                    if let Some(flow) = flow ?.out_of_loop(*label) {
+                        return Ok(flow)
+                    }
+                    index += step;
+                }
+                Ok(Flow::Next)
+            }
+            Stmt::While {span, label, expr, block} => {
+                let mut iterations = 0;
+                while self.eval_expr(frame, expr) ?.bools()[0] {
+                    iterations = count_iteration(*span, "while", iterations) ?;
+                    if let Some(flow) = self.exec_block(frame, block) ?.out_of_loop(*label) {
+                        return Ok(flow)
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Stmt::Loop {span, label, block} => {
+                let mut iterations = 0;
+                loop {
+                    iterations = count_iteration(*span, "loop", iterations) ?;
+                    if let Some(flow) = self.exec_block(frame, block) ?.out_of_loop(*label) {
+                        return Ok(flow)
+                    }
+                }
+            }
+            Stmt::If {expr, block_if_true, block_if_false, ..} => {
+                if self.eval_expr(frame, expr) ?.bools()[0] {
+                    self.exec_block(frame, block_if_true)
+                }
+                else if let Some(block_if_false) = block_if_false {
+                    self.exec_block(frame, block_if_false)
+                }
+                else {
+                    Ok(Flow::Next)
+                }
+            }
+            Stmt::Match {expr, matches, ..} => {
+                let value = self.eval_expr(frame, expr) ?.ints()[0];
+                match matches.iter().find( | match_item | match_item.enum_value.get() == Some(value as usize)) {
+                    Some(match_item) => self.exec_block(frame, &match_item.block),
+                    None => Ok(Flow::Next),
+                }
+            }
+            Stmt::Let {ty, ident, expr, shadow, ..} => {
+                let value = match expr {
+                    Some(expr) => self.eval_expr(frame, expr) ?,
+                    None => self.zero(ty.borrow().as_ref().unwrap()),
+                };
+                frame.locals.insert((*ident, shadow.get().unwrap()), value);
+                Ok(Flow::Next)
+            }
+            Stmt::Return {expr, ..} => Ok(Flow::Return(match expr {
+                Some(expr) => self.eval_expr(frame, expr) ?,
+                None => Value::Void,
+            })),
+            Stmt::Block {block, ..} => self.exec_block(frame, block),
+            Stmt::Expr {expr, ..} => {
+                self.eval_expr(frame, expr) ?;
+                Ok(Flow::Next)
+            }
+        }
+    }
+
+    // expressions
+
+    fn eval_expr(&mut self, frame: &mut Frame<'a>, expr: &'a Expr) -> Result<Value, LiveError> {
+        if let Some(Some(val)) = expr.const_val.borrow().as_ref() {
+            return Ok(Value::from_val(val))
+        }
+        let ty = expr.ty.borrow().clone().unwrap();
+        match &expr.kind {
+            ExprKind::Cond {expr, expr_if_true, expr_if_false, ..} => {
+                if self.eval_expr(frame, expr) ?.bools()[0] {
+                    self.eval_expr(frame, expr_if_true)
+                }
+                else {
+                    self.eval_expr(frame, expr_if_false)
+                }
+            }
+            ExprKind::Bin {span, op, left_expr, right_expr} => self.eval_bin_expr(frame, *span, *op, left_expr, right_expr),
+            ExprKind::Un {op, expr, ..} => {
+                let value = self.eval_expr(frame, expr) ?;
+                Ok(match (op, value) {
+                    (UnOp::Not, Value::Bools(value)) => Value::Bools(value.iter().map( | value | !value).collect()),
+                    (UnOp::Not, Value::Ints(value)) => Value::Ints(value.iter().map( | value | !value).collect()),
+                    (UnOp::Not, Value::Uints(value)) => Value::Uints(value.iter().map( | value | !value).collect()),
+                    (UnOp::Neg, Value::Ints(value)) => Value::Ints(value.iter().map( | value | value.wrapping_neg()).collect()),
+                    (UnOp::Neg, Value::Floats(value)) => Value::Floats(value.iter().map( | value | -value).collect()),
+                    _ => panic!()
+                })
+            }
+            ExprKind::Field {span, expr, field_ident} => {
+                match expr.ty.borrow().as_ref().unwrap() {
+                    Ty::DrawShader => self.field_value(*span, *field_ident),
+                    Ty::Struct(struct_ptr) => {
+                        let index = self.struct_field_index(*struct_ptr, *field_ident);
+                        match self.eval_expr(frame, expr) ? {
+                            Value::Composite(mut fields) => Ok(fields.swap_remove(index)),
+                            _ => panic!()
+                        }
+                    }
+                    _ => {
+                        let swizzle: Vec<usize> = Swizzle::parse(*field_ident).unwrap().iter().cloned().collect();
+                        Ok(self.eval_expr(frame, expr) ?.select(&swizzle))
+                    }
+                }
+            }
+            ExprKind::Index {span, expr, index_expr} => {
+                let base_ty = expr.ty.borrow().clone().unwrap();
+                let value = self.eval_expr(frame, expr) ?;
+                let index = self.eval_index(frame, *span, index_expr, &base_ty) ?;
+                Ok(match (value, index) {
+                    (Value::Composite(mut elems), PlaceStep::Member(index)) => elems.swap_remove(index),
+                    (value, PlaceStep::Components(indices)) => value.select(&indices),
+                    _ => panic!()
+                })
+            }
+            ExprKind::MethodCall {ident, arg_exprs, ..} => {
+                let (fn_def, arg_exprs) = self.shader.method_call_fn_def(*ident, arg_exprs);
+                self.call_fn(frame, fn_def, arg_exprs)
+            }
+            ExprKind::PlainCall {fn_ptr, param_index, arg_exprs, ..} => {
+                if let Some(param_index) = param_index.get() {
+                    self.call_closure(frame, param_index, arg_exprs)
+                }
+                else {
+                    let fn_def = self.shader.all_fns.get(&fn_ptr.unwrap()).unwrap();
+                    self.call_fn(frame, fn_def, arg_exprs)
+                }
+            }
+            ExprKind::BuiltinCall {span, ident, arg_exprs} => {
+                let mut args = Vec::new();
+                for arg_expr in arg_exprs {
+                    args.push(self.eval_expr(frame, arg_expr) ?);
+                }
+                let arg_tys: Vec<Ty> = arg_exprs.iter().map( | arg_expr | arg_expr.ty.borrow().clone().unwrap()).collect();
+                self.eval_builtin(*span, *ident, args, &arg_tys, &ty)
+            }
+            // only ever passed to a fn straight away
+            ExprKind::ClosureDef(_) => Ok(Value::Void),
+            ExprKind::ConsCall {arg_exprs, ..} => {
+                let mut args = Vec::new();
+                for arg_expr in arg_exprs {
+                    args.push((self.eval_expr(frame, arg_expr) ?, arg_expr.ty.borrow().clone().unwrap()));
+                }
+                Ok(self.eval_cons(args, &ty))
+            }
+            ExprKind::StructCons {struct_ptr, args, ..} => {
+                let struct_def = self.shader.structs.get(struct_ptr).unwrap();
+                let mut fields = Vec::new();
+                for field in &struct_def.fields {
+                    let (_, arg_expr) = args.iter().find( | (ident, _) | *ident == field.ident).unwrap();
+                    fields.push(self.eval_expr(frame, arg_expr) ?);
+                }
+                Ok(Value::Composite(fields))
+            }
+            ExprKind::ArrayCons {elem_exprs, ..} => {
+                let mut elems = Vec::new();
+                for elem_expr in elem_exprs {
+                    elems.push(self.eval_expr(frame, elem_expr) ?);
+                }
+                Ok(Value::Composite(elems))
+            }
+            ExprKind::Var {span, kind, ..} => match kind.get().unwrap() {
+                VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => Ok(self.var_value(frame, ident, shadow)),
+                VarKind::LiveValue(value_ptr) => self.live_value(*span, value_ptr, &ty),
+                VarKind::Const(const_ptr) => {
+                    let (_, items) = self.shader.const_array(const_ptr);
+                    Ok(Value::Composite(items.into_iter().map(Value::Floats).collect()))
+                }
+            },
+            ExprKind::Lit {lit, ..} => Ok(Value::from_val(&lit.to_val())),
+        }
+    }
+
+    fn var_value(&self, frame: &Frame<'a>, ident: Ident, shadow: ScopeSymShadow) -> Value {
+        match frame.locals.get(&(ident, shadow)) {
+            Some(value) => value.clone(),
+            // `self`
+            None => Value::Void,
+        }
+    }
+
+    fn struct_field_index(&self, struct_ptr: StructPtr, field_ident: Ident) -> usize {
+        let struct_def = self.shader.structs.get(&struct_ptr).unwrap();
+        struct_def.fields.iter().position( | field | field.ident == field_ident).unwrap()
+    }
+
+    fn eval_index(&mut self, frame: &mut Frame<'a>, span: TokenSpan, index_expr: &'a Expr, base_ty: &Ty) -> Result<PlaceStep, LiveError> {
+        let index = self.eval_expr(frame, index_expr) ?.ints()[0];
+        let len = match base_ty {
+            Ty::Array {len, ..} => *len,
+            Ty::Mat2 | Ty::Mat3 | Ty::Mat4 => matrix_size(base_ty),
+            _ => components(base_ty),
+        };
+        if index < 0 || index as usize >= len {
+            return Err(error(span, format!("index {} is out of bounds for a {}", index, base_ty)))
+        }
+        let index = index as usize;
+        Ok(match base_ty {
+            Ty::Array {..} => PlaceStep::Member(index),
+            // the column of a matrix
+            Ty::Mat2 | Ty::Mat3 | Ty::Mat4 => PlaceStep::Components((index * len..(index + 1) * len).collect()),
+            _ => PlaceStep::Components(vec![index]),
+        })
+    }
+
+    fn eval_bin_expr(
+        &mut self,
+        frame: &mut Frame<'a>,
+        span: TokenSpan,
+        op: BinOp,
+        left_expr: &'a Expr,
+        right_expr: &'a Expr,
+    ) -> Result<Value, LiveError> {
+        let left_ty = left_expr.ty.borrow().clone().unwrap();
+        let right_ty = right_expr.ty.borrow().clone().unwrap();
+        let op = match op {
+            BinOp::Assign => {
+                let place = self.place(frame, left_expr) ?;
+                let value = self.eval_expr(frame, right_expr) ?;
+                self.write_place(frame, &place, value.clone());
+                return Ok(value)
+            }
+            op if op.is_assign() => op.assign_op().unwrap(),
+            BinOp::And | BinOp::Or => {
+                // the right side is only evaluated when it decides the outcome
+                let left = self.eval_expr(frame, left_expr) ?.bools()[0];
+                if left == matches!(op, BinOp::Or) {
+                    return Ok(Value::Bools(vec![left]))
+                }
+                return self.eval_expr(frame, right_expr)
+            }
+            op => {
+                let left = self.eval_expr(frame, left_expr) ?;
+                let right = self.eval_expr(frame, right_expr) ?;
+                return eval_bin_op(span, op, left, &left_ty, right, &right_ty)
+            }
+        };
+        let place = self.place(frame, left_expr) ?;
+        let left = self.read_place(frame, span, &place) ?;
+        let right = self.eval_expr(frame, right_expr) ?;
+        let value = eval_bin_op(span, op, left, &left_ty, right, &right_ty) ?;
+        self.write_place(frame, &place, value.clone());
+        Ok(value)
+    }
+
+    fn eval_cons(&self, args: Vec<(Value, Ty)>, ty: &Ty) -> Value {
+        if let [(arg, arg_ty)] = args.as_slice() {
+            if arg_ty == ty {
+                return arg.clone()
+            }
+            let is_matrix = | ty: &Ty | matches!(ty, Ty::Mat2 | Ty::Mat3 | Ty::Mat4);
+            // a scalar goes on the diagonal of a matrix, a matrix is cut down or filled up from the identity
+            if is_matrix(ty) && (arg.components() == 1 || is_matrix(arg_ty)) {
+                let size = matrix_size(ty);
+                let arg_size = if arg.components() == 1 {0} else {matrix_size(arg_ty)};
+                return from_components(ty, (0..size * size).map( | index | {
+                    let (column, row) = (index / size, index % size);
+                    if column < arg_size && row < arg_size {
+                        arg.component(column * arg_size + row)
+                    }
+                    else if column != row {
+                        0.0
+                    }
+                    else if arg_size == 0 {
+                        arg.component(0)
+                    }
+                    else {
+                        1.0
+                    }
+                }))
+            }
+            if arg.components() == 1 {
+                return from_components(ty, std::iter::repeat(arg.component(0)).take(components(ty)))
+            }
+        }
+        let values = args.iter().flat_map( | (arg, _) | (0..arg.components()).map(move | index | arg.component(index)));
+        from_components(ty, values.take(components(ty)))
+    }
+
+    // places, the left hand sides of assignments and inout args
+
+    fn place(&mut self, frame: &mut Frame<'a>, expr: &'a Expr) -> Result<Place, LiveError> {
+        match &expr.kind {
+            ExprKind::Var {kind, ..} => Ok(Place {
+                root: match kind.get().unwrap() {
+                    VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow} => PlaceRoot::Local(ident, shadow),
+                    VarKind::LiveValue(value_ptr) => PlaceRoot::LiveValue(value_ptr),
+                    VarKind::Const(_) => panic!(),
+                },
+                steps: Vec::new(),
+            }),
+            ExprKind::Field {expr: base_expr, field_ident, ..} => match base_expr.ty.borrow().clone().unwrap() {
+                Ty::DrawShader => Ok(Place {root: PlaceRoot::Field(*field_ident), steps: Vec::new()}),
+                Ty::Struct(struct_ptr) => {
+                    let mut place = self.place(frame, base_expr) ?;
+                    place.steps.push(PlaceStep::Member(self.struct_field_index(struct_ptr, *field_ident)));
+                    Ok(place)
+                }
+                _ => {
+                    let mut place = self.place(frame, base_expr) ?;
+                    let swizzle: Vec<usize> = Swizzle::parse(*field_ident).unwrap().iter().cloned().collect();
+                    push_components(&mut place.steps, swizzle);
+                    Ok(place)
+                }
+            },
+            ExprKind::Index {span, expr: base_expr, index_expr} => {
+                let base_ty = base_expr.ty.borrow().clone().unwrap();
+                let mut place = self.place(frame, base_expr) ?;
+                match self.eval_index(frame, *span, index_expr, &base_ty) ? {
+                    PlaceStep::Components(indices) => push_components(&mut place.steps, indices),
+                    step => place.steps.push(step),
+                }
+                Ok(place)
+            }
+            _ => panic!()
+        }
+    }
+
+    fn read_place(&mut self, frame: &Frame<'a>, span: TokenSpan, place: &Place) -> Result<Value, LiveError> {
+        let mut value = match place.root {
+            PlaceRoot::Local(ident, shadow) => self.var_value(frame, ident, shadow),
+            PlaceRoot::Field(ident) => self.field_value(span, ident) ?,
+            PlaceRoot::LiveValue(value_ptr) => self.live_values.get(&value_ptr).cloned().unwrap(),
+        };
+        for step in &place.steps {
+            value = match (value, step) {
+                (Value::Composite(mut elems), PlaceStep::Member(index)) => elems.swap_remove(*index),
+                (value, PlaceStep::Components(indices)) => value.select(indices),
+                _ => panic!()
+            };
+        }
+        Ok(value)
+    }
+
+    fn write_place(&mut self, frame: &mut Frame<'a>, place: &Place, new_value: Value) {
+        let value = match place.root {
+            PlaceRoot::Local(ident, shadow) => frame.locals.get_mut(&(ident, shadow)).unwrap(),
+            PlaceRoot::Field(ident) => if let Some(stage_builtin) = self.shader.draw_shader_def.find_stage_builtin(ident) {
+                // the output builtins are scalars, written as a whole
+                self.builtins.entry(stage_builtin).or_insert(Value::Floats(vec![0.0]))
+            }
+            else {
+                // a field that isn't read first can still be partially written to
+                if !self.fields.contains_key(&ident) {
+                    let field = self.shader.draw_shader_def.fields.iter().find( | field | field.ident == ident).unwrap();
+                    let zero = self.zero(field.ty_expr.ty.borrow().as_ref().unwrap());
+                    self.fields.insert(ident, zero);
+                }
+                self.fields.get_mut(&ident).unwrap()
+            }
+            PlaceRoot::LiveValue(value_ptr) => self.live_values.get_mut(&value_ptr).unwrap(),
+        };
+        let mut value = value;
+        for step in &place.steps {
+            match (value, step) {
+                (Value::Composite(elems), PlaceStep::Member(index)) => value = &mut elems[*index],
+                (value, PlaceStep::Components(indices)) => {
+                    value.set_components(indices, &new_value);
+                    return
+                }
+                _ => panic!()
+            }
+        }
+        *value = new_value;
+    }
+
+    // builtins
+
+    fn eval_builtin(&self, span: TokenSpan, ident: Ident, args: Vec<Value>, arg_tys: &[Ty], ty: &Ty) -> Result<Value, LiveError> {
+        // float args are spread over the components of the vector args
+        let len = args.iter().map( | arg | arg.components()).max().unwrap_or(1);
+        let arg = | index: usize, component: usize | {
+            let arg = &args[index];
+            arg.component(if arg.components() == 1 {0} else {component}) as f32
+        };
+        let map1 = | f: fn(f32) -> f32 | Value::Floats((0..len).map( | i | f(arg(0, i))).collect());
+        let map2 = | f: fn(f32, f32) -> f32 | Value::Floats((0..len).map( | i | f(arg(0, i), arg(1, i))).collect());
+        let map3 = | f: fn(f32, f32, f32) -> f32 | Value::Floats((0..len).map( | i | f(arg(0, i), arg(1, i), arg(2, i))).collect());
+        let compare = | f: fn(f32, f32) -> bool | Value::Bools((0..len).map( | i | f(arg(0, i), arg(1, i))).collect());
+        let floats = | index: usize | args[index].floats();
+        Ok(match ident {
+            Ident(id!(abs)) if scalar_ty(ty) == Ty::Int => Value::Ints(args[0].ints().iter().map( | value | value.wrapping_abs()).collect()),
+            Ident(id!(abs)) => map1(f32::abs),
+            Ident(id!(acos)) => map1(f32::acos),
+            Ident(id!(all)) => Value::Bools(vec![args[0].bools().iter().all( | value | *value)]),
+            Ident(id!(any)) => Value::Bools(vec![args[0].bools().iter().any( | value | *value)]),
+            Ident(id!(asin)) => map1(f32::asin),
+            Ident(id!(atan)) if args.len() == 2 => map2(f32::atan2),
+            Ident(id!(atan)) => map1(f32::atan),
+            Ident(id!(ceil)) => map1(f32::ceil),
+            Ident(id!(clamp)) => map3( | x, low, high | x.max(low).min(high)),
+            Ident(id!(cos)) => map1(f32::cos),
+            Ident(id!(cross)) => {
+                let (a, b) = (floats(0), floats(1));
+                Value::Floats(vec![a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]])
+            }
+            // there are no neighbouring invocations to take a difference with
+            Ident(id!(dFdx)) | Ident(id!(dFdy)) => Value::Floats(vec![0.0; len]),
+            Ident(id!(degrees)) => map1(f32::to_degrees),
+            Ident(id!(distance)) => Value::Floats(vec![length(&sub(floats(0), floats(1)))]),
+            Ident(id!(dot)) => Value::Floats(vec![dot(floats(0), floats(1))]),
+            Ident(id!(equal)) => compare( | a, b | a == b),
+            Ident(id!(exp)) => map1(f32::exp),
+            Ident(id!(exp2)) => map1(f32::exp2),
+            Ident(id!(faceforward)) => {
+                let (n, i, n_ref) = (floats(0), floats(1), floats(2));
+                let sign = if dot(n_ref, i) < 0.0 {1.0} else {-1.0};
+                Value::Floats(n.iter().map( | value | value * sign).collect())
+            }
+            Ident(id!(floor)) => map1(f32::floor),
+            Ident(id!(fract)) => map1( | x | x - x.floor()),
+            Ident(id!(greaterThan)) => compare( | a, b | a > b),
+            Ident(id!(greaterThanEqual)) => compare( | a, b | a >= b),
+            Ident(id!(inverse)) => Value::Floats(inverse(floats(0), matrix_size(ty))),
+            Ident(id!(inversesqrt)) => map1( | x | 1.0 / x.sqrt()),
+            Ident(id!(length)) => Value::Floats(vec![length(floats(0))]),
+            Ident(id!(lessThan)) => compare( | a, b | a < b),
+            Ident(id!(lessThanEqual)) => compare( | a, b | a <= b),
+            Ident(id!(log)) => map1(f32::ln),
+            Ident(id!(log2)) => map1(f32::log2),
+            Ident(id!(matrixCompMult)) => map2( | a, b | a * b),
+            Ident(id!(max)) => map2(f32::max),
+            Ident(id!(min)) => map2(f32::min),
+            Ident(id!(mix)) => map3( | x, y, a | x * (1.0 - a) + y * a),
+            Ident(id!(mod)) => map2( | x, y | x - y * (x / y).floor()),
+            Ident(id!(normalize)) => {
+                let length = length(floats(0));
+                Value::Floats(floats(0).iter().map( | value | value / length).collect())
+            }
+            Ident(id!(not)) => Value::Bools(args[0].bools().iter().map( | value | !value).collect()),
+            Ident(id!(notEqual)) => compare( | a, b | a != b),
+            Ident(id!(pow)) => map2(f32::powf),
+            Ident(id!(radians)) => map1(f32::to_radians),
+            Ident(id!(reflect)) => {
+                let (i, n) = (floats(0), floats(1));
+                let d = 2.0 * dot(n, i);
+                Value::Floats(i.iter().zip(n).map( | (i, n) | i - d * n).collect())
+            }
+            Ident(id!(refract)) => {
+                let (i, n, eta) = (floats(0), floats(1), floats(2)[0]);
+                let d = dot(n, i);
+                let k = 1.0 - eta * eta * (1.0 - d * d);
+                if k < 0.0 {
+                    Value::Floats(vec![0.0; i.len()])
+                }
+                else {
+                    Value::Floats(i.iter().zip(n).map( | (i, n) | eta * i - (eta * d + k.sqrt()) * n).collect())
+                }
+            }
+            // there are no mipmaps, so the lod and the gradients make no difference
+            Ident(id!(sample2d)) | Ident(id!(sample2d_rt)) | Ident(id!(sample2d_lod)) | Ident(id!(sample2d_grad)) => {
+                self.sample(span, args[0].texture(), floats(1)) ?
+            }
+            Ident(id!(sample2d_offset)) => {
+                let image = self.image(span, args[0].texture()) ?;
+                let offset = args[2].ints();
+                let uv = [
+                    floats(1)[0] + offset[0] as f32 / image.width as f32,
+                    floats(1)[1] + offset[1] as f32 / image.height as f32
+                ];
+                self.sample(span, args[0].texture(), &uv) ?
+            }
+            Ident(id!(texture_size)) if arg_tys[0] == Ty::Texture2D => {
+                let image = self.image(span, args[0].texture()) ?;
+                let lod = args[1].ints()[0].max(0);
+                Value::Ints(vec![(image.width as i32 >> lod).max(1), (image.height as i32 >> lod).max(1)])
+            }
+            Ident(id!(texel_fetch)) if arg_tys[0] == Ty::Texture2D => {
+                let image = self.image(span, args[0].texture()) ?;
+                let coord = args[1].ints();
+                // out of range fetches are undefined, these read zero
+                let color = if (0..image.width as i32).contains(&coord[0]) && (0..image.height as i32).contains(&coord[1]) {
+                    image.texels[coord[1] as usize * image.width + coord[0] as usize]
+                }
+                else {
+                    Vec4::default()
+                };
+                Value::Floats(vec![color.x, color.y, color.z, color.w])
+            }
+            Ident(id!(sign)) => map1( | x | if x > 0.0 {1.0} else if x < 0.0 {-1.0} else {0.0}),
+            Ident(id!(sin)) => map1(f32::sin),
+            Ident(id!(smoothstep)) => map3( | edge0, edge1, x | {
+                let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+                t * t * (3.0 - 2.0 * t)
+            }),
+            Ident(id!(sqrt)) => map1(f32::sqrt),
+            Ident(id!(step)) => map2( | edge, x | if x < edge {0.0} else {1.0}),
+            Ident(id!(tan)) => map1(f32::tan),
+            Ident(id!(transpose)) => {
+                let size = matrix_size(&arg_tys[0]);
+                Value::Floats((0..size * size).map( | index | floats(0)[index % size * size + index / size]).collect())
+            }
+            _ => return Err(error(span, format!("the interpreter doesn't support `{}`", ident))),
+        })
+    }
+
+    fn image(&self, span: TokenSpan, ident: Ident) -> Result<&ShaderImage, LiveError> {
+        self.inputs.images.get(&ident.to_string()).ok_or_else( | | {
+            error(span, format!("no image for `{}` in the inputs", ident))
+        })
+    }
+
+    fn sample(&self, span: TokenSpan, ident: Ident, uv: &[f32]) -> Result<Value, LiveError> {
+        let image = self.image(span, ident) ?;
+        let sampler = self.shader.draw_shader_def.fields.iter().find_map( | field | match field.kind {
+            DrawShaderFieldKind::Texture {sampler, ..} if field.ident == ident => Some(sampler),
+            _ => None
+        }).unwrap();
+        let wrap = | index: i64, size: usize | {
+            let size = size as i64;
+            (match sampler.wrap {
+                SamplerWrap::Repeat => index.rem_euclid(size),
+                SamplerWrap::Clamp => index.max(0).min(size - 1),
+                SamplerWrap::Mirror => {
+                    let index = index.rem_euclid(2 * size);
+                    if index < size {index} else {2 * size - 1 - index}
+                }
+            }) as usize
+        };
+        let texel = | x: i64, y: i64 | image.texels[wrap(y, image.height) * image.width + wrap(x, image.width)];
+        let x = uv[0] * image.width as f32;
+        let y = uv[1] * image.height as f32;
+        let color = match sampler.filter {
+            SamplerFilter::Nearest => texel(x.floor() as i64, y.floor() as i64),
+            SamplerFilter::Linear => {
+                // between the centers of the four nearest texels
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = Vec4::from_lerp(texel(x0, y0), texel(x0 + 1, y0), fx);
+                let bottom = Vec4::from_lerp(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), fx);
+                Vec4::from_lerp(top, bottom, fy)
+            }
+        };
+        Ok(Value::Floats(vec![color.x, color.y, color.z, color.w]))
+    }
+}
+
+// merges the components picked by a swizzle or an index with the ones picked before
+fn push_components(steps: &mut Vec<PlaceStep>, indices: Vec<usize>) {
+    if let Some(PlaceStep::Components(prev_indices)) = steps.last_mut() {
+        *prev_indices = indices.iter().map( | index | prev_indices[*index]).collect();
+        return
+    }
+    steps.push(PlaceStep::Components(indices));
+}
+
+fn eval_bin_op(span: TokenSpan, op: BinOp, left: Value, left_ty: &Ty, right: Value, right_ty: &Ty) -> Result<Value, LiveError> {
+    let is_matrix = | ty: &Ty | matches!(ty, Ty::Mat2 | Ty::Mat3 | Ty::Mat4);
+    match op {
+        BinOp::Eq => return Ok(Value::Bools(vec![left == right])),
+        BinOp::Ne => return Ok(Value::Bools(vec![left != right])),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            let (left, right) = (left.component(0), right.component(0));
+            return Ok(Value::Bools(vec![match op {
+                BinOp::Lt => left < right,
+                BinOp::Le => left <= right,
+                BinOp::Gt => left > right,
+                _ => left >= right,
+            }]))
+        }
+        BinOp::Mul if is_matrix(left_ty) && is_matrix(right_ty) => {
+            let size = matrix_size(left_ty);
+            let (a, b) = (left.floats(), right.floats());
+            return Ok(Value::Floats((0..size * size).map( | index | {
+                let (column, row) = (index / size, index % size);
+                (0..size).map( | k | a[k * size + row] * b[column * size + k]).sum()
+            }).collect()))
+        }
+        BinOp::Mul if is_matrix(left_ty) && right.components() > 1 => {
+            let size = matrix_size(left_ty);
+            let (m, v) = (left.floats(), right.floats());
+            return Ok(Value::Floats((0..size).map( | row | (0..size).map( | k | m[k * size + row] * v[k]).sum()).collect()))
+        }
+        BinOp::Mul if is_matrix(right_ty) && left.components() > 1 => {
+            let size = matrix_size(right_ty);
+            let (v, m) = (left.floats(), right.floats());
+            return Ok(Value::Floats((0..size).map( | column | dot(v, &m[column * size..(column + 1) * size])).collect()))
+        }
+        _ => ()
+    }
+    // the rest is component wise, with scalars spread over the other side
+    let len = left.components().max(right.components());
+    if let BinOp::Shl | BinOp::Shr = op {
+        // the shift amount can have the other signedness, `>>` is arithmetic on `int`
+        let amount = | index: usize | right.component(if right.components() == 1 {0} else {index}) as i64 as u32;
+        let is_shl = matches!(op, BinOp::Shl);
+        return Ok(match &left {
+            Value::Ints(a) => Value::Ints((0..len).map( | index | {
+                if is_shl {pick(a, index).wrapping_shl(amount(index))} else {pick(a, index).wrapping_shr(amount(index))}
+            }).collect()),
+            Value::Uints(a) => Value::Uints((0..len).map( | index | {
+                if is_shl {pick(a, index).wrapping_shl(amount(index))} else {pick(a, index).wrapping_shr(amount(index))}
+            }).collect()),
+            _ => panic!()
+        })
+    }
+    let division_by_zero = | | Err(error(span, String::from("integer division by zero")));
+    Ok(match (&left, &right) {
+        (Value::Ints(a), Value::Ints(b)) => {
+            let mut values = Vec::new();
+            for index in 0..len {
+                let (a, b) = (pick(a, index), pick(b, index));
+                values.push(match op {
+                    BinOp::Add => a.wrapping_add(b),
+                    BinOp::Sub => a.wrapping_sub(b),
+                    BinOp::Mul => a.wrapping_mul(b),
+                    BinOp::BitAnd => a & b,
+                    BinOp::BitOr => a | b,
+                    BinOp::BitXor => a ^ b,
+                    _ if b == 0 => return division_by_zero(),
+                    BinOp::Rem => a.wrapping_rem(b),
+                    _ => a.wrapping_div(b),
+                });
+            }
+            Value::Ints(values)
+        }
+        (Value::Uints(a), Value::Uints(b)) => {
+            let mut values = Vec::new();
+            for index in 0..len {
+                let (a, b) = (pick(a, index), pick(b, index));
+                values.push(match op {
+                    BinOp::Add => a.wrapping_add(b),
+                    BinOp::Sub => a.wrapping_sub(b),
+                    BinOp::Mul => a.wrapping_mul(b),
+                    BinOp::BitAnd => a & b,
+                    BinOp::BitOr => a | b,
+                    BinOp::BitXor => a ^ b,
+                    _ if b == 0 => return division_by_zero(),
+                    BinOp::Rem => a % b,
+                    _ => a / b,
+                });
+            }
+            Value::Uints(values)
+        }
+        (Value::Floats(a), Value::Floats(b)) => Value::Floats((0..len).map( | index | {
+            let (a, b) = (pick(a, index), pick(b, index));
+            match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                _ => a / b,
+            }
+        }).collect()),
+        _ => panic!()
+    })
+}
+
+// a scalar is spread over the components of the other side
+fn pick<T: Copy>(values: &[T], index: usize) -> T {
+    if values.len() == 1 {values[0]} else {values[index]}
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map( | (a, b) | a * b).sum()
+}
+
+fn sub(a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter().zip(b).map( | (a, b) | a - b).collect()
+}
+
+fn length(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+// Gauss-Jordan elimination with partial pivoting, a singular matrix comes out as NaNs like on a GPU
+fn inverse(matrix: &[f32], size: usize) -> Vec<f32> {
+    let at = | m: &[f32], row: usize, column: usize | m[column * size + row];
+    let mut a: Vec<f32> = matrix.to_vec();
+    let mut inv: Vec<f32> = (0..size * size).map( | index | if index / size == index % size {1.0} else {0.0}).collect();
+    for column in 0..size {
+        let pivot = (column..size).max_by( | x, y | at(&a, *x, column).abs().partial_cmp(&at(&a, *y, column).abs()).unwrap()).unwrap();
+        for k in 0..size {
+            a.swap(k * size + column, k * size + pivot);
+            inv.swap(k * size + column, k * size + pivot);
+        }
+        let scale = 1.0 / at(&a, column, column);
+        for k in 0..size {
+            a[k * size + column] *= scale;
+            inv[k * size + column] *= scale;
+        }
+        for row in 0..size {
+            if row == column {
+                continue;
+            }
+            let factor = at(&a, row, column);
+            for k in 0..size {
+                a[k * size + row] -= factor * a[k * size + column];
+                inv[k * size + row] -= factor * inv[k * size + column];
+            }
+        }
+    }
+    inv
+}
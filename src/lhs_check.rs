@@ -65,6 +65,10 @@ impl<'a> LhsChecker<'a> {
                 span,
                 ..
             } => self.lhs_check_all_call_expr(span),
+            ExprKind::ArrayCons {
+                span,
+                ..
+            } => self.lhs_check_all_call_expr(span),
             ExprKind::Var {
                 span,
                 ref kind,
@@ -141,6 +145,16 @@ impl<'a> LhsChecker<'a> {
         // lets grab the ty from expr
         match expr.ty.borrow().as_ref().unwrap(){
             Ty::DrawShader=>{
+                if let Some(stage_builtin) = self.shader_registry.draw_shader_def.find_stage_builtin(field_ident) {
+                    if stage_builtin.is_output() {
+                        return Ok(())
+                    }
+                    return Err(LiveError {
+                        origin:live_error_origin!(),
+                        span:span.into(),
+                        message: format!("`self.{}` can't be assigned to", stage_builtin.name()),
+                    })
+                }
                 let field_decl = self.shader_registry.draw_shader_def.find_field(field_ident) .unwrap();
                 match &field_decl.kind{
                     DrawShaderFieldKind::Varying{..}=>{
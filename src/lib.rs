@@ -3,14 +3,19 @@
 mod shader;
 mod shader_ast;
 mod shader_parser;
+mod source_map;
 //mod env;
 mod analyse;
 mod builtin;
 mod const_eval;
 mod const_gather;
 mod dep_analyse;
+mod diagnostic;
 mod generate;
+mod interpret;
 mod lhs_check;
+mod reflection;
+mod spirv;
 mod swizzle;
 mod ty_check;
 mod util;
@@ -21,17 +26,28 @@ pub(crate) mod generate_glsl;
 pub(crate) mod generate_metal;
 //#[cfg(any(target_os = "windows", test))]
 pub(crate) mod generate_hlsl;
+pub(crate) mod generate_wgsl;
+pub(crate) mod generate_spirv;
+pub(crate) mod generate_rust;
 
 pub(crate) use crate::{
     shader::{DrawShaderQuery, ShaderEnum},
     shader_ast::{DrawShaderFieldKind, DrawShaderFlags, DrawShaderPtr, ValuePtr},
 };
-pub(crate) use makepad_live_compiler::{
-    self, makepad_live_tokenizer, makepad_math,
-};
+pub(crate) use makepad_live_compiler::{self, makepad_live_tokenizer};
+pub use makepad_live_compiler::makepad_math;
 pub(crate) use makepad_live_tokenizer::makepad_live_id;
 
 pub(crate) use crate::shader_ast::{DrawShaderConstTable, DrawShaderDef};
 
-pub use crate::shader_ast::ShaderTy;
+pub use crate::shader_ast::{SamplerFilter, SamplerMip, SamplerWrap, ShaderSampler, ShaderTy};
 pub use shader::Shader;
+pub use diagnostic::{ShaderDiagnostic, ShaderSeverity};
+pub use generate_glsl::{
+    GlslBinding, GlslBindingPolicy, GlslDefaultBindingPolicy, GlslOptions, GlslPrecision, GlslResource, GlslTarget,
+};
+pub use makepad_live_compiler::{LiveFileError, TextPos, TextSpan};
+pub use interpret::{ShaderImage, ShaderInputs, ShaderOutput, ShaderValue};
+pub use spirv::disassemble_spirv;
+pub use source_map::{ShaderLineMarkers, ShaderSourceMap, ShaderSourceMapEntry};
+pub use reflection::{ShaderReflection, ShaderReflectionEnum, ShaderReflectionField, ShaderReflectionUniformBlock};
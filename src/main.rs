@@ -0,0 +1,251 @@
+//! Offline cross-compiler, writes GLSL, MSL, HLSL, WGSL, SPIR-V, Rust and the reflection of a `.shader` file.
+//!
+//! ```text
+//! nanoshredder sprite.shader -a position:vec3 -u Projection:mat4 -o out/
+//! ```
+//!
+//! Attributes and uniforms can also come from a sidecar file passed with `--config`:
+//!
+//! ```toml
+//! [attributes]
+//! position = "vec3"
+//!
+//! [uniforms]
+//! Projection = "mat4"
+//! ```
+
+use {
+    nanoshredder::{GlslOptions, GlslTarget, Shader, ShaderLineMarkers, ShaderTy},
+    std::{fs, path::{Path, PathBuf}, process},
+};
+
+const USAGE: &str = "usage: nanoshredder <shader> [options]
+
+options:
+    -o, --out-dir <dir>        directory to write the generated files to, defaults to `.`
+    -a, --attribute <name:ty>  declare a geometry attribute, can be repeated
+    -u, --uniform <name:ty>    declare a pass uniform, can be repeated
+    -c, --config <file>        read attributes and uniforms from a TOML file
+        --glsl-target <target> 100, 100es, 300es, 330 or 450, defaults to 100
+        --named-uniforms       emit named uniforms instead of float tables in GLSL
+        --max-loop-iterations <n>
+                               bound of the `while` and `loop` statements in GLSL ES 1.00, defaults to 1024
+        --line-markers <kind>  `directives` or `comments` pointing the generated code back at the shader
+    -h, --help                 print this message";
+
+struct Args {
+    shader_path: PathBuf,
+    out_dir: PathBuf,
+    attributes: Vec<(String, ShaderTy)>,
+    uniforms: Vec<(String, ShaderTy)>,
+    glsl_options: GlslOptions,
+    line_markers: ShaderLineMarkers,
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&args) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let file_name = args.shader_path.to_string_lossy();
+    let source = fs::read_to_string(&args.shader_path)
+        .map_err( | err | format!("error: can't read {}: {}", file_name, err)) ?;
+
+    let mut shader = Shader::new_with_file_name(&file_name, &source).map_err( | err | err.to_string()) ?;
+    for (name, ty) in &args.attributes {
+        shader.add_attribute(name, ty.clone());
+    }
+    for (name, ty) in &args.uniforms {
+        shader.add_uniform(name, ty.clone());
+    }
+    match shader.compile() {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}\n", warning);
+            }
+        }
+        Err(diagnostics) => {
+            let errors = diagnostics.iter().filter( | diagnostic | diagnostic.is_error()).count();
+            let mut message = String::new();
+            for diagnostic in diagnostics {
+                message.push_str(&format!("{}\n\n", diagnostic));
+            }
+            message.push_str(&format!("error: could not compile {} due to {} error(s)", file_name, errors));
+            return Err(message);
+        }
+    }
+
+    shader.check_glsl_target(args.glsl_options.target).map_err( | err | err.to_string()) ?;
+
+    let stem = args.shader_path.file_stem().unwrap_or_default().to_string_lossy();
+    let ((vertex, _), (pixel, _)) = shader.generate_glsl_with_source_map(GlslOptions {
+        line_markers: args.line_markers,
+        ..args.glsl_options
+    });
+    fs::create_dir_all(&args.out_dir)
+        .map_err( | err | format!("error: can't create {}: {}", args.out_dir.display(), err)) ?;
+    write_output(&args.out_dir, &format!("{}.vert.glsl", stem), &vertex) ?;
+    write_output(&args.out_dir, &format!("{}.frag.glsl", stem), &pixel) ?;
+    write_output(&args.out_dir, &format!("{}.metal", stem), shader.generate_metal_with_source_map(args.line_markers).0) ?;
+    // point sprites only exist in some backends
+    match shader.check_hlsl() {
+        Ok(()) => write_output(&args.out_dir, &format!("{}.hlsl", stem), shader.generate_hlsl_with_source_map(args.line_markers).0) ?,
+        Err(err) => eprintln!("warning: no HLSL output for {}\n{}\n", file_name, err),
+    }
+    match shader.check_wgsl() {
+        Ok(()) => write_output(&args.out_dir, &format!("{}.wgsl", stem), shader.generate_wgsl_with_source_map(args.line_markers).0) ?,
+        Err(err) => eprintln!("warning: no WGSL output for {}\n{}\n", file_name, err),
+    }
+    let spirv: Vec<u8> = shader.generate_spirv().iter().flat_map( | word | word.to_le_bytes()).collect();
+    write_output(&args.out_dir, &format!("{}.spv", stem), spirv) ?;
+    // not every shader fits in `makepad_math`, which isn't worth failing the other backends for
+    match shader.generate_rust() {
+        Ok(rust) => write_output(&args.out_dir, &format!("{}.rs", stem), rust) ?,
+        Err(err) => eprintln!("warning: no Rust output for {}\n{}\n", file_name, err),
+    }
+    write_output(&args.out_dir, &format!("{}.json", stem), shader.reflection().to_json()) ?;
+    Ok(())
+}
+
+fn write_output(out_dir: &Path, file_name: &str, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    let path = out_dir.join(file_name);
+    fs::write(&path, contents).map_err( | err | format!("error: can't write {}: {}", path.display(), err))
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut shader_path = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut attributes = Vec::new();
+    let mut uniforms = Vec::new();
+    let mut glsl_options = GlslOptions::default();
+    let mut line_markers = ShaderLineMarkers::None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        let mut value = | | iter.next().ok_or_else( | | format!("missing value for `{}`", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-o" | "--out-dir" => out_dir = PathBuf::from(value() ?),
+            "-a" | "--attribute" => attributes.push(parse_decl(&value() ?) ?),
+            "-u" | "--uniform" => uniforms.push(parse_decl(&value() ?) ?),
+            "-c" | "--config" => {
+                let path = value() ?;
+                let config = fs::read_to_string(&path).map_err( | err | format!("can't read {}: {}", path, err)) ?;
+                parse_config(&config, &mut attributes, &mut uniforms).map_err( | err | format!("{}: {}", path, err)) ?;
+            }
+            "--glsl-target" => {
+                glsl_options.target = match value() ?.as_str() {
+                    "100" => GlslTarget::Glsl100,
+                    "100es" => GlslTarget::Glsl100Es,
+                    "300es" => GlslTarget::Glsl300Es,
+                    "330" => GlslTarget::Glsl330,
+                    "450" => GlslTarget::Glsl450,
+                    target => return Err(format!("unknown GLSL target `{}`", target)),
+                }
+            }
+            "--named-uniforms" => glsl_options.named_uniforms = true,
+            "--max-loop-iterations" => {
+                let n = value() ?;
+                glsl_options.max_loop_iterations = n.parse().map_err( | _ | format!("invalid loop iteration count `{}`", n)) ?;
+            }
+            "--line-markers" => {
+                line_markers = match value() ?.as_str() {
+                    "directives" => ShaderLineMarkers::Directives,
+                    "comments" => ShaderLineMarkers::Comments,
+                    kind => return Err(format!("unknown line markers `{}`", kind)),
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if shader_path.is_none() => shader_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    Ok(Args {
+        shader_path: shader_path.ok_or("missing shader file") ?,
+        out_dir,
+        attributes,
+        uniforms,
+        glsl_options,
+        line_markers,
+    })
+}
+
+// `position:vec3`
+fn parse_decl(decl: &str) -> Result<(String, ShaderTy), String> {
+    let (name, ty) = decl.split_once(':').ok_or_else( | | format!("expected `name:type`, got `{}`", decl)) ?;
+    Ok((name.trim().to_string(), parse_ty(ty.trim()) ?))
+}
+
+fn parse_ty(ty: &str) -> Result<ShaderTy, String> {
+    Ok(match ty {
+        "bool" => ShaderTy::Bool,
+        "int" => ShaderTy::Int,
+        "uint" => ShaderTy::Uint,
+        "float" => ShaderTy::Float,
+        "ivec2" => ShaderTy::Ivec2,
+        "ivec3" => ShaderTy::Ivec3,
+        "ivec4" => ShaderTy::Ivec4,
+        "uvec2" => ShaderTy::Uvec2,
+        "uvec3" => ShaderTy::Uvec3,
+        "uvec4" => ShaderTy::Uvec4,
+        "vec2" => ShaderTy::Vec2,
+        "vec3" => ShaderTy::Vec3,
+        "vec4" => ShaderTy::Vec4,
+        "mat2" => ShaderTy::Mat2,
+        "mat3" => ShaderTy::Mat3,
+        "mat4" => ShaderTy::Mat4,
+        "texture2d" => ShaderTy::Texture2D,
+        "textureCube" => ShaderTy::TextureCube,
+        "texture3d" => ShaderTy::Texture3D,
+        "texture2d_array" => ShaderTy::Texture2DArray,
+        "texture2d_depth" => ShaderTy::Texture2DDepth,
+        _ => return Err(format!("unknown type `{}`", ty)),
+    })
+}
+
+// only the bits of TOML the sidecar needs: `[attributes]` and `[uniforms]` tables of `name = "type"`
+fn parse_config(
+    config: &str,
+    attributes: &mut Vec<(String, ShaderTy)>,
+    uniforms: &mut Vec<(String, ShaderTy)>,
+) -> Result<(), String> {
+    let mut table = None;
+    for (line_index, line) in config.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = | message: &str | format!("line {}: {}", line_index + 1, message);
+        if line.starts_with('[') {
+            table = match line {
+                "[attributes]" => Some(&mut *attributes),
+                "[uniforms]" => Some(&mut *uniforms),
+                _ => return Err(error(&format!("unknown table `{}`", line))),
+            };
+            continue;
+        }
+        let table = table.as_mut().ok_or_else( | | error("expected `[attributes]` or `[uniforms]`")) ?;
+        let (name, ty) = line.split_once('=').ok_or_else( | | error("expected `name = \"type\"`")) ?;
+        let ty = ty.trim();
+        if ty.len() < 2 || !ty.starts_with('"') || !ty.ends_with('"') {
+            return Err(error("type must be a quoted string"));
+        }
+        let ty = parse_ty(&ty[1..ty.len() - 1]).map_err( | err | error(&err)) ?;
+        table.push((name.trim().to_string(), ty));
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use {
+    crate::{
+        generate::{DisplayDsIdent, DisplayPixelOutput},
+        makepad_live_id::LiveId,
+        shader::ShaderEnum,
+        shader_ast::*,
+    },
+    std::{collections::HashMap, fmt::Write},
+};
+
+/// The interface of a compiled shader, as seen from the CPU side.
+/// Slot offsets and packed identifiers match the layout the generators emit.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub attributes: Vec<ShaderReflectionField>,
+    pub instances: Vec<ShaderReflectionField>,
+    pub uniform_blocks: Vec<ShaderReflectionUniformBlock>,
+    pub textures: Vec<ShaderReflectionField>,
+    pub varyings: Vec<ShaderReflectionField>,
+    /// the render targets `pixel` writes, a single `color` unless it returns a struct
+    pub outputs: Vec<ShaderReflectionField>,
+    pub enums: Vec<ShaderReflectionEnum>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflectionField {
+    pub name: String,
+    pub ty: ShaderTy,
+    /// number of float slots, textures take a single slot
+    pub slots: usize,
+    /// offset in slots into the packed group (geometry, instance, varying or uniform table),
+    /// for textures this is the texture unit and for outputs the render target
+    pub slot_offset: usize,
+    /// identifier of the unpacked value in generated code, `ds_x`
+    pub ident: String,
+    /// packed variables this field is spread over, `packed_geometry_0`, `pass_table`, ...
+    pub packed_idents: Vec<String>,
+    /// byte offset into the `layout(std140)` block, uniforms only
+    pub std140_offset: Option<usize>,
+    /// sampler state declared on the field, textures only
+    pub sampler: Option<ShaderSampler>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflectionUniformBlock {
+    pub name: String,
+    /// name of the float table in GLSL and of the struct in Metal/HLSL
+    pub ident: String,
+    pub slots: usize,
+    /// name of the `layout(std140)` block in GLSL, see `GlslOptions::named_uniforms`
+    pub std140_ident: String,
+    /// size in bytes of the `layout(std140)` block
+    pub std140_size: usize,
+    pub uniforms: Vec<ShaderReflectionField>,
+}
+
+/// An enum declared in the shader. Enum fields take a single slot, set to the value of a variant
+/// as a float, or as an `i32` in the Rust backend and the interpreter.
+#[derive(Clone, Debug)]
+pub struct ShaderReflectionEnum {
+    pub name: String,
+    /// names and values of the variants, in declaration order
+    pub variants: Vec<(String, usize)>,
+}
+
+impl ShaderReflectionEnum {
+    pub fn variant_value(&self, variant: &str) -> Option<usize> {
+        self.variants.iter().find( | (name, _) | name == variant).map( | (_, value) | *value)
+    }
+}
+
+impl ShaderReflection {
+    pub(crate) fn from_draw_shader_def(draw_shader_def: &DrawShaderDef, enums: &HashMap<LiveId, ShaderEnum>, pixel_outputs: Option<Vec<(Ident, Ty)>>) -> Self {
+        let mut reflection = ShaderReflection::default();
+        let mut geometry_slots = 0;
+        let mut instance_slots = 0;
+        let mut varying_slots = 0;
+        let mut texture_index = 0;
+        for field in &draw_shader_def.fields {
+            let ty = field.ty_expr.ty.borrow().as_ref().unwrap().clone();
+            match &field.kind {
+                DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} => {
+                    reflection.attributes.push(packed_field(field.ident, &ty, geometry_slots, "packed_geometry"));
+                    geometry_slots += ty.slots();
+                    if is_used_in_pixel_shader.get() {
+                        reflection.varyings.push(packed_field(field.ident, &ty, varying_slots, "packed_varying"));
+                        varying_slots += ty.slots();
+                    }
+                }
+                DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} => {
+                    reflection.instances.push(packed_field(field.ident, &ty, instance_slots, "packed_instance"));
+                    instance_slots += ty.slots();
+                    if is_used_in_pixel_shader.get() {
+                        reflection.varyings.push(packed_field(field.ident, &ty, varying_slots, "packed_varying"));
+                        varying_slots += ty.slots();
+                    }
+                }
+                DrawShaderFieldKind::Varying {..} => {
+                    reflection.varyings.push(packed_field(field.ident, &ty, varying_slots, "packed_varying"));
+                    varying_slots += ty.slots();
+                }
+                DrawShaderFieldKind::Texture {sampler, ..} => {
+                    reflection.textures.push(ShaderReflectionField {
+                        name: field.ident.to_string(),
+                        ty,
+                        slots: 1,
+                        slot_offset: texture_index,
+                        ident: DisplayDsIdent(field.ident).to_string(),
+                        packed_idents: Vec::new(),
+                        std140_offset: None,
+                        sampler: Some(*sampler),
+                    });
+                    texture_index += 1;
+                }
+                DrawShaderFieldKind::Uniform {..} => ()
+            }
+        }
+
+        for (block_ident, fields) in draw_shader_def.fields_as_uniform_blocks() {
+            let table = format!("{}_table", block_ident);
+            let mut slots = 0;
+            let mut std140_size = 0;
+            let mut uniforms = Vec::new();
+            for (index, _) in fields {
+                let field = &draw_shader_def.fields[index];
+                let ty = field.ty_expr.ty.borrow().as_ref().unwrap().clone();
+                let (size, align) = ty.std140_size_align();
+                let std140_offset = (std140_size + align - 1) / align * align;
+                std140_size = std140_offset + size;
+                uniforms.push(ShaderReflectionField {
+                    name: field.ident.to_string(),
+                    slots: ty.slots(),
+                    slot_offset: slots,
+                    ident: DisplayDsIdent(field.ident).to_string(),
+                    packed_idents: vec![table.clone()],
+                    std140_offset: Some(std140_offset),
+                    sampler: None,
+                    ty,
+                });
+                slots += uniforms.last().unwrap().slots;
+            }
+            reflection.uniform_blocks.push(ShaderReflectionUniformBlock {
+                name: block_ident.to_string(),
+                ident: table,
+                slots,
+                std140_ident: format!("Uniforms_{}", block_ident),
+                // the block itself is aligned like a vec4
+                std140_size: (std140_size + 15) & !15,
+                uniforms,
+            });
+        }
+
+        match pixel_outputs {
+            Some(pixel_outputs) => for (index, (ident, ty)) in pixel_outputs.into_iter().enumerate() {
+                reflection.outputs.push(output_field(ident.to_string(), ty, index, DisplayPixelOutput(ident).to_string()));
+            }
+            None => reflection.outputs.push(output_field(String::from("color"), Ty::Vec4, 0, String::from("frag_color")))
+        }
+
+        for shader_enum in enums.values() {
+            reflection.enums.push(ShaderReflectionEnum {
+                name: shader_enum.enum_name.to_string(),
+                // matches the values `match` compares against
+                variants: shader_enum.variants.iter().enumerate().map( | (index, variant) | (variant.to_string(), index + 1)).collect(),
+            });
+        }
+        reflection.enums.sort_by( | a, b | a.name.cmp(&b.name));
+        reflection
+    }
+
+    pub fn find_enum(&self, name: &str) -> Option<&ShaderReflectionEnum> {
+        self.enums.iter().find( | shader_enum | shader_enum.name == name)
+    }
+
+    /// Serializes the reflection, for build tools that consume it outside of Rust.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        json.push_str("{\n");
+        write_json_fields(&mut json, "attributes", &self.attributes);
+        json.push_str(",\n");
+        write_json_fields(&mut json, "instances", &self.instances);
+        json.push_str(",\n  \"uniform_blocks\": [");
+        for (index, block) in self.uniform_blocks.iter().enumerate() {
+            if index != 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "\n    {{\"name\": {:?}, \"ident\": {:?}, \"slots\": {}, \"std140_ident\": {:?}, \"std140_size\": {}, \"uniforms\": [",
+                block.name,
+                block.ident,
+                block.slots,
+                block.std140_ident,
+                block.std140_size
+            ).unwrap();
+            for (index, field) in block.uniforms.iter().enumerate() {
+                if index != 0 {
+                    json.push(',');
+                }
+                json.push_str("\n      ");
+                write_json_field(&mut json, field);
+            }
+            json.push_str("\n    ]}");
+        }
+        json.push_str("\n  ],\n");
+        write_json_fields(&mut json, "textures", &self.textures);
+        json.push_str(",\n");
+        write_json_fields(&mut json, "varyings", &self.varyings);
+        json.push_str(",\n");
+        write_json_fields(&mut json, "outputs", &self.outputs);
+        json.push_str(",\n  \"enums\": [");
+        for (index, shader_enum) in self.enums.iter().enumerate() {
+            if index != 0 {
+                json.push(',');
+            }
+            write!(json, "\n    {{\"name\": {:?}, \"variants\": {{", shader_enum.name).unwrap();
+            for (index, (variant, value)) in shader_enum.variants.iter().enumerate() {
+                write!(json, "{}{:?}: {}", if index != 0 {", "} else {""}, variant, value).unwrap();
+            }
+            json.push_str("}}");
+        }
+        if !self.enums.is_empty() {
+            json.push_str("\n  ");
+        }
+        json.push_str("]\n}\n");
+        json
+    }
+}
+
+// names and identifiers are plain ascii, so Debug quoting is valid JSON
+fn write_json_fields(json: &mut String, key: &str, fields: &[ShaderReflectionField]) {
+    write!(json, "  {:?}: [", key).unwrap();
+    for (index, field) in fields.iter().enumerate() {
+        if index != 0 {
+            json.push(',');
+        }
+        json.push_str("\n    ");
+        write_json_field(json, field);
+    }
+    if !fields.is_empty() {
+        json.push_str("\n  ");
+    }
+    json.push(']');
+}
+
+fn write_json_field(json: &mut String, field: &ShaderReflectionField) {
+    write!(
+        json,
+        "{{\"name\": {:?}, \"ty\": \"{}\", \"slots\": {}, \"slot_offset\": {}, \"ident\": {:?}, \"packed_idents\": {:?}",
+        field.name,
+        field.ty,
+        field.slots,
+        field.slot_offset,
+        field.ident,
+        field.packed_idents
+    ).unwrap();
+    if let Some(std140_offset) = field.std140_offset {
+        write!(json, ", \"std140_offset\": {}", std140_offset).unwrap();
+    }
+    if let Some(sampler) = field.sampler {
+        let filter = match sampler.filter {
+            SamplerFilter::Nearest => "nearest",
+            SamplerFilter::Linear => "linear",
+        };
+        let wrap = match sampler.wrap {
+            SamplerWrap::Repeat => "repeat",
+            SamplerWrap::Clamp => "clamp",
+            SamplerWrap::Mirror => "mirror",
+        };
+        let mip = match sampler.mip {
+            SamplerMip::None => "none",
+            SamplerMip::Nearest => "nearest",
+            SamplerMip::Linear => "linear",
+        };
+        write!(json, ", \"sampler\": {{\"filter\": \"{}\", \"wrap\": \"{}\", \"mip\": \"{}\"}}", filter, wrap, mip).unwrap();
+    }
+    json.push('}');
+}
+
+// packed vars are filled 4 slots at a time in field order
+fn packed_field(ident: Ident, ty: &Ty, slot_offset: usize, packed_var_name: &str) -> ShaderReflectionField {
+    let slots = ty.slots();
+    let packed_idents = (slot_offset / 4..=(slot_offset + slots.max(1) - 1) / 4)
+        .map( | packed_var_index | format!("{}_{}", packed_var_name, packed_var_index))
+        .collect();
+    ShaderReflectionField {
+        name: ident.to_string(),
+        ty: ty.clone(),
+        slots,
+        slot_offset,
+        ident: DisplayDsIdent(ident).to_string(),
+        packed_idents,
+        std140_offset: None,
+        sampler: None,
+    }
+}
+
+// `ident` is the output variable in GLSL 3.00+ and SPIR-V, and the `PixelOutput` field on the other backends
+fn output_field(name: String, ty: Ty, index: usize, ident: String) -> ShaderReflectionField {
+    ShaderReflectionField {
+        name,
+        slots: ty.slots(),
+        ty,
+        slot_offset: index,
+        ident,
+        packed_idents: Vec::new(),
+        std140_offset: None,
+        sampler: None,
+    }
+}
@@ -1,6 +1,11 @@
 use {
     crate::{
         analyse::*,
+        diagnostic::{ShaderDiagnostic, ShaderSeverity},
+        generate_glsl::{GlslBinding, GlslBindingPolicy, GlslDefaultBindingPolicy, GlslOptions, GlslTarget},
+        interpret::{ShaderInputs, ShaderOutput, Stage},
+        reflection::ShaderReflection,
+        source_map::{resolve_marks, LineDirectives, ShaderLineMarkers, ShaderSourceMap},
         builtin::{generate_builtins, Builtin},
         makepad_live_compiler::*,
         makepad_live_id::*,
@@ -9,19 +14,28 @@ use {
     },
     std::{
         cell::{Cell, RefCell},
-        collections::{BTreeMap, HashMap, HashSet},
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     },
 };
 
 pub struct Shader {
     shader_file: LiveFile,
+    file_name: String,
+    source: String,
     pub(crate) all_fns: HashMap<FnPtr, FnDef>,
     pub(crate) draw_shader_def: DrawShaderDef,
     pub(crate) structs: HashMap<StructPtr, StructDef>,
+    // structs and functions referenced by the draw shader, dependencies first
+    pub(crate) deps: Vec<ShaderParserDep>,
     pub(crate) builtins: HashMap<Ident, Builtin>,
-    pub(crate) enums: HashMap<LiveType, ShaderEnum>,
+    pub(crate) enums: HashMap<LiveId, ShaderEnum>,
+    // errors recovered from and warnings found during `compile`
+    pub(crate) diagnostics: RefCell<Vec<(ShaderSeverity, LiveError)>>,
+    // syntax errors found by `new`, reported by `compile`
+    parse_errors: Vec<LiveError>,
 }
 
+/// A `Shape: Enum {Circle, Box}` declared in the shader, variants are numbered from 1 in order.
 pub(crate) struct ShaderEnum {
     pub(crate) enum_name: LiveId,
     pub(crate) variants: Vec<LiveId>,
@@ -32,9 +46,11 @@ pub(crate) enum LiveNodeFindResult {
     NotFound,
     Component(LivePtr),
     Struct(StructPtr),
+    Enum(LiveId),
     Function(FnPtr),
     PossibleStatic(StructPtr, FnPtr),
     LiveValue(ValuePtr, TyLit),
+    Const(ConstPtr),
     Error(LiveError),
 }
 
@@ -91,6 +107,98 @@ impl Shader {
         None
     }
 
+    /// The method a method call goes to and the args it is called with, draw shader methods are
+    /// called without their `self`.
+    pub(crate) fn method_call_fn_def<'b>(&self, ident: Ident, arg_exprs: &'b [Expr]) -> (&FnDef, &'b [Expr]) {
+        match arg_exprs[0].ty.borrow().as_ref().unwrap() {
+            Ty::Struct(struct_ptr) => {
+                let struct_def = self.structs.get(struct_ptr).unwrap();
+                (self.struct_method_decl_from_ident(struct_def, ident).unwrap(), arg_exprs)
+            }
+            Ty::DrawShader => {
+                (self.draw_shader_method_decl_from_ident(&self.draw_shader_def, ident).unwrap(), &arg_exprs[1..])
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// The render targets written by `pixel`, the fields of the struct it returns,
+    /// `None` if it returns a single `vec4`.
+    pub(crate) fn pixel_outputs(&self, draw_shader_def: &DrawShaderDef) -> Option<Vec<(Ident, Ty)>> {
+        let pixel_decl = self.draw_shader_method_decl_from_ident(draw_shader_def, Ident(id!(pixel)))?;
+        match pixel_decl.return_ty.borrow().as_ref()? {
+            Ty::Struct(struct_ptr) => Some(
+                self.structs.get(struct_ptr).unwrap().fields.iter()
+                    .map( | field | (field.ident, field.ty_expr.ty.borrow().clone().unwrap()))
+                    .collect()
+            ),
+            _ => None
+        }
+    }
+
+    /// The stage builtins `fns` use, usually `vertex_fns` or `pixel_fns`.
+    pub(crate) fn stage_builtins(&self, fns: &[FnPtr]) -> BTreeSet<StageBuiltin> {
+        let mut stage_builtins = BTreeSet::new();
+        for fn_ptr in fns {
+            let fn_def = self.all_fns.get(fn_ptr).unwrap();
+            stage_builtins.extend(fn_def.stage_builtins.borrow().as_ref().unwrap().keys().cloned());
+        }
+        stage_builtins
+    }
+
+    /// The first use in the draw shader of any of `stage_builtins`, for the backends that can't express them.
+    pub(crate) fn first_stage_builtin(&self, stage_builtins: &[StageBuiltin]) -> Option<(StageBuiltin, TokenSpan)> {
+        for fn_ptr in self.draw_shader_def.all_fns.borrow().iter() {
+            let fn_def = self.all_fns.get(fn_ptr).unwrap();
+            for (stage_builtin, span) in fn_def.stage_builtins.borrow().as_ref().unwrap() {
+                if stage_builtins.contains(stage_builtin) {
+                    return Some((*stage_builtin, *span))
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether any of `fns` calls one of `builtins`.
+    pub(crate) fn uses_builtin(&self, fns: &[FnPtr], builtins: &[Ident]) -> bool {
+        fns.iter().any(|fn_ptr| {
+            let fn_def = self.all_fns.get(fn_ptr).unwrap();
+            let builtin_deps = fn_def.builtin_deps.borrow();
+            // not `contains`, ids that never occur in the source order equal to anything
+            builtin_deps.as_ref().unwrap().keys().any(|dep| builtins.contains(dep))
+        })
+    }
+
+    /// The fns called from both stages that sample with an implicit mip level, themselves or
+    /// through a callee. A backend with both stages in one module gives the vertex stage its own
+    /// copy of them, with explicit levels.
+    pub(crate) fn vertex_copy_fns(&self) -> BTreeSet<FnPtr> {
+        let all_fns = self.draw_shader_def.all_fns.borrow();
+        let mut fns = BTreeSet::new();
+        loop {
+            let len = fns.len();
+            for fn_ptr in all_fns.iter() {
+                let fn_def = self.all_fns.get(fn_ptr).unwrap();
+                // a closure is generated into the fns it is passed to
+                let samples = fn_def.builtin_deps.borrow().as_ref().unwrap().keys().any(|dep| TextureOp::has_implicit_lod(*dep))
+                    || fn_def.callees.borrow().as_ref().unwrap().iter().any(|callee| fns.contains(callee))
+                    || fn_def.has_closure_args() && fns.iter().any(|caller| {
+                        self.all_fns.get(caller).unwrap().callees.borrow().as_ref().unwrap().contains(fn_ptr)
+                    });
+                if samples {
+                    fns.insert(*fn_ptr);
+                }
+            }
+            if fns.len() == len {
+                break;
+            }
+        }
+        let vertex_fns = self.draw_shader_def.vertex_fns.borrow();
+        let pixel_fns = self.draw_shader_def.pixel_fns.borrow();
+        fns.retain(|fn_ptr| vertex_fns.contains(fn_ptr) && pixel_fns.contains(fn_ptr));
+        fns
+    }
+
     pub(crate) fn struct_method_decl_from_ident(
         &self,
         struct_def: &StructDef,
@@ -176,6 +284,14 @@ impl Shader {
                 {
                     return LiveNodeFindResult::LiveValue(ValuePtr(now_ptr), TyLit::Vec4)
                 }
+                LiveValue::Array
+                    if shader_file.get_node_prefix(node.origin) == Some(id!(const)) =>
+                {
+                    if let Err(err) = const_array_items(nodes, index) {
+                        return LiveNodeFindResult::Error(err);
+                    }
+                    return LiveNodeFindResult::Const(ConstPtr(now_ptr))
+                }
                 LiveValue::Expr { .. }
                     if shader_file.get_node_prefix(node.origin) == Some(id!(const)) =>
                 {
@@ -236,6 +352,9 @@ impl Shader {
                         if clone == id!(Struct) {
                             return LiveNodeFindResult::Struct(StructPtr(now_ptr));
                         }
+                        if clone == id!(Enum) {
+                            return LiveNodeFindResult::Enum(node.id);
+                        }
                         return LiveNodeFindResult::Component(now_ptr);
                     }
                     match nodes.child_by_name(index, ids[0].as_field()) {
@@ -278,15 +397,109 @@ impl Shader {
         }
     }
 
-    // lets compile the thing
-    pub fn new(source: &str) -> Result<Shader, LiveError> {
-        let shader_file =
-            makepad_live_compiler::LiveFile::load(source).map_err(|err| LiveError {
-                origin: err.origin,
-                span: err.span.into(),
-                message: err.message.clone(),
-            })?;
+    // lets compile the thing, only a file that doesn't tokenize fails here,
+    // syntax errors in the shader code are reported by `compile`
+    pub fn new(source: &str) -> Result<Shader, LiveFileError> {
+        Self::new_with_file_name("<shader>", source)
+    }
+
+    /// Like `new`, `file_name` is only used to report errors.
+    pub fn new_with_file_name(file_name: &str, source: &str) -> Result<Shader, LiveFileError> {
+        let shader_file = makepad_live_compiler::LiveFile::load(source).map_err(|err| LiveFileError {
+            file: file_name.to_string(),
+            ..err
+        })?;
+
+        let mut shader = Shader {
+            shader_file,
+            file_name: file_name.to_string(),
+            source: source.to_string(),
+            structs: HashMap::new(),
+            deps: Vec::new(),
+            enums: HashMap::new(),
+            all_fns: HashMap::new(),
+            draw_shader_def: DrawShaderDef::default(),
+            builtins: generate_builtins(),
+            diagnostics: RefCell::new(Vec::new()),
+            parse_errors: Vec::new(),
+        };
+        shader.parse();
+        Ok(shader)
+    }
+
+    // the value a `const` in the shader file evaluates to
+    pub(crate) fn live_value_eval(&self, value_ptr: ValuePtr) -> Result<LiveEval, LiveError> {
+        let index = value_ptr.0.index as usize;
+        let nodes = &self.shader_file.expanded.nodes;
+        match &nodes[index].value {
+            LiveValue::Expr {..} => live_eval(&self.shader_file, index, &mut (index + 1), nodes),
+            _ => live_eval(&self.shader_file, index, &mut index.clone(), nodes),
+        }
+    }
+
+    // the type of a `const` array and the components of each of its items
+    pub(crate) fn const_array(&self, const_ptr: ConstPtr) -> (Ty, Vec<Vec<f32>>) {
+        const_array_items(&self.shader_file.expanded.nodes, const_ptr.index as usize).unwrap()
+    }
+
+    pub(crate) fn const_token_id(&self, const_ptr: ConstPtr) -> LiveTokenId {
+        self.shader_file.expanded.nodes[const_ptr.index as usize].origin.token_id().unwrap()
+    }
+
+    /// The items of the `const` arrays the shader indexes, each padded to 4 floats. The generated
+    /// shaders read them from `const_table`, which has to be set to these, except on `GlslTarget::Glsl450`
+    /// and in SPIR-V, which have them baked in.
+    pub fn const_table(&self) -> Vec<f32> {
+        self.draw_shader_const_table().table
+    }
+
+    fn draw_shader_const_table(&self) -> DrawShaderConstTable {
+        let mut const_table = DrawShaderConstTable::default();
+        for const_ptr in self.draw_shader_def.all_const_refs.borrow().keys() {
+            let (_, items) = self.const_array(*const_ptr);
+            let offset = const_table.table.len();
+            for item in &items {
+                const_table.table.extend(item.iter().cloned().chain(std::iter::repeat(0.0)).take(4));
+            }
+            const_table.table_index.insert(
+                self.const_token_id(*const_ptr),
+                ConstTableItem {offset, slots: items.len() * 4}
+            );
+        }
+        const_table
+    }
+
+    /// Resolves the span of an error against the shader source.
+    pub(crate) fn live_error_to_file_error(&self, err: LiveError) -> LiveFileError {
+        err.to_live_file_error(&self.file_name, &self.source, &self.shader_file.original)
+    }
+
+    pub(crate) fn token_span_to_text_span(&self, span: TokenSpan) -> TextSpan {
+        self.shader_file.original.token_span_to_span(span)
+    }
+
+    pub(crate) fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Re-reports an error a backend compiler found on `line` of a generated shader against the code
+    /// in the `.shader` file that line came from. `None` if the line is glue around the shader functions.
+    pub fn remap_error(&self, source_map: &ShaderSourceMap, line: u32, message: &str) -> Option<LiveFileError> {
+        let span = source_map.span_at_line(line) ?;
+        Some(LiveFileError {
+            origin: live_error_origin!(),
+            file: self.file_name.clone(),
+            span,
+            message: message.to_string(),
+            snippet: String::new(),
+        }.with_snippet(&self.source))
+    }
 
+    // a broken declaration or statement is reported and skipped, so that one
+    // `compile` reports all of the syntax errors
+    fn parse(&mut self) {
+        let shader_file = &self.shader_file;
+        let builtins = &self.builtins;
         let mut draw_shader_def = DrawShaderDef::default();
 
         let doc = &shader_file.expanded;
@@ -298,8 +511,40 @@ impl Shader {
         //     &mut draw_shader_def,
         // );
 
+        // enums first, fields can be declared before the enum they use
+        let mut errors = Vec::new();
+        let mut enums = HashMap::new();
+        let mut node_iter = doc.nodes.first_child(0);
+        while let Some(node_index) = node_iter {
+            let prop = &doc.nodes[node_index];
+            if let LiveValue::Clone(id!(Enum)) = prop.value {
+                let mut variants = Vec::new();
+                let mut variant_iter = doc.nodes.first_child(node_index);
+                while let Some(variant_index) = variant_iter {
+                    let variant = &doc.nodes[variant_index];
+                    if variants.contains(&variant.id) {
+                        errors.push(LiveError {
+                            origin: live_error_origin!(),
+                            span: variant.origin.token_id().unwrap().into(),
+                            message: format!("Variant double declaration {}::{}", prop.id, variant.id),
+                        });
+                    } else {
+                        variants.push(variant.id);
+                    }
+                    variant_iter = doc.nodes.next_child(variant_index);
+                }
+                enums.insert(prop.id, ShaderEnum {
+                    enum_name: prop.id,
+                    variants,
+                });
+            }
+            node_iter = doc.nodes.next_child(node_index);
+        }
+
         let mut parser_deps = Vec::new();
         let mut all_fns = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut deps = Vec::new();
         let mut node_iter = doc.nodes.first_child(0);
         let mut method_set = HashSet::new();
         while let Some(node_index) = node_iter {
@@ -322,23 +567,28 @@ impl Shader {
                 | LiveValue::Vec4(_)
                 | LiveValue::Expr { .. } => {
                     if prop.origin.prop_type() != LivePropType::Field {
-                        return Err(LiveError {
+                        errors.push(LiveError {
                             origin: live_error_origin!(),
                             span: prop.origin.token_id().unwrap().into(),
                             message: format!("Can only support field colon : values don't use ="),
                         });
+                        node_iter = doc.nodes.next_child(node_index);
+                        continue;
                     }
                     if prop.id == id!(size) {}
                     let first_def = prop.origin.first_def().unwrap();
                     let before = shader_file.get_node_prefix(prop.origin);
 
-                    let ty = match ShaderTy::from_live_node(&shader_file, node_index, &doc.nodes) {
-                        Ok(ty) => ty,
-                        Err(_) => {
-                            // just ignore it
-                            node_iter = doc.nodes.next_child(node_index);
-                            continue;
-                            //return Err(err)
+                    let ty = match prop.value {
+                        LiveValue::Id(id) if enums.contains_key(&id) => ShaderTy::Enum(id),
+                        _ => match ShaderTy::from_live_node(&shader_file, node_index, &doc.nodes) {
+                            Ok(ty) => ty,
+                            Err(_) => {
+                                // just ignore it
+                                node_iter = doc.nodes.next_child(node_index);
+                                continue;
+                                //return Err(err)
+                            }
                         }
                     };
                     let ty_expr = ty.to_ty_expr();
@@ -407,6 +657,7 @@ impl Shader {
                             draw_shader_def.fields.push(DrawShaderFieldDef {
                                 kind: DrawShaderFieldKind::Texture {
                                     var_def_ptr: Some(VarDefPtr(prop_ptr)),
+                                    sampler: ShaderSampler::default(),
                                 },
                                 span: first_def.into(),
                                 ident: Ident(prop.id),
@@ -431,7 +682,7 @@ impl Shader {
                             }
                         }
                         _ => {
-                            return Err(LiveError {
+                            errors.push(LiveError {
                                 origin: live_error_origin!(),
                                 span: first_def.into(),
                                 message: format!("Unexpected variable prefix {:?}", before),
@@ -439,6 +690,59 @@ impl Shader {
                         }
                     };
                 }
+                LiveValue::Clone(id) if TyLit::from_id(id).map_or(false, | ty_lit | ty_lit.to_ty().is_texture()) => {
+                    // a texture with sampler attributes
+                    let first_def = prop.origin.first_def().unwrap();
+                    if shader_file.get_node_prefix(prop.origin) != Some(id!(texture)) {
+                        errors.push(LiveError {
+                            origin: live_error_origin!(),
+                            span: first_def.into(),
+                            message: format!("Sampler attributes are only allowed on texture fields"),
+                        });
+                        node_iter = doc.nodes.next_child(node_index);
+                        continue;
+                    }
+                    let sampler = match ShaderSampler::from_live_node(&doc.nodes, node_index) {
+                        Ok(sampler) => sampler,
+                        Err(err) => {
+                            errors.push(err);
+                            node_iter = doc.nodes.next_child(node_index);
+                            continue;
+                        }
+                    };
+                    draw_shader_def.fields.push(DrawShaderFieldDef {
+                        kind: DrawShaderFieldKind::Texture {
+                            var_def_ptr: Some(VarDefPtr(prop_ptr)),
+                            sampler,
+                        },
+                        span: first_def.into(),
+                        ident: Ident(prop.id),
+                        ty_expr: TyLit::from_id(id).unwrap().to_ty().to_ty_expr(),
+                    });
+                }
+                LiveValue::Array if shader_file.get_node_prefix(prop.origin) == Some(id!(const)) => {
+                    // a const array, which is looked up when it is used
+                }
+                LiveValue::Array if shader_file.get_node_prefix(prop.origin).is_some() => {
+                    // an array field, `uniform lights: [vec4; 8]`, parsed from its prefix token
+                    let token_start = prop.origin.first_def().unwrap().token_index() - 1;
+                    let mut parser = ShaderParser::new(
+                        &shader_file,
+                        &shader_file.original.tokens[token_start..],
+                        &mut parser_deps,
+                        &mut errors,
+                        &builtins,
+                        Some(FnSelfKind::DrawShader),
+                        node_index,
+                        prop.origin.token_id().unwrap().file_id().unwrap(),
+                        token_start,
+                    );
+                    match parser.expect_self_decl(Ident(prop.id), prop_ptr) {
+                        Ok(Some(field)) => draw_shader_def.fields.push(field),
+                        Ok(None) => (),
+                        Err(err) => errors.push(err),
+                    }
+                }
                 LiveValue::Class { .. } => {
                     if prop.id == id!(geometry) {
                         // ext_self(
@@ -460,6 +764,8 @@ impl Shader {
                         &shader_file,
                         origin_doc.get_tokens(token_start as usize, token_count as usize),
                         &mut parser_deps,
+                        &mut errors,
+                        &builtins,
                         Some(FnSelfKind::DrawShader),
                         expand_index.unwrap() as usize,
                         prop.origin.token_id().unwrap().file_id().unwrap(),
@@ -469,16 +775,34 @@ impl Shader {
                     let token = &origin_doc.tokens[token_start as usize];
                     match token.token {
                         LiveToken::Ident(id!(fn)) => {
-                            let fn_def =
-                                parser.expect_method_def(FnPtr(prop_ptr), Ident(prop.id))?;
-                            if let Some(fn_def) = fn_def {
-                                method_set.insert(prop.id);
-                                draw_shader_def.methods.push(fn_def.fn_ptr);
-                                all_fns.insert(fn_def.fn_ptr, fn_def);
+                            match parser.expect_method_def(FnPtr(prop_ptr), Ident(prop.id)) {
+                                Ok(Some(fn_def)) => {
+                                    method_set.insert(prop.id);
+                                    draw_shader_def.methods.push(fn_def.fn_ptr);
+                                    all_fns.insert(fn_def.fn_ptr, fn_def);
+                                }
+                                Ok(None) => {
+                                    // no self, so its a plain function
+                                    Self::parse_plain_fn(
+                                        &shader_file,
+                                        &builtins,
+                                        None,
+                                        FnPtr(prop_ptr),
+                                        &mut structs,
+                                        &mut all_fns,
+                                        &mut deps,
+                                        &mut errors,
+                                    );
+                                }
+                                Err(err) => {
+                                    // it is there, just broken
+                                    method_set.insert(prop.id);
+                                    errors.push(err);
+                                }
                             }
                         }
                         _ => {
-                            return Err(LiveError {
+                            errors.push(LiveError {
                                 origin: live_error_origin!(),
                                 span: token.span.into(),
                                 message: format!("Unexpected in shader body {}", token),
@@ -496,7 +820,7 @@ impl Shader {
                 let field_a = &draw_shader_def.fields[i];
                 let field_b = &draw_shader_def.fields[j];
                 if field_a.ident == field_b.ident && !field_a.ident.0.is_empty() {
-                    return Err(LiveError {
+                    errors.push(LiveError {
                         origin: live_error_origin!(),
                         span: field_a.span.into(),
                         message: format!("Field double declaration  {}", field_b.ident),
@@ -506,7 +830,7 @@ impl Shader {
         }
 
         if !method_set.contains(&id!(vertex)) {
-            return Err(LiveError {
+            errors.push(LiveError {
                 origin: live_error_origin!(),
                 span: TokenSpan::default().into(),
                 message: format!("analyse_draw_shader missing vertex method"),
@@ -514,29 +838,317 @@ impl Shader {
         }
 
         if !method_set.contains(&id!(pixel)) {
-            return Err(LiveError {
+            errors.push(LiveError {
                 origin: live_error_origin!(),
                 span: TokenSpan::default().into(),
                 message: format!("analyse_draw_shader missing pixel method"),
             });
         }
 
-        assert!(parser_deps.len() == 0);
-        //self.analyse_deps(&shader_file, &parser_deps) ?;
+        Self::parse_deps(
+            &shader_file,
+            &builtins,
+            &parser_deps,
+            &mut structs,
+            &mut all_fns,
+            &mut deps,
+            &mut errors,
+        );
 
-        let mut shader = Shader {
-            shader_file,
-            structs: HashMap::new(),
-            enums: HashMap::new(),
-            all_fns,
-            draw_shader_def,
-            builtins: generate_builtins(),
+        self.structs = structs;
+        self.enums = enums;
+        self.deps = deps;
+        self.all_fns = all_fns;
+        self.draw_shader_def = draw_shader_def;
+        self.parse_errors = errors;
+    }
+
+    fn parse_deps(
+        shader_file: &LiveFile,
+        builtins: &HashMap<Ident, Builtin>,
+        parser_deps: &[ShaderParserDep],
+        structs: &mut HashMap<StructPtr, StructDef>,
+        all_fns: &mut HashMap<FnPtr, FnDef>,
+        deps: &mut Vec<ShaderParserDep>,
+        errors: &mut Vec<LiveError>,
+    ) {
+        for dep in parser_deps {
+            match dep {
+                ShaderParserDep::Struct(struct_ptr) => {
+                    Self::parse_struct(shader_file, builtins, *struct_ptr, structs, all_fns, deps, errors);
+                }
+                ShaderParserDep::Function(struct_ptr, fn_ptr) => {
+                    Self::parse_plain_fn(
+                        shader_file,
+                        builtins,
+                        *struct_ptr,
+                        *fn_ptr,
+                        structs,
+                        all_fns,
+                        deps,
+                        errors,
+                    );
+                }
+            }
+        }
+    }
+
+    fn parse_struct(
+        shader_file: &LiveFile,
+        builtins: &HashMap<Ident, Builtin>,
+        struct_ptr: StructPtr,
+        structs: &mut HashMap<StructPtr, StructDef>,
+        all_fns: &mut HashMap<FnPtr, FnDef>,
+        deps: &mut Vec<ShaderParserDep>,
+        errors: &mut Vec<LiveError>,
+    ) {
+        if structs.contains_key(&struct_ptr) {
+            return;
+        }
+        let doc = &shader_file.expanded;
+        let origin_doc = &shader_file.original;
+        let struct_node = &doc.nodes[struct_ptr.index as usize];
+        let mut struct_def = StructDef {
+            span: struct_node.origin.token_id().unwrap().into(),
+            struct_refs: RefCell::new(None),
+            fields: Vec::new(),
+            methods: Vec::new(),
         };
-        // ok we have all structs
-        Ok(shader)
+
+        let mut parser_deps = Vec::new();
+        let mut node_iter = doc.nodes.first_child(struct_ptr.index as usize);
+        while let Some(node_index) = node_iter {
+            let prop = &doc.nodes[node_index];
+            let prop_ptr = LivePtr {
+                index: node_index as _,
+            };
+            let file_id = prop.origin.token_id().unwrap().file_id().unwrap();
+            match prop.value {
+                LiveValue::DSL {
+                    token_start,
+                    token_count,
+                    expand_index,
+                } => {
+                    let token = &origin_doc.tokens[token_start as usize];
+                    match token.token {
+                        LiveToken::Ident(id!(fn)) => {
+                            let parser = ShaderParser::new(
+                                shader_file,
+                                origin_doc.get_tokens(token_start as usize, token_count as usize),
+                                &mut parser_deps,
+                                errors,
+                                builtins,
+                                Some(FnSelfKind::Struct(struct_ptr)),
+                                expand_index.unwrap() as usize,
+                                file_id,
+                                token_start as usize,
+                            );
+                            // static functions are parsed once something calls them
+                            match parser.expect_method_def(FnPtr(prop_ptr), Ident(prop.id)) {
+                                Ok(Some(fn_def)) => {
+                                    struct_def.methods.push(fn_def.fn_ptr);
+                                    all_fns.insert(fn_def.fn_ptr, fn_def);
+                                }
+                                Ok(None) => (),
+                                Err(err) => errors.push(err),
+                            }
+                        }
+                        _ => {
+                            errors.push(LiveError {
+                                origin: live_error_origin!(),
+                                span: token.span.into(),
+                                message: format!("Unexpected in struct body {}", token),
+                            });
+                        }
+                    }
+                }
+                _ if shader_file.get_node_prefix(prop.origin).is_some() => {
+                    // parse the field from its prefix token: field name: ty
+                    let token_start = prop.origin.first_def().unwrap().token_index() - 1;
+                    let mut parser = ShaderParser::new(
+                        shader_file,
+                        &origin_doc.tokens[token_start..],
+                        &mut parser_deps,
+                        errors,
+                        builtins,
+                        Some(FnSelfKind::Struct(struct_ptr)),
+                        node_index,
+                        file_id,
+                        token_start,
+                    );
+                    match parser.expect_field(Ident(prop.id), VarDefPtr(prop_ptr)) {
+                        Ok(Some(field)) => struct_def.fields.push(field),
+                        Ok(None) => (),
+                        Err(err) => errors.push(err),
+                    }
+                }
+                _ => {
+                    errors.push(LiveError {
+                        origin: live_error_origin!(),
+                        span: prop.origin.token_id().unwrap().into(),
+                        message: format!("Unexpected in struct body {}", prop.id),
+                    });
+                }
+            }
+            node_iter = doc.nodes.next_child(node_index);
+        }
+
+        structs.insert(struct_ptr, struct_def);
+        Self::parse_deps(shader_file, builtins, &parser_deps, structs, all_fns, deps, errors);
+        deps.push(ShaderParserDep::Struct(struct_ptr));
+    }
+
+    fn parse_plain_fn(
+        shader_file: &LiveFile,
+        builtins: &HashMap<Ident, Builtin>,
+        struct_ptr: Option<StructPtr>,
+        fn_ptr: FnPtr,
+        structs: &mut HashMap<StructPtr, StructDef>,
+        all_fns: &mut HashMap<FnPtr, FnDef>,
+        deps: &mut Vec<ShaderParserDep>,
+        errors: &mut Vec<LiveError>,
+    ) {
+        if all_fns.contains_key(&fn_ptr) {
+            return;
+        }
+        let fn_node = &shader_file.expanded.nodes[fn_ptr.index as usize];
+        let origin_doc = &shader_file.original;
+        match fn_node.value {
+            LiveValue::DSL {
+                token_start,
+                token_count,
+                expand_index,
+            } => {
+                let mut parser_deps = Vec::new();
+                let parser = ShaderParser::new(
+                    shader_file,
+                    origin_doc.get_tokens(token_start as usize, token_count as usize),
+                    &mut parser_deps,
+                    errors,
+                    builtins,
+                    struct_ptr.map(FnSelfKind::Struct),
+                    expand_index.unwrap() as usize,
+                    fn_node.origin.token_id().unwrap().file_id().unwrap(),
+                    token_start as usize,
+                );
+                match parser.expect_plain_fn_def(fn_ptr, Ident(fn_node.id)) {
+                    Ok(fn_def) => {
+                        all_fns.insert(fn_ptr, fn_def);
+                    }
+                    Err(err) => {
+                        errors.push(err);
+                        return;
+                    }
+                }
+                Self::parse_deps(shader_file, builtins, &parser_deps, structs, all_fns, deps, errors);
+                deps.push(ShaderParserDep::Function(struct_ptr, fn_ptr));
+            }
+            _ => errors.push(LiveError {
+                origin: live_error_origin!(),
+                span: fn_node.origin.token_id().unwrap().into(),
+                message: format!("Not a function {}", fn_node.id),
+            }),
+        }
+    }
+
+    /// Analyses the shader, continuing past errors where it safely can.
+    /// Returns the warnings on success, or all errors and warnings on failure,
+    /// ordered by their position in the source. A shader with syntax errors
+    /// reports all of them, it is analysed once it parses.
+    pub fn compile(&mut self) -> Result<Vec<ShaderDiagnostic>, Vec<ShaderDiagnostic>> {
+        self.diagnostics.borrow_mut().clear();
+        if self.parse_errors.is_empty() {
+            if let Err(err) = self.analyse() {
+                self.push_error(err);
+            }
+        } else {
+            for err in &self.parse_errors {
+                self.push_error(err.clone());
+            }
+        }
+        let mut diagnostics: Vec<_> = self
+            .diagnostics
+            .replace(Vec::new())
+            .into_iter()
+            .map(|(severity, err)| ShaderDiagnostic {
+                severity,
+                error: self.live_error_to_file_error(err),
+            })
+            .collect();
+        diagnostics.sort_by(|a, b| (a.error.span, &a.error.message).cmp(&(b.error.span, &b.error.message)));
+        // a broken helper is parsed again for every function calling it, the origins differ then
+        diagnostics.dedup_by(|a, b| a.error.span == b.error.span && a.error.message == b.error.message);
+        if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+            Err(diagnostics)
+        } else {
+            Ok(diagnostics)
+        }
+    }
+
+    pub(crate) fn push_error(&self, err: LiveError) {
+        self.diagnostics
+            .borrow_mut()
+            .push((ShaderSeverity::Error, err));
+    }
+
+    pub(crate) fn push_warning(&self, span: TokenSpan, message: String) {
+        self.diagnostics.borrow_mut().push((
+            ShaderSeverity::Warning,
+            LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message,
+            },
+        ));
+    }
+
+    pub(crate) fn error_count(&self) -> usize {
+        self.diagnostics
+            .borrow()
+            .iter()
+            .filter(|(severity, _)| *severity == ShaderSeverity::Error)
+            .count()
     }
 
-    pub fn compile(&mut self) -> Result<(), LiveError> {
+    fn analyse(&mut self) -> Result<(), LiveError> {
+        // structs and plain functions first, the draw shader methods need their decls
+        for dep in &self.deps {
+            match dep {
+                ShaderParserDep::Struct(struct_ptr) => {
+                    StructAnalyser {
+                        struct_def: self.structs.get(struct_ptr).unwrap(),
+                        scopes: &mut Scopes::new(),
+                        file: &self.shader_file,
+                        shader_registry: self,
+                        options: ShaderAnalyseOptions {
+                            no_const_collapse: true,
+                        },
+                    }
+                    .analyse_struct()?;
+                }
+                ShaderParserDep::Function(_, fn_ptr) => {
+                    let mut fa = FnDefAnalyser {
+                        fn_def: self.all_fns.get(fn_ptr).unwrap(),
+                        closure_return_ty: None,
+                        scopes: &mut Scopes::new(),
+                        file: &self.shader_file,
+                        shader_registry: self,
+                        options: ShaderAnalyseOptions {
+                            no_const_collapse: true,
+                        },
+                        loop_labels: Vec::new(),
+                        unused_locals: Vec::new(),
+                        failed_lets: Vec::new(),
+                    };
+                    // callers need the decl, the body only matters to this function
+                    fa.analyse_fn_decl()?;
+                    if let Err(err) = fa.analyse_fn_def() {
+                        self.push_error(err);
+                    }
+                }
+            }
+        }
+
         let mut sa = DrawShaderAnalyser {
             file: &self.shader_file,
             shader_registry: self,
@@ -554,38 +1166,221 @@ impl Shader {
             .add_geometry(id, ty, TokenSpan::default());
     }
 
+    /// A texture type declares a texture with the default sampler.
     pub fn add_uniform(&mut self, uniform_name: &str, ty: ShaderTy) {
         let id = LiveId::from_str(uniform_name).unwrap();
+        if ty.is_texture() {
+            self.draw_shader_def.add_texture(id, ty, TokenSpan::default());
+            return
+        }
         self.draw_shader_def
             .add_uniform(id, id_from_str!(pass).unwrap(), ty, TokenSpan::default());
     }
 
+    /// Describes the attributes, instances, uniforms, textures, varyings and outputs
+    /// of the shader. Only valid after `compile`.
+    pub fn reflection(&self) -> ShaderReflection {
+        ShaderReflection::from_draw_shader_def(&self.draw_shader_def, &self.enums, self.pixel_outputs(&self.draw_shader_def))
+    }
+
     pub fn generate_glsl(&self) -> (String, String) {
-        let const_table = DrawShaderConstTable::default();
+        self.generate_glsl_with_options(GlslOptions::default())
+    }
 
-        let vertex =
-            crate::generate_glsl::generate_vertex_shader(&self.draw_shader_def, &const_table, self);
-        let pixel =
-            crate::generate_glsl::generate_pixel_shader(&self.draw_shader_def, &const_table, self);
+    /// Generates the vertex and pixel shader for a specific GLSL dialect.
+    /// `GlslTarget::Glsl450` gets its bindings from `GlslDefaultBindingPolicy`.
+    pub fn generate_glsl_with_options(&self, options: GlslOptions) -> (String, String) {
+        let (vertex, pixel, _) = self.generate_glsl_with_bindings(options, &mut GlslDefaultBindingPolicy::default());
+        (vertex, pixel)
+    }
+
+    /// Fails on what `target` can't express, the generated GLSL doesn't compile then.
+    /// Only GLSL ES 1.00 lacks any of the textures and texture builtins.
+    pub fn check_glsl_target(&self, target: GlslTarget) -> Result<(), LiveFileError> {
+        crate::generate_glsl::check_target(&self.draw_shader_def, self, target).map_err( | err | self.live_error_to_file_error(err))
+    }
 
+    /// Like `generate_glsl_with_options`, with the descriptor set and binding of every uniform block
+    /// and texture on `GlslTarget::Glsl450` picked by `policy`. Returns the bindings along with the shaders,
+    /// they are empty for the other targets.
+    pub fn generate_glsl_with_bindings(
+        &self,
+        options: GlslOptions,
+        policy: &mut dyn GlslBindingPolicy,
+    ) -> (String, String, Vec<GlslBinding>) {
+        let ((vertex, _), (pixel, _), bindings) = self.generate_glsl_with_source_map_and_bindings(options, policy);
+        (vertex, pixel, bindings)
+    }
+
+    /// Like `generate_glsl_with_options`, along with the source maps of the vertex and the pixel shader.
+    pub fn generate_glsl_with_source_map(&self, options: GlslOptions) -> ((String, ShaderSourceMap), (String, ShaderSourceMap)) {
+        let (vertex, pixel, _) = self.generate_glsl_with_source_map_and_bindings(options, &mut GlslDefaultBindingPolicy::default());
         (vertex, pixel)
     }
 
+    fn generate_glsl_with_source_map_and_bindings(
+        &self,
+        options: GlslOptions,
+        policy: &mut dyn GlslBindingPolicy,
+    ) -> ((String, ShaderSourceMap), (String, ShaderSourceMap), Vec<GlslBinding>) {
+        let const_table = self.draw_shader_const_table();
+
+        let bindings = if options.target == GlslTarget::Glsl450 {
+            crate::generate_glsl::generate_bindings(&self.draw_shader_def, policy)
+        } else {
+            Vec::new()
+        };
+        let vertex = crate::generate_glsl::generate_vertex_shader(
+            &self.draw_shader_def,
+            &const_table,
+            self,
+            options,
+            &bindings,
+        );
+        let pixel = crate::generate_glsl::generate_pixel_shader(
+            &self.draw_shader_def,
+            &const_table,
+            self,
+            options,
+            &bindings,
+        );
+
+        let line_directives = options.target.line_directives();
+        (
+            resolve_marks(&vertex, self, options.line_markers, line_directives),
+            resolve_marks(&pixel, self, options.line_markers, line_directives),
+            bindings
+        )
+    }
+
     pub fn generate_metal(&self) -> String {
-        let const_table = DrawShaderConstTable::default();
+        self.generate_metal_with_source_map(ShaderLineMarkers::None).0
+    }
+
+    /// Like `generate_metal`, with `#line` directives or comments pointing back at the `.shader` file
+    /// and the source map of the generated lines.
+    pub fn generate_metal_with_source_map(&self, line_markers: ShaderLineMarkers) -> (String, ShaderSourceMap) {
+        let const_table = self.draw_shader_const_table();
 
         let shader =
             crate::generate_metal::generate_shader(&self.draw_shader_def, &const_table, self);
 
-        shader.mtlsl
+        resolve_marks(&shader.mtlsl, self, line_markers, LineDirectives::CStyle)
+    }
+
+    /// Fails on the stage builtins HLSL can't express, the generated HLSL ignores them then.
+    pub fn check_hlsl(&self) -> Result<(), LiveFileError> {
+        crate::generate_hlsl::check_shader(self).map_err( | err | self.live_error_to_file_error(err))
     }
 
     pub fn generate_hlsl(&self) -> String {
-        let const_table = DrawShaderConstTable::default();
+        self.generate_hlsl_with_source_map(ShaderLineMarkers::None).0
+    }
+
+    /// Like `generate_hlsl`, with `#line` directives or comments pointing back at the `.shader` file
+    /// and the source map of the generated lines.
+    pub fn generate_hlsl_with_source_map(&self, line_markers: ShaderLineMarkers) -> (String, ShaderSourceMap) {
+        let const_table = self.draw_shader_const_table();
 
         let shader =
             crate::generate_hlsl::generate_shader(&self.draw_shader_def, &const_table, self);
 
-        shader
+        resolve_marks(&shader, self, line_markers, LineDirectives::CStyle)
+    }
+
+    /// Fails on the stage builtins WGSL can't express, the generated WGSL ignores them then.
+    pub fn check_wgsl(&self) -> Result<(), LiveFileError> {
+        crate::generate_wgsl::check_shader(self).map_err( | err | self.live_error_to_file_error(err))
+    }
+
+    /// Generates a WGSL module with a `vertex_main` and a `fragment_main` entry point.
+    pub fn generate_wgsl(&self) -> String {
+        self.generate_wgsl_with_source_map(ShaderLineMarkers::None).0
+    }
+
+    /// Like `generate_wgsl`, with comments pointing back at the `.shader` file and the source map
+    /// of the generated lines. WGSL has no `#line`, `ShaderLineMarkers::Directives` gives comments too.
+    pub fn generate_wgsl_with_source_map(&self, line_markers: ShaderLineMarkers) -> (String, ShaderSourceMap) {
+        let const_table = self.draw_shader_const_table();
+
+        let shader = crate::generate_wgsl::generate_shader(&self.draw_shader_def, &const_table, self);
+
+        resolve_marks(&shader, self, line_markers, LineDirectives::None)
+    }
+
+    /// Generates a SPIR-V module with a `vertex_main` and a `fragment_main` entry point,
+    /// with its bindings from `GlslDefaultBindingPolicy`. `disassemble_spirv` turns it into text.
+    pub fn generate_spirv(&self) -> Vec<u32> {
+        let (words, _) = self.generate_spirv_with_bindings(&mut GlslDefaultBindingPolicy::default());
+        words
+    }
+
+    /// Like `generate_spirv`, with the descriptor set and binding of every uniform block and texture
+    /// picked by `policy`, the same way as for `GlslTarget::Glsl450`.
+    pub fn generate_spirv_with_bindings(&self, policy: &mut dyn GlslBindingPolicy) -> (Vec<u32>, Vec<GlslBinding>) {
+        let bindings = crate::generate_glsl::generate_bindings(&self.draw_shader_def, policy);
+        let words = crate::generate_spirv::generate_shader(&self.draw_shader_def, self, &bindings);
+        (words, bindings)
+    }
+
+    /// Generates a Rust module on top of `makepad_math`, with a `vertex` and a `pixel` fn taking
+    /// the fields as plain structs. Fails on the types `makepad_math` has no counterpart for.
+    pub fn generate_rust(&self) -> Result<String, LiveFileError> {
+        crate::generate_rust::generate_shader(&self.draw_shader_def, self).map_err( | err | self.live_error_to_file_error(err))
+    }
+
+    /// Runs `vertex` on the CPU for a single vertex, with the geometry, instance and uniform fields
+    /// from `inputs`. Returns `gl_Position` and the varyings.
+    pub fn interpret_vertex(&self, inputs: &ShaderInputs) -> Result<ShaderOutput, LiveFileError> {
+        crate::interpret::interpret(self, inputs, Stage::Vertex).map_err( | err | self.live_error_to_file_error(err))
+    }
+
+    /// Runs `pixel` on the CPU for a single pixel. The varyings come from `inputs`, usually through
+    /// `ShaderInputs::set_varyings`. Returns the colour.
+    pub fn interpret_pixel(&self, inputs: &ShaderInputs) -> Result<ShaderOutput, LiveFileError> {
+        crate::interpret::interpret(self, inputs, Stage::Pixel).map_err( | err | self.live_error_to_file_error(err))
+    }
+}
+
+// a `const` array holds numbers, colors or vectors of one size, numbers become floats
+fn const_array_items(nodes: &[LiveNode], index: usize) -> Result<(Ty, Vec<Vec<f32>>), LiveError> {
+    let mut elem_ty = None;
+    let mut items = Vec::new();
+    let mut node_iter = nodes.first_child(index);
+    while let Some(node_index) = node_iter {
+        let (ty, item) = match nodes[node_index].value {
+            LiveValue::Int(value) => (Ty::Float, vec![value as f32]),
+            LiveValue::Float(value) => (Ty::Float, vec![value as f32]),
+            LiveValue::Vec2(value) => (Ty::Vec2, vec![value.x, value.y]),
+            LiveValue::Vec3(value) => (Ty::Vec3, vec![value.x, value.y, value.z]),
+            LiveValue::Vec4(value) => (Ty::Vec4, vec![value.x, value.y, value.z, value.w]),
+            LiveValue::Color(color) => {
+                let value = makepad_math::Vec4::from_u32(color);
+                (Ty::Vec4, vec![value.x, value.y, value.z, value.w])
+            }
+            _ => return Err(LiveError {
+                origin: live_error_origin!(),
+                span: nodes[node_index].origin.token_id().unwrap().into(),
+                message: String::from("const array items must be numbers, colors or vectors"),
+            }),
+        };
+        let elem_ty = elem_ty.get_or_insert_with( || ty.clone());
+        if *elem_ty != ty {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: nodes[node_index].origin.token_id().unwrap().into(),
+                message: format!("const array items must all be of the same type, expected `{}`, got `{}`", elem_ty, ty),
+            });
+        }
+        items.push(item);
+        node_iter = nodes.next_child(node_index);
+    }
+    match elem_ty {
+        Some(elem_ty) => Ok((Ty::Array {elem_ty: std::rc::Rc::new(elem_ty), len: items.len()}, items)),
+        None => Err(LiveError {
+            origin: live_error_origin!(),
+            span: nodes[index].origin.token_id().unwrap().into(),
+            message: String::from("const arrays can't be empty"),
+        }),
     }
 }
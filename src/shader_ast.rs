@@ -29,10 +29,10 @@ impl DerefMut for StructPtr {fn deref_mut(&mut self) -> &mut Self::Target {&mut
 
 pub type DrawShaderPtr = LivePtr;
 
-//#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
-//pub struct ConstPtr(pub LivePtr);
-//impl Deref for ConstPtr{type Target = LivePtr;fn deref(&self) -> &Self::Target {&self.0}}
-//impl DerefMut for ConstPtr{fn deref_mut(&mut self) -> &mut Self::Target {&mut self.0}}
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct ConstPtr(pub LivePtr);
+impl Deref for ConstPtr {type Target = LivePtr; fn deref(&self) -> &Self::Target {&self.0}}
+impl DerefMut for ConstPtr {fn deref_mut(&mut self) -> &mut Self::Target {&mut self.0}}
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub struct ValuePtr(pub LivePtr);
@@ -79,7 +79,7 @@ pub struct DrawShaderDef {
     pub methods: Vec<FnPtr>,
     pub enums: Vec<LiveType>,
     // analysis results:
-    //pub all_const_refs: RefCell<BTreeSet<ConstPtr>>,
+    pub all_const_refs: RefCell<BTreeMap<ConstPtr, Ty >>,
     pub all_live_refs: RefCell<BTreeMap<ValuePtr, Ty >>,
     pub all_fns: RefCell<Vec<FnPtr >>,
     pub vertex_fns: RefCell<Vec<FnPtr >>,
@@ -123,6 +123,7 @@ pub enum DrawShaderFieldKind {
     },
     Texture {
         var_def_ptr: Option<VarDefPtr>,
+        sampler: ShaderSampler,
         //input_type: DrawShaderInputType,
     },
     Uniform {
@@ -178,6 +179,8 @@ pub enum HiddenArgKind {
     Textures,
     Uniform(Ident),
     LiveUniforms,
+    // the stage builtins, for backends that can't read them from anywhere
+    Builtins,
 }
 
 #[derive(Clone, Debug)]
@@ -188,9 +191,12 @@ pub struct FnDef {
     
     pub self_kind: Option<FnSelfKind>,
     pub has_return: Cell<bool>,
+    // the first `discard`, for the stage check
+    pub discard_span: Cell<Option<TokenSpan>>,
     
     pub callees: RefCell<Option<BTreeSet<FnPtr >> >,
-    pub builtin_deps: RefCell<Option<BTreeSet<Ident >> >,
+    // with the first call, for the GLSL ES 1.00 check
+    pub builtin_deps: RefCell<Option<BTreeMap<Ident, TokenSpan >> >,
     // pub closure_deps: RefCell<Option<BTreeSet<Ident >> >,
     
     // the const table (per function)
@@ -199,7 +205,9 @@ pub struct FnDef {
     
     pub hidden_args: RefCell<Option<BTreeSet<HiddenArgKind >> >,
     pub draw_shader_refs: RefCell<Option<BTreeSet<Ident >> >,
-    //pub const_refs: RefCell<Option<BTreeSet<ConstPtr >> >,
+    // with the first use, for the stage check
+    pub stage_builtins: RefCell<Option<BTreeMap<StageBuiltin, TokenSpan >> >,
+    pub const_refs: RefCell<Option<BTreeMap<ConstPtr, Ty >> >,
     pub live_refs: RefCell<Option<BTreeMap<ValuePtr, Ty >> >,
     
     pub struct_refs: RefCell<Option<BTreeSet<StructPtr >> >,
@@ -293,22 +301,90 @@ pub struct Block {
     pub stmts: Vec<Stmt>,
 }
 
+/// The `break 'label` and `continue 'label` that leave a loop nested in the loop named `label`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LabelledJumps {
+    pub breaks: bool,
+    pub continues: bool,
+}
+
+impl Block {
+    /// The jumps to `label` out of the loops nested in this block, the backends without
+    /// labelled jumps carry those out of the inner loops with flags.
+    pub fn jumps_out_of_inner_loops(&self, label: Ident) -> LabelledJumps {
+        let mut jumps = LabelledJumps::default();
+        self.find_labelled_jumps(label, false, &mut jumps);
+        jumps
+    }
+    
+    /// The jumps to `label` anywhere in this block.
+    pub fn jumps_to(&self, label: Ident) -> LabelledJumps {
+        let mut jumps = LabelledJumps::default();
+        self.find_labelled_jumps(label, true, &mut jumps);
+        jumps
+    }
+    
+    fn find_labelled_jumps(&self, label: Ident, is_nested: bool, jumps: &mut LabelledJumps) {
+        for stmt in &self.stmts {
+            match stmt {
+                Stmt::Break {label: Some(break_label), ..} if *break_label == label && is_nested => jumps.breaks = true,
+                Stmt::Continue {label: Some(continue_label), ..} if *continue_label == label && is_nested => jumps.continues = true,
+                // an inner loop with the same label takes the jumps to it
+                Stmt::For {label: loop_label, block, ..}
+                | Stmt::While {label: loop_label, block, ..}
+                | Stmt::Loop {label: loop_label, block, ..} if *loop_label != Some(label) => {
+                    block.find_labelled_jumps(label, true, jumps)
+                }
+                Stmt::If {block_if_true, block_if_false, ..} => {
+                    block_if_true.find_labelled_jumps(label, is_nested, jumps);
+                    if let Some(block_if_false) = block_if_false {
+                        block_if_false.find_labelled_jumps(label, is_nested, jumps);
+                    }
+                }
+                Stmt::Match {matches, ..} => for match_item in matches {
+                    match_item.block.find_labelled_jumps(label, is_nested, jumps);
+                }
+                Stmt::Block {block, ..} => block.find_labelled_jumps(label, is_nested, jumps),
+                _ => ()
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Stmt {
     Break {
         span: TokenSpan,
+        label: Option<Ident>,
     },
     Continue {
         span: TokenSpan,
+        label: Option<Ident>,
+    },
+    Discard {
+        span: TokenSpan,
     },
     For {
         span: TokenSpan,
+        label: Option<Ident>,
         ident: Ident,
+        shadow: Cell<Option<ScopeSymShadow >>,
         from_expr: Expr,
         to_expr: Expr,
         step_expr: Option<Expr>,
         block: Box<Block>,
     },
+    While {
+        span: TokenSpan,
+        label: Option<Ident>,
+        expr: Expr,
+        block: Box<Block>,
+    },
+    Loop {
+        span: TokenSpan,
+        label: Option<Ident>,
+        block: Box<Block>,
+    },
     If {
         span: TokenSpan,
         expr: Expr,
@@ -343,6 +419,25 @@ pub enum Stmt {
     },
 }
 
+impl Stmt {
+    pub fn span(&self) -> TokenSpan {
+        match self {
+            Stmt::Break {span, ..}
+            | Stmt::Continue {span, ..}
+            | Stmt::Discard {span}
+            | Stmt::For {span, ..}
+            | Stmt::While {span, ..}
+            | Stmt::Loop {span, ..}
+            | Stmt::If {span, ..}
+            | Stmt::Match {span, ..}
+            | Stmt::Let {span, ..}
+            | Stmt::Return {span, ..}
+            | Stmt::Block {span, ..}
+            | Stmt::Expr {span, ..} => *span,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Match {
     pub span: TokenSpan,
@@ -421,6 +516,11 @@ pub enum ExprKind {
         span: TokenSpan,
         args: Vec<(Ident, Expr)>
     },
+    // `[a, b, c]`, only as the initializer of a `let`
+    ArrayCons {
+        span: TokenSpan,
+        elem_exprs: Vec<Expr>,
+    },
     Var {
         span: TokenSpan,
         ident: Option<Ident>,
@@ -434,6 +534,129 @@ pub enum ExprKind {
     },
 }
 
+impl Expr {
+    /// The components of an `ivec2(1i, -1i)` or `ivec3(..)` of int literals, texel offsets have to be
+    /// constant on every backend.
+    pub fn const_offset(&self) -> Option<Vec<i32>> {
+        let arg_exprs = match &self.kind {
+            ExprKind::ConsCall {ty_lit: TyLit::Ivec2, arg_exprs, ..} | ExprKind::ConsCall {ty_lit: TyLit::Ivec3, arg_exprs, ..} => arg_exprs,
+            _ => return None
+        };
+        let offset: Vec<i32> = arg_exprs.iter().filter_map( | arg_expr | match &arg_expr.kind {
+            ExprKind::Lit {lit: Lit::Int(value), ..} => Some(*value),
+            ExprKind::Un {op: UnOp::Neg, expr, ..} => match expr.kind {
+                ExprKind::Lit {lit: Lit::Int(value), ..} => Some(-value),
+                _ => None
+            },
+            _ => None
+        }).collect();
+        if offset.len() != arg_exprs.len() {
+            return None
+        }
+        Some(offset)
+    }
+}
+
+/// What a texture builtin other than `sample2d` and `sample2d_rt` does.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureOp {
+    Sample,
+    Lod,
+    Grad,
+    Offset,
+    Compare,
+    CompareOffset,
+    Size,
+    Fetch,
+}
+
+impl TextureOp {
+    pub fn from_builtin(ident: Ident) -> Option<TextureOp> {
+        Some(match ident.0 {
+            id!(sample_cube) | id!(sample3d) | id!(sample2d_array) => TextureOp::Sample,
+            id!(sample2d_lod) | id!(sample_cube_lod) | id!(sample3d_lod) | id!(sample2d_array_lod) => TextureOp::Lod,
+            id!(sample2d_grad) | id!(sample_cube_grad) | id!(sample3d_grad) | id!(sample2d_array_grad) => TextureOp::Grad,
+            id!(sample2d_offset) | id!(sample3d_offset) | id!(sample2d_array_offset) => TextureOp::Offset,
+            id!(sample2d_compare) => TextureOp::Compare,
+            id!(sample2d_compare_offset) => TextureOp::CompareOffset,
+            id!(texture_size) => TextureOp::Size,
+            id!(texel_fetch) => TextureOp::Fetch,
+            _ => return None
+        })
+    }
+    
+    /// Whether the builtin picks its mip level from the derivatives of its uv, which
+    /// only the pixel stage has.
+    pub fn has_implicit_lod(ident: Ident) -> bool {
+        match ident.0 {
+            id!(sample2d) | id!(sample2d_rt) => true,
+            _ => matches!(
+                TextureOp::from_builtin(ident),
+                Some(TextureOp::Sample) | Some(TextureOp::Offset) | Some(TextureOp::Compare) | Some(TextureOp::CompareOffset)
+            )
+        }
+    }
+}
+
+/// A value of the pipeline, read or written as `self.<name>` in one stage
+/// when no field of the same name shadows it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub enum StageBuiltin {
+    VertexId,
+    InstanceId,
+    PointSize,
+    FragCoord,
+    FrontFacing,
+    PointCoord,
+    FragDepth,
+}
+
+impl StageBuiltin {
+    pub fn from_ident(ident: Ident) -> Option<StageBuiltin> {
+        Some(match ident.0 {
+            id!(vertex_id) => StageBuiltin::VertexId,
+            id!(instance_id) => StageBuiltin::InstanceId,
+            id!(point_size) => StageBuiltin::PointSize,
+            id!(frag_coord) => StageBuiltin::FragCoord,
+            id!(front_facing) => StageBuiltin::FrontFacing,
+            id!(point_coord) => StageBuiltin::PointCoord,
+            id!(frag_depth) => StageBuiltin::FragDepth,
+            _ => return None
+        })
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            StageBuiltin::VertexId => "vertex_id",
+            StageBuiltin::InstanceId => "instance_id",
+            StageBuiltin::PointSize => "point_size",
+            StageBuiltin::FragCoord => "frag_coord",
+            StageBuiltin::FrontFacing => "front_facing",
+            StageBuiltin::PointCoord => "point_coord",
+            StageBuiltin::FragDepth => "frag_depth",
+        }
+    }
+    
+    pub fn ty(self) -> Ty {
+        match self {
+            StageBuiltin::VertexId | StageBuiltin::InstanceId => Ty::Int,
+            StageBuiltin::PointSize | StageBuiltin::FragDepth => Ty::Float,
+            StageBuiltin::FragCoord => Ty::Vec4,
+            StageBuiltin::FrontFacing => Ty::Bool,
+            StageBuiltin::PointCoord => Ty::Vec2,
+        }
+    }
+    
+    pub fn is_pixel(self) -> bool {
+        matches!(self, StageBuiltin::FragCoord | StageBuiltin::FrontFacing | StageBuiltin::PointCoord | StageBuiltin::FragDepth)
+    }
+    
+    /// written by the shader, starting out as `1.0` for `point_size` and `frag_coord.z` for `frag_depth`
+    pub fn is_output(self) -> bool {
+        matches!(self, StageBuiltin::PointSize | StageBuiltin::FragDepth)
+    }
+}
+
 pub enum PlainCallType {
     Plain {
         
@@ -444,14 +667,16 @@ pub enum PlainCallType {
 pub enum VarResolve {
     NotFound,
     Function(FnPtr),
-    LiveValue(ValuePtr, TyLit)
+    LiveValue(ValuePtr, TyLit),
+    Const(ConstPtr)
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum VarKind {
     Local {ident: Ident, shadow: ScopeSymShadow},
     MutLocal {ident: Ident, shadow: ScopeSymShadow},
-    LiveValue(ValuePtr)
+    LiveValue(ValuePtr),
+    Const(ConstPtr)
 }
 
 #[derive(Clone, Debug)]
@@ -468,7 +693,7 @@ pub enum TyExprKind {
         len: u32,
     },
     Struct(StructPtr),
-    Enum(LiveType),
+    Enum(LiveId),
     DrawShader,
     Lit {
         ty_lit: TyLit,
@@ -492,6 +717,12 @@ pub enum BinOp {
     SubAssign,
     MulAssign,
     DivAssign,
+    RemAssign,
+    BitAndAssign,
+    BitOrAssign,
+    BitXorAssign,
+    ShlAssign,
+    ShrAssign,
     Or,
     And,
     Eq,
@@ -504,6 +735,13 @@ pub enum BinOp {
     Sub,
     Mul,
     Div,
+    /// `%`, integers only, it truncates like the C operator
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
 
@@ -526,6 +764,10 @@ pub enum ShaderTy {
     Ivec2,
     Ivec3,
     Ivec4,
+    Uint,
+    Uvec2,
+    Uvec3,
+    Uvec4,
     Vec2,
     Vec3,
     Vec4,
@@ -533,9 +775,15 @@ pub enum ShaderTy {
     Mat3,
     Mat4,
     Texture2D,
+    TextureCube,
+    Texture3D,
+    Texture2DArray,
+    /// a depth texture, sampled with a comparison sampler
+    Texture2DDepth,
     Array {elem_ty: Rc<ShaderTy>, len: usize},
     Struct(StructPtr),
-    Enum(LiveType),
+    /// a user-declared `Name: Enum {..}`, by its name
+    Enum(LiveId),
     DrawShader,
     ClosureDef(ClosureDefIndex),
     ClosureDecl
@@ -543,6 +791,83 @@ pub enum ShaderTy {
 
 pub type Ty = ShaderTy;
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SamplerFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SamplerWrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SamplerMip {
+    None,
+    Nearest,
+    Linear,
+}
+
+/// Sampler state of a texture field, `texture tex: texture2d {filter: linear, wrap: repeat, mip: linear}`.
+/// Attributes that are left out default to `nearest`, `clamp` and `none`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ShaderSampler {
+    pub filter: SamplerFilter,
+    pub wrap: SamplerWrap,
+    pub mip: SamplerMip,
+}
+
+impl Default for ShaderSampler {
+    fn default() -> Self {
+        ShaderSampler {
+            filter: SamplerFilter::Nearest,
+            wrap: SamplerWrap::Clamp,
+            mip: SamplerMip::None,
+        }
+    }
+}
+
+impl ShaderSampler {
+    // reads the attributes of a `texture2d {..}` node
+    pub fn from_live_node(nodes: &[LiveNode], index: usize) -> Result<Self, LiveError> {
+        let mut sampler = ShaderSampler::default();
+        let mut node_iter = nodes.first_child(index);
+        while let Some(node_index) = node_iter {
+            let node = &nodes[node_index];
+            let value = if let LiveValue::Id(value) = node.value {value} else {LiveId(0)};
+            match (node.id, value) {
+                (id!(filter), id!(nearest)) => sampler.filter = SamplerFilter::Nearest,
+                (id!(filter), id!(linear)) => sampler.filter = SamplerFilter::Linear,
+                (id!(wrap), id!(repeat)) => sampler.wrap = SamplerWrap::Repeat,
+                (id!(wrap), id!(clamp)) => sampler.wrap = SamplerWrap::Clamp,
+                (id!(wrap), id!(mirror)) => sampler.wrap = SamplerWrap::Mirror,
+                (id!(mip), id!(none)) => sampler.mip = SamplerMip::None,
+                (id!(mip), id!(nearest)) => sampler.mip = SamplerMip::Nearest,
+                (id!(mip), id!(linear)) => sampler.mip = SamplerMip::Linear,
+                (id!(filter), _) | (id!(wrap), _) | (id!(mip), _) => {
+                    return Err(LiveError {
+                        origin: live_error_origin!(),
+                        message: format!("Invalid value {:?} for sampler attribute {}", node.value, node.id),
+                        span: node.origin.token_id().unwrap().into()
+                    })
+                }
+                _ => {
+                    return Err(LiveError {
+                        origin: live_error_origin!(),
+                        message: format!("Unknown sampler attribute {}, expected filter, wrap or mip", node.id),
+                        span: node.origin.token_id().unwrap().into()
+                    })
+                }
+            }
+            node_iter = nodes.next_child(node_index);
+        }
+        Ok(sampler)
+    }
+}
+
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub enum TyLit {
@@ -555,6 +880,10 @@ pub enum TyLit {
     Ivec2,
     Ivec3,
     Ivec4,
+    Uint,
+    Uvec2,
+    Uvec3,
+    Uvec4,
     Vec2,
     Vec3,
     Vec4,
@@ -562,12 +891,17 @@ pub enum TyLit {
     Mat3,
     Mat4,
     Texture2D,
+    TextureCube,
+    Texture3D,
+    Texture2DArray,
+    Texture2DDepth,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Lit {
     Bool(bool),
     Int(i32),
+    Uint(u32),
     Float(f32),
     Color(u32),
 }
@@ -576,6 +910,7 @@ pub enum Lit {
 pub enum Val {
     Bool(bool),
     Int(i32),
+    Uint(u32),
     Float(f32),
     Vec4(Vec4),
 }
@@ -730,11 +1065,14 @@ impl FnDef {
             block,
             closure_defs,
             has_return: Cell::new(false),
+            discard_span: Cell::new(None),
             hidden_args: RefCell::new(None),
             closure_sites: RefCell::new(None),
             live_refs: RefCell::new(None),
+            const_refs: RefCell::new(None),
             struct_refs: RefCell::new(None),
             draw_shader_refs: RefCell::new(None),
+            stage_builtins: RefCell::new(None),
             return_ty: RefCell::new(None),
             callees: RefCell::new(None),
             builtin_deps: RefCell::new(None),
@@ -747,11 +1085,13 @@ impl FnDef {
     pub fn init_analysis(&self) {
         *self.struct_refs.borrow_mut() = Some(BTreeSet::new());
         *self.callees.borrow_mut() = Some(BTreeSet::new());
-        *self.builtin_deps.borrow_mut() = Some(BTreeSet::new());
+        *self.builtin_deps.borrow_mut() = Some(BTreeMap::new());
         //*self.closure_deps.borrow_mut() = Some(BTreeSet::new());
         *self.constructor_fn_deps.borrow_mut() = Some(BTreeSet::new());
         *self.draw_shader_refs.borrow_mut() = Some(BTreeSet::new());
+        *self.stage_builtins.borrow_mut() = Some(BTreeMap::new());
         *self.live_refs.borrow_mut() = Some(BTreeMap::new());
+        *self.const_refs.borrow_mut() = Some(BTreeMap::new());
         *self.const_table.borrow_mut() = Some(Vec::new());
         *self.const_table_spans.borrow_mut() = Some(Vec::new());
     }
@@ -764,6 +1104,11 @@ impl FnDef {
         }
         return false
     }
+    
+    // draw shader methods are called without their `self`
+    pub fn params_for_args(&self, arg_count: usize) -> &[Param] {
+        &self.params[self.params.len() - arg_count..]
+    }
 }
 
 impl DrawShaderDef {
@@ -774,6 +1119,14 @@ impl DrawShaderDef {
         })
     }
     
+    /// `self.<ident>` as a stage builtin, fields shadow them.
+    pub fn find_stage_builtin(&self, ident: Ident) -> Option<StageBuiltin> {
+        if self.find_field(ident).is_some() {
+            return None
+        }
+        StageBuiltin::from_ident(ident)
+    }
+    
     pub fn fields_as_uniform_blocks(&self) -> BTreeMap<Ident, Vec<(usize, Ident) >> {
         let mut uniform_blocks = BTreeMap::new();
         for (field_index, field) in self.fields.iter().enumerate() {
@@ -841,7 +1194,8 @@ impl DrawShaderDef {
         self.fields.push(
             DrawShaderFieldDef {
                 kind: DrawShaderFieldKind::Texture {
-                    var_def_ptr: None
+                    var_def_ptr: None,
+                    sampler: ShaderSampler::default(),
                 },
                 span,
                 ident: Ident(id),
@@ -859,6 +1213,12 @@ impl BinOp {
             LiveToken::Punct(id!( -=)) => Some(BinOp::SubAssign),
             LiveToken::Punct(id!( *=)) => Some(BinOp::MulAssign),
             LiveToken::Punct(id!( /=)) => Some(BinOp::DivAssign),
+            LiveToken::Punct(id!( %=)) => Some(BinOp::RemAssign),
+            LiveToken::Punct(id!( &=)) => Some(BinOp::BitAndAssign),
+            LiveToken::Punct(id!( |=)) => Some(BinOp::BitOrAssign),
+            LiveToken::Punct(id!( ^=)) => Some(BinOp::BitXorAssign),
+            LiveToken::Punct(id!( <<=)) => Some(BinOp::ShlAssign),
+            LiveToken::Punct(id!( >>=)) => Some(BinOp::ShrAssign),
             _ => None,
         }
     }
@@ -895,6 +1255,35 @@ impl BinOp {
         }
     }
     
+    pub fn from_bit_or_op(token: LiveToken) -> Option<BinOp> {
+        match token {
+            LiveToken::Punct(id!( |)) => Some(BinOp::BitOr),
+            _ => None,
+        }
+    }
+    
+    pub fn from_bit_xor_op(token: LiveToken) -> Option<BinOp> {
+        match token {
+            LiveToken::Punct(id!( ^)) => Some(BinOp::BitXor),
+            _ => None,
+        }
+    }
+    
+    pub fn from_bit_and_op(token: LiveToken) -> Option<BinOp> {
+        match token {
+            LiveToken::Punct(id!( &)) => Some(BinOp::BitAnd),
+            _ => None,
+        }
+    }
+    
+    pub fn from_shift_op(token: LiveToken) -> Option<BinOp> {
+        match token {
+            LiveToken::Punct(id!( <<)) => Some(BinOp::Shl),
+            LiveToken::Punct(id!( >>)) => Some(BinOp::Shr),
+            _ => None,
+        }
+    }
+    
     pub fn from_add_op(token: LiveToken) -> Option<BinOp> {
         match token {
             LiveToken::Punct(id!( +)) => Some(BinOp::Add),
@@ -907,9 +1296,36 @@ impl BinOp {
         match token {
             LiveToken::Punct(id!(*)) => Some(BinOp::Mul),
             LiveToken::Punct(id!( /)) => Some(BinOp::Div),
+            LiveToken::Punct(id!( %)) => Some(BinOp::Rem),
             _ => None,
         }
     }
+    
+    pub fn is_assign(&self) -> bool {
+        match self {
+            BinOp::Assign | BinOp::AddAssign | BinOp::SubAssign | BinOp::MulAssign | BinOp::DivAssign
+                | BinOp::RemAssign | BinOp::BitAndAssign | BinOp::BitOrAssign | BinOp::BitXorAssign
+                | BinOp::ShlAssign | BinOp::ShrAssign => true,
+            _ => false
+        }
+    }
+    
+    /// The op a compound assignment applies, `Add` for `+=`.
+    pub fn assign_op(&self) -> Option<BinOp> {
+        match self {
+            BinOp::AddAssign => Some(BinOp::Add),
+            BinOp::SubAssign => Some(BinOp::Sub),
+            BinOp::MulAssign => Some(BinOp::Mul),
+            BinOp::DivAssign => Some(BinOp::Div),
+            BinOp::RemAssign => Some(BinOp::Rem),
+            BinOp::BitAndAssign => Some(BinOp::BitAnd),
+            BinOp::BitOrAssign => Some(BinOp::BitOr),
+            BinOp::BitXorAssign => Some(BinOp::BitXor),
+            BinOp::ShlAssign => Some(BinOp::Shl),
+            BinOp::ShrAssign => Some(BinOp::Shr),
+            _ => None
+        }
+    }
 }
 
 impl fmt::Display for BinOp {
@@ -923,6 +1339,12 @@ impl fmt::Display for BinOp {
                 BinOp::SubAssign => "-=",
                 BinOp::MulAssign => "*=",
                 BinOp::DivAssign => "/=",
+                BinOp::RemAssign => "%=",
+                BinOp::BitAndAssign => "&=",
+                BinOp::BitOrAssign => "|=",
+                BinOp::BitXorAssign => "^=",
+                BinOp::ShlAssign => "<<=",
+                BinOp::ShrAssign => ">>=",
                 BinOp::Or => "||",
                 BinOp::And => "&&",
                 BinOp::Eq => "==",
@@ -935,6 +1357,12 @@ impl fmt::Display for BinOp {
                 BinOp::Sub => "-",
                 BinOp::Mul => "*",
                 BinOp::Div => "/",
+                BinOp::Rem => "%",
+                BinOp::BitAnd => "&",
+                BinOp::BitOr => "|",
+                BinOp::BitXor => "^",
+                BinOp::Shl => "<<",
+                BinOp::Shr => ">>",
             }
         )
     }
@@ -978,13 +1406,21 @@ impl Ty {
             Ty::Ivec2 => Some(TyLit::Ivec2),
             Ty::Ivec3 => Some(TyLit::Ivec3),
             Ty::Ivec4 => Some(TyLit::Ivec4),
+            Ty::Uint => Some(TyLit::Uint),
+            Ty::Uvec2 => Some(TyLit::Uvec2),
+            Ty::Uvec3 => Some(TyLit::Uvec3),
+            Ty::Uvec4 => Some(TyLit::Uvec4),
             Ty::Vec2 => Some(TyLit::Vec2),
             Ty::Vec3 => Some(TyLit::Vec3),
             Ty::Vec4 => Some(TyLit::Vec4),
             Ty::Mat2 => Some(TyLit::Mat2),
             Ty::Mat3 => Some(TyLit::Mat3),
             Ty::Mat4 => Some(TyLit::Mat4),
-            Ty::Texture2D => Some(TyLit::Bool),
+            Ty::Texture2D => Some(TyLit::Texture2D),
+            Ty::TextureCube => Some(TyLit::TextureCube),
+            Ty::Texture3D => Some(TyLit::Texture3D),
+            Ty::Texture2DArray => Some(TyLit::Texture2DArray),
+            Ty::Texture2DDepth => Some(TyLit::Texture2DDepth),
             Ty::Array {..} => None,
             Ty::Struct(_) => None,
             Ty::Enum(_) => None,
@@ -996,7 +1432,7 @@ impl Ty {
     
     pub fn is_scalar(&self) -> bool {
         match self {
-            Ty::Bool | Ty::Int | Ty::Float => true,
+            Ty::Bool | Ty::Int | Ty::Uint | Ty::Float => true,
             _ => false,
         }
     }
@@ -1009,6 +1445,9 @@ impl Ty {
                 | Ty::Ivec2
                 | Ty::Ivec3
                 | Ty::Ivec4
+                | Ty::Uvec2
+                | Ty::Uvec3
+                | Ty::Uvec4
                 | Ty::Vec2
                 | Ty::Vec3
                 | Ty::Vec4 => true,
@@ -1023,16 +1462,31 @@ impl Ty {
         }
     }
     
+    /// `int`, `uint` and their vectors, the types the bitwise and `%` operators work on.
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Ty::Int | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 | Ty::Uint | Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => true,
+            _ => false,
+        }
+    }
+    
+    pub fn is_texture(&self) -> bool {
+        match self {
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => true,
+            _ => false,
+        }
+    }
+    
     pub fn slots(&self) -> usize {
         match self {
             Ty::Void => 0,
-            Ty::Bool | Ty::Int | Ty::Float => 1,
-            Ty::Bvec2 | Ty::Ivec2 | Ty::Vec2 => 2,
-            Ty::Bvec3 | Ty::Ivec3 | Ty::Vec3 => 3,
-            Ty::Bvec4 | Ty::Ivec4 | Ty::Vec4 | Ty::Mat2 => 4,
+            Ty::Bool | Ty::Int | Ty::Uint | Ty::Float => 1,
+            Ty::Bvec2 | Ty::Ivec2 | Ty::Uvec2 | Ty::Vec2 => 2,
+            Ty::Bvec3 | Ty::Ivec3 | Ty::Uvec3 | Ty::Vec3 => 3,
+            Ty::Bvec4 | Ty::Ivec4 | Ty::Uvec4 | Ty::Vec4 | Ty::Mat2 => 4,
             Ty::Mat3 => 9,
             Ty::Mat4 => 16,
-            Ty::Texture2D {..} => panic!(),
+            Ty::Texture2D | Ty::TextureCube | Ty::Texture3D | Ty::Texture2DArray | Ty::Texture2DDepth => panic!(),
             Ty::Array {elem_ty, len} => elem_ty.slots() * len,
             Ty::Enum(_) => 1,
             Ty::Struct(_) => panic!(),
@@ -1042,6 +1496,25 @@ impl Ty {
         }
     }
     
+    /// Size and base alignment in bytes inside a `layout(std140)` uniform block.
+    pub fn std140_size_align(&self) -> (usize, usize) {
+        match self {
+            Ty::Bool | Ty::Int | Ty::Uint | Ty::Float | Ty::Enum(_) => (4, 4),
+            Ty::Bvec2 | Ty::Ivec2 | Ty::Uvec2 | Ty::Vec2 => (8, 8),
+            Ty::Bvec3 | Ty::Ivec3 | Ty::Uvec3 | Ty::Vec3 => (12, 16),
+            Ty::Bvec4 | Ty::Ivec4 | Ty::Uvec4 | Ty::Vec4 => (16, 16),
+            // matrices are arrays of column vectors, padded to a vec4 each
+            Ty::Mat2 => (32, 16),
+            Ty::Mat3 => (48, 16),
+            Ty::Mat4 => (64, 16),
+            Ty::Array {elem_ty, len} => {
+                let stride = (elem_ty.std140_size_align().0 + 15) & !15;
+                (stride * len, 16)
+            }
+            _ => panic!(),
+        }
+    }
+    
     pub fn to_ty_expr(&self) -> TyExpr {
         TyExpr {
             ty: RefCell::new(None),
@@ -1057,6 +1530,10 @@ impl Ty {
                 Ty::Ivec2 => TyExprKind::Lit {ty_lit: TyLit::Ivec2},
                 Ty::Ivec3 => TyExprKind::Lit {ty_lit: TyLit::Ivec3},
                 Ty::Ivec4 => TyExprKind::Lit {ty_lit: TyLit::Ivec4},
+                Ty::Uint => TyExprKind::Lit {ty_lit: TyLit::Uint},
+                Ty::Uvec2 => TyExprKind::Lit {ty_lit: TyLit::Uvec2},
+                Ty::Uvec3 => TyExprKind::Lit {ty_lit: TyLit::Uvec3},
+                Ty::Uvec4 => TyExprKind::Lit {ty_lit: TyLit::Uvec4},
                 Ty::Vec2 => TyExprKind::Lit {ty_lit: TyLit::Vec2},
                 Ty::Vec3 => TyExprKind::Lit {ty_lit: TyLit::Vec3},
                 Ty::Vec4 => TyExprKind::Lit {ty_lit: TyLit::Vec4},
@@ -1064,6 +1541,10 @@ impl Ty {
                 Ty::Mat3 => TyExprKind::Lit {ty_lit: TyLit::Mat3},
                 Ty::Mat4 => TyExprKind::Lit {ty_lit: TyLit::Mat4},
                 Ty::Texture2D => TyExprKind::Lit {ty_lit: TyLit::Texture2D},
+                Ty::TextureCube => TyExprKind::Lit {ty_lit: TyLit::TextureCube},
+                Ty::Texture3D => TyExprKind::Lit {ty_lit: TyLit::Texture3D},
+                Ty::Texture2DArray => TyExprKind::Lit {ty_lit: TyLit::Texture2DArray},
+                Ty::Texture2DDepth => TyExprKind::Lit {ty_lit: TyLit::Texture2DDepth},
                 Ty::Array {elem_ty, len} => {
                     TyExprKind::Array {
                         elem_ty_expr: Box::new(elem_ty.to_ty_expr()),
@@ -1076,8 +1557,8 @@ impl Ty {
                 Ty::DrawShader => {
                     TyExprKind::DrawShader
                 },
-                Ty::Enum(live_type) => {
-                    TyExprKind::Enum(*live_type)
+                Ty::Enum(enum_name) => {
+                    TyExprKind::Enum(*enum_name)
                 },
                 Ty::ClosureDef(_) => panic!(),
                 Ty::ClosureDecl => panic!()
@@ -1120,11 +1601,22 @@ impl Ty {
             LiveValue::Id(id) => match id {
                 id!(bool) => Self::Bool,
                 id!(int) => Self::Int,
+                id!(uint) => Self::Uint,
+                id!(uvec2) => Self::Uvec2,
+                id!(uvec3) => Self::Uvec3,
+                id!(uvec4) => Self::Uvec4,
                 id!(float) => Self::Float,
                 id!(vec2) => Self::Vec2,
                 id!(vec3) => Self::Vec3,
                 id!(vec4) => Self::Vec4,
+                id!(mat2) => Self::Mat2,
+                id!(mat3) => Self::Mat3,
+                id!(mat4) => Self::Mat4,
                 id!(texture2d) => Self::Texture2D,
+                id!(textureCube) => Self::TextureCube,
+                id!(texture3d) => Self::Texture3D,
+                id!(texture2d_array) => Self::Texture2DArray,
+                id!(texture2d_depth) => Self::Texture2DDepth,
                 _ => {
                     return Err(LiveError {
                         origin: live_error_origin!(),
@@ -1162,6 +1654,10 @@ impl fmt::Display for Ty {
             Ty::Ivec2 => write!(f, "ivec2"),
             Ty::Ivec3 => write!(f, "ivec3"),
             Ty::Ivec4 => write!(f, "ivec4"),
+            Ty::Uint => write!(f, "uint"),
+            Ty::Uvec2 => write!(f, "uvec2"),
+            Ty::Uvec3 => write!(f, "uvec3"),
+            Ty::Uvec4 => write!(f, "uvec4"),
             Ty::Vec2 => write!(f, "vec2"),
             Ty::Vec3 => write!(f, "vec3"),
             Ty::Vec4 => write!(f, "vec4"),
@@ -1169,10 +1665,14 @@ impl fmt::Display for Ty {
             Ty::Mat3 => write!(f, "mat3"),
             Ty::Mat4 => write!(f, "mat4"),
             Ty::Texture2D => write!(f, "texture2D"),
-            Ty::Array {elem_ty, len} => write!(f, "{}[{}]", elem_ty, len),
+            Ty::TextureCube => write!(f, "textureCube"),
+            Ty::Texture3D => write!(f, "texture3d"),
+            Ty::Texture2DArray => write!(f, "texture2d_array"),
+            Ty::Texture2DDepth => write!(f, "texture2d_depth"),
+            Ty::Array {elem_ty, len} => write!(f, "[{}; {}]", elem_ty, len),
             Ty::Struct(struct_ptr) => write!(f, "Struct:{:?}", struct_ptr),
             Ty::DrawShader => write!(f, "DrawShader"),
-            Ty::Enum(_) => write!(f, "Enum"),
+            Ty::Enum(enum_name) => write!(f, "{}", enum_name),
             Ty::ClosureDecl => write!(f, "ClosureDecl"),
             Ty::ClosureDef {..} => write!(f, "ClosureDef"),
         }
@@ -1194,10 +1694,18 @@ impl TyLit {
             id!(bvec2) => Some(TyLit::Bvec2),
             id!(bvec3) => Some(TyLit::Bvec3),
             id!(bvec4) => Some(TyLit::Bvec4),
-            id!(ivec2) => Some(TyLit::Ivec4),
-            id!(ivec3) => Some(TyLit::Ivec4),
+            id!(ivec2) => Some(TyLit::Ivec2),
+            id!(ivec3) => Some(TyLit::Ivec3),
             id!(ivec4) => Some(TyLit::Ivec4),
-            id!(texture2D) => Some(TyLit::Texture2D),
+            id!(uint) => Some(TyLit::Uint),
+            id!(uvec2) => Some(TyLit::Uvec2),
+            id!(uvec3) => Some(TyLit::Uvec3),
+            id!(uvec4) => Some(TyLit::Uvec4),
+            id!(texture2D) | id!(texture2d) => Some(TyLit::Texture2D),
+            id!(textureCube) => Some(TyLit::TextureCube),
+            id!(texture3d) => Some(TyLit::Texture3D),
+            id!(texture2d_array) => Some(TyLit::Texture2DArray),
+            id!(texture2d_depth) => Some(TyLit::Texture2DDepth),
             _ => None
         }
     }
@@ -1213,6 +1721,10 @@ impl TyLit {
             TyLit::Ivec2 => Ty::Ivec2,
             TyLit::Ivec3 => Ty::Ivec3,
             TyLit::Ivec4 => Ty::Ivec4,
+            TyLit::Uint => Ty::Uint,
+            TyLit::Uvec2 => Ty::Uvec2,
+            TyLit::Uvec3 => Ty::Uvec3,
+            TyLit::Uvec4 => Ty::Uvec4,
             TyLit::Vec2 => Ty::Vec2,
             TyLit::Vec3 => Ty::Vec3,
             TyLit::Vec4 => Ty::Vec4,
@@ -1220,6 +1732,10 @@ impl TyLit {
             TyLit::Mat3 => Ty::Mat3,
             TyLit::Mat4 => Ty::Mat4,
             TyLit::Texture2D => Ty::Texture2D,
+            TyLit::TextureCube => Ty::TextureCube,
+            TyLit::Texture3D => Ty::Texture3D,
+            TyLit::Texture2DArray => Ty::Texture2DArray,
+            TyLit::Texture2DDepth => Ty::Texture2DDepth,
         }
     }
     
@@ -1240,6 +1756,10 @@ impl fmt::Display for TyLit {
                 TyLit::Ivec2 => "ivec2",
                 TyLit::Ivec3 => "ivec3",
                 TyLit::Ivec4 => "ivec4",
+                TyLit::Uint => "uint",
+                TyLit::Uvec2 => "uvec2",
+                TyLit::Uvec3 => "uvec3",
+                TyLit::Uvec4 => "uvec4",
                 TyLit::Vec2 => "vec2",
                 TyLit::Vec3 => "vec3",
                 TyLit::Vec4 => "vec4",
@@ -1247,6 +1767,10 @@ impl fmt::Display for TyLit {
                 TyLit::Mat3 => "mat3",
                 TyLit::Mat4 => "mat4",
                 TyLit::Texture2D => "texture2D",
+                TyLit::TextureCube => "textureCube",
+                TyLit::Texture3D => "texture3d",
+                TyLit::Texture2DArray => "texture2d_array",
+                TyLit::Texture2DDepth => "texture2d_depth",
             }
         )
     }
@@ -1258,6 +1782,7 @@ impl Lit {
         match self {
             Lit::Bool(_) => Ty::Bool,
             Lit::Int(_) => Ty::Int,
+            Lit::Uint(_) => Ty::Uint,
             Lit::Float(_) => Ty::Float,
             Lit::Color(_) => Ty::Vec4
         }
@@ -1267,6 +1792,7 @@ impl Lit {
         match self {
             Lit::Bool(v) => Val::Bool(v),
             Lit::Int(v) => Val::Int(v),
+            Lit::Uint(v) => Val::Uint(v),
             Lit::Float(v) => Val::Float(v),
             Lit::Color(v) => Val::Vec4(Vec4::from_u32(v))
         }
@@ -1276,6 +1802,8 @@ impl Lit {
         match token {
             LiveToken::Bool(v) => Some(Lit::Bool(v)),
             LiveToken::Int(v) => Some(Lit::Int(v as i32)),
+            LiveToken::Sint(v) => Some(Lit::Int(v as i32)),
+            LiveToken::Uint(v) => Some(Lit::Uint(v as u32)),
             LiveToken::Float(v) => Some(Lit::Float(v as f32)),
             LiveToken::Color(v) => Some(Lit::Color(v)),
             _ => None
@@ -1288,6 +1816,7 @@ impl fmt::Display for Lit {
         match self {
             Lit::Bool(lit) => write!(f, "{}", lit),
             Lit::Int(lit) => write!(f, "{}", lit),
+            Lit::Uint(lit) => write!(f, "{}u", lit),
             Lit::Float(lit) => write!(f, "{}", PrettyPrintedF32(*lit)),
             Lit::Color(lit) => {
                 let v = Vec4::from_u32(*lit);
@@ -1389,6 +1918,7 @@ impl fmt::Display for Val {
         match *self {
             Val::Bool(val) => write!(f, "{}", val),
             Val::Int(val) => write!(f, "{}", val),
+            Val::Uint(val) => write!(f, "{}u", val),
             Val::Float(v) => write!(f, "{}", PrettyPrintedF32(v)),
             Val::Vec4(val) => write!(f, "{}", val),
         }
@@ -1412,3 +1942,9 @@ impl fmt::Display for ValuePtr {
         write!(f, "live_{}", self.0)
     }
 }
+
+impl fmt::Display for ConstPtr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "const_{}", self.0)
+    }
+}
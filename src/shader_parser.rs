@@ -2,9 +2,11 @@ use {
     std::{
         iter::Cloned,
         slice::Iter,
-        cell::{Cell, RefCell}
+        cell::{Cell, RefCell},
+        collections::HashMap,
     },
     crate::{
+        builtin::Builtin,
         makepad_live_id::*,
         makepad_live_compiler::*,
         makepad_live_compiler::makepad_live_tokenizer::Delim,
@@ -26,10 +28,15 @@ pub struct ShaderParser<'a> {
     pub tokens_with_span: Cloned<Iter<'a, TokenWithSpan >>,
     pub shader_file: &'a LiveFile,
     pub type_deps: &'a mut Vec<ShaderParserDep>,
+    // syntax errors of statements that were skipped, parsing goes on after them
+    pub errors: &'a mut Vec<LiveError>,
+    pub builtins: &'a HashMap<Ident, Builtin>,
     pub closure_defs: Vec<ClosureDef>,
     pub token_with_span: TokenWithSpan,
     pub self_kind: Option<FnSelfKind>,
     pub end: TextPos,
+    // off in the conditions of `if` and `match`, where `x {` starts the block
+    pub allow_struct_cons: bool,
 }
 
 impl<'a> ShaderParser<'a> {
@@ -37,6 +44,8 @@ impl<'a> ShaderParser<'a> {
         shader_file: &'a LiveFile,
         tokens: &'a [TokenWithSpan],
         type_deps: &'a mut Vec<ShaderParserDep>,
+        errors: &'a mut Vec<LiveError>,
+        builtins: &'a HashMap<Ident, Builtin>,
         self_kind: Option<FnSelfKind>,
         dsl_expand_index: usize,
         origin_file_id: LiveFileId,
@@ -50,11 +59,14 @@ impl<'a> ShaderParser<'a> {
             dsl_expand_index,
             origin_file_id,
             type_deps,
+            errors,
+            builtins,
             tokens_with_span,
             token_with_span,
             token_index: token_start,
             end: TextPos::default(),
-            self_kind
+            self_kind,
+            allow_struct_cons: true,
         }
     }
 }
@@ -171,7 +183,7 @@ impl<'a> ShaderParser<'a> {
     
     fn expect_specific_ident(&mut self, specific_id: LiveId) -> Result<(), LiveError> {
         match self.peek_token() {
-            LiveToken::Ident(id) if id == id => {
+            LiveToken::Ident(id) if id == specific_id => {
                 self.skip_token();
                 Ok(())
             }
@@ -264,6 +276,7 @@ impl<'a> ShaderParser<'a> {
                 return span.end(self, | span | Ok(Some(DrawShaderFieldDef {
                     kind: DrawShaderFieldKind::Texture {
                         var_def_ptr: Some(VarDefPtr(decl_node_ptr)),
+                        sampler: ShaderSampler::default(),
                         //input_type: DrawShaderInputType::VarDef(decl_node_ptr),
                     },
                     span,
@@ -309,7 +322,6 @@ impl<'a> ShaderParser<'a> {
     }
     
     // lets parse a function.
-    /*
     pub fn expect_field(&mut self, ident: Ident, var_def_ptr: VarDefPtr) -> Result<Option<StructFieldDef>, LiveError> {
         let span = self.begin_span();
         let decl_ty = self.expect_ident(live_error_origin!()) ?;
@@ -333,7 +345,7 @@ impl<'a> ShaderParser<'a> {
                 return Err(span.error(self, live_error_origin!(), format!("unexpected decl type in struct `{}`", decl_ty).into()))
             }
         }
-    }*/
+    }
     
     // lets parse a function.
     pub fn expect_method_def(mut self, fn_ptr: FnPtr, outer_ident: Ident) -> Result<Option<FnDef>, LiveError> {
@@ -439,27 +451,27 @@ impl<'a> ShaderParser<'a> {
     
     fn expect_ty_expr(&mut self) -> Result<TyExpr, LiveError> {
         let span = self.begin_span();
-        let mut acc = self.expect_prim_ty_expr() ?;
+        // `[elem_ty; len]`
         if self.accept_token(LiveToken::Open(Delim::Bracket)) {
-            let elem_ty_expr = Box::new(acc);
+            let elem_ty_expr = Box::new(self.expect_ty_expr() ?);
+            self.expect_token(LiveToken::Punct(id!(;))) ?;
             let token = self.peek_token();
-            if let Some(Lit::Int(len)) = Lit::from_token(token) {
-                self.skip_token();
-                self.expect_token(LiveToken::Close(Delim::Bracket)) ?;
-                acc = span.end(self, | span | TyExpr {
-                    ty: RefCell::new(None),
-                    span,
-                    kind: TyExprKind::Array {
-                        elem_ty_expr,
-                        len: len as u32,
-                    },
-                });
-            }
-            else {
-                return Err(span.error(self, live_error_origin!(), format!("unexpected token `{}`", token).into()))
-            }
+            let len = match Lit::from_token(token) {
+                Some(Lit::Int(len)) if len > 0 => len,
+                _ => return Err(span.error(self, live_error_origin!(), format!("expected an array length, got `{}`", token).into()))
+            };
+            self.skip_token();
+            self.expect_token(LiveToken::Close(Delim::Bracket)) ?;
+            return Ok(span.end(self, | span | TyExpr {
+                ty: RefCell::new(None),
+                span,
+                kind: TyExprKind::Array {
+                    elem_ty_expr,
+                    len: len as u32,
+                },
+            }))
         }
-        Ok(acc)
+        self.expect_prim_ty_expr()
     }
     
     fn expect_prim_ty_expr(&mut self) -> Result<TyExpr, LiveError> {
@@ -530,9 +542,17 @@ impl<'a> ShaderParser<'a> {
                             LiveNodeFindResult::Function(_)
                                 | LiveNodeFindResult::Component(_)
                                 | LiveNodeFindResult::LiveValue(_, _)
+                                | LiveNodeFindResult::Const(_)
                                 | LiveNodeFindResult::PossibleStatic(_, _) => {
                                 return Err(span.error(self, live_error_origin!(), format!("Not a Struct type `{}`", ident_path).into()))
                             }
+                            LiveNodeFindResult::Enum(enum_name) => {
+                                return Ok(span.end(self, | span | TyExpr {
+                                    span,
+                                    ty: RefCell::new(None),
+                                    kind: TyExprKind::Enum(enum_name),
+                                }))
+                            }
                             LiveNodeFindResult::Struct(struct_ptr) => {
                                 //yay .. lets make a struct typedep
                                 self.type_deps.push(ShaderParserDep::Struct(struct_ptr));
@@ -572,17 +592,58 @@ impl<'a> ShaderParser<'a> {
         self.expect_token(LiveToken::Open(Delim::Brace)) ?;
         let mut stmts = Vec::new();
         while !self.accept_token(LiveToken::Close(Delim::Brace)) {
-            stmts.push(self.expect_stmt() ?);
+            match self.expect_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => {
+                    self.errors.push(err);
+                    self.skip_stmt();
+                    if self.peek_token() == LiveToken::Eof {
+                        break;
+                    }
+                }
+            }
         }
         Ok(Block {stmts})
     }
     
+    // skips the rest of a broken statement, up to and including its `;` or
+    // its closing `}`, stops before the `}` of the enclosing block
+    fn skip_stmt(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek_token() {
+                LiveToken::Eof => return,
+                LiveToken::Open(_) => depth += 1,
+                LiveToken::Close(Delim::Brace) if depth == 0 => return,
+                LiveToken::Close(delim) => {
+                    if depth > 0 {
+                        depth -= 1;
+                        if depth == 0 && delim == Delim::Brace {
+                            self.skip_token();
+                            return
+                        }
+                    }
+                }
+                LiveToken::Punct(id!(;)) if depth == 0 => {
+                    self.skip_token();
+                    return
+                }
+                _ => ()
+            }
+            self.skip_token();
+        }
+    }
+    
     
     fn expect_stmt(&mut self) -> Result<Stmt, LiveError> {
         match self.peek_token() {
             LiveToken::Ident(id!(break)) => self.expect_break_stmt(),
             LiveToken::Ident(id!(continue)) => self.expect_continue_stmt(),
-            LiveToken::Ident(id!(for)) => self.expect_for_stmt(),
+            LiveToken::Ident(id!(discard)) => self.expect_discard_stmt(),
+            LiveToken::Lifetime(_)
+                | LiveToken::Ident(id!(for))
+                | LiveToken::Ident(id!(while))
+                | LiveToken::Ident(id!(loop)) => self.expect_loop_stmt(),
             LiveToken::Ident(id!(if)) => self.expect_if_stmt(),
             LiveToken::Ident(id!(match)) => self.expect_match_stmt(),
             LiveToken::Ident(id!(let)) => self.expect_let_stmt(),
@@ -594,21 +655,61 @@ impl<'a> ShaderParser<'a> {
     fn expect_break_stmt(&mut self) -> Result<Stmt, LiveError> {
         let span = self.begin_span();
         self.expect_token(LiveToken::Ident(id!(break))) ?;
+        let label = self.accept_label();
         self.accept_optional_delim();
         //self.expect_token(Token::Punct(id!(;))) ?;
-        Ok(span.end(self, | span | Stmt::Break {span}))
+        Ok(span.end(self, | span | Stmt::Break {span, label}))
     }
     
     fn expect_continue_stmt(&mut self) -> Result<Stmt, LiveError> {
         let span = self.begin_span();
         self.expect_token(LiveToken::Ident(id!(continue))) ?;
+        let label = self.accept_label();
         self.accept_optional_delim();
         //self.expect_token(Token::Punct(id!(;))) ?;
-        Ok(span.end(self, | span | Stmt::Continue {span}))
+        Ok(span.end(self, | span | Stmt::Continue {span, label}))
     }
     
-    fn expect_for_stmt(&mut self) -> Result<Stmt, LiveError> {
+    fn expect_discard_stmt(&mut self) -> Result<Stmt, LiveError> {
         let span = self.begin_span();
+        self.expect_token(LiveToken::Ident(id!(discard))) ?;
+        self.accept_optional_delim();
+        Ok(span.end(self, | span | Stmt::Discard {span}))
+    }
+    
+    fn accept_label(&mut self) -> Option<Ident> {
+        if let LiveToken::Lifetime(label) = self.peek_token() {
+            self.skip_token();
+            return Some(Ident(label))
+        }
+        None
+    }
+    
+    // `for`, `while` and `loop`, with an optional `'label:` in front
+    fn expect_loop_stmt(&mut self) -> Result<Stmt, LiveError> {
+        let span = self.begin_span();
+        let label = self.accept_label();
+        if label.is_some() {
+            self.expect_token(LiveToken::Punct(id!(:))) ?;
+        }
+        match self.peek_token() {
+            LiveToken::Ident(id!(for)) => self.expect_for_stmt(span, label),
+            LiveToken::Ident(id!(while)) => {
+                self.skip_token();
+                let expr = self.expect_expr_with_struct_cons(false) ?;
+                let block = Box::new(self.expect_block() ?);
+                Ok(span.end(self, | span | Stmt::While {span, label, expr, block}))
+            }
+            LiveToken::Ident(id!(loop)) => {
+                self.skip_token();
+                let block = Box::new(self.expect_block() ?);
+                Ok(span.end(self, | span | Stmt::Loop {span, label, block}))
+            }
+            token => Err(span.error(self, live_error_origin!(), format!("expected a loop after the label, got `{}`", token))),
+        }
+    }
+    
+    fn expect_for_stmt(&mut self, span: SpanTracker, label: Option<Ident>) -> Result<Stmt, LiveError> {
         self.expect_token(LiveToken::Ident(id!(for))) ?;
         let ident = self.expect_ident(live_error_origin!()) ?;
         self.expect_token(LiveToken::Ident(id!(from))) ?;
@@ -623,7 +724,9 @@ impl<'a> ShaderParser<'a> {
         let block = Box::new(self.expect_block() ?);
         Ok(span.end(self, | span | Stmt::For {
             span,
+            label,
             ident,
+            shadow: Cell::new(None),
             from_expr,
             to_expr,
             step_expr,
@@ -635,7 +738,7 @@ impl<'a> ShaderParser<'a> {
     fn expect_if_stmt(&mut self) -> Result<Stmt, LiveError> {
         let span = self.begin_span();
         self.expect_token(LiveToken::Ident(id!(if))) ?;
-        let expr = self.expect_expr() ?;
+        let expr = self.expect_expr_with_struct_cons(false) ?;
         let block_if_true = Box::new(self.expect_block() ?);
         let block_if_false = if self.accept_token(LiveToken::Ident(id!(else))) {
             if self.peek_token() == LiveToken::Ident(id!(if)) {
@@ -676,7 +779,7 @@ impl<'a> ShaderParser<'a> {
         let span = self.begin_span();
         self.expect_token(LiveToken::Ident(id!(match))) ?;
         
-        let expr = self.expect_expr() ?;
+        let expr = self.expect_expr_with_struct_cons(false) ?;
         
         // now we parse our match block
         self.expect_token(LiveToken::Open(Delim::Brace)) ?;
@@ -750,6 +853,13 @@ impl<'a> ShaderParser<'a> {
         self.expect_assign_expr()
     }
     
+    fn expect_expr_with_struct_cons(&mut self, allow_struct_cons: bool) -> Result<Expr, LiveError> {
+        let old_allow_struct_cons = std::mem::replace(&mut self.allow_struct_cons, allow_struct_cons);
+        let expr = self.expect_expr();
+        self.allow_struct_cons = old_allow_struct_cons;
+        expr
+    }
+    
     fn expect_assign_expr(&mut self) -> Result<Expr, LiveError> {
         let span = self.begin_span();
         let expr = self.expect_cond_expr() ?;
@@ -874,8 +984,101 @@ impl<'a> ShaderParser<'a> {
     
     fn expect_rel_expr(&mut self) -> Result<Expr, LiveError> {
         let span = self.begin_span();
-        let mut acc = self.expect_add_expr() ?;
+        let mut acc = self.expect_bit_or_expr() ?;
         while let Some(op) = BinOp::from_rel_op(self.peek_token()) {
+            self.skip_token();
+            let left_expr = Box::new(acc);
+            let right_expr = Box::new(self.expect_bit_or_expr() ?);
+            acc = span.end(self, | span | Expr {
+                span,
+                ty: RefCell::new(None),
+                const_val: RefCell::new(None),
+                const_index: Cell::new(None),
+                kind: ExprKind::Bin {
+                    span,
+                    op,
+                    left_expr,
+                    right_expr,
+                },
+            });
+        }
+        Ok(acc)
+    }
+    
+    // the bitwise operators bind tighter than comparisons, like in Rust
+    fn expect_bit_or_expr(&mut self) -> Result<Expr, LiveError> {
+        let span = self.begin_span();
+        let mut acc = self.expect_bit_xor_expr() ?;
+        while let Some(op) = BinOp::from_bit_or_op(self.peek_token()) {
+            self.skip_token();
+            let left_expr = Box::new(acc);
+            let right_expr = Box::new(self.expect_bit_xor_expr() ?);
+            acc = span.end(self, | span | Expr {
+                span,
+                ty: RefCell::new(None),
+                const_val: RefCell::new(None),
+                const_index: Cell::new(None),
+                kind: ExprKind::Bin {
+                    span,
+                    op,
+                    left_expr,
+                    right_expr,
+                },
+            });
+        }
+        Ok(acc)
+    }
+    
+    fn expect_bit_xor_expr(&mut self) -> Result<Expr, LiveError> {
+        let span = self.begin_span();
+        let mut acc = self.expect_bit_and_expr() ?;
+        while let Some(op) = BinOp::from_bit_xor_op(self.peek_token()) {
+            self.skip_token();
+            let left_expr = Box::new(acc);
+            let right_expr = Box::new(self.expect_bit_and_expr() ?);
+            acc = span.end(self, | span | Expr {
+                span,
+                ty: RefCell::new(None),
+                const_val: RefCell::new(None),
+                const_index: Cell::new(None),
+                kind: ExprKind::Bin {
+                    span,
+                    op,
+                    left_expr,
+                    right_expr,
+                },
+            });
+        }
+        Ok(acc)
+    }
+    
+    fn expect_bit_and_expr(&mut self) -> Result<Expr, LiveError> {
+        let span = self.begin_span();
+        let mut acc = self.expect_shift_expr() ?;
+        while let Some(op) = BinOp::from_bit_and_op(self.peek_token()) {
+            self.skip_token();
+            let left_expr = Box::new(acc);
+            let right_expr = Box::new(self.expect_shift_expr() ?);
+            acc = span.end(self, | span | Expr {
+                span,
+                ty: RefCell::new(None),
+                const_val: RefCell::new(None),
+                const_index: Cell::new(None),
+                kind: ExprKind::Bin {
+                    span,
+                    op,
+                    left_expr,
+                    right_expr,
+                },
+            });
+        }
+        Ok(acc)
+    }
+    
+    fn expect_shift_expr(&mut self) -> Result<Expr, LiveError> {
+        let span = self.begin_span();
+        let mut acc = self.expect_add_expr() ?;
+        while let Some(op) = BinOp::from_shift_op(self.peek_token()) {
             self.skip_token();
             let left_expr = Box::new(acc);
             let right_expr = Box::new(self.expect_add_expr() ?);
@@ -1004,7 +1207,7 @@ impl<'a> ShaderParser<'a> {
                         })
                     }
                 }
-                LiveToken::Close(Delim::Bracket) => {
+                LiveToken::Open(Delim::Bracket) => {
                     self.skip_token();
                     let expr = Box::new(acc);
                     let index_expr = Box::new(self.expect_expr() ?);
@@ -1060,7 +1263,7 @@ impl<'a> ShaderParser<'a> {
                 else {
                     let ident_path = self.expect_ident_path() ?;
                     match self.peek_token() {
-                        LiveToken::Open(Delim::Brace) => { // its a struct constructor call
+                        LiveToken::Open(Delim::Brace) if self.allow_struct_cons => { // its a struct constructor call
                             
                             let struct_ptr = if ident_path.len() == 1 && ident_path.segs[0] == id!(Self) {
                                 if let Some(FnSelfKind::Struct(struct_node_ptr)) = self.self_kind {
@@ -1085,7 +1288,8 @@ impl<'a> ShaderParser<'a> {
                                     LiveNodeFindResult::PossibleStatic(_, _)
                                         | LiveNodeFindResult::Function(_)
                                         | LiveNodeFindResult::Component(_)
-                                    //    | LiveNodeFindResult::Const(_)
+                                        | LiveNodeFindResult::Enum(_)
+                                        | LiveNodeFindResult::Const(_)
                                         | LiveNodeFindResult::LiveValue(_, _) => {
                                         return Err(span.error(self, live_error_origin!(), format!("Not a struct `{}`", ident_path).into()))
                                     }
@@ -1129,20 +1333,8 @@ impl<'a> ShaderParser<'a> {
                         }
                         LiveToken::Open(Delim::Paren) => {
                             let arg_exprs = self.expect_arg_exprs() ?;
-                            if ident_path.len() == 1 && false {// && self.shader_registry.builtins.get(&Ident(ident_path.segs[0])).is_some() {
-                                Ok(span.end(self, | span | Expr {
-                                    span,
-                                    ty: RefCell::new(None),
-                                    const_val: RefCell::new(None),
-                                    const_index: Cell::new(None),
-                                    kind: ExprKind::BuiltinCall {
-                                        span,
-                                        ident: Ident(ident_path.segs[0]),
-                                        arg_exprs,
-                                    },
-                                }))
-                            }
-                            else if let Some(ptr) = self.shader_file.find_scope_ptr_via_expand_index(self.dsl_expand_index, ident_path.segs[0]) {
+                            // functions declared in the shader shadow the builtins
+                            if let Some(ptr) = self.shader_file.find_scope_ptr_via_expand_index(self.dsl_expand_index, ident_path.segs[0]) {
                                 match Shader::find_live_node_by_path(self.shader_file, ptr, &ident_path.segs[1..ident_path.len()]) {
                                     LiveNodeFindResult::Error(err) => {
                                         return Err(err)
@@ -1152,7 +1344,8 @@ impl<'a> ShaderParser<'a> {
                                     }
                                     LiveNodeFindResult::Component(_)
                                         | LiveNodeFindResult::Struct(_)
-                                    //    | LiveNodeFindResult::Const(_)
+                                        | LiveNodeFindResult::Enum(_)
+                                        | LiveNodeFindResult::Const(_)
                                         | LiveNodeFindResult::LiveValue(_, _) => {
                                         Err(span.error(self, live_error_origin!(), format!("Not a function `{}`", ident_path).into()))
                                     }
@@ -1197,6 +1390,19 @@ impl<'a> ShaderParser<'a> {
                                     
                                 }
                             }
+                            else if ident_path.len() == 1 && self.builtins.get(&Ident(ident_path.segs[0])).is_some() {
+                                Ok(span.end(self, | span | Expr {
+                                    span,
+                                    ty: RefCell::new(None),
+                                    const_val: RefCell::new(None),
+                                    const_index: Cell::new(None),
+                                    kind: ExprKind::BuiltinCall {
+                                        span,
+                                        ident: Ident(ident_path.segs[0]),
+                                        arg_exprs,
+                                    },
+                                }))
+                            }
                             else if ident_path.len() == 1 {
                                 // it must be a closure call, even though we don't know if its really there.
                                 Ok(span.end(self, | span | Expr {
@@ -1233,10 +1439,9 @@ impl<'a> ShaderParser<'a> {
                                     LiveNodeFindResult::LiveValue(value_ptr, ty) => {
                                         var_resolve = VarResolve::LiveValue(value_ptr, ty);
                                     }
-                                    //LiveNodeFindResult::Const(const_ptr) => {
-                                    //    self.type_deps.push(ShaderParserDep::Const(const_ptr));
-                                    //    var_resolve = VarResolve::Const(const_ptr);
-                                    // }
+                                    LiveNodeFindResult::Const(const_ptr) => {
+                                        var_resolve = VarResolve::Const(const_ptr);
+                                    }
                                     LiveNodeFindResult::Function(fn_ptr) => {
                                         self.type_deps.push(ShaderParserDep::Function(None, fn_ptr));
                                         var_resolve = VarResolve::Function(fn_ptr);
@@ -1286,6 +1491,35 @@ impl<'a> ShaderParser<'a> {
                     kind: ExprKind::Lit {span, lit: Lit::Float(v as f32)},
                 }))
             }
+            LiveToken::Sint(v) => {
+                self.skip_token();
+                // literals up to `u32::MAX` keep their bits, `0xffffffff` is -1
+                let lit = match v {
+                    v if v <= i32::MAX as i64 => Lit::Int(v as i32),
+                    v if v <= u32::MAX as i64 => Lit::Int(v as u32 as i32),
+                    _ => return Err(span.error(self, live_error_origin!(), format!("literal `{}` out of range for int", v)))
+                };
+                Ok(span.end(self, | span | Expr {
+                    span,
+                    ty: RefCell::new(None),
+                    const_val: RefCell::new(None),
+                    const_index: Cell::new(None),
+                    kind: ExprKind::Lit {span, lit},
+                }))
+            }
+            LiveToken::Uint(v) => {
+                self.skip_token();
+                if v > u32::MAX as u64 {
+                    return Err(span.error(self, live_error_origin!(), format!("literal `{}u` out of range for uint", v)))
+                }
+                Ok(span.end(self, | span | Expr {
+                    span,
+                    ty: RefCell::new(None),
+                    const_val: RefCell::new(None),
+                    const_index: Cell::new(None),
+                    kind: ExprKind::Lit {span, lit: Lit::Uint(v as u32)},
+                }))
+            }
             LiveToken::Float(v) => {
                 self.skip_token();
                 Ok(span.end(self, | span | Expr {
@@ -1363,11 +1597,37 @@ impl<'a> ShaderParser<'a> {
             }
             LiveToken::Open(Delim::Paren) => {
                 self.skip_token();
-                let expr = self.expect_expr() ?;
+                let expr = self.expect_expr_with_struct_cons(true) ?;
                 self.expect_token(LiveToken::Close(Delim::Paren)) ?;
                 Ok(expr)
             }
-            token => Err(span.error(self, live_error_origin!(), format!("unexpected token `{}`", token).into())),
+            LiveToken::Open(Delim::Bracket) => {
+                self.skip_token();
+                let mut elem_exprs = Vec::new();
+                if !self.accept_token(LiveToken::Close(Delim::Bracket)) {
+                    loop {
+                        elem_exprs.push(self.expect_expr_with_struct_cons(true) ?);
+                        if !self.accept_token(LiveToken::Punct(id!(,))) {
+                            break;
+                        }
+                        if self.peek_token() == LiveToken::Close(Delim::Bracket) {
+                            break;
+                        }
+                    }
+                    self.expect_token(LiveToken::Close(Delim::Bracket)) ?;
+                }
+                Ok(span.end(self, | span | Expr {
+                    span,
+                    ty: RefCell::new(None),
+                    const_val: RefCell::new(None),
+                    const_index: Cell::new(None),
+                    kind: ExprKind::ArrayCons {
+                        span,
+                        elem_exprs,
+                    },
+                }))
+            }
+            token =>Err(span.error(self, live_error_origin!(), format!("unexpected token `{}`", token).into())),
         }
     }
     
@@ -1376,7 +1636,7 @@ impl<'a> ShaderParser<'a> {
         let mut arg_exprs = Vec::new();
         if !self.accept_token(LiveToken::Close(Delim::Paren)) {
             loop {
-                arg_exprs.push(self.expect_expr() ?);
+                arg_exprs.push(self.expect_expr_with_struct_cons(true) ?);
                 if !self.accept_token(LiveToken::Punct(id!(,))) {
                     break;
                 }
@@ -0,0 +1,144 @@
+use {
+    std::{fmt::Write, ops::Range},
+    crate::{
+        makepad_live_compiler::{LiveTokenId, TextSpan, TokenSpan},
+        shader::Shader,
+    }
+};
+
+/// How generated code points back at the `.shader` file, next to the `ShaderSourceMap`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShaderLineMarkers {
+    None,
+    /// `#line` directives in GLSL, HLSL and Metal, WGSL has no preprocessor and gets comments
+    Directives,
+    /// a `// file:line:column` comment above every statement
+    Comments,
+}
+
+impl Default for ShaderLineMarkers {
+    fn default() -> Self {
+        ShaderLineMarkers::None
+    }
+}
+
+/// Maps lines of a generated shader to the code in the `.shader` file they were generated from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShaderSourceMap {
+    pub entries: Vec<ShaderSourceMapEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderSourceMapEntry {
+    /// generated lines, counted from 1 like backend compilers do
+    pub lines: Range<u32>,
+    pub span: TextSpan,
+}
+
+impl ShaderSourceMap {
+    /// The code a generated line comes from, `None` for the glue around the shader functions.
+    pub fn span_at_line(&self, line: u32) -> Option<TextSpan> {
+        self.entries.iter().find( | entry | entry.lines.contains(&line)).map( | entry | entry.span)
+    }
+}
+
+// in-band marks, written by the generators where the code of a span starts and where the glue
+// around it continues, and taken out again by `resolve_marks`
+const MARK: char = '\u{1}';
+
+pub fn write_start_mark(string: &mut String, span: TokenSpan) {
+    write!(string, "{}{}:{}{}", MARK, span.token_id.to_bits(), span.len, MARK).unwrap();
+}
+
+pub fn write_end_mark(string: &mut String) {
+    write!(string, "{}{}", MARK, MARK).unwrap();
+}
+
+/// The syntax of `#line` for a backend.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineDirectives {
+    /// `#line 12`, GLSL has source string numbers instead of file names
+    Glsl,
+    /// `#line 11` for line 12, GLSL ES 1.00 numbers the line after the directive `line + 1`
+    Glsl100,
+    /// `#line 12 "sprite.shader"`
+    CStyle,
+    None,
+}
+
+/// Takes the marks out of `string`, turns them into directives or comments and builds the source map.
+pub fn resolve_marks(string: &str, shader: &Shader, line_markers: ShaderLineMarkers, line_directives: LineDirectives) -> (String, ShaderSourceMap) {
+    let line_markers = match (line_markers, line_directives) {
+        (ShaderLineMarkers::Directives, LineDirectives::None) => ShaderLineMarkers::Comments,
+        (line_markers, _) => line_markers,
+    };
+    let mut output = String::with_capacity(string.len());
+    let mut source_map = ShaderSourceMap::default();
+    let mut output_line = 1;
+    let mut current: Option<TextSpan> = None;
+    let mut pending = None;
+    for line in string.split_inclusive('\n') {
+        let mut text = String::with_capacity(line.len());
+        let mut state = pending.take();
+        let mut parts = line.split(MARK);
+        text.push_str(parts.next().unwrap());
+        while let (Some(mark), Some(rest)) = (parts.next(), parts.next()) {
+            let mark_state = Some(parse_mark(mark).map( | span | shader.token_span_to_text_span(span)));
+            // a mark behind code on the same line takes effect on the next line
+            if text.trim().is_empty() {
+                state = mark_state;
+            }
+            else {
+                pending = mark_state;
+            }
+            text.push_str(rest);
+        }
+        if let Some(state) = state {
+            if state != current {
+                let indent = &text[..text.len() - text.trim_start().len()];
+                let marker = match (line_markers, state) {
+                    (ShaderLineMarkers::Directives, Some(span)) => Some(match line_directives {
+                        LineDirectives::Glsl => format!("#line {}", span.start.line + 1),
+                        LineDirectives::Glsl100 => format!("#line {}", span.start.line),
+                        _ => format!("#line {} {:?}", span.start.line + 1, shader.file_name()),
+                    }),
+                    // the next line is the one after the directive
+                    (ShaderLineMarkers::Directives, None) => Some(match line_directives {
+                        LineDirectives::Glsl => format!("#line {}", output_line + 1),
+                        LineDirectives::Glsl100 => format!("#line {}", output_line),
+                        _ => format!("#line {} \"<generated>\"", output_line + 1),
+                    }),
+                    (ShaderLineMarkers::Comments, Some(span)) => {
+                        Some(format!("{}// {}:{}:{}", indent, shader.file_name(), span.start.line + 1, span.start.column + 1))
+                    }
+                    _ => None,
+                };
+                if let Some(marker) = marker {
+                    output.push_str(&marker);
+                    output.push('\n');
+                    output_line += 1;
+                }
+                current = state;
+            }
+        }
+        if let Some(span) = current {
+            match source_map.entries.last_mut() {
+                Some(entry) if entry.span == span && entry.lines.end == output_line => entry.lines.end += 1,
+                _ => source_map.entries.push(ShaderSourceMapEntry {lines: output_line..output_line + 1, span}),
+            }
+        }
+        output.push_str(&text);
+        if text.ends_with('\n') {
+            output_line += 1;
+        }
+    }
+    (output, source_map)
+}
+
+fn parse_mark(mark: &str) -> Option<TokenSpan> {
+    let (token_id, len) = mark.split_once(':') ?;
+    Some(TokenSpan {
+        token_id: LiveTokenId::from_bits(token_id.parse().ok() ?) ?,
+        len: len.parse().ok() ?,
+    })
+}
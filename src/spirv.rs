@@ -0,0 +1,631 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+pub const MAGIC: u32 = 0x0723_0203;
+pub const VERSION_1_0: u32 = 0x0001_0000;
+
+/// The opcodes the SPIR-V backend emits, and the disassembler understands.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Op {
+    Source = 3,
+    Name = 5,
+    MemberName = 6,
+    ExtInstImport = 11,
+    ExtInst = 12,
+    MemoryModel = 14,
+    EntryPoint = 15,
+    ExecutionMode = 16,
+    Capability = 17,
+    TypeVoid = 19,
+    TypeBool = 20,
+    TypeInt = 21,
+    TypeFloat = 22,
+    TypeVector = 23,
+    TypeMatrix = 24,
+    TypeImage = 25,
+    TypeSampledImage = 27,
+    TypeArray = 28,
+    TypeStruct = 30,
+    TypePointer = 32,
+    TypeFunction = 33,
+    ConstantTrue = 41,
+    ConstantFalse = 42,
+    Constant = 43,
+    ConstantComposite = 44,
+    Function = 54,
+    FunctionParameter = 55,
+    FunctionEnd = 56,
+    FunctionCall = 57,
+    Variable = 59,
+    Load = 61,
+    Store = 62,
+    AccessChain = 65,
+    Decorate = 71,
+    MemberDecorate = 72,
+    VectorExtractDynamic = 77,
+    VectorShuffle = 79,
+    CompositeConstruct = 80,
+    CompositeExtract = 81,
+    CompositeInsert = 82,
+    Transpose = 84,
+    ImageSampleImplicitLod = 87,
+    ImageSampleExplicitLod = 88,
+    ImageSampleDrefImplicitLod = 89,
+    ImageSampleDrefExplicitLod = 90,
+    ImageFetch = 95,
+    Image = 100,
+    ImageQuerySizeLod = 103,
+    ConvertFToU = 109,
+    ConvertFToS = 110,
+    ConvertSToF = 111,
+    ConvertUToF = 112,
+    Bitcast = 124,
+    SNegate = 126,
+    FNegate = 127,
+    IAdd = 128,
+    FAdd = 129,
+    ISub = 130,
+    FSub = 131,
+    IMul = 132,
+    FMul = 133,
+    UDiv = 134,
+    SDiv = 135,
+    FDiv = 136,
+    UMod = 137,
+    SRem = 138,
+    FMod = 141,
+    VectorTimesScalar = 142,
+    MatrixTimesScalar = 143,
+    VectorTimesMatrix = 144,
+    MatrixTimesVector = 145,
+    MatrixTimesMatrix = 146,
+    Dot = 148,
+    Any = 154,
+    All = 155,
+    LogicalEqual = 164,
+    LogicalNotEqual = 165,
+    LogicalOr = 166,
+    LogicalAnd = 167,
+    LogicalNot = 168,
+    Select = 169,
+    IEqual = 170,
+    INotEqual = 171,
+    UGreaterThan = 172,
+    SGreaterThan = 173,
+    UGreaterThanEqual = 174,
+    SGreaterThanEqual = 175,
+    ULessThan = 176,
+    SLessThan = 177,
+    ULessThanEqual = 178,
+    SLessThanEqual = 179,
+    FOrdEqual = 180,
+    FUnordNotEqual = 183,
+    FOrdLessThan = 184,
+    FOrdGreaterThan = 186,
+    FOrdLessThanEqual = 188,
+    FOrdGreaterThanEqual = 190,
+    ShiftRightLogical = 194,
+    ShiftRightArithmetic = 195,
+    ShiftLeftLogical = 196,
+    BitwiseOr = 197,
+    BitwiseXor = 198,
+    BitwiseAnd = 199,
+    Not = 200,
+    DPdx = 207,
+    DPdy = 208,
+    Phi = 245,
+    LoopMerge = 246,
+    SelectionMerge = 247,
+    Label = 248,
+    Branch = 249,
+    BranchConditional = 250,
+    Kill = 252,
+    Return = 253,
+    ReturnValue = 254,
+    Unreachable = 255,
+}
+
+const OPS: &[Op] = &[
+    Op::Source, Op::Name, Op::MemberName, Op::ExtInstImport, Op::ExtInst, Op::MemoryModel, Op::EntryPoint,
+    Op::ExecutionMode, Op::Capability, Op::TypeVoid, Op::TypeBool, Op::TypeInt, Op::TypeFloat, Op::TypeVector,
+    Op::TypeMatrix, Op::TypeImage, Op::TypeSampledImage, Op::TypeArray, Op::TypeStruct, Op::TypePointer,
+    Op::TypeFunction, Op::ConstantTrue, Op::ConstantFalse, Op::Constant, Op::ConstantComposite, Op::Function,
+    Op::FunctionParameter, Op::FunctionEnd, Op::FunctionCall, Op::Variable, Op::Load, Op::Store, Op::AccessChain,
+    Op::Decorate, Op::MemberDecorate, Op::VectorExtractDynamic, Op::VectorShuffle, Op::CompositeConstruct,
+    Op::CompositeExtract, Op::CompositeInsert, Op::Transpose, Op::ImageSampleImplicitLod, Op::ImageSampleExplicitLod,
+    Op::ImageSampleDrefImplicitLod, Op::ImageSampleDrefExplicitLod, Op::ImageFetch, Op::Image, Op::ImageQuerySizeLod,
+    Op::ConvertFToU, Op::ConvertFToS, Op::ConvertSToF, Op::ConvertUToF, Op::Bitcast, Op::SNegate, Op::FNegate, Op::IAdd,
+    Op::FAdd, Op::ISub, Op::FSub, Op::IMul, Op::FMul, Op::UDiv, Op::SDiv, Op::FDiv, Op::UMod, Op::SRem, Op::FMod, Op::VectorTimesScalar, Op::MatrixTimesScalar, Op::VectorTimesMatrix,
+    Op::MatrixTimesVector, Op::MatrixTimesMatrix, Op::Dot, Op::Any, Op::All, Op::LogicalEqual, Op::LogicalNotEqual,
+    Op::LogicalOr, Op::LogicalAnd, Op::LogicalNot, Op::Select, Op::IEqual, Op::INotEqual, Op::SGreaterThan,
+    Op::SGreaterThanEqual, Op::SLessThan, Op::SLessThanEqual, Op::UGreaterThan, Op::UGreaterThanEqual, Op::ULessThan,
+    Op::ULessThanEqual, Op::FOrdEqual, Op::FUnordNotEqual, Op::FOrdLessThan,
+    Op::FOrdGreaterThan, Op::FOrdLessThanEqual, Op::FOrdGreaterThanEqual, Op::ShiftRightLogical,
+    Op::ShiftRightArithmetic, Op::ShiftLeftLogical, Op::BitwiseOr, Op::BitwiseXor, Op::BitwiseAnd, Op::Not, Op::DPdx, Op::DPdy, Op::Phi, Op::LoopMerge,
+    Op::SelectionMerge, Op::Label, Op::Branch, Op::BranchConditional, Op::Kill, Op::Return, Op::ReturnValue, Op::Unreachable,
+];
+
+pub mod capability {
+    pub const SHADER: u32 = 1;
+    pub const IMAGE_QUERY: u32 = 50;
+}
+
+pub mod execution_model {
+    pub const VERTEX: u32 = 0;
+    pub const FRAGMENT: u32 = 4;
+}
+
+pub mod execution_mode {
+    pub const ORIGIN_UPPER_LEFT: u32 = 7;
+    pub const DEPTH_REPLACING: u32 = 12;
+}
+
+pub mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const OUTPUT: u32 = 3;
+    pub const PRIVATE: u32 = 6;
+    pub const FUNCTION: u32 = 7;
+}
+
+pub mod decoration {
+    pub const BLOCK: u32 = 2;
+    pub const COL_MAJOR: u32 = 5;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+pub mod built_in {
+    pub const POSITION: u32 = 0;
+    pub const POINT_SIZE: u32 = 1;
+    pub const FRAG_COORD: u32 = 15;
+    pub const POINT_COORD: u32 = 16;
+    pub const FRONT_FACING: u32 = 17;
+    pub const FRAG_DEPTH: u32 = 22;
+    pub const VERTEX_INDEX: u32 = 42;
+    pub const INSTANCE_INDEX: u32 = 43;
+}
+
+pub mod image_operands {
+    pub const LOD: u32 = 0x2;
+    pub const GRAD: u32 = 0x4;
+    pub const CONST_OFFSET: u32 = 0x8;
+}
+
+pub mod dim {
+    pub const DIM_2D: u32 = 1;
+    pub const DIM_3D: u32 = 2;
+    pub const DIM_CUBE: u32 = 3;
+}
+
+/// Instruction numbers of the `GLSL.std.450` extended instruction set.
+pub mod glsl_std_450 {
+    pub const F_ABS: u32 = 4;
+    pub const S_ABS: u32 = 5;
+    pub const F_SIGN: u32 = 6;
+    pub const FLOOR: u32 = 8;
+    pub const CEIL: u32 = 9;
+    pub const FRACT: u32 = 10;
+    pub const RADIANS: u32 = 11;
+    pub const DEGREES: u32 = 12;
+    pub const SIN: u32 = 13;
+    pub const COS: u32 = 14;
+    pub const TAN: u32 = 15;
+    pub const ASIN: u32 = 16;
+    pub const ACOS: u32 = 17;
+    pub const ATAN: u32 = 18;
+    pub const ATAN2: u32 = 25;
+    pub const POW: u32 = 26;
+    pub const EXP: u32 = 27;
+    pub const LOG: u32 = 28;
+    pub const EXP2: u32 = 29;
+    pub const LOG2: u32 = 30;
+    pub const SQRT: u32 = 31;
+    pub const INVERSE_SQRT: u32 = 32;
+    pub const MATRIX_INVERSE: u32 = 34;
+    pub const F_MIN: u32 = 37;
+    pub const F_MAX: u32 = 40;
+    pub const F_CLAMP: u32 = 43;
+    pub const F_MIX: u32 = 46;
+    pub const STEP: u32 = 48;
+    pub const SMOOTH_STEP: u32 = 49;
+    pub const LENGTH: u32 = 66;
+    pub const DISTANCE: u32 = 67;
+    pub const CROSS: u32 = 68;
+    pub const NORMALIZE: u32 = 69;
+    pub const FACE_FORWARD: u32 = 70;
+    pub const REFLECT: u32 = 71;
+    pub const REFRACT: u32 = 72;
+}
+
+type Enumerants = &'static [(u32, &'static str)];
+
+const CAPABILITIES: Enumerants = &[(0, "Matrix"), (1, "Shader"), (50, "ImageQuery")];
+const ADDRESSING_MODELS: Enumerants = &[(0, "Logical")];
+const MEMORY_MODELS: Enumerants = &[(0, "Simple"), (1, "GLSL450")];
+const EXECUTION_MODELS: Enumerants = &[(0, "Vertex"), (4, "Fragment"), (5, "GLCompute")];
+const EXECUTION_MODES: Enumerants = &[(7, "OriginUpperLeft"), (8, "OriginLowerLeft"), (12, "DepthReplacing")];
+const SOURCE_LANGUAGES: Enumerants = &[(0, "Unknown"), (2, "GLSL")];
+const STORAGE_CLASSES: Enumerants = &[
+    (0, "UniformConstant"), (1, "Input"), (2, "Uniform"), (3, "Output"), (6, "Private"), (7, "Function"),
+];
+const DIMS: Enumerants = &[(0, "1D"), (1, "2D"), (2, "3D"), (3, "Cube")];
+const IMAGE_FORMATS: Enumerants = &[(0, "Unknown")];
+const DECORATIONS: Enumerants = &[
+    (0, "RelaxedPrecision"), (2, "Block"), (4, "RowMajor"), (5, "ColMajor"), (6, "ArrayStride"),
+    (7, "MatrixStride"), (11, "BuiltIn"), (13, "NoPerspective"), (14, "Flat"), (30, "Location"),
+    (33, "Binding"), (34, "DescriptorSet"), (35, "Offset"),
+];
+const BUILT_INS: Enumerants = &[
+    (0, "Position"), (1, "PointSize"), (15, "FragCoord"), (16, "PointCoord"), (17, "FrontFacing"),
+    (22, "FragDepth"), (42, "VertexIndex"), (43, "InstanceIndex"),
+];
+const FUNCTION_CONTROLS: Enumerants = &[(1, "Inline"), (2, "DontInline"), (4, "Pure"), (8, "Const")];
+const SELECTION_CONTROLS: Enumerants = &[(1, "Flatten"), (2, "DontFlatten")];
+const LOOP_CONTROLS: Enumerants = &[(1, "Unroll"), (2, "DontUnroll")];
+const IMAGE_OPERANDS: Enumerants = &[(1, "Bias"), (2, "Lod"), (4, "Grad"), (8, "ConstOffset")];
+const GLSL_STD_450: Enumerants = &[
+    (1, "Round"), (2, "RoundEven"), (3, "Trunc"), (4, "FAbs"), (5, "SAbs"), (6, "FSign"), (7, "SSign"),
+    (8, "Floor"), (9, "Ceil"), (10, "Fract"), (11, "Radians"), (12, "Degrees"), (13, "Sin"), (14, "Cos"),
+    (15, "Tan"), (16, "Asin"), (17, "Acos"), (18, "Atan"), (25, "Atan2"), (26, "Pow"), (27, "Exp"),
+    (28, "Log"), (29, "Exp2"), (30, "Log2"), (31, "Sqrt"), (32, "InverseSqrt"), (33, "Determinant"),
+    (34, "MatrixInverse"), (37, "FMin"), (38, "UMin"), (39, "SMin"), (40, "FMax"), (41, "UMax"), (42, "SMax"),
+    (43, "FClamp"), (44, "UClamp"), (45, "SClamp"), (46, "FMix"), (48, "Step"), (49, "SmoothStep"),
+    (66, "Length"), (67, "Distance"), (68, "Cross"), (69, "Normalize"), (70, "FaceForward"), (71, "Reflect"),
+    (72, "Refract"),
+];
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Id,
+    // all remaining words are ids
+    Ids,
+    Literal,
+    Literals,
+    String,
+    // a literal of the result type, for `OpConstant`
+    Value,
+    Enum(Enumerants),
+    Mask(Enumerants),
+    // an instruction of the set named by the previous operand
+    ExtInst,
+    // a decoration followed by its literals
+    Decoration,
+    // an image operands mask followed by its ids
+    ImageOperands,
+}
+
+impl Op {
+    pub fn from_u32(opcode: u32) -> Option<Op> {
+        OPS.iter().find( | op | **op as u32 == opcode).cloned()
+    }
+
+    // whether the instruction has a result type and a result id, then the rest of its operands
+    fn signature(self) -> (bool, bool, &'static [Operand]) {
+        use Operand::*;
+        match self {
+            Op::Source => (false, false, &[Enum(SOURCE_LANGUAGES), Literal, Id, String]),
+            Op::Name => (false, false, &[Id, String]),
+            Op::MemberName => (false, false, &[Id, Literal, String]),
+            Op::ExtInstImport => (false, true, &[String]),
+            Op::ExtInst => (true, true, &[Id, ExtInst, Ids]),
+            Op::MemoryModel => (false, false, &[Enum(ADDRESSING_MODELS), Enum(MEMORY_MODELS)]),
+            Op::EntryPoint => (false, false, &[Enum(EXECUTION_MODELS), Id, String, Ids]),
+            Op::ExecutionMode => (false, false, &[Id, Enum(EXECUTION_MODES), Literals]),
+            Op::Capability => (false, false, &[Enum(CAPABILITIES)]),
+            Op::TypeVoid | Op::TypeBool => (false, true, &[]),
+            Op::TypeInt => (false, true, &[Literal, Literal]),
+            Op::TypeFloat => (false, true, &[Literal]),
+            Op::TypeVector | Op::TypeMatrix => (false, true, &[Id, Literal]),
+            Op::TypeImage => (false, true, &[Id, Enum(DIMS), Literal, Literal, Literal, Literal, Enum(IMAGE_FORMATS)]),
+            Op::TypeSampledImage => (false, true, &[Id]),
+            Op::TypeArray => (false, true, &[Id, Id]),
+            Op::TypeStruct | Op::TypeFunction => (false, true, &[Ids]),
+            Op::TypePointer => (false, true, &[Enum(STORAGE_CLASSES), Id]),
+            Op::ConstantTrue | Op::ConstantFalse => (true, true, &[]),
+            Op::Constant => (true, true, &[Value]),
+            Op::Function => (true, true, &[Mask(FUNCTION_CONTROLS), Id]),
+            Op::FunctionParameter => (true, true, &[]),
+            Op::FunctionEnd | Op::Kill | Op::Return | Op::Unreachable => (false, false, &[]),
+            Op::Variable => (true, true, &[Enum(STORAGE_CLASSES), Id]),
+            Op::Store => (false, false, &[Id, Id]),
+            Op::Decorate => (false, false, &[Id, Decoration]),
+            Op::MemberDecorate => (false, false, &[Id, Literal, Decoration]),
+            Op::VectorShuffle => (true, true, &[Id, Id, Literals]),
+            Op::CompositeExtract => (true, true, &[Id, Literals]),
+            Op::CompositeInsert => (true, true, &[Id, Id, Literals]),
+            Op::ImageSampleImplicitLod | Op::ImageSampleExplicitLod | Op::ImageFetch => (true, true, &[Id, Id, ImageOperands]),
+            Op::ImageSampleDrefImplicitLod | Op::ImageSampleDrefExplicitLod => (true, true, &[Id, Id, Id, ImageOperands]),
+            Op::LoopMerge => (false, false, &[Id, Id, Mask(LOOP_CONTROLS)]),
+            Op::SelectionMerge => (false, false, &[Id, Mask(SELECTION_CONTROLS)]),
+            Op::Label => (false, true, &[]),
+            Op::Branch | Op::ReturnValue => (false, false, &[Id]),
+            Op::BranchConditional => (false, false, &[Id, Id, Id, Literals]),
+            _ => (true, true, &[Ids]),
+        }
+    }
+}
+
+/// Appends an instruction to `words`.
+pub fn push_inst(words: &mut Vec<u32>, op: Op, operands: &[u32]) {
+    words.push(((operands.len() as u32 + 1) << 16) | op as u32);
+    words.extend_from_slice(operands);
+}
+
+/// A literal string operand, nul terminated and padded to a whole word.
+pub fn string_words(string: &str) -> Vec<u32> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+    while bytes.len() % 4 != 0 {
+        bytes.push(0);
+    }
+    bytes.chunks(4).map( | chunk | u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+}
+
+// the string at the start of `words`, and the number of words it takes
+fn decode_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1)
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+fn enumerant(enumerants: Enumerants, value: u32) -> String {
+    match enumerants.iter().find( | (v, _) | *v == value) {
+        Some((_, name)) => name.to_string(),
+        None => value.to_string()
+    }
+}
+
+fn mask(enumerants: Enumerants, value: u32) -> String {
+    if value == 0 {
+        return "None".to_string()
+    }
+    let mut names = Vec::new();
+    for bit in 0..32 {
+        if value & (1 << bit) != 0 {
+            names.push(enumerant(enumerants, 1 << bit));
+        }
+    }
+    names.join("|")
+}
+
+#[derive(Clone, Copy)]
+enum ScalarKind {
+    Float,
+    Int,
+    Uint,
+}
+
+/// Disassembles a SPIR-V module into the text form of `spirv-dis`, with ids named after their
+/// `OpName`, or after the type or constant they define.
+pub fn disassemble_spirv(words: &[u32]) -> String {
+    let mut string = String::new();
+    if words.len() < 5 || words[0] != MAGIC {
+        writeln!(string, "; not a SPIR-V module").unwrap();
+        return string
+    }
+    writeln!(string, "; SPIR-V").unwrap();
+    writeln!(string, "; Version: {}.{}", (words[1] >> 16) & 0xff, (words[1] >> 8) & 0xff).unwrap();
+    writeln!(string, "; Generator: {}", words[2]).unwrap();
+    writeln!(string, "; Bound: {}", words[3]).unwrap();
+    writeln!(string, "; Schema: {}", words[4]).unwrap();
+
+    let mut insts = Vec::new();
+    let mut offset = 5;
+    while offset < words.len() {
+        let count = (words[offset] >> 16) as usize;
+        if count == 0 || offset + count > words.len() {
+            break
+        }
+        insts.push((words[offset] & 0xffff, &words[offset + 1..offset + count]));
+        offset += count;
+    }
+
+    let names = friendly_names(&insts);
+    let id = | id: u32 | match names.get(&id) {
+        Some(name) => format!("%{}", name),
+        None => format!("%{}", id)
+    };
+
+    let mut scalar_kinds = HashMap::new();
+    let mut glsl_std_450_sets = HashSet::new();
+    for (opcode, operands) in &insts {
+        let op = match Op::from_u32(*opcode) {
+            Some(op) => op,
+            None => {
+                write!(string, "               Op{}", opcode).unwrap();
+                for operand in operands.iter() {
+                    write!(string, " {}", operand).unwrap();
+                }
+                writeln!(string).unwrap();
+                continue
+            }
+        };
+        match op {
+            Op::TypeFloat => {scalar_kinds.insert(operands[0], ScalarKind::Float);}
+            Op::TypeInt => {
+                scalar_kinds.insert(operands[0], if operands[2] == 1 {ScalarKind::Int} else {ScalarKind::Uint});
+            }
+            Op::ExtInstImport if decode_string(&operands[1..]).0 == "GLSL.std.450" => {
+                glsl_std_450_sets.insert(operands[0]);
+            }
+            _ => ()
+        }
+
+        let (has_result_ty, has_result, kinds) = op.signature();
+        let mut cursor = 0;
+        let result_ty = if has_result_ty {
+            cursor += 1;
+            Some(operands[0])
+        } else {
+            None
+        };
+        if has_result {
+            write!(string, "{:>12} = ", id(operands[cursor])).unwrap();
+            cursor += 1;
+        }
+        else {
+            write!(string, "               ").unwrap();
+        }
+        write!(string, "Op{:?}", op).unwrap();
+        if let Some(result_ty) = result_ty {
+            write!(string, " {}", id(result_ty)).unwrap();
+        }
+        for kind in kinds {
+            if cursor >= operands.len() {
+                break
+            }
+            match kind {
+                Operand::Id => write!(string, " {}", id(operands[cursor])).unwrap(),
+                Operand::Ids => {
+                    for operand in &operands[cursor..] {
+                        write!(string, " {}", id(*operand)).unwrap();
+                    }
+                    cursor = operands.len();
+                }
+                Operand::Literal => write!(string, " {}", operands[cursor]).unwrap(),
+                Operand::Literals => {
+                    for operand in &operands[cursor..] {
+                        write!(string, " {}", operand).unwrap();
+                    }
+                    cursor = operands.len();
+                }
+                Operand::String => {
+                    let (value, len) = decode_string(&operands[cursor..]);
+                    write!(string, " {:?}", value).unwrap();
+                    cursor += len - 1;
+                }
+                Operand::Value => {
+                    let value = operands[cursor];
+                    match result_ty.and_then( | ty | scalar_kinds.get(&ty)) {
+                        Some(ScalarKind::Float) => write!(string, " {}", f32::from_bits(value)).unwrap(),
+                        Some(ScalarKind::Int) => write!(string, " {}", value as i32).unwrap(),
+                        _ => write!(string, " {}", value).unwrap(),
+                    }
+                }
+                Operand::Enum(enumerants) => write!(string, " {}", enumerant(enumerants, operands[cursor])).unwrap(),
+                Operand::Mask(enumerants) => write!(string, " {}", mask(enumerants, operands[cursor])).unwrap(),
+                Operand::ExtInst => {
+                    if glsl_std_450_sets.contains(&operands[cursor - 1]) {
+                        write!(string, " {}", enumerant(GLSL_STD_450, operands[cursor])).unwrap()
+                    }
+                    else {
+                        write!(string, " {}", operands[cursor]).unwrap()
+                    }
+                }
+                Operand::Decoration => {
+                    let decoration = operands[cursor];
+                    write!(string, " {}", enumerant(DECORATIONS, decoration)).unwrap();
+                    for operand in &operands[cursor + 1..] {
+                        if decoration == decoration::BUILT_IN {
+                            write!(string, " {}", enumerant(BUILT_INS, *operand)).unwrap();
+                        }
+                        else {
+                            write!(string, " {}", operand).unwrap();
+                        }
+                    }
+                    cursor = operands.len();
+                }
+                Operand::ImageOperands => {
+                    write!(string, " {}", mask(IMAGE_OPERANDS, operands[cursor])).unwrap();
+                    for operand in &operands[cursor + 1..] {
+                        write!(string, " {}", id(*operand)).unwrap();
+                    }
+                    cursor = operands.len();
+                }
+            }
+            cursor += 1;
+        }
+        writeln!(string).unwrap();
+    }
+    string
+}
+
+// `OpName`s first, then names derived from types and scalar constants, made unique
+fn friendly_names(insts: &[(u32, &[u32])]) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    let mut used = HashSet::new();
+    let mut assign = | names: &mut HashMap<u32, String>, id: u32, name: String | {
+        if names.contains_key(&id) || name.is_empty() {
+            return
+        }
+        let name: String = name.chars().map( | c | if c.is_ascii_alphanumeric() {c} else {'_'}).collect();
+        let mut unique = name.clone();
+        let mut index = 1;
+        while !used.insert(unique.clone()) {
+            unique = format!("{}_{}", name, index);
+            index += 1;
+        }
+        names.insert(id, unique);
+    };
+    for (opcode, operands) in insts {
+        if *opcode == Op::Name as u32 {
+            assign(&mut names, operands[0], decode_string(&operands[1..]).0);
+        }
+    }
+    let mut scalar_kinds = HashMap::new();
+    for (opcode, operands) in insts {
+        let name_of = | names: &HashMap<u32, String>, id: u32 | {
+            names.get(&id).cloned().unwrap_or_else( | | id.to_string())
+        };
+        let name = match Op::from_u32(*opcode) {
+            Some(Op::TypeVoid) => "void".to_string(),
+            Some(Op::TypeBool) => "bool".to_string(),
+            Some(Op::TypeInt) => {
+                let kind = if operands[2] == 1 {ScalarKind::Int} else {ScalarKind::Uint};
+                scalar_kinds.insert(operands[0], kind);
+                if operands[2] == 1 {"int"} else {"uint"}.to_string()
+            }
+            Some(Op::TypeFloat) => {
+                scalar_kinds.insert(operands[0], ScalarKind::Float);
+                "float".to_string()
+            }
+            Some(Op::TypeVector) => format!("v{}{}", operands[2], name_of(&names, operands[1])),
+            Some(Op::TypeMatrix) => format!("mat{}{}", operands[2], name_of(&names, operands[1])),
+            Some(Op::TypeImage) => format!(
+                "type_{}_image{}",
+                enumerant(DIMS, operands[2]).to_lowercase(),
+                if operands[4] == 1 {"_array"} else {""}
+            ),
+            Some(Op::TypeSampledImage) => "type_sampled_image".to_string(),
+            Some(Op::TypePointer) => format!(
+                "_ptr_{}_{}",
+                enumerant(STORAGE_CLASSES, operands[1]),
+                name_of(&names, operands[2])
+            ),
+            Some(Op::ConstantTrue) => "true".to_string(),
+            Some(Op::ConstantFalse) => "false".to_string(),
+            Some(Op::Constant) => {
+                let value = match scalar_kinds.get(&operands[0]) {
+                    Some(ScalarKind::Float) => f32::from_bits(operands[2]).to_string(),
+                    Some(ScalarKind::Int) => (operands[2] as i32).to_string(),
+                    _ => operands[2].to_string()
+                };
+                format!("{}_{}", name_of(&names, operands[0]), value.replace('-', "n"))
+            }
+            _ => continue
+        };
+        let id = if *opcode == Op::Constant as u32 || *opcode == Op::ConstantTrue as u32 || *opcode == Op::ConstantFalse as u32 {
+            operands[1]
+        } else {
+            operands[0]
+        };
+        assign(&mut names, id, name);
+    }
+    names
+}
@@ -9,6 +9,8 @@ use{
     crate::{
         makepad_live_compiler::*,
         shader_ast::*,
+        analyse::ShaderAnalyseOptions,
+        const_eval::ConstEvaluator,
         lhs_check::LhsChecker,
         swizzle::Swizzle,
         util::CommaSep,
@@ -16,6 +18,10 @@ use{
     }
 };
 
+pub fn undefined_var_message(ident: Ident) -> String {
+    format!("`{}` is not defined in this scope", ident)
+}
+
 #[derive(Clone)]
 pub struct TyChecker<'a> {
     pub scopes: &'a Scopes,
@@ -36,7 +42,7 @@ impl<'a> TyChecker<'a> {
             } => self.ty_check_array_ty_expr(ty_expr.span, elem_ty_expr, *len),
             TyExprKind::Lit {ty_lit} => self.ty_check_lit_ty_expr(ty_expr.span, *ty_lit),
             TyExprKind::Struct(struct_ptr) => Ok(Ty::Struct(*struct_ptr)),
-            TyExprKind::Enum(live_type) => Ok(Ty::Enum(*live_type)),
+            TyExprKind::Enum(enum_name) => Ok(Ty::Enum(*enum_name)),
             TyExprKind::DrawShader => Ok(Ty::DrawShader),
             TyExprKind::ClosureDecl {return_ty_expr, params, return_ty} => {
                 // check the closure
@@ -59,19 +65,89 @@ impl<'a> TyChecker<'a> {
     
     fn ty_check_array_ty_expr(
         &mut self,
-        _span: TokenSpan,
+        span: TokenSpan,
         elem_ty_expr: &TyExpr,
         len: u32,
     ) -> Result<Ty, LiveError> {
-        let elem_ty = Rc::new(self.ty_check_ty_expr(elem_ty_expr) ?);
-        let len = len as usize;
-        Ok(Ty::Array {elem_ty, len})
+        let elem_ty = self.ty_check_ty_expr(elem_ty_expr) ?;
+        if elem_ty.is_texture() || matches!(elem_ty, Ty::Array {..} | Ty::DrawShader | Ty::ClosureDecl | Ty::ClosureDef(_)) {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: format!("can't make an array of `{}`", elem_ty),
+            })
+        }
+        if len == 0 {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("arrays can't be empty"),
+            })
+        }
+        Ok(Ty::Array {elem_ty: Rc::new(elem_ty), len: len as usize})
     }
     
     fn ty_check_lit_ty_expr(&mut self, _span: TokenSpan, ty_lit: TyLit) -> Result<Ty, LiveError> {
         Ok(ty_lit.to_ty())
     }
     
+    pub fn ty_check_param(&mut self, param: &Param) -> Result<Ty, LiveError> {
+        let ty = self.ty_check_ty_expr(&param.ty_expr) ?;
+        if let Ty::Array {..} = ty {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: param.span.into(),
+                message: String::from("function parameters can't be arrays"),
+            })
+        }
+        Ok(ty)
+    }
+    
+    pub fn ty_check_return_ty_expr(&mut self, return_ty_expr: &Option<TyExpr>) -> Result<Ty, LiveError> {
+        let return_ty_expr = match return_ty_expr {
+            Some(return_ty_expr) => return_ty_expr,
+            None => return Ok(Ty::Void)
+        };
+        let ty = self.ty_check_ty_expr(return_ty_expr) ?;
+        if ty.is_texture() {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: return_ty_expr.span.into(),
+                message: String::from("functions can't return textures"),
+            })
+        }
+        Ok(ty)
+    }
+    
+    /// Like `ty_check_expr`, but also takes the array literals only a `let` can be initialized with.
+    pub fn ty_check_let_init_expr(&mut self, expr: &Expr) -> Result<Ty, LiveError> {
+        let (span, elem_exprs) = match &expr.kind {
+            ExprKind::ArrayCons {span, elem_exprs} => (*span, elem_exprs),
+            _ => return self.ty_check_expr(expr)
+        };
+        let elem_ty = match elem_exprs.first() {
+            Some(elem_expr) => self.ty_check_expr(elem_expr) ?,
+            None => return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("arrays can't be empty"),
+            })
+        };
+        for elem_expr in &elem_exprs[1..] {
+            self.ty_check_expr_with_expected_ty(elem_expr.span, elem_expr, &elem_ty) ?;
+        }
+        if elem_ty.is_texture() || matches!(elem_ty, Ty::Array {..} | Ty::Void) {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: format!("can't make an array of `{}`", elem_ty),
+            })
+        }
+        let ty = Ty::Array {elem_ty: Rc::new(elem_ty), len: elem_exprs.len()};
+        *expr.ty.borrow_mut() = Some(ty.clone());
+        Ok(ty)
+    }
+    
     pub fn ty_check_expr_with_expected_ty(
         &mut self,
         span: TokenSpan,
@@ -84,7 +160,7 @@ impl<'a> TyChecker<'a> {
                 origin: live_error_origin!(),
                 span:span.into(),
                 message: format!(
-                    "can't match expected type `{}` with actual type `{}",
+                    "can't match expected type `{}` with actual type `{}`",
                     expected_ty,
                     actual_ty
                 ),
@@ -153,7 +229,12 @@ impl<'a> TyChecker<'a> {
                 ref kind,
                 var_resolve,
                 ident,
-            } => self.ty_check_var_expr(span, kind, var_resolve, ident),
+            } => self.ty_check_var_expr(span, kind, var_resolve, ident, false),
+            ExprKind::ArrayCons {span, ..} => Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("an array literal can only initialize a `let`"),
+            }),
             ExprKind::StructCons {
                 struct_ptr,
                 span,
@@ -184,6 +265,20 @@ impl<'a> TyChecker<'a> {
         self.ty_check_expr_with_expected_ty(span, expr, &Ty::Bool) ?;
         let ty_if_true = self.ty_check_expr(expr_if_true) ?;
         self.ty_check_expr_with_expected_ty(span, expr_if_false, &ty_if_true) ?;
+        if let Ty::Array {..} = ty_if_true {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("a conditional can't pick between arrays"),
+            });
+        }
+        if ty_if_true.is_texture() {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("a conditional can't pick between textures, sample both instead"),
+            });
+        }
         Ok(ty_if_true)
     }
     
@@ -201,11 +296,31 @@ impl<'a> TyChecker<'a> {
                 | BinOp::AddAssign
                 | BinOp::SubAssign
                 | BinOp::MulAssign
-                | BinOp::DivAssign => {
+                | BinOp::DivAssign
+                | BinOp::RemAssign
+                | BinOp::BitAndAssign
+                | BinOp::BitOrAssign
+                | BinOp::BitXorAssign
+                | BinOp::ShlAssign
+                | BinOp::ShrAssign => {
                 self.lhs_checker().lhs_check_expr(left_expr) ?;
             }
             _ => {}
         }
+        if let (BinOp::Assign, Ty::Array {..}) = (op, &left_ty) {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("arrays can't be assigned, assign their elements instead"),
+            });
+        }
+        if matches!(op, BinOp::Assign) && left_ty.is_texture() {
+            return Err(LiveError {
+                origin: live_error_origin!(),
+                span: span.into(),
+                message: String::from("textures can't be assigned"),
+            });
+        }
         match op {
             BinOp::Assign => {
                 if left_ty == right_ty {
@@ -223,6 +338,13 @@ impl<'a> TyChecker<'a> {
                 (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
                 (Ty::Ivec4, Ty::Int) => Some(Ty::Ivec4),
                 (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uint) => Some(Ty::Uvec2),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uint) => Some(Ty::Uvec3),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uint) => Some(Ty::Uvec4),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
                 (Ty::Vec2, Ty::Float) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Vec2) => Some(Ty::Vec2),
                 (Ty::Vec3, Ty::Float) => Some(Ty::Vec3),
@@ -246,6 +368,13 @@ impl<'a> TyChecker<'a> {
                 (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
                 (Ty::Ivec4, Ty::Int) => Some(Ty::Ivec4),
                 (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uint) => Some(Ty::Uvec2),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uint) => Some(Ty::Uvec3),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uint) => Some(Ty::Uvec4),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
                 (Ty::Vec2, Ty::Float) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Vec2) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Mat2) => Some(Ty::Vec2),
@@ -274,6 +403,10 @@ impl<'a> TyChecker<'a> {
                 (Ty::Ivec2, Ty::Ivec2) => Some(Ty::Bool),
                 (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Bool),
                 (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Bool),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Bool),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Bool),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Bool),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Bool),
                 (Ty::Vec2, Ty::Vec2) => Some(Ty::Bool),
                 (Ty::Vec3, Ty::Vec3) => Some(Ty::Bool),
                 (Ty::Vec4, Ty::Vec4) => Some(Ty::Bool),
@@ -284,6 +417,7 @@ impl<'a> TyChecker<'a> {
             },
             BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => match (&left_ty, &right_ty) {
                 (Ty::Int, Ty::Int) => Some(Ty::Bool),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Bool),
                 (Ty::Float, Ty::Float) => Some(Ty::Bool),
                 _ => None,
             },
@@ -302,6 +436,13 @@ impl<'a> TyChecker<'a> {
                 (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
                 (Ty::Ivec4, Ty::Int) => Some(Ty::Ivec4),
                 (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uint) => Some(Ty::Uvec2),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uint) => Some(Ty::Uvec3),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uint) => Some(Ty::Uvec4),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
                 (Ty::Vec2, Ty::Float) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Vec2) => Some(Ty::Vec2),
                 (Ty::Vec3, Ty::Float) => Some(Ty::Vec3),
@@ -331,6 +472,13 @@ impl<'a> TyChecker<'a> {
                 (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
                 (Ty::Ivec4, Ty::Int) => Some(Ty::Ivec4),
                 (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uint) => Some(Ty::Uvec2),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uint) => Some(Ty::Uvec3),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uint) => Some(Ty::Uvec4),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
                 (Ty::Vec2, Ty::Float) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Vec2) => Some(Ty::Vec2),
                 (Ty::Vec2, Ty::Mat2) => Some(Ty::Vec2),
@@ -351,12 +499,54 @@ impl<'a> TyChecker<'a> {
                 (Ty::Mat4, Ty::Mat4) => Some(Ty::Mat4),
                 _ => None,
             },
+            BinOp::Rem | BinOp::RemAssign => match (&left_ty, &right_ty) {
+                (Ty::Int, Ty::Int) => Some(Ty::Int),
+                (Ty::Ivec2, Ty::Int) => Some(Ty::Ivec2),
+                (Ty::Ivec2, Ty::Ivec2) => Some(Ty::Ivec2),
+                (Ty::Ivec3, Ty::Int) => Some(Ty::Ivec3),
+                (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
+                (Ty::Ivec4, Ty::Int) => Some(Ty::Ivec4),
+                (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uint) => Some(Ty::Uvec2),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uint) => Some(Ty::Uvec3),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uint) => Some(Ty::Uvec4),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
+                _ => None,
+            },
+            // WGSL has no bitwise operators between a vector and a scalar
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor
+                | BinOp::BitAndAssign | BinOp::BitOrAssign | BinOp::BitXorAssign => match (&left_ty, &right_ty) {
+                (Ty::Int, Ty::Int) => Some(Ty::Int),
+                (Ty::Ivec2, Ty::Ivec2) => Some(Ty::Ivec2),
+                (Ty::Ivec3, Ty::Ivec3) => Some(Ty::Ivec3),
+                (Ty::Ivec4, Ty::Ivec4) => Some(Ty::Ivec4),
+                (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
+                _ => None,
+            },
+            // the shift amount can be either signedness, but needs as many components as the value
+            BinOp::Shl | BinOp::Shr | BinOp::ShlAssign | BinOp::ShrAssign => match (&left_ty, &right_ty) {
+                (Ty::Int, Ty::Int) | (Ty::Int, Ty::Uint) => Some(Ty::Int),
+                (Ty::Uint, Ty::Int) | (Ty::Uint, Ty::Uint) => Some(Ty::Uint),
+                (Ty::Ivec2, Ty::Ivec2) | (Ty::Ivec2, Ty::Uvec2) => Some(Ty::Ivec2),
+                (Ty::Ivec3, Ty::Ivec3) | (Ty::Ivec3, Ty::Uvec3) => Some(Ty::Ivec3),
+                (Ty::Ivec4, Ty::Ivec4) | (Ty::Ivec4, Ty::Uvec4) => Some(Ty::Ivec4),
+                (Ty::Uvec2, Ty::Ivec2) | (Ty::Uvec2, Ty::Uvec2) => Some(Ty::Uvec2),
+                (Ty::Uvec3, Ty::Ivec3) | (Ty::Uvec3, Ty::Uvec3) => Some(Ty::Uvec3),
+                (Ty::Uvec4, Ty::Ivec4) | (Ty::Uvec4, Ty::Uvec4) => Some(Ty::Uvec4),
+                _ => None,
+            },
         }
         .ok_or_else( || LiveError {
             origin: live_error_origin!(),
             span:span.into(),
             message: format!(
-                "can't apply binary operator `{}` to operands of type `{}` and `{}",
+                "can't apply binary operator `{}` to operands of type `{}` and `{}`",
                 op,
                 left_ty,
                 right_ty
@@ -368,12 +558,17 @@ impl<'a> TyChecker<'a> {
     fn ty_check_un_expr(&mut self, span: TokenSpan, op: UnOp, expr: &Expr) -> Result<Ty, LiveError> {
         let ty = self.ty_check_expr(expr) ?;
         match op {
+            // on integers `!` flips the bits, like in Rust
             UnOp::Not => match ty {
                 Ty::Bool => Some(Ty::Bool),
+                _ if ty.is_integer() => Some(ty.clone()),
                 _ => None,
             },
             UnOp::Neg => match ty {
                 Ty::Int => Some(Ty::Int),
+                Ty::Ivec2 => Some(Ty::Ivec2),
+                Ty::Ivec3 => Some(Ty::Ivec3),
+                Ty::Ivec4 => Some(Ty::Ivec4),
                 Ty::Float => Some(Ty::Float),
                 Ty::Vec2 => Some(Ty::Vec2),
                 Ty::Vec3 => Some(Ty::Vec3),
@@ -560,6 +755,17 @@ impl<'a> TyChecker<'a> {
             self.ty_check_expr(arg_expr) ?;
         }
         
+        if let Some(TextureOp::Offset) | Some(TextureOp::CompareOffset) = TextureOp::from_builtin(ident) {
+            let offset_expr = arg_exprs.last().unwrap();
+            if offset_expr.const_offset().is_none() {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: offset_expr.span.into(),
+                    message: format!("the offset of `{}` must be an `ivec2` or `ivec3` of int literals", ident),
+                })
+            }
+        }
+        
         let builtin = self.shader_registry.builtins.get(&ident).unwrap();
         let arg_tys = arg_exprs
             .iter()
@@ -737,6 +943,13 @@ impl<'a> TyChecker<'a> {
                         4 => Ty::Ivec4,
                         _ => panic!(),
                     },
+                    Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => match swizzle.len() {
+                        1 => Ty::Uint,
+                        2 => Ty::Uvec2,
+                        3 => Ty::Uvec3,
+                        4 => Ty::Uvec4,
+                        _ => panic!(),
+                    },
                     Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => match swizzle.len() {
                         1 => Ty::Float,
                         2 => Ty::Vec2,
@@ -755,6 +968,9 @@ impl<'a> TyChecker<'a> {
                 }) ? .ty_expr .ty .borrow() .as_ref() .unwrap() .clone())
             },
             Ty::DrawShader => {
+                if let Some(stage_builtin) = self.shader_registry.draw_shader_def.find_stage_builtin(field_ident) {
+                    return Ok(stage_builtin.ty())
+                }
                 Ok(self.shader_registry.draw_shader_def.find_field(field_ident) .ok_or(LiveError {
                     origin: live_error_origin!(),
                     span:span.into(),
@@ -775,15 +991,25 @@ impl<'a> TyChecker<'a> {
         expr: &Expr,
         index_expr: &Expr,
     ) -> Result<Ty, LiveError> {
-        let ty = self.ty_check_expr(expr) ?;
+        // a const array can only be indexed, so it is checked here rather than in `ty_check_expr`
+        let ty = match expr.kind {
+            ExprKind::Var {span, ref kind, var_resolve, ident} => {
+                let ty = self.ty_check_var_expr(span, kind, var_resolve, ident, true) ?;
+                *expr.ty.borrow_mut() = Some(ty.clone());
+                ty
+            }
+            _ => self.ty_check_expr(expr) ?
+        };
         let index_ty = self.ty_check_expr(index_expr) ?;
-        let elem_ty = match ty {
-            Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => Ty::Bool,
-            Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => Ty::Int,
-            Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => Ty::Float,
-            Ty::Mat2 => Ty::Vec2,
-            Ty::Mat3 => Ty::Vec3,
-            Ty::Mat4 => Ty::Vec4,
+        let (elem_ty, len) = match ty {
+            Ty::Bvec2 | Ty::Bvec3 | Ty::Bvec4 => (Ty::Bool, ty.slots()),
+            Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => (Ty::Int, ty.slots()),
+            Ty::Uvec2 | Ty::Uvec3 | Ty::Uvec4 => (Ty::Uint, ty.slots()),
+            Ty::Vec2 | Ty::Vec3 | Ty::Vec4 => (Ty::Float, ty.slots()),
+            Ty::Mat2 => (Ty::Vec2, 2),
+            Ty::Mat3 => (Ty::Vec3, 3),
+            Ty::Mat4 => (Ty::Vec4, 4),
+            Ty::Array {ref elem_ty, len} => ((**elem_ty).clone(), len),
             _ => {
                 return Err(LiveError {
                     origin: live_error_origin!(),
@@ -799,6 +1025,16 @@ impl<'a> TyChecker<'a> {
                 message: "index is not an integer".into(),
             });
         }
+        let const_evaluator = ConstEvaluator {options: ShaderAnalyseOptions {no_const_collapse: false}};
+        if let Some(Val::Int(index)) = const_evaluator.try_const_eval_expr(index_expr) {
+            if index < 0 || index as usize >= len {
+                return Err(LiveError {
+                    origin: live_error_origin!(),
+                    span: index_expr.span.into(),
+                    message: format!("index out of bounds: the length is {} but the index is {}", len, index),
+                });
+            }
+        }
         Ok(elem_ty)
     }
     
@@ -883,6 +1119,7 @@ impl<'a> TyChecker<'a> {
         kind: &Cell<Option<VarKind >>,
         var_resolve: VarResolve,
         ident: Option<Ident>,
+        indexed: bool,
     ) -> Result<Ty, LiveError> {
         
         if let Some(ident) = ident{
@@ -919,6 +1156,17 @@ impl<'a> TyChecker<'a> {
                 kind.set(Some(VarKind::LiveValue(value_ptr)));
                 return Ok(ty_lit.to_ty());
             }
+            VarResolve::Const(const_ptr) => {
+                if !indexed {
+                    return Err(LiveError {
+                        origin: live_error_origin!(),
+                        span:span.into(),
+                        message: String::from("a const array can only be indexed"),
+                    })
+                }
+                kind.set(Some(VarKind::Const(const_ptr)));
+                return Ok(self.shader_registry.const_array(const_ptr).0);
+            }
             VarResolve::Function(fn_ptr) => {
                 return Err(LiveError {
                     origin: live_error_origin!(),
@@ -931,7 +1179,7 @@ impl<'a> TyChecker<'a> {
                 return Err(LiveError {
                     origin: live_error_origin!(),
                     span:span.into(),
-                    message: format!("`{}` is not defined in this scope", ident.unwrap()),
+                    message: undefined_var_message(ident.unwrap()),
                 })
             }
         }
@@ -0,0 +1,15 @@
+nanoshredder_macros::shader! {
+    attributes {position: vec3}
+    uniforms {Projection: mat4}
+
+    fn vertex(self) -> vec4 {
+        let = self.Projection * vec4(self.position, 1.0);
+        return #f00;
+    }
+
+    fn pixel(self) -> vec4 {
+        return #f00;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+nanoshredder_macros::shader! {
+    attributes {position: vec3}
+    uniforms {Projection: mat4}
+
+    fn vertex(self) -> vec4 {
+        return self.Projection * vec4(self.position, 1.0);
+    }
+
+    fn pixel(self) -> vec4 {
+        let c = vec2(1.0) + true;
+        return #f00;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,73 @@
+// Generated by nanoshredder, do not edit.
+#![allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals)]
+#![allow(unreachable_code, unused_labels, unused_mut, unused_parens, unused_variables)]
+
+use makepad_math::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Geometry {
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Instance {
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Uniforms {
+    pub lights: [Vec4; 4],
+    pub weights: [f32; 3],
+    pub Projection: Mat4,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Varyings {
+}
+
+struct DrawShader {
+    lights: [Vec4; 4],
+    weights: [f32; 3],
+    position: Vec3,
+    Projection: Mat4,
+}
+
+const const_9: [Vec4; 3] = [Vec4 {x: 1.0f32, y: 0.0f32, z: 0.0f32, w: 1.0f32}, Vec4 {x: 0.0f32, y: 1.0f32, z: 0.0f32, w: 1.0f32}, Vec4 {x: 0.0f32, y: 0.0f32, z: 1.0f32, w: 1.0f32}];
+const const_14: [f32; 3] = [0.25f32, 0.5f32, 1.0f32];
+
+fn fn_20_pixel(ds: &mut DrawShader) -> Vec4 {
+    let mut var_steps_0: [f32; 3] = [1.0f32, 2.0f32, 4.0f32];
+    let mut var_total_0: f32 = 0.0f32;
+    let mut for_i = 0;
+    while for_i < 3 {
+        let mut var_i_0 = for_i;
+        for_i += 1;
+        var_total_0 = (var_total_0 + ((var_steps_0[var_i_0 as usize] * ds.weights[var_i_0 as usize]) * const_14[var_i_0 as usize]));
+    }
+    var_steps_0[2 as usize] = 8.0f32;
+    return (((const_9[1 as usize] * var_total_0) + ds.lights[3 as usize]) + vec4(var_steps_0[2 as usize], 0.0f32, 0.0f32, 0.0f32));
+}
+
+fn fn_19_vertex(ds: &mut DrawShader) -> Vec4 {
+    return mat_mul_vec(ds.Projection, vec4(ds.position.x, ds.position.y, ds.position.z, 1.0f32));
+}
+
+pub fn vertex(geometry: &Geometry, instance: &Instance, uniforms: &Uniforms) -> (Vec4, Varyings) {
+    let mut ds = DrawShader {
+        lights: uniforms.lights,
+        weights: uniforms.weights,
+        position: geometry.position,
+        Projection: uniforms.Projection,
+    };
+    let position = fn_19_vertex(&mut ds);
+    (position, Varyings {})
+}
+
+pub fn pixel(varyings: &Varyings, uniforms: &Uniforms) -> Vec4 {
+    let mut ds = DrawShader {
+        lights: uniforms.lights,
+        weights: uniforms.weights,
+        position: Default::default(),
+        Projection: uniforms.Projection,
+    };
+    fn_20_pixel(&mut ds)
+}
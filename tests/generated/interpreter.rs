@@ -0,0 +1,109 @@
+// Generated by nanoshredder, do not edit.
+#![allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals)]
+#![allow(unreachable_code, unused_labels, unused_mut, unused_parens, unused_variables)]
+
+use makepad_math::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Geometry {
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Instance {
+    pub color: Vec4,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Uniforms {
+    pub scale: f32,
+    pub Projection: Mat4,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Varyings {
+    pub color: Vec4,
+    pub uv: Vec2,
+    pub total: f32,
+}
+
+// samples a texture at a uv coordinate
+pub struct Textures<'a> {
+    pub tex: &'a dyn Fn(Vec2) -> Vec4,
+}
+
+struct DrawShader<'a> {
+    tex: &'a dyn Fn(Vec2) -> Vec4,
+    color: Vec4,
+    scale: f32,
+    uv: Vec2,
+    total: f32,
+    position: Vec3,
+    Projection: Mat4,
+}
+
+const live_1: f32 = 2.0f32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct struct_10 {
+    f_dir: Vec3,
+    f_power: f32,
+}
+
+fn fn_13_bump(mut var_self_0: struct_10, mut var_amount_0: f32, ds: &mut DrawShader) -> ((), struct_10) {
+    var_self_0.f_power = (var_self_0.f_power + var_amount_0);
+    ((), var_self_0)
+}
+
+fn fn_18_pixel(ds: &mut DrawShader) -> Vec4 {
+    let mut var_l_0: struct_10 = (struct_10 {f_dir: vec3(0.0f32, 0.0f32, 1.0f32), f_power: 1.0f32});
+    ({let (r, t0) = fn_13_bump(var_l_0, 0.5f32, ds); var_l_0 = t0; r});
+    let mut var_c_0: Vec4 = (ds.tex)(((ds.uv * 0.5f32) + 0.25f32));
+    return ({let t1 = ((vec3(var_c_0.x, var_c_0.y, var_c_0.z) * vec3(ds.color.x, ds.color.y, ds.color.z)) * var_l_0.f_power); vec4(t1.x, t1.y, t1.z, smoothstep(0.0f32, 4.0f32, ds.total))});
+}
+
+fn fn_16_sum(mut param_f: &dyn Fn(f32, &mut DrawShader) -> f32, ds: &mut DrawShader) -> f32 {
+    return ((param_f(0.0f32, ds) + param_f(1.0f32, ds)) + param_f(2.0f32, ds));
+}
+
+fn fn_15_twice(mut var_v_0: Vec2, ds: &mut DrawShader) -> ((), Vec2) {
+    var_v_0 = (var_v_0 * live_1);
+    ((), var_v_0)
+}
+
+fn fn_17_vertex(ds: &mut DrawShader) -> Vec4 {
+    ds.uv = vec2(ds.position.x, ds.position.y);
+    ({let (r, t0) = fn_15_twice(ds.uv, ds); ds.uv = t0; r});
+    let mut var_r_0: f32 = (ds.scale * 2.0f32);
+    ds.total = fn_16_sum(&move |mut var_v_0: f32, ds: &mut DrawShader| -> f32 {
+        (var_v_0 * var_r_0)
+    }, ds);
+    return mat_mul_vec(ds.Projection, vec4(ds.position.x, ds.position.y, ds.position.z, 1.0f32));
+}
+
+pub fn vertex(geometry: &Geometry, instance: &Instance, uniforms: &Uniforms, textures: &Textures) -> (Vec4, Varyings) {
+    let mut ds = DrawShader {
+        tex: textures.tex,
+        color: instance.color,
+        scale: uniforms.scale,
+        uv: Default::default(),
+        total: Default::default(),
+        position: geometry.position,
+        Projection: uniforms.Projection,
+    };
+    let position = fn_17_vertex(&mut ds);
+    (position, Varyings {color: ds.color, uv: ds.uv, total: ds.total})
+}
+
+pub fn pixel(varyings: &Varyings, uniforms: &Uniforms, textures: &Textures) -> Vec4 {
+    let mut ds = DrawShader {
+        tex: textures.tex,
+        color: varyings.color,
+        scale: uniforms.scale,
+        uv: varyings.uv,
+        total: varyings.total,
+        position: Default::default(),
+        Projection: uniforms.Projection,
+    };
+    fn_18_pixel(&mut ds)
+}
@@ -0,0 +1,83 @@
+// Generated by nanoshredder, do not edit.
+#![allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals)]
+#![allow(unreachable_code, unused_labels, unused_mut, unused_parens, unused_variables)]
+
+use makepad_math::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Geometry {
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Instance {
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Uniforms {
+    pub Projection: Mat4,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Varyings {
+    pub uv: Vec2,
+}
+
+struct DrawShader {
+    uv: Vec2,
+    position: Vec3,
+    Projection: Mat4,
+}
+
+fn fn_3_halve(mut var_v_0: f32, ds: &mut DrawShader) -> ((), f32) {
+    var_v_0 = (var_v_0 * 0.5f32);
+    ((), var_v_0)
+}
+
+fn fn_2_rotate(mut var_a_0: f32, ds: &mut DrawShader) -> Mat2 {
+    return Mat2 {v: [cos(var_a_0), sin(var_a_0), (-sin(var_a_0)), cos(var_a_0)]};
+}
+
+fn fn_5_pixel(ds: &mut DrawShader) -> Vec4 {
+    let mut var_r_0: Mat2 = mat_mul(fn_2_rotate(0.5f32, ds), Mat2 {v: [2.0f32, 0.0, 0.0, 2.0f32]});
+    let mut var_p_0: Vec2 = (mat_mul_vec(var_r_0, ds.uv) + vec_mul_mat(ds.uv, var_r_0));
+    let mut var_m_0: Mat3 = ({let t0 = Mat2 {v: [0.5f32, 0.0, 0.0, 0.5f32]}; Mat3 {v: [t0.v[0], t0.v[1], 0.0, t0.v[2], t0.v[3], 0.0, 0.0, 0.0, 1.0]}});
+    var_m_0[1 as usize] = vec3(0.5f32, 1.0f32, 0.0f32);
+    var_m_0[2 as usize].z = (var_m_0[2 as usize].z + var_p_0[0 as usize]);
+    let mut var_c_0: Vec4 = Vec4::all(0.0f32);
+    let mut for_i = 0;
+    while for_i < 3 {
+        let mut var_i_0 = for_i;
+        for_i += 1;
+        var_c_0[var_i_0 as usize] = (var_m_0[var_i_0 as usize][var_i_0 as usize] + transpose(var_m_0)[var_i_0 as usize].x);
+        ({let t1 = i32::wrapping_sub(2, var_i_0); let (r, t2) = fn_3_halve(var_c_0[t1 as usize], ds); var_c_0[t1 as usize] = t2; r});
+    }
+    var_c_0.w = mat_mul_vec(({let t3 = var_m_0[0 as usize]; let t4 = var_m_0[1 as usize]; let t5 = vec3(0.0f32, 0.0f32, 2.0f32); Mat3 {v: [t3.x, t3.y, t3.z, t4.x, t4.y, t4.z, t5.x, t5.y, t5.z]}}), Vec3::all(1.0f32))[1 as usize];
+    return var_c_0;
+}
+
+fn fn_4_vertex(ds: &mut DrawShader) -> Vec4 {
+    let mut var_m_0: Mat3 = Mat3 {v: [ds.Projection.v[0], ds.Projection.v[1], ds.Projection.v[2], ds.Projection.v[4], ds.Projection.v[5], ds.Projection.v[6], ds.Projection.v[8], ds.Projection.v[9], ds.Projection.v[10]]};
+    var_m_0[2 as usize] = vec3(0.5f32, 0.25f32, 1.0f32);
+    ds.uv = ({let t0 = mat_mul_vec(var_m_0, ds.position); vec2(t0.x, t0.y)});
+    return mat_mul_vec(ds.Projection, vec4(ds.position.x, ds.position.y, ds.position.z, 1.0f32));
+}
+
+pub fn vertex(geometry: &Geometry, instance: &Instance, uniforms: &Uniforms) -> (Vec4, Varyings) {
+    let mut ds = DrawShader {
+        uv: Default::default(),
+        position: geometry.position,
+        Projection: uniforms.Projection,
+    };
+    let position = fn_4_vertex(&mut ds);
+    (position, Varyings {uv: ds.uv})
+}
+
+pub fn pixel(varyings: &Varyings, uniforms: &Uniforms) -> Vec4 {
+    let mut ds = DrawShader {
+        uv: varyings.uv,
+        position: Default::default(),
+        Projection: uniforms.Projection,
+    };
+    fn_5_pixel(&mut ds)
+}
@@ -1,6 +1,56 @@
 #![allow(warnings)]
 
-use nanoshredder::{Shader, ShaderTy};
+use nanoshredder::{
+    disassemble_spirv, GlslBinding, GlslDefaultBindingPolicy, GlslOptions, GlslPrecision, GlslResource, GlslTarget, SamplerFilter, SamplerMip, SamplerWrap, Shader, ShaderSampler,
+    ShaderImage, ShaderInputs, ShaderLineMarkers, ShaderSeverity, ShaderTy, ShaderValue,
+    makepad_math::{Mat4, Vec2, Vec3, Vec4},
+};
+
+#[path = "generated/interpreter.rs"]
+mod generated_interpreter;
+
+#[path = "generated/matrices.rs"]
+mod generated_matrices;
+
+#[path = "generated/arrays.rs"]
+mod generated_arrays;
+
+// a shader of `source` with the `position` attribute and the `Projection` uniform
+fn projected_shader(source: &str) -> Shader {
+    let mut shader = Shader::new(source).unwrap();
+    shader.add_attribute("position", ShaderTy::Vec3);
+    shader.add_uniform("Projection", ShaderTy::Mat4);
+    shader
+}
+
+fn compiled(source: &str) -> Shader {
+    let mut shader = projected_shader(source);
+    shader.compile().unwrap();
+    shader
+}
+
+// the first error of a shader that doesn't compile
+fn compile_error(source: &str) -> String {
+    projected_shader(source).compile().unwrap_err()[0].error.message.clone()
+}
+
+fn glsl(shader: &Shader, target: GlslTarget) -> (String, String) {
+    shader.generate_glsl_with_options(GlslOptions {target, ..GlslOptions::default()})
+}
+
+// whether `code` has `line`, whatever its indentation
+fn has_line(code: &str, line: &str) -> bool {
+    code.lines().any( | code_line | code_line.trim() == line)
+}
+
+// each `(from, to, message)` breaks `source` by replacing `from` with `to`, the first error is `message`
+fn assert_compile_errors(source: &str, cases: &[(&str, &str, &str)]) {
+    for (from, to, message) in cases {
+        let broken = source.replace(from, to);
+        assert_ne!(broken, source, "`{}` isn't in the source", from);
+        assert_eq!(compile_error(&broken), *message);
+    }
+}
 
 #[test]
 fn main() {
@@ -36,3 +86,1766 @@ const SOURCE: &'static str = r#"
             return #f0f
         }
 "#;
+
+#[test]
+fn structs() {
+    let shader = compiled(STRUCT_SOURCE);
+    let (_, glsl_pixel) = shader.generate_glsl();
+    assert!(glsl_pixel.contains("f_albedo;"));
+
+    let metal = shader.generate_metal();
+    assert!(metal.contains("f_dir;"));
+
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("f_mat;"));
+}
+
+const STRUCT_SOURCE: &'static str = r#"
+        Material: Struct {
+            field albedo: vec4
+
+            fn tint(self, c: vec4) -> vec4 {
+                return self.albedo * c;
+            }
+        }
+
+        Light: Struct {
+            field dir: vec3
+            field mat: Material
+
+            fn shade(self, n: vec3) -> vec4 {
+                return self.mat.tint(vec4(1.0)) * max(dot(n, self.dir), 0.0);
+            }
+
+            fn new(c: vec4) -> Self {
+                return Light {dir: vec3(0, 0, 1), mat: Material {albedo: c}};
+            }
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            let light = Light::new(#f00);
+            return light.shade(vec3(0, 0, 1)) + light.mat.albedo;
+        }
+"#;
+
+#[test]
+fn plain_functions() {
+    let shader = compiled(PLAIN_FN_SOURCE);
+    let (glsl_vertex, glsl_pixel) = shader.generate_glsl();
+    assert_eq!(glsl_vertex.matches("float fn_1_sdf_circle(").count(), 1);
+    assert_eq!(glsl_pixel.matches("float fn_1_sdf_circle(").count(), 1);
+    assert!(!glsl_pixel.contains("unused_helper"));
+
+    let metal = shader.generate_metal();
+    assert_eq!(metal.matches("float fn_1_sdf_circle(").count(), 1);
+
+    let hlsl = shader.generate_hlsl();
+    assert_eq!(hlsl.matches("float fn_1_sdf_circle(").count(), 1);
+}
+
+const PLAIN_FN_SOURCE: &'static str = r#"
+        fn sdf_circle(p: vec2, r: float) -> float {
+            return length(p) - r;
+        }
+
+        fn unused_helper(x: float) -> float {
+            return x * 2.0;
+        }
+
+        fn vertex(self) -> vec4 {
+            let d = sdf_circle(self.position.xy, 1.0);
+            return self.Projection * vec4(self.position, d);
+        }
+
+        fn pixel(self) -> vec4 {
+            return vec4(sdf_circle(vec2(0.5), 0.25));
+        }
+"#;
+
+#[test]
+fn reflection() {
+    let mut shader = Shader::new(REFLECTION_SOURCE).unwrap();
+
+    shader.add_attribute("position", ShaderTy::Vec3);
+    shader.add_attribute("texcoord", ShaderTy::Vec2);
+    shader.add_uniform("Projection", ShaderTy::Mat4);
+
+    shader.compile().unwrap();
+    let reflection = shader.reflection();
+
+    let texcoord = &reflection.attributes[1];
+    assert_eq!(texcoord.name, "texcoord");
+    assert_eq!(texcoord.ty, ShaderTy::Vec2);
+    assert_eq!((texcoord.slots, texcoord.slot_offset), (2, 3));
+    assert_eq!(texcoord.ident, "ds_texcoord");
+    assert_eq!(texcoord.packed_idents, vec!["packed_geometry_0", "packed_geometry_1"]);
+
+    assert_eq!(reflection.uniform_blocks.len(), 2);
+    let pass = &reflection.uniform_blocks[0];
+    assert_eq!((pass.name.as_str(), pass.ident.as_str(), pass.slots), ("pass", "pass_table", 16));
+    let user = &reflection.uniform_blocks[1];
+    assert_eq!(user.uniforms[0].name, "tint");
+
+    assert_eq!(reflection.textures[0].ident, "ds_tex");
+    let varyings: Vec<_> = reflection.varyings.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(varyings, vec!["uv", "texcoord"]);
+}
+
+const REFLECTION_SOURCE: &'static str = r#"
+        uniform tint: vec4
+        texture tex: texture2d
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.texcoord;
+            return self.Projection * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.tex, self.uv) * self.tint + vec4(self.texcoord, 0, 0);
+        }
+"#;
+
+#[test]
+fn diagnostics() {
+    let mut shader = Shader::new_with_file_name("typo.shader", DIAGNOSTICS_SOURCE).unwrap();
+    shader.add_attribute("position", ShaderTy::Vec3);
+
+    let diagnostics = shader.compile().unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    let err = &diagnostics[0].error;
+    assert_eq!(err.file, "typo.shader");
+    assert_eq!((err.span.start.line, err.span.start.column), (6, 20));
+    assert_eq!(
+        err.to_string(),
+        "error: Function not found sinn\n --> typo.shader:7:21\n  |\n7 |             let x = sinn(1.0);\n  |                     ^^^^^^^^^"
+    );
+
+    let mut shader = Shader::new("fn pixel(self) -> vec4 { return vec4(1.0 +); }").unwrap();
+    let err = shader.compile().unwrap_err().pop().unwrap().error;
+    assert_eq!((err.span.start.line, err.span.start.column), (0, 42));
+    assert!(err.to_string().ends_with("\n  |                                           ^"));
+}
+
+const DIAGNOSTICS_SOURCE: &'static str = r#"
+        fn vertex(self) -> vec4 {
+            return vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            let x = sinn(1.0);
+            return vec4(x);
+        }
+"#;
+
+#[test]
+fn multiple_diagnostics() {
+    let diagnostics = projected_shader(MULTIPLE_DIAGNOSTICS_SOURCE).compile().unwrap_err();
+    let summary: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.severity, d.error.span.start.line + 1, d.error.message.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (ShaderSeverity::Error, 6, "Function not found foo"),
+            (ShaderSeverity::Warning, 11, "unused variable `unused`"),
+            (ShaderSeverity::Warning, 14, "unreachable statement"),
+            (ShaderSeverity::Warning, 14, "unused variable `after`"),
+            (ShaderSeverity::Error, 18, "Function not found bar"),
+        ]
+    );
+    assert!(diagnostics[1].to_string().starts_with("warning: unused variable `unused`\n  --> <shader>:11:13"));
+
+    let warnings = projected_shader(&MULTIPLE_DIAGNOSTICS_SOURCE.replace("foo", "abs").replace("bar", "abs")).compile().unwrap();
+    let messages: Vec<_> = warnings.iter().map(|d| d.error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "varying `unused_v` is never read by the pixel shader",
+            "unused variable `unused`",
+            "unreachable statement",
+            "unused variable `after`",
+        ]
+    );
+
+    // an error in a fn both stages call is reported once
+    let diagnostics = projected_shader(r#"
+        fn apply(x: float) -> float {
+            let f = |y| y * 2.0;
+            return f(x);
+        }
+        fn vertex(self) -> vec4 {return vec4(self.position, apply(1.0));}
+        fn pixel(self) -> vec4 {return vec4(apply(0.5));}
+    "#).compile().unwrap_err();
+    assert_eq!(diagnostics.iter().filter(|d| d.error.message == "Function not found f").count(), 1);
+}
+
+#[test]
+fn syntax_error_recovery() {
+    let diagnostics = projected_shader(SYNTAX_ERRORS_SOURCE).compile().unwrap_err();
+    let summary: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.error.span.start.line + 1, d.error.message.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (3, "unexpected token `;`"),
+            (8, "unexpected token `)`"),
+            (14, "unexpected token `{`"),
+            (17, "expected ) unexpected token `1`"),
+        ]
+    );
+
+    // once it parses, a failed `let` doesn't hide the statements after it
+    let fixed = SYNTAX_ERRORS_SOURCE
+        .replace("x * ;", "x * 2.0;")
+        .replace("helper(1.0 +)", "helper(1.0)")
+        .replace("b.x > {", "b.x > 0.0 {")
+        .replace("c 1.0)", "c, 1.0)");
+    let diagnostics = projected_shader(&fixed).compile().unwrap_err();
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Function not found sinn",
+            "can't apply binary operator `+` to operands of type `vec2` and `bool`",
+        ]
+    );
+}
+
+const SYNTAX_ERRORS_SOURCE: &'static str = r#"
+        fn helper(x: float) -> float {
+            let y = x * ;
+            return y + 1.0;
+        }
+
+        fn vertex(self) -> vec4 {
+            let a = helper(1.0 +);
+            return vec4(self.position, a);
+        }
+
+        fn pixel(self) -> vec4 {
+            let b = vec4(self.position, 1.0);
+            if b.x > { return b; }
+            let c = sinn(b.x);
+            let d = vec2(b.x) + true;
+            return vec4(b.xy, c 1.0);
+        }
+"#;
+
+const MULTIPLE_DIAGNOSTICS_SOURCE: &'static str = r#"
+        varying uv: vec2
+        varying unused_v: vec2
+
+        fn helper(x: float) -> float {
+            let y = foo(x);
+            return y;
+        }
+
+        fn vertex(self) -> vec4 {
+            let unused = 1.0;
+            self.uv = vec2(helper(1.0), 0.0);
+            return vec4(self.position, 1);
+            let after = 1.0;
+        }
+
+        fn pixel(self) -> vec4 {
+            let c = bar(self.uv.x);
+            return vec4(self.uv, c, 1);
+        }
+"#;
+
+#[test]
+fn glsl_targets() {
+    let shader = compiled(GLSL_TARGETS_SOURCE);
+
+    let (vertex, pixel) = shader.generate_glsl();
+    assert!(vertex.starts_with("precision lowp float;\n"));
+    assert!(vertex.contains("attribute vec3 packed_geometry_0;"));
+    assert!(vertex.contains("polyfill_inverse(ds_Projection)"));
+    assert!(pixel.contains("texture2D(ds_tex, ds_uv)"));
+    assert!(pixel.contains("gl_FragColor = "));
+
+    let (vertex, pixel) = shader.generate_glsl_with_options(GlslOptions {
+        target: GlslTarget::Glsl100Es,
+        vertex_precision: GlslPrecision::Highp,
+        pixel_precision: GlslPrecision::Mediump,
+        ..GlslOptions::default()
+    });
+    assert!(vertex.starts_with("#version 100\nprecision highp float;\n"));
+    assert!(pixel.starts_with("#version 100\n#extension GL_OES_standard_derivatives : enable\nprecision mediump float;\n"));
+
+    for (target, version) in [(GlslTarget::Glsl300Es, "#version 300 es\n"), (GlslTarget::Glsl330, "#version 330\n")] {
+        let (vertex, pixel) = shader.generate_glsl_with_options(GlslOptions {
+            target,
+            ..GlslOptions::default()
+        });
+        assert!(vertex.starts_with(version));
+        assert!(vertex.contains("in vec3 packed_geometry_0;"));
+        assert!(vertex.contains("out vec2 packed_varying_0;"));
+        assert!(vertex.contains("inverse(ds_Projection)") && !vertex.contains("polyfill"));
+        assert!(pixel.contains("in vec2 packed_varying_0;"));
+        assert!(pixel.contains("layout(location = 0) out vec4 frag_color;"));
+        assert!(pixel.contains("texture(ds_tex, ds_uv)"));
+        assert!(pixel.contains("frag_color = ") && !pixel.contains("gl_FragColor"));
+        assert!(!pixel.contains("#extension"));
+        assert_eq!(vertex.contains("precision lowp float;"), target == GlslTarget::Glsl300Es);
+    }
+}
+
+const GLSL_TARGETS_SOURCE: &'static str = r#"
+        texture tex: texture2d
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return inverse(self.Projection) * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.tex, self.uv) + vec4(dFdx(self.uv.x));
+        }
+"#;
+
+#[test]
+fn named_uniforms() {
+    let shader = compiled(NAMED_UNIFORMS_SOURCE);
+
+    let (vertex, pixel) = shader.generate_glsl_with_options(GlslOptions {
+        named_uniforms: true,
+        ..GlslOptions::default()
+    });
+    assert!(vertex.contains("uniform mat4 ds_Projection;\n"));
+    assert!(pixel.contains("uniform vec3 ds_tint;\nuniform float ds_scale;\n"));
+    assert!(!vertex.contains("pass_table") && !pixel.contains("user_table"));
+
+    let (vertex, _) = shader.generate_glsl_with_options(GlslOptions {
+        target: GlslTarget::Glsl330,
+        named_uniforms: true,
+        ..GlslOptions::default()
+    });
+    assert!(vertex.contains("layout(std140) uniform Uniforms_user {\n    vec3 ds_tint;\n    float ds_scale;\n    mat2 ds_skew;\n};"));
+    assert!(!vertex.contains("ds_tint = "));
+
+    let reflection = shader.reflection();
+    let user = &reflection.uniform_blocks[1];
+    assert_eq!(user.std140_ident, "Uniforms_user");
+    let offsets: Vec<_> = user.uniforms.iter().map(|u| u.std140_offset.unwrap()).collect();
+    assert_eq!(offsets, vec![0, 12, 16]);
+    assert_eq!(user.std140_size, 48);
+}
+
+const NAMED_UNIFORMS_SOURCE: &'static str = r#"
+        uniform tint: vec3
+        uniform scale: float
+        uniform skew: mat2
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position * self.scale, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return vec4(self.tint * self.scale, (self.skew * vec2(1, 0)).x);
+        }
+"#;
+
+#[test]
+fn vulkan_glsl() {
+    let shader = compiled(VULKAN_GLSL_SOURCE);
+
+    let options = GlslOptions {target: GlslTarget::Glsl450, ..GlslOptions::default()};
+    let (vertex, pixel) = shader.generate_glsl_with_options(options);
+    assert!(vertex.starts_with("#version 450\n"));
+    assert!(!vertex.contains("precision") && !pixel.contains("precision"));
+    assert!(vertex.contains("layout(std140, set = 0, binding = 0) uniform Uniforms_pass {\n    mat4 ds_Projection;\n};"));
+    assert!(vertex.contains("layout(std140, set = 0, binding = 1) uniform Uniforms_user {\n    float ds_scale;\n};"));
+    assert!(vertex.contains("layout(set = 1, binding = 0) uniform sampler2D ds_tex;"));
+    assert!(vertex.contains("layout(set = 1, binding = 1) uniform sampler2D ds_mask;"));
+    assert!(vertex.contains("layout(location = 0) in vec3 packed_geometry_0;"));
+    assert!(vertex.contains("layout(location = 1) in vec4 packed_instance_0;"));
+    assert!(vertex.contains("layout(location = 2) in float packed_instance_1;"));
+    assert!(vertex.contains("layout(location = 0) out vec4 packed_varying_0;"));
+    assert!(vertex.contains("layout(location = 1) out vec3 packed_varying_1;"));
+    assert!(pixel.contains("layout(location = 0) in vec4 packed_varying_0;"));
+    assert!(pixel.contains("layout(location = 0) out vec4 frag_color;"));
+    assert!(!vertex.contains("_table") && !pixel.contains("_table"));
+
+    let mut textures = 0;
+    let (vertex, pixel, bindings) = shader.generate_glsl_with_bindings(options, &mut |resource: &GlslResource| {
+        match resource {
+            GlslResource::UniformBlock(name) if name == "pass" => (0, 0),
+            GlslResource::UniformBlock(_) => (2, 0),
+            GlslResource::Texture(_) => {
+                textures += 1;
+                (1, textures * 2)
+            }
+        }
+    });
+    assert!(vertex.contains("layout(std140, set = 2, binding = 0) uniform Uniforms_user {"));
+    assert!(pixel.contains("layout(set = 1, binding = 4) uniform sampler2D ds_mask;"));
+    assert_eq!(bindings, vec![
+        GlslBinding {resource: GlslResource::UniformBlock("pass".into()), ident: "Uniforms_pass".into(), set: 0, binding: 0},
+        GlslBinding {resource: GlslResource::UniformBlock("user".into()), ident: "Uniforms_user".into(), set: 2, binding: 0},
+        GlslBinding {resource: GlslResource::Texture("tex".into()), ident: "ds_tex".into(), set: 1, binding: 2},
+        GlslBinding {resource: GlslResource::Texture("mask".into()), ident: "ds_mask".into(), set: 1, binding: 4},
+    ]);
+
+    let (_, _, bindings) = shader.generate_glsl_with_bindings(GlslOptions::default(), &mut GlslDefaultBindingPolicy::default());
+    assert!(bindings.is_empty());
+}
+
+const VULKAN_GLSL_SOURCE: &'static str = r#"
+        texture tex: texture2d
+        texture mask: texture2d
+        instance color: vec4
+        instance alpha: float
+        uniform scale: float
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy * self.scale;
+            return self.Projection * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.tex, self.uv) * sample2d(self.mask, self.uv).x * self.color * self.alpha;
+        }
+"#;
+
+#[test]
+fn spirv() {
+    let shader = compiled(SPIRV_SOURCE);
+
+    let words = shader.generate_spirv();
+    assert_eq!(words[0], 0x07230203);
+    let text = disassemble_spirv(&words);
+    assert!(text.starts_with("; SPIR-V\n; Version: 1.0\n"));
+    assert!(text.contains("OpEntryPoint Vertex %vertex_main \"vertex_main\" %packed_geometry_0 %packed_instance_0 %position %packed_varying_0"));
+    assert!(text.contains("OpEntryPoint Fragment %fragment_main \"fragment_main\" %packed_varying_0_1 %packed_varying_1_1 %frag_color"));
+    assert!(text.contains("OpExecutionMode %fragment_main OriginUpperLeft"));
+    assert!(text.contains("OpDecorate %position BuiltIn Position"));
+    assert!(text.contains("OpDecorate %packed_instance_0 Location 1"));
+    assert!(text.contains("OpDecorate %frag_color Location 0"));
+    assert!(text.contains("OpMemberDecorate %Uniforms_pass 0 MatrixStride 16"));
+    assert!(text.contains("OpMemberDecorate %Uniforms_user 1 Offset 16"));
+    assert!(text.contains("OpDecorate %uniforms_user Binding 1"));
+    assert!(text.contains("OpDecorate %ds_tex DescriptorSet 1"));
+    assert!(text.contains("%float_0_5 = OpConstant %float 0.5"));
+    assert!(text.contains("OpExtInst %float %1 Length"));
+    assert!(text.contains("OpImageSampleExplicitLod %v4float"));
+    assert!(text.contains("OpImageSampleImplicitLod %v4float"));
+    assert!(text.contains("%fn_6_shade = OpFunction %v4float None"));
+
+    let (_, bindings) = shader.generate_spirv_with_bindings(&mut |resource: &GlslResource| {
+        match resource {
+            GlslResource::UniformBlock(_) => (3, 0),
+            GlslResource::Texture(_) => (4, 5),
+        }
+    });
+    assert_eq!(bindings[0].set, 3);
+    assert_eq!(bindings.last().unwrap().binding, 5);
+}
+
+const SPIRV_SOURCE: &'static str = r#"
+        texture tex: texture2d
+        instance color: vec4
+        uniform scale: float
+        uniform offset: vec3
+        varying uv: vec2
+
+        fn shade(self, c: vec4) -> vec4 {
+            let k = length(self.uv) > 0.5 ? 1.0 : 0.5;
+            return c * k;
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy * self.scale + sample2d(self.tex, self.offset.xy).xy;
+            return self.Projection * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return self.shade(sample2d(self.tex, self.uv) * self.color);
+        }
+"#;
+
+#[test]
+fn texture_samplers() {
+    let shader = compiled(TEXTURE_SAMPLERS_SOURCE);
+
+    let metal = shader.generate_metal();
+    assert!(metal.contains("constexpr sampler ds_tex_sampler(mag_filter::linear, min_filter::linear, address::repeat, mip_filter::linear);"));
+    assert!(metal.contains("constexpr sampler ds_mask_sampler(mag_filter::nearest, min_filter::nearest, address::clamp_to_edge, mip_filter::none);"));
+    assert!(metal.contains("sample2d(textures.ds_tex, ds_tex_sampler, varyings.ds_uv)"));
+    assert!(metal.contains("sample2d(textures.ds_mask, ds_mask_sampler, varyings.ds_uv)"));
+
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("SamplerState ds_tex_sampler: register(s0);"));
+    assert!(hlsl.contains("SamplerState ds_mask_sampler: register(s1);"));
+    assert!(hlsl.contains("sample2d(ds_mask, ds_mask_sampler, varyings.ds_uv)"));
+
+    let reflection = shader.reflection();
+    let samplers: Vec<_> = reflection.textures.iter().map(|t| t.sampler.unwrap()).collect();
+    assert_eq!(samplers, vec![
+        ShaderSampler {filter: SamplerFilter::Linear, wrap: SamplerWrap::Repeat, mip: SamplerMip::Linear},
+        ShaderSampler::default(),
+    ]);
+
+    let message = compile_error(&TEXTURE_SAMPLERS_SOURCE.replace("mip: linear", "mip: cubic"));
+    assert!(message.contains("mip"), "{}", message);
+}
+
+const TEXTURE_SAMPLERS_SOURCE: &'static str = r#"
+        texture tex: texture2d {filter: linear, wrap: repeat, mip: linear}
+        texture mask: texture2d
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.tex, self.uv) * sample2d(self.mask, self.uv).x;
+        }
+"#;
+
+#[test]
+fn textured_backends() {
+    for source in TEXTURED_SOURCES {
+        let shader = compiled(source);
+
+        for target in [GlslTarget::Glsl100, GlslTarget::Glsl100Es, GlslTarget::Glsl300Es, GlslTarget::Glsl330] {
+            let (_, pixel) = glsl(&shader, target);
+            assert!(pixel.contains("uniform sampler2D ds_tex;"));
+        }
+        let metal = shader.generate_metal();
+        assert!(metal.contains("ds_tex_sampler"));
+        let hlsl = shader.generate_hlsl();
+        assert!(hlsl.contains("ds_tex_sampler"));
+        assert!(!hlsl.contains(", )") && !hlsl.contains(", ,"));
+    }
+
+    let shader = compiled(TEXTURED_SOURCES[1]);
+    assert!(shader.generate_metal().contains("(texture2d<float> var_t_0, sampler var_t_0_sampler, float2 var_p_0)"));
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("(Texture2D var_t_0, SamplerState var_t_0_sampler, float2 var_p_0)"));
+    assert!(hlsl.contains("(ds_tex, ds_tex_sampler, varyings.ds_uv)"));
+
+    // a texture only travels as a field or a parameter, its sampler goes along with it
+    assert_compile_errors(TEXTURED_SOURCES[1], &[
+        ("sample2d(t, p)", "sample2d(p.x > 0.5 ? t : t, p)", "a conditional can't pick between textures, sample both instead"),
+        ("return sample2d(t, p)", "let u = t;\n            return sample2d(u, p)", "`u` can't hold a texture, use the texture directly"),
+        ("return sample2d(t, p)", "t = t;\n            return sample2d(t, p)", "textures can't be assigned"),
+        ("p: vec2) -> vec4", "p: vec2) -> texture2d", "functions can't return textures"),
+    ]);
+}
+
+const TEXTURED_SOURCES: [&'static str; 4] = [
+    r#"
+        texture tex: texture2d
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.tex, self.uv);
+        }
+    "#,
+    r#"
+        texture tex: texture2d {filter: linear}
+        varying uv: vec2
+
+        fn tinted(t: texture2d, p: vec2) -> vec4 {
+            return sample2d(t, p) * 0.5;
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            return tinted(self.tex, self.uv);
+        }
+    "#,
+    r#"
+        texture tex: texture2d {wrap: repeat}
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return vec4(self.position, 1);
+        }
+
+        fn fetch(self, offset: vec2) -> vec4 {
+            return sample2d(self.tex, self.uv + offset);
+        }
+
+        fn pixel(self) -> vec4 {
+            return self.fetch(vec2(0, 0)) + self.fetch(vec2(0.5, 0));
+        }
+    "#,
+    r#"
+        texture tex: texture2d {filter: linear, mip: linear}
+        varying uv: vec2
+
+        fn inner(t: texture2d, p: vec2) -> vec4 {
+            return sample2d(t, p);
+        }
+
+        fn outer(t: texture2d, p: vec2, f: fn(v: vec4) -> vec4) -> vec4 {
+            return f(sample2d(t, p));
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            let scale = 0.5;
+            return outer(self.tex, self.uv, |v| v * scale) + inner(self.tex, self.uv);
+        }
+    "#,
+];
+
+#[test]
+fn wgsl() {
+    let shader = compiled(WGSL_SOURCE);
+
+    let wgsl = shader.generate_wgsl();
+    assert!(wgsl.contains("@vertex\nfn vertex_main(attributes: Attributes) -> Varyings {"));
+    assert!(wgsl.contains("@fragment\nfn fragment_main(varyings: Varyings) -> @location(0) vec4<f32> {"));
+    assert!(wgsl.contains("@location(0) packed_geometry_0: vec3<f32>,"));
+    assert!(wgsl.contains("@builtin(position) position: vec4<f32>,"));
+    assert!(wgsl.contains("@group(0) @binding(0) var<uniform> pass_table: array<vec4<f32>, 4>;"));
+    assert!(wgsl.contains("var ds_tex: texture_2d<f32>;"));
+    assert!(wgsl.contains("var ds_tex_sampler: sampler;"));
+    assert!(wgsl.contains("var<private> ds_uv: vec2<f32>;"));
+    // inout params are pointers, draw shader fields go through a wrapper that copies them
+    assert!(wgsl.contains("_twice(var_v_0: ptr<function, vec2<f32>>) {"));
+    assert!(wgsl.contains("(*var_v_0) = ((*var_v_0) * 2.0);"));
+    assert!(wgsl.contains("_twice (&var_t_0);"));
+    assert!(wgsl.contains("_twice_fields_1 (&ds_uv);"));
+    assert!(wgsl.contains("ptr<private, vec2<f32>>"));
+    // params are immutable, they are copied into a var
+    assert!(wgsl.contains("var var_x_0 = param_var_x_0;"));
+    assert!(wgsl.contains("fn closure_0_in_fn_"));
+    assert!(wgsl.contains("select(0.0, 1.0, (var_d_0 > 0.5))"));
+    assert!(wgsl.contains("polyfill_mod_vec2(var_t_0, vec2<f32>(2.0))"));
+    assert!(wgsl.contains("atan2(var_t_0.y, var_t_0.x)"));
+    assert!(wgsl.contains("mat4x4<f32>(x, 0.0, 0.0, 0.0, 0.0, x,"));
+    // no implicit derivatives in the vertex stage
+    assert!(wgsl.contains("textureSampleLevel(tex, smp, pos, 0.0)"));
+    assert!(wgsl.contains("textureSample(ds_tex, ds_tex_sampler, var_k_0)"));
+    // a helper both stages sample in gets a copy with explicit levels for the vertex stage
+    assert!(wgsl.contains("_tap(param_var_p_0: vec2<f32>) -> vec4<f32> {\n    var var_p_0 = param_var_p_0;\n    return textureSample("));
+    assert!(wgsl.contains("_tap_vertex(param_var_p_0: vec2<f32>) -> vec4<f32> {\n    var var_p_0 = param_var_p_0;\n    return polyfill_sample2d_level("));
+    assert!(wgsl.contains("_tap_vertex (ds_uv)"));
+    assert!(wgsl.contains("_tap (var_t_0)"));
+    // there are no pointers to vector components, the wrapper takes the vector and copies them
+    assert!(wgsl.contains("_twice_fields_1yx (&ds_uv);"));
+    assert!(wgsl.contains("    (*p0).y = a0.x;\n    (*p0).x = a0.y;\n"));
+    assert!(wgsl.contains("_halve_fields_1y (&ds_uv);"));
+    assert!(wgsl.contains("_halve_fields_0x (&var_t_0);"));
+    assert!(wgsl.contains("(p0: ptr<function, vec2<f32>>) {\n    var a0 = (*p0).x;\n"));
+
+    // and so does SPIR-V, the vertex entry point can't reach an `OpImageSampleImplicitLod`
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    let tap = &spirv[spirv.find("_tap = OpFunction").unwrap()..];
+    assert!(tap[..tap.find("OpFunctionEnd").unwrap()].contains("OpImageSampleImplicitLod"));
+    let tap_vertex = &spirv[spirv.find("_tap_vertex = OpFunction").unwrap()..];
+    assert!(tap_vertex[..tap_vertex.find("OpFunctionEnd").unwrap()].contains("OpImageSampleExplicitLod"));
+    let vertex = &spirv[spirv.find("%fn_15_vertex = OpFunction").unwrap()..];
+    assert!(vertex[..vertex.find("OpFunctionEnd").unwrap()].contains("OpFunctionCall %v4float %fn_12_tap_vertex"));
+}
+
+const WGSL_SOURCE: &'static str = r#"
+        texture tex: texture2d
+        instance color: vec4
+        uniform scale: float
+        varying uv: vec2
+
+        Light: Struct {
+            field dir: vec3
+            field power: float
+
+            fn bump(inout self, amount: float) {
+                self.power += amount;
+            }
+        }
+
+        fn twice(inout v: vec2) {
+            v = v * 2.0;
+        }
+
+        fn halve(inout x: float) {
+            x = x * 0.5;
+        }
+
+        fn tap(self, p: vec2) -> vec4 {
+            return sample2d(self.tex, p);
+        }
+
+        fn shift(x: float) -> float {
+            x = x + 1.0;
+            return x;
+        }
+
+        fn apply(p: vec2, f: fn(v: vec2) -> vec2) -> vec2 {
+            return f(p);
+        }
+
+        fn vertex(self) -> vec4 {
+            let m = mat4(1.0);
+            let s = sample2d(self.tex, self.position.xy);
+            self.uv = self.position.xy + s.xy;
+            twice(self.uv);
+            twice(self.uv.yx);
+            halve(self.uv.y);
+            self.uv += self.tap(self.uv).xy;
+            return self.Projection * m * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let l = Light {dir: vec3(0.0, 0.0, 1.0), power: 1.0};
+            l.bump(0.5);
+            let d = shift(l.power);
+            let t = self.uv;
+            twice(t);
+            halve(t.x);
+            let q = apply(t, |v| v * self.scale) + self.tap(t).xy;
+            let k = mod(t, 2.0) + vec2(atan(t.y, t.x), 0.0) + q;
+            let g = d > 0.5 ? 1.0 : 0.0;
+            return sample2d(self.tex, k) * self.color * g;
+        }
+"#;
+
+#[test]
+fn command_line() {
+    let dir = std::env::temp_dir().join(format!("nanoshredder_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("sprite.shader"), TEXTURED_SOURCES[1]).unwrap();
+    std::fs::write(dir.join("sprite.toml"), "[attributes]\nposition = \"vec3\"\n").unwrap();
+    std::fs::write(dir.join("broken.shader"), DIAGNOSTICS_SOURCE).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nanoshredder"))
+        .arg(dir.join("sprite.shader"))
+        .args(["-c", dir.join("sprite.toml").to_str().unwrap()])
+        .args(["-u", "Projection:mat4", "-u", "mask:texture2d", "-u", "sky:textureCube", "--glsl-target", "330", "-o"])
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let vertex = std::fs::read_to_string(dir.join("out/sprite.vert.glsl")).unwrap();
+    assert!(vertex.starts_with("#version 330"));
+    assert!(std::fs::read_to_string(dir.join("out/sprite.metal")).unwrap().contains("ds_tex_sampler"));
+    assert!(std::fs::read_to_string(dir.join("out/sprite.hlsl")).unwrap().contains("ds_tex_sampler"));
+    assert!(std::fs::read_to_string(dir.join("out/sprite.wgsl")).unwrap().contains("ds_tex_sampler"));
+    assert_eq!(std::fs::read(dir.join("out/sprite.spv")).unwrap()[..4], [0x03, 0x02, 0x23, 0x07]);
+    assert!(std::fs::read_to_string(dir.join("out/sprite.rs")).unwrap().contains("pub fn pixel("));
+    let json = std::fs::read_to_string(dir.join("out/sprite.json")).unwrap();
+    assert!(json.contains("\"name\": \"position\", \"ty\": \"vec3\""));
+    assert!(json.contains("\"sampler\": {\"filter\": \"linear\", \"wrap\": \"clamp\", \"mip\": \"none\"}"));
+    // a texture uniform becomes a texture with the default sampler
+    assert!(json.contains("{\"name\": \"mask\", \"ty\": \"texture2D\", \"slots\": 1, \"slot_offset\": 1,"));
+    assert!(json.contains("{\"name\": \"sky\", \"ty\": \"textureCube\", \"slots\": 1, \"slot_offset\": 2,"));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nanoshredder"))
+        .arg(dir.join("broken.shader"))
+        .args(["-a", "position:vec3", "-o"])
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("broken.shader:"), "{}", stderr);
+    assert!(!dir.join("out/broken.metal").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interpreter() {
+    let shader = compiled(INTERPRETER_SOURCE);
+
+    let mut projection = Mat4::identity();
+    projection.v[12] = 1.0;
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.25, y: 0.5, z: 0.0})
+        .set("Projection", projection)
+        .set("scale", 0.5f32)
+        .set("color", Vec4 {x: 1.0, y: 0.5, z: 1.0, w: 1.0});
+    let vertex = shader.interpret_vertex(&inputs).unwrap();
+    assert_eq!(vertex.value, Vec4 {x: 1.25, y: 0.5, z: 0.0, w: 1.0});
+    assert_eq!(vertex.varying("uv"), Some(&ShaderValue::Vec2(Vec2 {x: 0.5, y: 1.0})));
+    assert_eq!(vertex.varying("total"), Some(&ShaderValue::Float(3.0)));
+    assert_eq!(vertex.varying("color"), Some(&ShaderValue::Vec4(Vec4 {x: 1.0, y: 0.5, z: 1.0, w: 1.0})));
+
+    let err = shader.interpret_pixel(&inputs.set_varyings(&vertex)).err().unwrap();
+    assert!(err.message.contains("no image for `tex`"), "{}", err);
+
+    let red = Vec4 {x: 1.0, y: 0.0, z: 0.0, w: 1.0};
+    let blue = Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 1.0};
+    inputs.set_image("tex", ShaderImage {width: 2, height: 2, texels: vec![red, blue, blue, red]});
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 1.5, y: 0.0, z: 0.0, w: 0.84375});
+
+    inputs.set("position", 1.0f32);
+    let err = shader.interpret_vertex(&inputs).err().unwrap();
+    assert!(err.message.contains("expected a vec3 value for `position`"), "{}", err);
+}
+
+#[test]
+fn rust_backend() {
+    let shader = compiled(INTERPRETER_SOURCE);
+    assert_eq!(shader.generate_rust().unwrap(), include_str!("generated/interpreter.rs"));
+
+    // the generated module has to agree with the interpreter to the bit
+    use generated_interpreter::{Geometry, Instance, Textures, Uniforms};
+    let mut projection = Mat4::identity();
+    projection.v[12] = 1.0;
+    let position = Vec3 {x: 0.25, y: 0.5, z: 0.0};
+    let color = Vec4 {x: 1.0, y: 0.5, z: 1.0, w: 1.0};
+    let red = Vec4 {x: 1.0, y: 0.0, z: 0.0, w: 1.0};
+    let blue = Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 1.0};
+    let texels = vec![red, blue, blue, red];
+    let tex = | uv: Vec2 | {
+        let x = ((uv.x * 2.0).floor() as i64).rem_euclid(2) as usize;
+        let y = ((uv.y * 2.0).floor() as i64).rem_euclid(2) as usize;
+        texels[y * 2 + x]
+    };
+    let textures = Textures {tex: &tex};
+    let uniforms = Uniforms {scale: 0.5, Projection: projection};
+    let (vertex, varyings) = generated_interpreter::vertex(&Geometry {position}, &Instance {color}, &uniforms, &textures);
+    let pixel = generated_interpreter::pixel(&varyings, &uniforms, &textures);
+
+    let mut inputs = ShaderInputs::new();
+    inputs.set("position", position).set("Projection", projection).set("scale", 0.5f32).set("color", color);
+    inputs.set_image("tex", ShaderImage {width: 2, height: 2, texels});
+    let interpreted_vertex = shader.interpret_vertex(&inputs).unwrap();
+    assert_eq!(vertex, interpreted_vertex.value);
+    assert_eq!(interpreted_vertex.varying("uv"), Some(&ShaderValue::Vec2(varyings.uv)));
+    assert_eq!(interpreted_vertex.varying("total"), Some(&ShaderValue::Float(varyings.total)));
+    assert_eq!(shader.interpret_pixel(inputs.set_varyings(&interpreted_vertex)).unwrap().value, pixel);
+
+    // GLSL `fract` rounds down, unlike `f32::fract`
+    let shader = compiled(r#"
+        fn vertex(self) -> vec4 {return vec4(self.position, 1.0);}
+        fn pixel(self) -> vec4 {return vec4(fract(vec2(-1.25, 0.5)), fract(-0.75), 1.0);}
+    "#);
+    let rust = shader.generate_rust().unwrap();
+    assert!(rust.contains("({let t0 = vec2((-1.25f32), 0.5f32); (t0 - floor(t0))})"));
+    assert!(rust.contains("({let t1 = (-0.75f32); (t1 - floor(t1))})"));
+
+    // mat2 and mat3 go through the `ShaderMatrix` fns, indices pick components and columns
+    let shader = compiled(MATRICES_SOURCE);
+    assert_eq!(shader.generate_rust().unwrap(), include_str!("generated/matrices.rs"));
+
+    let geometry = generated_matrices::Geometry {position};
+    let uniforms = generated_matrices::Uniforms {Projection: projection};
+    let (vertex, varyings) = generated_matrices::vertex(&geometry, &generated_matrices::Instance {}, &uniforms);
+    let pixel = generated_matrices::pixel(&varyings, &uniforms);
+
+    let mut inputs = ShaderInputs::new();
+    inputs.set("position", position).set("Projection", projection);
+    let interpreted_vertex = shader.interpret_vertex(&inputs).unwrap();
+    assert_eq!(vertex, interpreted_vertex.value);
+    assert_eq!(interpreted_vertex.varying("uv"), Some(&ShaderValue::Vec2(varyings.uv)));
+    assert_eq!(shader.interpret_pixel(inputs.set_varyings(&interpreted_vertex)).unwrap().value, pixel);
+}
+
+mod struct_shader {
+    nanoshredder_macros::shader! {
+        attributes {position: vec3}
+        uniforms {Projection: mat4}
+
+        Material: Struct {
+            field albedo: vec4
+
+            fn tint(self, c: vec4) -> vec4 {
+                return self.albedo * c;
+            }
+        }
+
+        Light: Struct {
+            field dir: vec3
+            field mat: Material
+
+            fn shade(self, n: vec3) -> vec4 {
+                return self.mat.tint(vec4(1.0)) * max(dot(n, self.dir), 0.0);
+            }
+
+            fn new(c: vec4) -> Self {
+                return Light {dir: vec3(0, 0, 1), mat: Material {albedo: c}};
+            }
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1);
+        }
+
+        fn pixel(self) -> vec4 {
+            let light = Light::new(#f00);
+            return light.shade(vec3(0, 0, 1)) + light.mat.albedo;
+        }
+    }
+}
+
+mod textured_shader {
+    nanoshredder_macros::include_shader!("tests/shaders/textured.shader", attributes {position: vec3}, uniforms {Projection: mat4});
+}
+
+#[test]
+fn shader_macro() {
+    let shader = compiled(STRUCT_SOURCE);
+    assert_eq!((struct_shader::GLSL_VERTEX.to_string(), struct_shader::GLSL_PIXEL.to_string()), shader.generate_glsl());
+    assert_eq!(struct_shader::METAL, shader.generate_metal());
+    assert_eq!(struct_shader::HLSL, Some(&shader.generate_hlsl()[..]));
+    assert_eq!(struct_shader::WGSL, Some(&shader.generate_wgsl()[..]));
+    assert_eq!(struct_shader::SPIRV, &shader.generate_spirv()[..]);
+    assert_eq!(struct_shader::RUST, Some(&shader.generate_rust().unwrap()[..]));
+    assert_eq!(struct_shader::REFLECTION_JSON, shader.reflection().to_json());
+    assert_eq!(struct_shader::ATTRIBUTES, &[("position", "vec3", 3, 0)]);
+    assert_eq!(struct_shader::UNIFORM_BLOCKS[0].1[0], ("Projection", "mat4", 16, 0));
+    assert!(struct_shader::ENUMS.is_empty());
+
+    let shader = compiled(include_str!("shaders/textured.shader"));
+    assert_eq!(textured_shader::WGSL, Some(&shader.generate_wgsl()[..]));
+    assert_eq!(textured_shader::TEXTURES, &[("tex", "texture2D", 1, 0)]);
+    assert_eq!(textured_shader::VARYINGS, &[("uv", "vec2", 2, 0)]);
+}
+
+// the errors of an inline shader point at the token they are about
+#[test]
+fn shader_macro_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/compile_fail/*.rs");
+}
+
+const INTERPRETER_SOURCE: &'static str = r#"
+        const gain: 2.0
+        texture tex: texture2d {filter: nearest, wrap: repeat}
+        instance color: vec4
+        uniform scale: float
+        varying uv: vec2
+        varying total: float
+
+        Light: Struct {
+            field dir: vec3
+            field power: float
+
+            fn bump(inout self, amount: float) {
+                self.power += amount;
+            }
+        }
+
+        fn twice(inout v: vec2) {
+            v = v * gain;
+        }
+
+        fn sum(f: fn(v: float) -> float) -> float {
+            return f(0.0) + f(1.0) + f(2.0);
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            twice(self.uv);
+            let r = self.scale * 2.0;
+            self.total = sum(|v| v * r);
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let l = Light {dir: vec3(0.0, 0.0, 1.0), power: 1.0};
+            l.bump(0.5);
+            let c = sample2d(self.tex, self.uv * 0.5 + 0.25);
+            return vec4(c.xyz * self.color.xyz * l.power, smoothstep(0.0, 4.0, self.total));
+        }
+"#;
+
+const MATRICES_SOURCE: &'static str = r#"
+        varying uv: vec2
+
+        fn rotate(a: float) -> mat2 {
+            return mat2(cos(a), sin(a), -sin(a), cos(a));
+        }
+
+        fn halve(inout v: float) {
+            v = v * 0.5;
+        }
+
+        fn vertex(self) -> vec4 {
+            let m = mat3(self.Projection);
+            m[2i] = vec3(0.5, 0.25, 1.0);
+            self.uv = (m * self.position).xy;
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let r = rotate(0.5) * mat2(2.0);
+            let p = r * self.uv + self.uv * r;
+            let m = mat3(mat2(0.5));
+            m[1i] = vec3(0.5, 1.0, 0.0);
+            m[2i].z += p[0i];
+            let c = vec4(0.0);
+            for i from 0i to 3i {
+                c[i] = m[i][i] + transpose(m)[i].x;
+                halve(c[2i - i]);
+            }
+            c.w = (mat3(m[0i], m[1i], vec3(0.0, 0.0, 2.0)) * vec3(1.0))[1i];
+            return c;
+        }
+"#;
+
+#[test]
+fn source_maps() {
+    let mut shader = Shader::new_with_file_name("sprite.shader", SOURCE_MAPS_SOURCE).unwrap();
+    shader.add_attribute("position", ShaderTy::Vec3);
+    shader.add_uniform("Projection", ShaderTy::Mat4);
+    shader.compile().unwrap();
+
+    // without markers the map is still there, and the output is unchanged
+    let ((vertex, vertex_map), _) = shader.generate_glsl_with_source_map(GlslOptions::default());
+    assert_eq!(vertex, shader.generate_glsl().0);
+    assert!(!vertex.contains('\u{1}'));
+    let line = vertex.lines().position( | line | line.contains("float var_y_0 =")).unwrap() as u32 + 1;
+    let span = vertex_map.span_at_line(line).unwrap();
+    assert_eq!((span.start.line, span.start.column), (2, 12));
+    let err = shader.remap_error(&vertex_map, line, "'var_y_0' : undeclared identifier").unwrap();
+    assert_eq!(err.span.start.line, 2);
+    assert!(err.to_string().contains("--> sprite.shader:3:13"));
+    assert!(err.to_string().contains("let y = x * 2.0;"));
+    assert_eq!(vertex_map.span_at_line(1), None);
+
+    // ES 1.00 numbers the line after `#line n` as n + 1, later versions as n
+    let ((vertex, _), _) = shader.generate_glsl_with_source_map(GlslOptions {line_markers: ShaderLineMarkers::Directives, ..GlslOptions::default()});
+    assert!(vertex.contains("#line 2\n    float var_y_0 ="));
+    let ((vertex, _), (pixel, pixel_map)) = shader.generate_glsl_with_source_map(GlslOptions {
+        target: GlslTarget::Glsl330,
+        line_markers: ShaderLineMarkers::Directives,
+        ..GlslOptions::default()
+    });
+    assert!(vertex.contains("#line 3\n    float var_y_0 ="));
+    // the glue after a function continues at its real line
+    let line = pixel.lines().position( | line | line.starts_with("void main")).unwrap();
+    assert_eq!(pixel.lines().nth(line - 2), Some(&*format!("#line {}", line)));
+    let line = pixel.lines().position( | line | line.contains("(var_v_0 * 0.5)")).unwrap() as u32 + 1;
+    assert_eq!(pixel_map.span_at_line(line).unwrap().start.line, 11);
+
+    let (hlsl, hlsl_map) = shader.generate_hlsl_with_source_map(ShaderLineMarkers::Directives);
+    assert!(hlsl.contains("#line 3 \"sprite.shader\"\n    float var_y_0 ="));
+    let (metal, _) = shader.generate_metal_with_source_map(ShaderLineMarkers::Comments);
+    assert!(metal.contains("    // sprite.shader:3:13\n    float var_y_0 ="));
+    // WGSL has no `#line`
+    let (wgsl, wgsl_map) = shader.generate_wgsl_with_source_map(ShaderLineMarkers::Directives);
+    assert!(!wgsl.contains("#line"));
+    assert!(wgsl.contains("// sprite.shader:3:13"));
+    let line = wgsl.lines().position( | line | line.contains("var var_y_0")).unwrap() as u32 + 1;
+    assert_eq!(wgsl_map.span_at_line(line).unwrap().start.line, 2);
+    let line = hlsl.lines().position( | line | line.contains("float var_y_0 =")).unwrap() as u32 + 1;
+    assert_eq!(hlsl_map.span_at_line(line).unwrap().start.line, 2);
+}
+
+const SOURCE_MAPS_SOURCE: &'static str = r#"
+        fn helper(x: float) -> float {
+            let y = x * 2.0;
+            return y;
+        }
+
+        fn apply(f: fn(v: float) -> float) -> float {
+            return f(1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let a = apply(|v| v * 0.5);
+            return vec4(a, 0.0, 0.0, 1.0);
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, helper(1.0));
+        }
+"#;
+
+#[test]
+fn enums() {
+    let shader = compiled(ENUMS_SOURCE);
+
+    let reflection = shader.reflection();
+    let shape = reflection.find_enum("Shape").unwrap();
+    assert_eq!(shape.variants, vec![("Circle".to_string(), 1), ("Box".to_string(), 2), ("Hex".to_string(), 3)]);
+    assert_eq!(shape.variant_value("Hex"), Some(3));
+    assert_eq!(reflection.instances[0].ty.to_string(), "Shape");
+    assert_eq!(reflection.instances[0].slots, 1);
+    assert!(reflection.to_json().contains("{\"name\": \"Shape\", \"variants\": {\"Circle\": 1, \"Box\": 2, \"Hex\": 3}}"));
+
+    // a float in every GPU backend, compared with some slack after interpolation
+    let (_, pixel) = shader.generate_glsl();
+    assert!(pixel.contains("if(abs(ds_mode - 3.0)<0.5)"));
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("float ds_shape: INSTA;"));
+    assert!(hlsl.contains("if(abs(var_s_0 - 2.0)<0.5)"));
+    assert!(shader.generate_metal().contains("float  ds_shape;"));
+    assert!(shader.generate_wgsl().contains("var<private> ds_shape: f32;"));
+    assert!(disassemble_spirv(&shader.generate_spirv()).contains("OpFSub %float"));
+    let rust = shader.generate_rust().unwrap();
+    assert!(rust.contains("pub shape: i32,"));
+    assert!(rust.contains("        3 => {"));
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.0, y: 0.0, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set("shape", shape.variant_value("Box").unwrap() as i32)
+        .set("mode", shape.variant_value("Hex").unwrap() as i32);
+    let vertex = shader.interpret_vertex(&inputs).unwrap();
+    let pixel = shader.interpret_pixel(inputs.set_varyings(&vertex)).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 0.0, y: 0.5, z: 0.0, w: 0.5});
+    inputs.set("shape", 3).set("mode", 1);
+    assert_eq!(shader.interpret_pixel(&inputs).unwrap().value, Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 1.0});
+
+    let diagnostics = projected_shader(&ENUMS_SOURCE.replace("Shape::Hex", "Shape::Star")).compile().unwrap_err();
+    assert_eq!(diagnostics[0].error.message, "Variant not found on enum Shape::Star");
+    assert_eq!(diagnostics[0].error.span.start.line, 22);
+
+    assert_eq!(compile_error(&ENUMS_SOURCE.replace("match self.mode", "match self.position.x")), "Can only match on enum types");
+}
+
+const ENUMS_SOURCE: &'static str = r#"
+        Shape: Enum {Circle, Box, Hex}
+        Blend: Enum {Alpha, Add}
+
+        instance shape: Shape
+        uniform mode: Shape
+
+        fn tint(s: Shape) -> vec4 {
+            match s {
+                Shape::Circle => {return #f00;}
+                Shape::Box => {return #0f0;}
+            }
+            return #00f;
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let c = tint(self.shape);
+            match self.mode {
+                Shape::Hex => {c = c * 0.5;}
+            }
+            return c;
+        }
+"#;
+
+#[test]
+fn integer_ops() {
+    let shader = compiled(INTEGER_OPS_SOURCE);
+
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl300Es);
+    assert!(pixel.contains("uint fn_3_hash(uint var_x_0) {"));
+    assert!(pixel.contains("var_h_0 *= 73244475u;"));
+    assert!(pixel.contains("var_h_0 ^= (var_h_0 >> 16);"));
+    assert!(pixel.contains("int var_column_0 = (ds_cell % 4);"));
+    assert!(pixel.contains("(~var_column_0 == (-1 - var_column_0))"));
+
+    // GLSL 100 has neither `uint` nor the operators
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl100Es);
+    assert!(pixel.contains("int fn_3_hash(int var_x_0) {"));
+    assert!(pixel.contains("int polyfill_rem(int a, int b) {\n    return a - (a / b) * b;\n}"));
+    assert!(pixel.contains("int polyfill_shr(int a, int b) {\n    return a / int(exp2(float(b)));\n}"));
+    assert!(pixel.contains("var_h_0 = polyfill_bit_xor(var_h_0, (polyfill_shr(var_h_0, 16)));"));
+    assert!(pixel.contains("((-1 - var_column_0) == (-1 - var_column_0))"));
+    assert!(pixel.find("int polyfill_bit_and(").unwrap() < pixel.find("int fn_3_hash(").unwrap());
+
+    assert!(shader.generate_hlsl().contains("uint ds_seed;"));
+    assert!(shader.generate_metal().contains("uint var_low_0 = (var_h_0 & 255u);"));
+    // WGSL only shifts by a `u32`
+    let wgsl = shader.generate_wgsl();
+    assert!(wgsl.contains("var_h_0 ^= (var_h_0 >> u32(16));"));
+    assert!(wgsl.contains("ds_seed = u32(user_table[0].x);"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("OpShiftRightArithmetic %int"));
+    assert!(spirv.contains("OpUMod %uint"));
+    assert!(spirv.contains("OpSRem %int"));
+    assert!(shader.generate_rust().unwrap().contains("pub seed: u32,"));
+
+    let hash = | x: u32 | {
+        let h = (x ^ (x >> 16)).wrapping_mul(0x45d9f3b);
+        h ^ (h >> 16)
+    };
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.0, y: 0.0, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set("seed", 12345u32)
+        .set("cell", 11);
+    let h = hash(12345);
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 3.0, y: 2.0, z: (((h >> 8) | 1) % 7) as f32, w: (h & 1) as f32});
+
+    assert_eq!(compile_error(&INTEGER_OPS_SOURCE.replace("self.cell % 4i", "self.cell % 4.0")), "can't apply binary operator `%` to operands of type `int` and `float`");
+
+    assert_eq!(compile_error(&INTEGER_OPS_SOURCE.replace("0xffu", "0x1ffffffffu")), "literal `8589934591u` out of range for uint");
+}
+
+const INTEGER_OPS_SOURCE: &'static str = r#"
+        uniform seed: uint
+        uniform cell: int
+
+        fn hash(x: uint) -> uint {
+            let h = x ^ (x >> 16u);
+            h *= 0x45d9f3bu;
+            h ^= h >> 16i;
+            return h;
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let h = hash(self.seed);
+            let column = self.cell % 4i;
+            let row = self.cell >> 2u;
+            let low = h & 0xffu;
+            let on = (low & 1u) != 0u && !column == -1i - column;
+            return vec4(float(column), float(row), float(((h >> 8u) | 1u) % 7u), float(on));
+        }
+"#;
+
+#[test]
+fn loops() {
+    let shader = compiled(LOOPS_SOURCE);
+
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl300Es);
+    assert!(pixel.contains("while ((var_t_0 < var_d_0)) {"));
+    assert!(pixel.contains("while (true) {"));
+    // labelled jumps out of the inner loop go through flags
+    assert!(pixel.contains("bool loop_break_outer = false;\n    for (int var_i_0 = 0; var_i_0 < 4; var_i_0 += 1) {\n        bool loop_continue_outer = false;"));
+    assert!(pixel.contains("loop_continue_outer = true; break;"));
+    assert!(pixel.contains("loop_break_outer = true; break;"));
+    assert!(pixel.contains("}\n        if (loop_break_outer) {break;}\n        if (loop_continue_outer) {continue;}\n"));
+
+    // GLSL 100 only has bounded `for` loops
+    let (_, pixel) = shader.generate_glsl_with_options(GlslOptions {max_loop_iterations: 64, ..GlslOptions::default()});
+    assert!(pixel.contains("for (int loop_index_1 = 0; loop_index_1 < 64; loop_index_1++) {\n        if (!(var_t_0 < var_d_0)) {break;}\n"));
+    assert!(pixel.contains("for (int loop_index_2 = 0; loop_index_2 < 64; loop_index_2++) {"));
+    assert!(!pixel.contains("while"));
+
+    assert!(shader.generate_wgsl().contains("var loop_break_outer: bool = false;"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("%loop_break_outer = OpVariable %_ptr_Function_bool Function"));
+    assert_eq!(spirv.matches("OpLoopMerge").count(), 3);
+    let rust = shader.generate_rust().unwrap();
+    assert!(rust.contains("'label_outer: while for_i < 4 {"));
+    assert!(rust.contains("let mut var_i_0 = for_i;"));
+    assert!(rust.contains("continue 'label_outer;"));
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.0, y: 0.0, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set("distance", 2.2f32);
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 2.5, y: 5.0, z: 0.0, w: 1.0});
+    // a loop kept going by its inputs fails instead of hanging
+    inputs.set("distance", 1000.0f32);
+    assert_eq!(shader.interpret_pixel(&inputs).unwrap_err().message, "`while` ran more than 1024 iterations");
+
+    assert_eq!(compile_error(&LOOPS_SOURCE.replace("break 'outer", "break 'inner")), "use of undeclared label `'inner`");
+}
+
+const LOOPS_SOURCE: &'static str = r#"
+        uniform distance: float
+
+        fn march(d: float) -> float {
+            let t = 0.0;
+            while t < d {
+                t += 0.5;
+            }
+            return t;
+        }
+
+        fn search() -> float {
+            let found = 0.0;
+            'outer: for i from 0i to 4i {
+                let j = 0.0;
+                loop {
+                    j += 1.0;
+                    if j > 2.0 {
+                        continue 'outer;
+                    }
+                    if found > 4.0 {
+                        break 'outer;
+                    }
+                    found += 1.0;
+                }
+            }
+            return found;
+        }
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            return vec4(march(self.distance), search(), 0.0, 1.0);
+        }
+"#;
+
+#[test]
+fn arrays() {
+    let shader = compiled(ARRAYS_SOURCE);
+
+    // const array items take 4 floats each
+    assert_eq!(shader.const_table(), vec![
+        1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0,
+        0.25, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+    ]);
+    let (_, pixel) = shader.generate_glsl();
+    assert!(pixel.contains("float var_steps_0[3];\n    var_steps_0[0] = 1.0;\n    var_steps_0[1] = 2.0;\n    var_steps_0[2] = 4.0;"));
+    assert!(pixel.contains("float const_14(int index) {\n    int slot = 12 + index * 4;\n    return const_table[slot];\n}"));
+    assert!(pixel.contains("ds_lights[3] = vec4(user_table[12], user_table[13], user_table[14], user_table[15]);"));
+    assert!(pixel.contains("ds_weights[2] = user_table[18];"));
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl450);
+    assert!(pixel.contains("vec4 ds_lights[4];\n    float ds_weights[3];"));
+    assert!(shader.generate_hlsl().contains("float const_14(int index) {\n    return const_table[3 + index].x;\n}"));
+    assert!(shader.generate_metal().contains("const_9(const_table, 1)"));
+    assert!(shader.generate_wgsl().contains("ds_weights[1] = user_table[4].y;"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("OpDecorate %36 ArrayStride 16"));
+    assert!(spirv.contains("OpMemberDecorate %Uniforms_user 1 Offset 64"));
+    assert!(spirv.contains("%const_9 = OpVariable %_ptr_Private_24 Private %25"));
+
+    // uniform arrays have a std140 stride of 16 bytes
+    let block = &shader.reflection().uniform_blocks[1];
+    assert_eq!(block.uniforms[1].std140_offset, Some(64));
+    assert_eq!(block.std140_size, 112);
+    assert_eq!(block.slots, 19);
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.0, y: 0.0, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set("lights", vec![Vec4::default(), Vec4::default(), Vec4::default(), Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 0.0}])
+        .set("weights", vec![1.0f32, 0.5, 0.25]);
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 8.0, y: 1.75, z: 1.0, w: 1.75});
+
+    // Rust keeps them as arrays, the const arrays become consts
+    assert_eq!(shader.generate_rust().unwrap(), include_str!("generated/arrays.rs"));
+    let uniforms = generated_arrays::Uniforms {
+        lights: [Vec4::default(), Vec4::default(), Vec4::default(), Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 0.0}],
+        weights: [1.0, 0.5, 0.25],
+        Projection: Mat4::identity(),
+    };
+    assert_eq!(generated_arrays::pixel(&generated_arrays::Varyings {}, &uniforms), pixel.value);
+
+    assert_compile_errors(ARRAYS_SOURCE, &[
+        ("steps[2i] = 8.0", "steps[3i] = 8.0", "index out of bounds: the length is 3 but the index is 3"),
+        ("offsets[i]", "offsets", "a const array can only be indexed"),
+        ("let steps: [float; 3] = [1.0, 2.0, 4.0]", "let steps = [1.0, 2.0, vec2(4.0)]", "can't match expected type `float` with actual type `vec2`"),
+        ("steps[2i] = 8.0", "steps = steps", "arrays can't be assigned, assign their elements instead"),
+    ]);
+
+    // HLSL and Metal lay the uniforms out by their own rules, narrow items of arrays are padded to
+    // a `float4` to land at the std140 offsets
+    let source = ARRAYS_SOURCE
+        .replace("uniform weights: [float; 3]", "uniform weights: [float; 3]\n        uniform dirs: [vec3; 2]\n        uniform gain: float\n        uniform flags: [bool; 2]")
+        .replace("+ self.lights[3i]", "+ self.lights[3i] + vec4(self.dirs[1i], self.flags[1i] ? self.gain : 0.0)");
+    let shader = compiled(&source);
+    let offsets: Vec<(String, usize)> = shader.reflection().uniform_blocks[1].uniforms.iter().map( | uniform | {
+        (uniform.ident.clone(), uniform.std140_offset.unwrap())
+    }).collect();
+    assert_eq!(offsets, [("ds_lights", 0), ("ds_weights", 64), ("ds_dirs", 112), ("ds_gain", 144), ("ds_flags", 160)].map( | (ident, offset) | (ident.to_string(), offset)));
+    let hlsl = shader.generate_hlsl();
+    assert_eq!(declared_offsets(&hlsl, "cbuffer Uniforms_user"), offsets);
+    assert!(hlsl.contains("(var_steps_0[var_i_0] * ds_weights[var_i_0].x)"));
+    let metal = shader.generate_metal();
+    assert_eq!(declared_offsets(&metal, "struct Uniforms_user"), offsets);
+    assert!(metal.contains("float4(uniforms_user.ds_dirs[1].xyz, (bool(uniforms_user.ds_flags[1].x) ? uniforms_user.ds_gain : 0.0))"));
+}
+
+// the offsets of the fields of a cbuffer or a Metal struct of `float`s, `float4`s, `uint4`s and arrays
+// of them, where an array or a 4 component vector starts at a multiple of 16 bytes
+fn declared_offsets(source: &str, decl: &str) -> Vec<(String, usize)> {
+    let body = &source[source.find(decl).unwrap()..];
+    let body = &body[body.find('{').unwrap() + 1..body.find('}').unwrap()];
+    let mut offset = 0;
+    body.lines().map(str::trim).filter( | line | !line.is_empty()).map( | line | {
+        let (ty, name) = line.trim_end_matches(';').split_once(' ').unwrap();
+        let (name, len) = match name.split_once('[') {
+            Some((name, len)) => (name, Some(len.trim_end_matches(']').parse::<usize>().unwrap())),
+            None => (name, None),
+        };
+        let size = match ty {
+            "float" => 4,
+            "float4" | "uint4" => 16,
+            _ => panic!("unexpected uniform type {}", ty),
+        };
+        if len.is_some() || size == 16 {
+            offset = (offset + 15) & !15;
+        }
+        let field = (name.to_string(), offset);
+        offset += size * len.unwrap_or(1);
+        field
+    }).collect()
+}
+
+const ARRAYS_SOURCE: &'static str = r#"
+        uniform lights: [vec4; 4]
+        uniform weights: [float; 3]
+        const palette: [#f00, #0f0, #00f]
+        const offsets: [0.25, 0.5, 1.0]
+
+        fn vertex(self) -> vec4 {
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let steps: [float; 3] = [1.0, 2.0, 4.0];
+            let total = 0.0;
+            for i from 0i to 3i {
+                total += steps[i] * self.weights[i] * offsets[i];
+            }
+            steps[2i] = 8.0;
+            return palette[1i] * total + self.lights[3i] + vec4(steps[2i], 0.0, 0.0, 0.0);
+        }
+"#;
+
+#[test]
+fn textures() {
+    let shader = compiled(TEXTURES_SOURCE);
+
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl300Es);
+    assert!(pixel.contains("precision lowp sampler3D;"));
+    assert!(pixel.contains("textureLod(ds_sky, ds_dir, 1.0)"));
+    assert!(pixel.contains("texture(ds_layers, vec3(ds_uv, float(2)))"));
+    assert!(pixel.contains("textureOffset(ds_albedo, ds_uv, ivec2(1, -1))"));
+    assert!(pixel.contains("texture(ds_shadow, vec3(ds_uv, 0.5))"));
+    let (vertex, pixel) = glsl(&shader, GlslTarget::Glsl100Es);
+    assert!(vertex.contains("texture2DLod(ds_albedo, ds_uv, 0.0)"));
+    assert!(pixel.contains("#extension GL_EXT_shader_texture_lod : enable"));
+    assert!(pixel.contains("textureCube(ds_sky, ds_dir) + textureCubeLodEXT(ds_sky, ds_dir, 1.0)"));
+    // which is as far as GLSL ES 1.00 goes
+    let err = shader.check_glsl_target(GlslTarget::Glsl100Es).unwrap_err();
+    assert_eq!(err.message, "`texture3d` needs GLSL ES 3.00, GLSL ES 1.00 only has `texture2d` and `textureCube`");
+    assert_eq!((err.span.start.line, err.span.start.column), (3, 16));
+    assert!(shader.check_glsl_target(GlslTarget::Glsl300Es).is_ok());
+    for (to, message, column) in [
+        ("sample2d(self.albedo, self.uv)", "", 0),
+        ("sample2d_offset(self.albedo, self.uv, ivec2(1i, 0i))", "`sample2d_offset` needs GLSL ES 3.00", 19),
+        ("texel_fetch(self.albedo, ivec2(0i, 0i), 0i)", "`texel_fetch` needs GLSL ES 3.00", 19),
+        ("vec4(vec2(texture_size(self.albedo, 0i)), 0.0, 1.0)", "`texture_size` needs GLSL ES 3.00", 29),
+    ].iter() {
+        let shader = compiled(&LEGACY_TEXTURES_SOURCE.replace("sample2d(self.albedo, self.uv)", to));
+        match shader.check_glsl_target(GlslTarget::Glsl100) {
+            Ok(()) => assert_eq!(*message, ""),
+            Err(err) => assert_eq!((err.message.as_str(), err.span.start.line, err.span.start.column), (*message, 11, *column)),
+        }
+    }
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("SamplerComparisonState ds_shadow_sampler: register(s4);"));
+    assert!(hlsl.contains("ds_shadow.SampleCmp(ds_shadow_sampler, varyings.ds_uv, 0.5, int2(1, 0))"));
+    assert!(shader.generate_metal().contains("depth2d<float> ds_shadow [[texture(4)]];"));
+    assert!(shader.generate_wgsl().contains("var ds_shadow_sampler: sampler_comparison;"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("OpCapability ImageQuery"));
+    assert!(spirv.contains("OpTypeImage %float Cube 0 0 0 1 Unknown"));
+    assert!(spirv.contains("OpImageSampleDrefImplicitLod %float"));
+
+    let textures = &shader.reflection().textures;
+    assert_eq!(textures[1].ty, ShaderTy::TextureCube);
+    assert_eq!(textures[4].ty, ShaderTy::Texture2DDepth);
+    assert!(shader.reflection().to_json().contains("\"ty\": \"textureCube\""));
+
+    // the interpreter only has the 2D texture builtins
+    let red = Vec4 {x: 1.0, y: 0.0, z: 0.0, w: 1.0};
+    let blue = Vec4 {x: 0.0, y: 0.0, z: 1.0, w: 1.0};
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.25, y: 0.25, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set_image("albedo", ShaderImage {width: 2, height: 2, texels: vec![blue, blue, blue, red]});
+    let vertex = shader.interpret_vertex(&inputs).unwrap();
+    assert_eq!(vertex.value, Vec4 {x: 0.25, y: 1.25, z: 0.0, w: 1.0});
+    let err = shader.interpret_pixel(&inputs.set_varyings(&vertex)).err().unwrap();
+    assert!(err.message.contains("the interpreter doesn't support `sample_cube`"), "{}", err);
+
+    assert_compile_errors(TEXTURES_SOURCE, &[
+        ("ivec2(1i, -1i)", "ivec2(self.uv)", "the offset of `sample2d_offset` must be an `ivec2` or `ivec3` of int literals"),
+        ("sample_cube(self.sky, self.dir)", "sample_cube(self.albedo, self.dir)", "can't apply builtin `sample_cube` to arguments of types texture2D, vec3"),
+        ("texture volume: texture3d", "texture volume: vec3", "texture must be a texture2d, textureCube, texture3d, texture2d_array or texture2d_depth"),
+    ]);
+}
+
+const TEXTURES_SOURCE: &'static str = r#"
+        texture albedo: texture2d
+        texture sky: textureCube
+        texture volume: texture3d
+        texture layers: texture2d_array
+        texture shadow: texture2d_depth {filter: linear, wrap: clamp}
+        varying uv: vec2
+        varying dir: vec3
+
+        fn fog(v: texture3d, p: vec3) -> vec4 {
+            return sample3d_lod(v, p, 0.0);
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            self.dir = self.position;
+            let size = texture_size(self.albedo, 0i);
+            let h = sample2d_lod(self.albedo, self.uv, 0.0).x + texel_fetch(self.albedo, size - ivec2(1i, 1i), 0i).x;
+            return self.Projection * vec4(self.position + vec3(0.0, h, 0.0), 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            let c = sample_cube(self.sky, self.dir) + sample_cube_lod(self.sky, self.dir, 1.0);
+            c += sample2d_grad(self.albedo, self.uv, dFdx(self.uv), dFdy(self.uv));
+            c += sample2d_offset(self.albedo, self.uv, ivec2(1i, -1i));
+            c += sample3d(self.volume, self.dir) + fog(self.volume, self.dir) + sample3d_offset(self.volume, self.dir, ivec3(0i, 1i, 0i));
+            c += sample2d_array(self.layers, self.uv, 2i) + sample2d_array_lod(self.layers, self.uv, 1i, 0.0);
+            let lit = sample2d_compare(self.shadow, self.uv, 0.5) + sample2d_compare_offset(self.shadow, self.uv, 0.5, ivec2(1i, 0i));
+            let size = texture_size(self.albedo, 0i);
+            let vsize = texture_size(self.layers, 0i);
+            c += texel_fetch(self.albedo, size - ivec2(1i, 1i), 0i) + texel_fetch(self.layers, ivec3(0i, 0i, vsize.z - 1i), 0i);
+            return c * lit;
+        }
+"#;
+
+const LEGACY_TEXTURES_SOURCE: &'static str = r#"
+        texture albedo: texture2d
+        texture sky: textureCube
+        varying uv: vec2
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            return sample2d(self.albedo, self.uv) + sample_cube(self.sky, vec3(self.uv, 1.0));
+        }
+"#;
+
+#[test]
+fn render_targets() {
+    let shader = compiled(RENDER_TARGETS_SOURCE);
+
+    let (_, pixel) = glsl(&shader, GlslTarget::Glsl300Es);
+    assert!(pixel.contains("layout(location = 1) out vec3 frag_normal;"));
+    assert!(pixel.contains("frag_material = pixel_output.f_material;"));
+    let (_, pixel) = shader.generate_glsl();
+    assert!(pixel.contains("#extension GL_EXT_draw_buffers : require"));
+    assert!(pixel.contains("gl_FragData[3] = vec4(pixel_output.f_depth, 0.0, 0.0, 1.0);"));
+    assert!(has_line(&shader.generate_hlsl(), "float3 frag_normal: SV_Target1;"));
+    assert!(shader.generate_metal().contains("fragment PixelOutput fragment_main("));
+    assert!(has_line(&shader.generate_wgsl(), "@location(2) frag_material: vec2<f32>,"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("OpDecorate %frag_depth Location 3"));
+    assert!(shader.generate_rust().unwrap().contains("pub fn pixel(varyings: &Varyings, uniforms: &Uniforms) -> PixelOutput {"));
+
+    let outputs = &shader.reflection().outputs;
+    assert_eq!(outputs.len(), 4);
+    assert_eq!((outputs[1].name.as_str(), &outputs[1].ty, outputs[1].slot_offset), ("normal", &ShaderTy::Vec3, 1));
+    assert_eq!(outputs[1].ident, "frag_normal");
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("uv", Vec2 {x: 0.5, y: 0.25})
+        .set("normal", Vec3 {x: 0.0, y: 0.0, z: 2.0});
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 0.5, y: 0.25, z: 0.0, w: 1.0});
+    assert_eq!(pixel.output("normal"), Some(&ShaderValue::Vec3(Vec3 {x: 0.0, y: 0.0, z: 1.0})));
+    assert_eq!(pixel.output("depth"), Some(&ShaderValue::Float(0.75)));
+
+    // a plain `vec4` is a single `color` target
+    let shader = compiled(INTERPRETER_SOURCE);
+    let outputs = &shader.reflection().outputs;
+    assert_eq!((outputs[0].name.as_str(), outputs[0].ident.as_str(), outputs.len()), ("color", "frag_color", 1));
+
+    assert_compile_errors(RENDER_TARGETS_SOURCE, &[
+        ("field depth: float", "field depth: mat4", "function `pixel` can only return a struct of 1 to 8 `float`, `vec2`, `vec3` or `vec4` fields"),
+        ("fn pixel(self) -> GBuffer", "fn pixel(self) -> vec3", "function `pixel` must return a value of type `vec4` or a struct"),
+    ]);
+}
+
+const RENDER_TARGETS_SOURCE: &'static str = r#"
+        varying uv: vec2
+        varying normal: vec3
+
+        GBuffer: Struct {
+            field albedo: vec4
+            field normal: vec3
+            field material: vec2
+            field depth: float
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            self.normal = vec3(0.0, 0.0, 1.0);
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> GBuffer {
+            return GBuffer {albedo: vec4(self.uv, 0.0, 1.0), normal: normalize(self.normal), material: vec2(0.5, 0.25), depth: 0.75};
+        }
+"#;
+
+#[test]
+fn stage_builtins() {
+    let shader = compiled(STAGE_BUILTINS_SOURCE);
+
+    let (vertex, pixel) = glsl(&shader, GlslTarget::Glsl300Es);
+    assert!(vertex.contains("ds_uv = vec2(float(gl_VertexID), fn_2_spin ());"));
+    assert!(has_line(&vertex, "gl_PointSize = 1.0;"));
+    assert!(pixel.contains("if(gl_FrontFacing) {"));
+    assert!(has_line(&pixel, "gl_FragDepth = gl_FragCoord.z;"));
+    let (vertex, _) = glsl(&shader, GlslTarget::Glsl450);
+    assert!(vertex.contains("float(gl_InstanceIndex)"));
+    let err = shader.check_glsl_target(GlslTarget::Glsl100).unwrap_err();
+    assert_eq!((err.message.as_str(), err.span.start.line), ("`self.vertex_id` needs GLSL ES 3.00", 8));
+    let (_, pixel) = shader.generate_glsl();
+    assert!(pixel.contains("#extension GL_EXT_frag_depth : enable"));
+    assert!(pixel.contains("gl_FragDepthEXT = (gl_FragCoord.z * 0.5);"));
+    let hlsl = shader.generate_hlsl();
+    assert!(hlsl.contains("uint inst_id: SV_InstanceID, uint vertex_id: SV_VertexID) {"));
+    assert!(hlsl.contains("PixelOutput pixel_main(Varyings varyings, bool front_facing: SV_IsFrontFace) {"));
+    assert!(has_line(&hlsl, "float depth: SV_Depth;"));
+    assert_eq!(shader.check_hlsl().unwrap_err().message, "HLSL doesn't support `self.point_size`");
+    assert_eq!(shader.check_wgsl().unwrap_err().message, "WGSL doesn't support `self.point_size`");
+    assert_eq!((point_shader::HLSL, point_shader::WGSL), (None, None));
+    assert!(point_shader::METAL.contains("[[point_size]]"));
+    let metal = shader.generate_metal();
+    assert!(has_line(&metal, "float point_size [[point_size]];"));
+    assert!(metal.contains(", float2 point_coord [[point_coord]]"));
+    assert!(has_line(&metal, "float depth [[depth(any)]];"));
+    let wgsl = shader.generate_wgsl();
+    assert!(wgsl.contains("@builtin(instance_index) instance_index: u32"));
+    assert!(wgsl.contains("return PixelOutput(pixel_output, builtin_frag_depth);"));
+    let spirv = disassemble_spirv(&shader.generate_spirv());
+    assert!(spirv.contains("OpDecorate %vertex_id BuiltIn VertexIndex"));
+    assert!(spirv.contains("OpExecutionMode %fragment_main DepthReplacing"));
+    assert_eq!(shader.generate_rust().unwrap_err().message, "the Rust backend doesn't support `self.front_facing`");
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set("position", Vec3 {x: 0.0, y: 0.0, z: 0.0})
+        .set("Projection", Mat4::identity())
+        .set("vertex_id", 3)
+        .set("instance_id", 2);
+    let vertex = shader.interpret_vertex(&inputs).unwrap();
+    assert_eq!(vertex.varying("uv"), Some(&ShaderValue::Vec2(Vec2 {x: 3.0, y: 1.0})));
+    assert_eq!(vertex.output("point_size"), Some(&ShaderValue::Float(4.0)));
+
+    let mut inputs = ShaderInputs::new();
+    inputs
+        .set_varyings(&vertex)
+        .set("frag_coord", Vec4 {x: 8.0, y: 8.0, z: 0.5, w: 1.0})
+        .set("point_coord", Vec2 {x: 0.25, y: 0.75})
+        .set("front_facing", false);
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert_eq!(pixel.value, Vec4 {x: 0.25, y: 0.75, z: 3.0, w: 1.0});
+    assert_eq!(pixel.output("frag_depth"), Some(&ShaderValue::Float(0.5)));
+    inputs.set("front_facing", true);
+    assert_eq!(shader.interpret_pixel(&inputs).unwrap().output("frag_depth"), Some(&ShaderValue::Float(0.25)));
+
+    assert_compile_errors(STAGE_BUILTINS_SOURCE, &[
+        ("vec4(self.point_coord", "vec4(vec2(float(self.vertex_id))", "`self.vertex_id` can only be used in the vertex shader"),
+        ("self.point_size = 4.0", "self.vertex_id = 4i", "`self.vertex_id` can't be assigned to"),
+    ]);
+}
+
+mod point_shader {
+    nanoshredder_macros::shader! {
+        attributes {position: vec3}
+
+        fn vertex(self) -> vec4 {
+            self.point_size = 4.0;
+            return vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            return vec4(self.point_coord, 0.0, 1.0);
+        }
+    }
+}
+
+const STAGE_BUILTINS_SOURCE: &'static str = r#"
+        varying uv: vec2
+
+        fn spin(self) -> float {
+            return float(self.instance_id) * 0.5;
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = vec2(float(self.vertex_id), self.spin());
+            self.point_size = 4.0;
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            if self.front_facing {
+                self.frag_depth = self.frag_coord.z * 0.5;
+            }
+            return vec4(self.point_coord, self.uv.x, 1.0);
+        }
+"#;
+
+#[test]
+fn discard() {
+    let shader = compiled(DISCARD_SOURCE);
+
+    let (vertex, pixel) = shader.generate_glsl();
+    assert!(!vertex.contains("discard"));
+    assert!(has_line(&pixel, "discard;"));
+    assert!(has_line(&shader.generate_hlsl(), "discard;"));
+    assert!(has_line(&shader.generate_metal(), "discard_fragment();"));
+    assert!(has_line(&shader.generate_wgsl(), "discard;"));
+    assert!(has_line(&disassemble_spirv(&shader.generate_spirv()), "OpKill"));
+    assert_eq!(shader.generate_rust().unwrap_err().message, "the Rust backend doesn't support `discard`");
+
+    let mut inputs = ShaderInputs::new();
+    inputs.set("uv", Vec2 {x: 0.75, y: 0.25});
+    let pixel = shader.interpret_pixel(&inputs).unwrap();
+    assert!(!pixel.discarded);
+    assert_eq!(pixel.value, Vec4 {x: 0.75, y: 0.25, z: 0.0, w: 1.0});
+    inputs.set("uv", Vec2 {x: 0.25, y: 0.25});
+    assert!(shader.interpret_pixel(&inputs).unwrap().discarded);
+
+    let source = DISCARD_SOURCE.replace("self.uv = self.position.xy;", "self.uv = self.position.xy;\n            self.clip(self.uv.x);");
+    assert_eq!(compile_error(&source), "`discard` can only be used in the pixel shader");
+}
+
+const DISCARD_SOURCE: &'static str = r#"
+        varying uv: vec2
+
+        fn clip(self, alpha: float) {
+            if alpha < 0.5 {
+                discard;
+            }
+        }
+
+        fn vertex(self) -> vec4 {
+            self.uv = self.position.xy;
+            return self.Projection * vec4(self.position, 1.0);
+        }
+
+        fn pixel(self) -> vec4 {
+            self.clip(self.uv.x);
+            return vec4(self.uv, 0.0, 1.0);
+        }
+"#;
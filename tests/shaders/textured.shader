@@ -0,0 +1,11 @@
+texture tex: texture2d {filter: nearest, wrap: repeat}
+varying uv: vec2
+
+fn vertex(self) -> vec4 {
+    self.uv = self.position.xy;
+    return self.Projection * vec4(self.position, 1.0);
+}
+
+fn pixel(self) -> vec4 {
+    return sample2d(self.tex, self.uv) * #fff8;
+}